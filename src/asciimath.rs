@@ -0,0 +1,578 @@
+use super::{BuiltinOp, ConstantSymbol, MathNode};
+use crate::cursor::{self, Cursor};
+use crate::entities;
+use crate::error::ParseError;
+use crate::infix::{invalid, negated};
+
+/// The symbols of AsciiMath this parser knows, longest first
+const SYMBOLS: &[&str] = &[
+    "<=>", "=>", "<=", ">=", "!=", "-:", "//", "+", "-", "*", "/", "^", "_", "!", "=", "<", ">",
+    "(", ")", "[", "]", "{", "}", "|", ",",
+];
+
+/// The words of AsciiMath this parser knows, besides the Greek letters. Letters
+/// that don't start one are single identifiers, so `xy` is `x y`
+const WORDS: &str = "and or not xx oo pi sum prod int sqrt root frac abs floor ceil exp ln \
+                     log sin cos tan sec csc cot sinh cosh tanh sech csch coth arcsin arccos \
+                     arctan max min gcd lcm le ge ne";
+const GREEK: &str = "alpha beta gamma delta epsilon varepsilon zeta eta theta vartheta iota \
+                     kappa lambda mu nu xi rho sigma tau upsilon phi varphi chi psi omega Gamma \
+                     Delta Theta Lambda Xi Pi Sigma Phi Psi Omega";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token<'a> {
+    Number(&'a str),
+    /// A single letter
+    Letter(&'a str),
+    Word(&'a str),
+    Symbol(&'static str),
+    End,
+}
+
+/// The longest word of `WORDS` or `GREEK` that `rest` starts with
+fn word(rest: &str) -> Option<&str> {
+    WORDS
+        .split_whitespace()
+        .chain(GREEK.split_whitespace())
+        .filter(|w| rest.starts_with(w))
+        .max_by_key(|w| w.len())
+}
+
+fn tokenize(text: &str) -> Result<Vec<(Token<'_>, usize)>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let position = text.len() - rest.len();
+        let (token, len) = if c.is_ascii_digit() || c == '.' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            (Token::Number(&rest[..len]), len)
+        } else if let Some(word) = word(rest) {
+            (Token::Word(word), word.len())
+        } else if c.is_alphabetic() {
+            (Token::Letter(&rest[..c.len_utf8()]), c.len_utf8())
+        } else {
+            match SYMBOLS.iter().find(|s| rest.starts_with(**s)) {
+                Some(symbol) => (Token::Symbol(symbol), symbol.len()),
+                None => return Err(invalid(position, format!("unexpected '{}'", c))),
+            }
+        };
+        tokens.push((token, position));
+        rest = rest[len..].trim_start();
+    }
+    tokens.push((Token::End, text.len()));
+    Ok(tokens)
+}
+
+/// The functions written before their argument, as in `sin x` or `abs(x)`
+fn function(word: &str) -> Option<BuiltinOp> {
+    use BuiltinOp::*;
+    let op = match word {
+        "sqrt" => root,
+        "abs" => abs,
+        "floor" => floor,
+        "ceil" => ceiling,
+        "exp" => exp,
+        "ln" => ln,
+        "log" => log,
+        "sin" => sin,
+        "cos" => cos,
+        "tan" => tan,
+        "sec" => sec,
+        "csc" => csc,
+        "cot" => cot,
+        "sinh" => sinh,
+        "cosh" => cosh,
+        "tanh" => tanh,
+        "sech" => sech,
+        "csch" => csch,
+        "coth" => coth,
+        "arcsin" => arcsin,
+        "arccos" => arccos,
+        "arctan" => arctan,
+        "max" => max,
+        "min" => min,
+        "gcd" => gcd,
+        "lcm" => lcm,
+        _ => return None,
+    };
+    Some(op)
+}
+
+fn relation(token: Token) -> Option<BuiltinOp> {
+    use BuiltinOp::*;
+    let op = match token {
+        Token::Symbol("=") => eq,
+        Token::Symbol("!=") | Token::Word("ne") => neq,
+        Token::Symbol("<") => lt,
+        Token::Symbol(">") => gt,
+        Token::Symbol("<=") | Token::Word("le") => leq,
+        Token::Symbol(">=") | Token::Word("ge") => geq,
+        _ => return None,
+    };
+    Some(op)
+}
+
+impl cursor::Token for Token<'_> {
+    const END: Self = Token::End;
+    fn describe(self) -> String {
+        match self {
+            Token::Number(text) | Token::Letter(text) | Token::Word(text) => format!("'{}'", text),
+            Token::Symbol(symbol) => format!("'{}'", symbol),
+            Token::End => "the end of the input".to_owned(),
+        }
+    }
+}
+
+/// Appends `operand` to `lhs` if it applies `op` from the same chain, as in
+/// `a + b + c`, and otherwise applies `op` to both
+fn chain(lhs: MathNode, op: BuiltinOp, rhs: MathNode, chained: bool) -> MathNode {
+    match lhs {
+        MathNode::Apply {
+            mut children,
+            attributes,
+        } if chained && children.first() == Some(&MathNode::Op(op)) => {
+            children.push(rhs);
+            MathNode::Apply {
+                children,
+                attributes,
+            }
+        }
+        lhs => MathNode::apply(op, vec![lhs, rhs]),
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    tokens: Cursor<Token<'a>>,
+    /// How many `|` of absolute values are open, which then can't start another
+    open_bars: usize,
+    /// How many integrals are open, in which `d x` ends the integrand
+    open_integrals: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn expr(&mut self) -> Result<MathNode, ParseError> {
+        let mut lhs = self.or()?;
+        loop {
+            let op = match self.tokens.peek() {
+                Token::Symbol("=>") => BuiltinOp::implies,
+                Token::Symbol("<=>") => BuiltinOp::equivalent,
+                _ => return Ok(lhs),
+            };
+            self.tokens.advance();
+            lhs = MathNode::apply(op, vec![lhs, self.or()?]);
+        }
+    }
+    fn or(&mut self) -> Result<MathNode, ParseError> {
+        let mut lhs = self.and()?;
+        let mut chained = false;
+        while self.tokens.eat(Token::Word("or")) {
+            lhs = chain(lhs, BuiltinOp::or, self.and()?, chained);
+            chained = true;
+        }
+        Ok(lhs)
+    }
+    fn and(&mut self) -> Result<MathNode, ParseError> {
+        let mut lhs = self.not()?;
+        let mut chained = false;
+        while self.tokens.eat(Token::Word("and")) {
+            lhs = chain(lhs, BuiltinOp::and, self.not()?, chained);
+            chained = true;
+        }
+        Ok(lhs)
+    }
+    /// `not`, which binds looser than relations, as in `not x > 1`
+    fn not(&mut self) -> Result<MathNode, ParseError> {
+        if self.tokens.eat(Token::Word("not")) {
+            Ok(MathNode::apply(BuiltinOp::not, vec![self.not()?]))
+        } else {
+            self.relation()
+        }
+    }
+    /// Relations, where `a < b < c` is a single `<lt/>` of three operands
+    fn relation(&mut self) -> Result<MathNode, ParseError> {
+        let mut lhs = self.additive()?;
+        let mut previous = None;
+        while let Some(op) = relation(self.tokens.peek()) {
+            self.tokens.advance();
+            let rhs = self.additive()?;
+            lhs = chain(lhs, op, rhs, previous == Some(op) && op != BuiltinOp::neq);
+            previous = Some(op);
+        }
+        Ok(lhs)
+    }
+    fn additive(&mut self) -> Result<MathNode, ParseError> {
+        let mut lhs = self.term()?;
+        let mut chained = false;
+        loop {
+            if self.tokens.eat(Token::Symbol("+")) {
+                lhs = chain(lhs, BuiltinOp::plus, self.term()?, chained);
+                chained = true;
+            } else if self.tokens.eat(Token::Symbol("-")) {
+                lhs = MathNode::apply(BuiltinOp::minus, vec![lhs, self.term()?]);
+                chained = false;
+            } else {
+                return Ok(lhs);
+            }
+        }
+    }
+    /// Products, explicit with `*` or `xx` or implied by juxtaposition as in
+    /// `2 x sin y`, and divisions with `-:`
+    fn term(&mut self) -> Result<MathNode, ParseError> {
+        let mut lhs = self.unary()?;
+        let mut chained = false;
+        loop {
+            if self.tokens.eat(Token::Symbol("-:")) || self.tokens.eat(Token::Symbol("//")) {
+                lhs = MathNode::apply(BuiltinOp::divide, vec![lhs, self.unary()?]);
+                chained = false;
+                continue;
+            }
+            let explicit =
+                self.tokens.eat(Token::Symbol("*")) || self.tokens.eat(Token::Word("xx"));
+            if !explicit && (!self.starts_factor() || self.at_differential()) {
+                return Ok(lhs);
+            }
+            lhs = chain(lhs, BuiltinOp::times, self.unary()?, chained);
+            chained = true;
+        }
+    }
+    fn starts_factor(&self) -> bool {
+        match self.tokens.peek() {
+            Token::Number(_) | Token::Letter(_) => true,
+            Token::Word(word) => !matches!(word, "and" | "or" | "not" | "xx" | "le" | "ge" | "ne"),
+            Token::Symbol("|") => self.open_bars == 0,
+            Token::Symbol(symbol) => matches!(symbol, "(" | "[" | "{"),
+            Token::End => false,
+        }
+    }
+    /// Whether the `d x` ending an integrand comes next
+    fn at_differential(&self) -> bool {
+        self.open_integrals > 0
+            && self.tokens.peek() == Token::Letter("d")
+            && matches!(self.tokens.peek_second(), Token::Letter(_) | Token::Word(_))
+    }
+    fn unary(&mut self) -> Result<MathNode, ParseError> {
+        if self.tokens.eat(Token::Symbol("-")) {
+            Ok(negated(self.unary()?))
+        } else if self.tokens.eat(Token::Symbol("+")) {
+            self.unary()
+        } else {
+            self.fraction()
+        }
+    }
+    /// `/` binds tighter than products, as in AsciiMath `2x/3` is `2 x/3`
+    fn fraction(&mut self) -> Result<MathNode, ParseError> {
+        let mut lhs = self.power()?;
+        while self.tokens.eat(Token::Symbol("/")) {
+            lhs = MathNode::apply(BuiltinOp::divide, vec![lhs, self.power()?]);
+        }
+        Ok(lhs)
+    }
+    /// `^`, right associative, with an exponent that may be negated
+    fn power(&mut self) -> Result<MathNode, ParseError> {
+        let base = self.postfix()?;
+        if !self.tokens.eat(Token::Symbol("^")) {
+            return Ok(base);
+        }
+        Ok(MathNode::apply(
+            BuiltinOp::power,
+            vec![base, self.script(true)?],
+        ))
+    }
+    /// A sub- or superscript, e.g. `2`, `-1` or `(n+1)`, with `^` after it
+    /// when `powers` is set
+    fn script(&mut self, powers: bool) -> Result<MathNode, ParseError> {
+        if self.tokens.eat(Token::Symbol("-")) {
+            return Ok(negated(self.script(powers)?));
+        }
+        if powers {
+            self.power()
+        } else {
+            self.primary()
+        }
+    }
+    fn postfix(&mut self) -> Result<MathNode, ParseError> {
+        let mut node = self.primary()?;
+        while self.tokens.eat(Token::Symbol("!")) {
+            node = MathNode::apply(BuiltinOp::factorial, vec![node]);
+        }
+        Ok(node)
+    }
+    /// An identifier, with a subscript becoming part of its name, e.g. `k_1`
+    /// or `k_(cat)`
+    fn identifier(&mut self, name: &str) -> Result<MathNode, ParseError> {
+        if !self.tokens.eat(Token::Symbol("_")) {
+            return Ok(MathNode::ci(name));
+        }
+        let start = self.tokens.position();
+        let subscript = match self.tokens.advance() {
+            Token::Number(text) | Token::Letter(text) | Token::Word(text) => text.to_owned(),
+            Token::Symbol("(") => {
+                let mut depth = 1;
+                while depth > 0 {
+                    match self.tokens.advance() {
+                        Token::Symbol("(") => depth += 1,
+                        Token::Symbol(")") => depth -= 1,
+                        Token::End => return self.tokens.error("')'"),
+                        _ => {}
+                    }
+                }
+                let end = self.tokens.previous_position();
+                self.text[start + 1..end].split_whitespace().collect()
+            }
+            _ => {
+                self.tokens.back();
+                return self.tokens.error("a subscript");
+            }
+        };
+        Ok(MathNode::ci(format!("{}_{}", name, subscript)))
+    }
+    /// The arguments of a function: a parenthesized list, or the factor right
+    /// after it, so `sin 2x` is `sin(2) x`
+    fn arguments(&mut self) -> Result<(Vec<MathNode>, bool), ParseError> {
+        if !self.tokens.eat(Token::Symbol("(")) {
+            return Ok((vec![self.script(true)?], false));
+        }
+        let mut args = vec![self.expr()?];
+        while self.tokens.eat(Token::Symbol(",")) {
+            args.push(self.expr()?);
+        }
+        if !self.tokens.eat(Token::Symbol(")")) {
+            return self.tokens.error("')'");
+        }
+        Ok((args, true))
+    }
+    /// `sum_(i=1)^n body`, `prod` alike, or `int_a^b body dx`
+    fn big_operator(&mut self, op: BuiltinOp) -> Result<MathNode, ParseError> {
+        let mut qualifiers = Vec::new();
+        let mut bvar = None;
+        if self.tokens.eat(Token::Symbol("_")) {
+            let position = self.tokens.position();
+            let lower = self.script(false)?;
+            if op == BuiltinOp::int {
+                qualifiers.push(MathNode::Lowlimit(Box::new(lower)));
+            } else {
+                match lower {
+                    MathNode::Apply { ref children, .. }
+                        if children.len() == 3 && children[0] == MathNode::Op(BuiltinOp::eq) =>
+                    {
+                        match (&children[1], &children[2]) {
+                            (MathNode::Ci { children: name, .. }, low) => {
+                                bvar = Some(crate::text_content(name));
+                                qualifiers.push(MathNode::Lowlimit(Box::new(low.clone())));
+                            }
+                            _ => return Err(invalid(position, "expected a variable = a limit")),
+                        }
+                    }
+                    MathNode::Ci { ref children, .. } => bvar = Some(crate::text_content(children)),
+                    _ => return Err(invalid(position, "expected a variable = a limit")),
+                }
+            }
+        }
+        if self.tokens.eat(Token::Symbol("^")) {
+            qualifiers.push(MathNode::Uplimit(Box::new(self.script(false)?)));
+        }
+        let body = if op == BuiltinOp::int {
+            self.open_integrals += 1;
+            let body = self.term();
+            self.open_integrals -= 1;
+            let body = body?;
+            if !self.tokens.eat(Token::Letter("d")) {
+                return self.tokens.error("the 'd x' of the integral");
+            }
+            bvar = match self.tokens.advance() {
+                Token::Letter(name) | Token::Word(name) => {
+                    Some(greek(name).unwrap_or(name).to_owned())
+                }
+                _ => {
+                    self.tokens.back();
+                    return self.tokens.error("the variable of integration");
+                }
+            };
+            body
+        } else {
+            self.term()?
+        };
+        let mut children = vec![MathNode::Op(op)];
+        if let Some(name) = bvar {
+            children.push(MathNode::Bvar { name, degree: None });
+        }
+        children.extend(qualifiers);
+        children.push(body);
+        Ok(MathNode::Apply {
+            children,
+            attributes: Vec::new(),
+        })
+    }
+    fn group(&mut self, close: &[&str]) -> Result<MathNode, ParseError> {
+        let inner = self.expr()?;
+        match self.tokens.advance() {
+            Token::Symbol(symbol) if close.contains(&symbol) => Ok(inner),
+            _ => {
+                self.tokens.back();
+                self.tokens.error(&format!("'{}'", close[0]))
+            }
+        }
+    }
+    fn primary(&mut self) -> Result<MathNode, ParseError> {
+        let position = self.tokens.position();
+        match self.tokens.advance() {
+            Token::Number(text) => {
+                let number =
+                    match text.parse() {
+                        Ok(integer) => MathNode::integer(integer),
+                        Err(_) => MathNode::cn(text.parse().map_err(|_| {
+                            invalid(position, format!("'{}' is not a number", text))
+                        })?),
+                    };
+                Ok(number)
+            }
+            Token::Letter("e") if self.tokens.peek() != Token::Symbol("_") => {
+                Ok(MathNode::Constant(ConstantSymbol::ExponentialE))
+            }
+            Token::Letter(name) => self.identifier(name),
+            Token::Word("pi") => Ok(MathNode::Constant(ConstantSymbol::Pi)),
+            Token::Word("oo") => Ok(MathNode::Constant(ConstantSymbol::Infinity)),
+            Token::Word(word) if greek(word).is_some() => {
+                self.identifier(greek(word).unwrap_or(word))
+            }
+            Token::Word("sum") => self.big_operator(BuiltinOp::sum),
+            Token::Word("prod") => self.big_operator(BuiltinOp::product),
+            Token::Word("int") => self.big_operator(BuiltinOp::int),
+            Token::Word("frac") => {
+                let numerator = self.primary()?;
+                let denominator = self.primary()?;
+                Ok(MathNode::apply(
+                    BuiltinOp::divide,
+                    vec![numerator, denominator],
+                ))
+            }
+            Token::Word("root") => {
+                let degree = self.primary()?;
+                let radicand = self.primary()?;
+                Ok(MathNode::apply(
+                    BuiltinOp::root,
+                    vec![MathNode::Degree(Box::new(degree)), radicand],
+                ))
+            }
+            Token::Word("log") if self.tokens.eat(Token::Symbol("_")) => {
+                let base = self.script(false)?;
+                let (mut args, _) = self.arguments()?;
+                args.insert(0, MathNode::Logbase(Box::new(base)));
+                Ok(MathNode::apply(BuiltinOp::log, args))
+            }
+            Token::Word(word) if function(word).is_some() => {
+                let op = function(word).unwrap_or(BuiltinOp::abs);
+                let (args, parenthesized) = self.arguments()?;
+                let applied = MathNode::apply(op, args);
+                // `sin(x)^2` squares the sine
+                if parenthesized && self.tokens.eat(Token::Symbol("^")) {
+                    return Ok(MathNode::apply(
+                        BuiltinOp::power,
+                        vec![applied, self.script(true)?],
+                    ));
+                }
+                Ok(applied)
+            }
+            Token::Symbol("(") => self.group(&[")", "]"]),
+            Token::Symbol("[") => self.group(&["]", ")"]),
+            Token::Symbol("{") => self.group(&["}"]),
+            Token::Symbol("|") => {
+                self.open_bars += 1;
+                let inner = self.expr();
+                self.open_bars -= 1;
+                let inner = inner?;
+                if !self.tokens.eat(Token::Symbol("|")) {
+                    return self.tokens.error("'|'");
+                }
+                Ok(MathNode::apply(BuiltinOp::abs, vec![inner]))
+            }
+            _ => {
+                self.tokens.back();
+                self.tokens.error("an expression")
+            }
+        }
+    }
+}
+
+/// The character of a Greek letter's name, e.g. `α` for `alpha`
+fn greek(name: &str) -> Option<&'static str> {
+    if GREEK.split_whitespace().any(|g| g == name) {
+        entities::lookup(name)
+    } else {
+        None
+    }
+}
+
+/// Parses [AsciiMath](http://asciimath.org), e.g. `sum_(i=1)^n i^2`, into
+/// content MathML. Juxtaposition multiplies, `/` binds tighter than it,
+/// functions like `sin x` apply to the factor right after them, and `sum`,
+/// `prod` and `int_a^b ... dx` bind their variable. Letters not part of a
+/// known word are single identifiers, so `xy` is `x y`, with `e` being
+/// Euler's number and subscripts part of the name, as in `k_1`
+pub fn parse_asciimath(text: &str) -> Result<MathNode, ParseError> {
+    let mut parser = Parser {
+        text,
+        tokens: Cursor::new(tokenize(text)?),
+        open_bars: 0,
+        open_integrals: 0,
+    };
+    let node = parser.expr()?;
+    if parser.tokens.peek() != Token::End {
+        return parser.tokens.error("an operator");
+    }
+    Ok(node)
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_asciimath;
+    use crate::parse_document;
+
+    #[test]
+    fn test_parse_asciimath() {
+        let expected = parse_document(
+            r#"<apply><sum/><bvar><ci>i</ci></bvar><lowlimit><cn type="integer">1</cn></lowlimit><uplimit><ci>n</ci></uplimit>
+                 <apply><power/><ci>i</ci><cn type="integer">2</cn></apply>
+               </apply>"#,
+        )
+        .unwrap();
+        assert_eq!(parse_asciimath("sum_(i=1)^n i^2").unwrap(), expected);
+
+        let test = "2x/3 + sin 2x - sqrt(b^2 - 4a c) + |x - 1| + e^(-k_1 t) + alpha!";
+        assert_eq!(
+            parse_asciimath(test).unwrap().to_string(),
+            "2 * x / 3 + sin(2) * x - sqrt(b^2 - 4 * a * c) + abs(x - 1) + e^(-k_1 * t) + α!"
+        );
+        let test = "int_0^pi sin(x)^2 dx <= frac(pi)(2) and not x >= 1";
+        assert_eq!(
+            parse_asciimath(test).unwrap().to_string(),
+            "int(sin(x)^2, x = 0..pi) <= pi / 2 && !(x >= 1)"
+        );
+        approx::assert_abs_diff_eq!(
+            parse_asciimath("int_0^pi sin(x)^2 dx")
+                .unwrap()
+                .eval(&Default::default())
+                .unwrap(),
+            std::f64::consts::FRAC_PI_2,
+            epsilon = 1e-9
+        );
+        assert!(parse_asciimath("(a + b").is_err());
+        assert!(parse_asciimath("int x").is_err());
+        let error = |text: &str| parse_asciimath(text).unwrap_err().to_string();
+        assert_eq!(
+            error(""),
+            "invalid formula at offset 0: expected an expression, found the end of the input"
+        );
+        assert_eq!(
+            error(" "),
+            "invalid formula at offset 1: expected an expression, found the end of the input"
+        );
+        assert_eq!(
+            error("(x"),
+            "invalid formula at offset 2: expected ')', found the end of the input"
+        );
+    }
+}
@@ -0,0 +1,79 @@
+use roxmltree::Node;
+use serde_derive::{Deserialize, Serialize};
+
+/// The name of an attribute the parser has no field for, with the prefix the
+/// document used so writing it back reproduces the original markup
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone, Hash, PartialOrd, Ord)]
+pub struct QualifiedName {
+    pub prefix: Option<String>,
+    pub namespace: Option<String>,
+    pub local: String,
+}
+
+/// Preserved attributes in document order
+pub type Attributes = Vec<(QualifiedName, String)>;
+
+/// The attributes of `node` other than the un-namespaced ones in `known`
+pub(crate) fn extra_attributes(node: Node, known: &[&str]) -> Attributes {
+    node.attributes()
+        .iter()
+        .filter(|a| a.namespace().is_some() || !known.contains(&a.name()))
+        .map(|a| {
+            let name = QualifiedName {
+                prefix: a
+                    .namespace()
+                    .and_then(|ns| node.lookup_prefix(ns))
+                    .map(str::to_owned),
+                namespace: a.namespace().map(str::to_owned),
+                local: a.name().to_owned(),
+            };
+            (name, a.value().to_owned())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{parse_document, MathNode};
+
+    #[test]
+    fn test_preserved_attributes() {
+        let test = r#"<math xmlns="http://www.w3.org/1998/Math/MathML" xmlns:ex="http://example.org/annotations">
+                        <apply><csymbol id="t" xml:lang="en" definitionURL="http://example.org/time">time</csymbol>
+                            <cn ex:source="fit" id="c1">2</cn>
+                        </apply>
+                      </math>"#;
+        let parsed = parse_document(test).unwrap();
+        let cn = parsed
+            .iter()
+            .find(|n| matches!(n, MathNode::Cn { .. }))
+            .unwrap();
+        match cn {
+            MathNode::Cn { attributes, .. } => {
+                assert_eq!(attributes[0].0.prefix.as_deref(), Some("ex"));
+                assert_eq!(attributes[0].0.local, "source");
+                assert_eq!(attributes[1].0.namespace, None);
+            }
+            _ => unreachable!(),
+        }
+        let written = parsed.to_mathml();
+        assert!(written
+            .contains(r#"<csymbol definitionURL="http://example.org/time" id="t" xml:lang="en">"#));
+        assert!(written
+            .contains(r#" xmlns:ex="http://example.org/annotations" ex:source="fit" id="c1">"#));
+        assert_eq!(parse_document(&written).unwrap(), parsed);
+    }
+    #[test]
+    fn test_element_attributes() {
+        let test = r#"<math xmlns="http://www.w3.org/1998/Math/MathML" xmlns:copasi="http://www.copasi.org/static/sbml" display="block">
+                        <apply id="rate" copasi:key="Reaction_0"><times/><ci class="species" xref="s1">S</ci><cn>2</cn></apply>
+                      </math>"#;
+        let parsed = parse_document(test).unwrap();
+        let written = parsed.to_mathml();
+        assert!(written.starts_with(
+            r#"<math xmlns="http://www.w3.org/1998/Math/MathML" display="block"><apply xmlns:copasi="http://www.copasi.org/static/sbml" id="rate" copasi:key="Reaction_0">"#
+        ));
+        assert!(written.contains(r#"<ci class="species" xref="s1">S</ci>"#));
+        assert_eq!(parse_document(&written).unwrap(), parsed);
+    }
+}
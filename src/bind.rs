@@ -0,0 +1,117 @@
+use super::MathNode;
+use crate::error::ParseError;
+
+/// Whether `binder` is the `lambda` of the `fns1` content dictionary
+fn is_lambda(binder: &MathNode) -> bool {
+    matches!(binder, MathNode::Csymbol { cd, name, .. }
+        if cd.as_deref() == Some("fns1") && name.trim() == "lambda")
+}
+
+/// Builds a `<bind>` from its element children: the binder, its `<bvar>`s,
+/// an optional `<condition>` and the body
+pub(crate) fn parse_bind(children: Vec<MathNode>) -> Result<MathNode, ParseError> {
+    let mut children = children.into_iter();
+    let binder = children
+        .next()
+        .ok_or_else(|| ParseError::InvalidStructure("<bind> is missing its binder".to_owned()))?;
+    let mut bound_vars = Vec::new();
+    let mut condition = None;
+    let mut body = None;
+    for child in children {
+        match child {
+            MathNode::Bvar { name, degree: None } if body.is_none() && condition.is_none() => {
+                bound_vars.push(name)
+            }
+            MathNode::Condition(inner) if body.is_none() && condition.is_none() => {
+                condition = Some(inner)
+            }
+            _ if body.is_none() => body = Some(Box::new(child)),
+            _ => {
+                return Err(ParseError::InvalidStructure(
+                    "<bind> holds a binder, <bvar>s without a <degree>, an optional <condition> \
+                     and one body"
+                        .to_owned(),
+                ))
+            }
+        }
+    }
+    let body =
+        body.ok_or_else(|| ParseError::InvalidStructure("<bind> is missing its body".to_owned()))?;
+    Ok(MathNode::Bind {
+        binder: Box::new(binder),
+        bound_vars,
+        condition,
+        body,
+    })
+}
+
+impl MathNode {
+    /// The pragmatic form of a `<bind>`: an `<apply>` of its binder to its
+    /// `<bvar>`s, condition and body, or a `<lambda>` when bound by `fns1`'s
+    /// `lambda` without a condition. Other nodes are returned as they are
+    pub fn to_apply(&self) -> MathNode {
+        let (binder, bound_vars, condition, body) = match self {
+            MathNode::Bind {
+                binder,
+                bound_vars,
+                condition,
+                body,
+            } => (binder, bound_vars, condition, body),
+            _ => return self.clone(),
+        };
+        if is_lambda(binder) && condition.is_none() {
+            return MathNode::Lambda {
+                bound_vars: bound_vars.clone(),
+                body: body.clone(),
+            };
+        }
+        let mut children = vec![(**binder).clone()];
+        children.extend(bound_vars.iter().map(|name| MathNode::Bvar {
+            name: name.clone(),
+            degree: None,
+        }));
+        children.extend(condition.clone().map(MathNode::Condition));
+        children.push((**body).clone());
+        MathNode::Apply {
+            children,
+            attributes: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{parse_document, BuiltinOp, MathNode};
+
+    #[test]
+    fn test_bind() {
+        let test = r#"<bind><forall/><bvar><ci>x</ci></bvar><condition><apply><in/><ci>x</ci><reals/></apply></condition>
+                        <apply><geq/><apply><abs/><ci>x</ci></apply><cn type="integer">0</cn></apply>
+                      </bind>"#;
+        let parsed = parse_document(test).unwrap();
+        match &parsed {
+            MathNode::Bind {
+                binder,
+                bound_vars,
+                condition,
+                ..
+            } => {
+                assert_eq!(**binder, MathNode::Op(BuiltinOp::forall));
+                assert_eq!(bound_vars, &["x"]);
+                assert!(condition.is_some());
+            }
+            _ => unreachable!(),
+        }
+        assert_eq!(parse_document(&parsed.to_mathml()).unwrap(), parsed);
+        let apply = test.replace("bind>", "apply>");
+        assert_eq!(parsed.to_apply(), parse_document(&apply).unwrap());
+        assert_eq!(parsed.to_string(), parsed.to_apply().to_string());
+
+        let test = r#"<bind><csymbol cd="fns1">lambda</csymbol><bvar><ci>y</ci></bvar><apply><sin/><ci>y</ci></apply></bind>"#;
+        assert!(matches!(
+            parse_document(test).unwrap().to_apply(),
+            MathNode::Lambda { .. }
+        ));
+        assert!(parse_document("<bind><exists/><bvar><ci>x</ci></bvar></bind>").is_err());
+    }
+}
@@ -0,0 +1,557 @@
+use super::{BuiltinOp, MathNode};
+use crate::error::EvalError;
+use crate::eval::{check_arity, constant_symbol, number};
+use std::collections::HashMap;
+use std::f64::consts::{FRAC_PI_2, PI};
+
+/// The float after `x`, towards infinity
+fn next_up(x: f64) -> f64 {
+    if x.is_nan() || x == f64::INFINITY {
+        x
+    } else if x == 0.0 {
+        f64::from_bits(1)
+    } else if x > 0.0 {
+        f64::from_bits(x.to_bits() + 1)
+    } else {
+        f64::from_bits(x.to_bits() - 1)
+    }
+}
+
+fn next_down(x: f64) -> f64 {
+    -next_up(-x)
+}
+
+/// A closed range of real numbers `[lo, hi]`, the value of an expression
+/// evaluated with `MathNode::eval_bounds`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub lo: f64,
+    pub hi: f64,
+}
+
+impl Bounds {
+    pub fn new(lo: f64, hi: f64) -> Self {
+        Bounds { lo, hi }
+    }
+    /// The range holding just `value`
+    pub fn point(value: f64) -> Self {
+        Bounds::new(value, value)
+    }
+    fn everything() -> Self {
+        Bounds::new(f64::NEG_INFINITY, f64::INFINITY)
+    }
+    /// The value of a condition: `[1, 1]` if it certainly holds, `[0, 1]` if
+    /// it possibly does and `[0, 0]` if it can't
+    fn truth(certain: bool, possible: bool) -> Self {
+        let bit = |b: bool| if b { 1.0 } else { 0.0 };
+        Bounds::new(bit(certain), bit(possible))
+    }
+    pub fn contains(&self, value: f64) -> bool {
+        self.lo <= value && value <= self.hi
+    }
+    /// The smallest range holding both
+    pub fn hull(&self, other: &Bounds) -> Bounds {
+        Bounds::new(self.lo.min(other.lo), self.hi.max(other.hi))
+    }
+    fn is_point(&self) -> bool {
+        self.lo == self.hi
+    }
+    /// Whether every value is nonzero, and whether any is
+    fn truths(&self) -> (bool, bool) {
+        (!self.contains(0.0), !(self.lo == 0.0 && self.hi == 0.0))
+    }
+    /// One ulp wider on each side, to hold whatever rounding the ends went
+    /// through
+    fn outward(&self) -> Bounds {
+        Bounds::new(next_down(self.lo), next_up(self.hi))
+    }
+    /// The range of `f`, which is increasing and exact, like `floor`
+    fn map(&self, f: impl Fn(f64) -> f64) -> Bounds {
+        Bounds::new(f(self.lo), f(self.hi))
+    }
+    fn increasing(&self, f: impl Fn(f64) -> f64) -> Bounds {
+        self.map(f).outward()
+    }
+    fn decreasing(&self, f: impl Fn(f64) -> f64) -> Bounds {
+        Bounds::new(f(self.hi), f(self.lo)).outward()
+    }
+    /// The range of `f(a, b)` for `f` monotonic in each argument, which it
+    /// takes at the corners. Corners that are NaN, like ∞/∞, are left out
+    fn corners(&self, other: &Bounds, f: impl Fn(f64, f64) -> f64) -> Bounds {
+        let values = [
+            f(self.lo, other.lo),
+            f(self.lo, other.hi),
+            f(self.hi, other.lo),
+            f(self.hi, other.hi),
+        ];
+        let lo = values.iter().cloned().fold(f64::INFINITY, f64::min);
+        let hi = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        if lo > hi {
+            Bounds::everything()
+        } else {
+            Bounds::new(lo, hi).outward()
+        }
+    }
+    fn add(&self, other: &Bounds) -> Bounds {
+        // ∞ - ∞ could be anything
+        self.corners(other, |a, b| a + b)
+    }
+    fn neg(&self) -> Bounds {
+        Bounds::new(-self.hi, -self.lo)
+    }
+    fn mul(&self, other: &Bounds) -> Bounds {
+        // 0 times anything is 0, even times an unbounded end
+        self.corners(other, |a, b| if a == 0.0 || b == 0.0 { 0.0 } else { a * b })
+    }
+    fn div(&self, other: &Bounds) -> Bounds {
+        if other.contains(0.0) {
+            Bounds::everything()
+        } else {
+            self.corners(other, |a, b| a / b)
+        }
+    }
+    fn recip(&self) -> Bounds {
+        Bounds::point(1.0).div(self)
+    }
+    /// The range of `x.abs()`
+    fn magnitude(&self) -> Bounds {
+        if self.lo >= 0.0 {
+            *self
+        } else if self.hi <= 0.0 {
+            self.neg()
+        } else {
+            Bounds::new(0.0, (-self.lo).max(self.hi))
+        }
+    }
+    /// The part of the range within the domain `[lo, hi]` of `op`, failing if
+    /// there is none
+    fn within(&self, lo: f64, hi: f64, op: &BuiltinOp) -> Result<Bounds, EvalError> {
+        if self.hi < lo || self.lo > hi {
+            return Err(EvalError::NonFinite(op.to_string()));
+        }
+        Ok(Bounds::new(self.lo.max(lo), self.hi.min(hi)))
+    }
+    fn pow(&self, exponent: &Bounds) -> Bounds {
+        let n = exponent.lo;
+        if exponent.is_point() && n.fract() == 0.0 {
+            let power = |x: f64| x.powf(n);
+            return match (n < 0.0, n % 2.0 == 0.0) {
+                (true, _) if self.contains(0.0) => Bounds::everything(),
+                (true, true) => self.magnitude().decreasing(power),
+                (true, false) => self.decreasing(power),
+                (false, true) => self.magnitude().increasing(power),
+                (false, false) => self.increasing(power),
+            };
+        }
+        if self.lo < 0.0 {
+            return Bounds::everything();
+        }
+        self.corners(exponent, f64::powf)
+    }
+    /// The range of `f`, which has period 2π, its maximum 1 at `peak` and
+    /// its minimum -1 half a period later, like `sin` and `cos`
+    fn wave(&self, f: fn(f64) -> f64, peak: f64) -> Bounds {
+        if self.hi - self.lo >= 2.0 * PI {
+            return Bounds::new(-1.0, 1.0);
+        }
+        // Whether x + 2kπ is in the range for some integer k
+        let reaches = |x: f64| x + 2.0 * PI * ((self.lo - x) / (2.0 * PI)).ceil() <= self.hi;
+        let ends = Bounds::point(f(self.lo)).hull(&Bounds::point(f(self.hi)));
+        Bounds::new(
+            if reaches(peak - PI) { -1.0 } else { ends.lo },
+            if reaches(peak) { 1.0 } else { ends.hi },
+        )
+        .outward()
+    }
+    fn tan(&self) -> Bounds {
+        // tan increases between its poles, so it only decreases across one
+        if self.hi - self.lo >= PI || self.lo.tan() > self.hi.tan() {
+            Bounds::everything()
+        } else {
+            self.increasing(f64::tan)
+        }
+    }
+    /// The range of `a % b`, which has the sign of `a` and is smaller than `b`
+    fn rem(&self, other: &Bounds) -> Bounds {
+        let most = other.magnitude().hi;
+        Bounds::new(self.lo.min(0.0).max(-most), self.hi.max(0.0).min(most))
+    }
+}
+
+/// Relations are n-ary in MathML: `<lt/> a b c` means `a < b < c`.
+/// `relation` says whether it certainly holds between two ranges, and
+/// whether it can
+fn chain(values: &[Bounds], relation: impl Fn(&Bounds, &Bounds) -> (bool, bool)) -> Bounds {
+    let truths: Vec<(bool, bool)> = values.windows(2).map(|w| relation(&w[0], &w[1])).collect();
+    Bounds::truth(truths.iter().all(|t| t.0), truths.iter().all(|t| t.1))
+}
+
+fn equal(a: &Bounds, b: &Bounds) -> (bool, bool) {
+    (a.is_point() && a == b, a.lo <= b.hi && b.lo <= a.hi)
+}
+
+/// The range of what `op` gives for arguments within `values`. With only
+/// points that is exactly what `eval` gives
+fn eval_op(op: &BuiltinOp, values: &[Bounds]) -> Result<Bounds, EvalError> {
+    use BuiltinOp::*;
+    let any = usize::MAX;
+    let points: Vec<f64> = values.iter().map(|b| b.lo).collect();
+    if values.iter().all(Bounds::is_point) {
+        // A zero could be -0 as well, which e.g. 1/x tells apart
+        let flipped: Vec<f64> = points
+            .iter()
+            .map(|x| if *x == 0.0 { -x } else { *x })
+            .collect();
+        let (value, other) = (
+            crate::eval::eval_op(op, &points)?,
+            crate::eval::eval_op(op, &flipped)?,
+        );
+        if value.is_nan() || other.is_nan() {
+            return Err(EvalError::NonFinite(op.to_string()));
+        }
+        return Ok(Bounds::point(value).hull(&Bounds::point(other)));
+    }
+    let arity = |least: usize, most: usize| check_arity(op, &points, least, most);
+    let unary = || -> Result<Bounds, EvalError> {
+        arity(1, 1)?;
+        Ok(values[0])
+    };
+    let binary = || -> Result<(Bounds, Bounds), EvalError> {
+        arity(2, 2)?;
+        Ok((values[0], values[1]))
+    };
+    let truths: Vec<(bool, bool)> = values.iter().map(Bounds::truths).collect();
+    let value = match op {
+        plus => values.iter().skip(1).fold(values[0], |a, b| a.add(b)),
+        times => values.iter().skip(1).fold(values[0], |a, b| a.mul(b)),
+        minus => {
+            arity(1, 2)?;
+            match values {
+                [x] => x.neg(),
+                [a, b] => a.add(&b.neg()),
+                _ => unreachable!(),
+            }
+        }
+        divide => {
+            let (a, b) = binary()?;
+            a.div(&b)
+        }
+        power => {
+            let (a, b) = binary()?;
+            a.pow(&b)
+        }
+        quotient => {
+            let (a, b) = binary()?;
+            a.div(&b).map(f64::trunc)
+        }
+        rem => {
+            let (a, b) = binary()?;
+            a.rem(&b)
+        }
+        factorof => {
+            binary()?;
+            Bounds::truth(false, true)
+        }
+        implies => {
+            let (a, b) = binary()?;
+            let ((a_certain, a_possible), (b_certain, b_possible)) = (a.truths(), b.truths());
+            Bounds::truth(!a_possible || b_certain, !a_certain || b_possible)
+        }
+        max | min => {
+            arity(1, any)?;
+            let pick: fn(f64, f64) -> f64 = if *op == max { f64::max } else { f64::min };
+            values[1..].iter().fold(values[0], |a, b| {
+                Bounds::new(pick(a.lo, b.lo), pick(a.hi, b.hi))
+            })
+        }
+        // Both increase with each argument
+        mean | median => {
+            arity(1, any)?;
+            let f: fn(&[f64]) -> f64 = if *op == mean {
+                crate::eval::mean
+            } else {
+                crate::eval::median
+            };
+            let ends = |end: fn(&Bounds) -> f64| f(&values.iter().map(end).collect::<Vec<_>>());
+            Bounds::new(ends(|b| b.lo), ends(|b| b.hi)).outward()
+        }
+        mode => {
+            arity(1, any)?;
+            values[1..].iter().fold(values[0], |a, b| a.hull(b))
+        }
+        gcd | lcm | sdev | variance => {
+            arity(1, any)?;
+            let all = values[1..].iter().fold(values[0], |a, b| a.hull(b));
+            let most = match op {
+                gcd => all.magnitude().hi,
+                lcm => {
+                    values
+                        .iter()
+                        .fold(Bounds::point(1.0), |a, b| a.mul(&b.magnitude()))
+                        .hi
+                }
+                // Popoviciu: at most a quarter of the square of the spread
+                _ => {
+                    Bounds::new(0.0, all.hi - all.lo)
+                        .pow(&Bounds::point(2.0))
+                        .div(&Bounds::point(4.0))
+                        .hi
+                }
+            };
+            let range = Bounds::new(0.0, most);
+            match op {
+                sdev => range.increasing(f64::sqrt),
+                _ => range,
+            }
+        }
+        abs => unary()?.magnitude(),
+        root => unary()?
+            .within(0.0, f64::INFINITY, op)?
+            .increasing(f64::sqrt),
+        factorial => {
+            let x = unary()?.within(0.0, f64::INFINITY, op)?;
+            // Only integers have factorials
+            let x = x.within(x.lo.ceil(), x.hi.floor(), op)?;
+            x.increasing(crate::eval::factorial)
+        }
+        exp => unary()?.increasing(f64::exp),
+        ln => unary()?.within(0.0, f64::INFINITY, op)?.increasing(f64::ln),
+        log => unary()?
+            .within(0.0, f64::INFINITY, op)?
+            .increasing(f64::log10),
+        floor => unary()?.map(f64::floor),
+        ceiling => unary()?.map(f64::ceil),
+        sin => unary()?.wave(f64::sin, FRAC_PI_2),
+        cos => unary()?.wave(f64::cos, 0.0),
+        tan => unary()?.tan(),
+        sec => unary()?.wave(f64::cos, 0.0).recip(),
+        csc => unary()?.wave(f64::sin, FRAC_PI_2).recip(),
+        cot => unary()?.tan().recip(),
+        sinh => unary()?.increasing(f64::sinh),
+        cosh => unary()?.magnitude().increasing(f64::cosh),
+        tanh => unary()?.increasing(f64::tanh),
+        sech => unary()?.magnitude().increasing(f64::cosh).recip(),
+        csch => unary()?.increasing(f64::sinh).recip(),
+        coth => unary()?.increasing(f64::tanh).recip(),
+        arcsin => unary()?.within(-1.0, 1.0, op)?.increasing(f64::asin),
+        arccos => unary()?.within(-1.0, 1.0, op)?.decreasing(f64::acos),
+        arctan => unary()?.increasing(f64::atan),
+        arcsec => unary()?
+            .recip()
+            .within(-1.0, 1.0, op)?
+            .decreasing(f64::acos),
+        arccsc => unary()?
+            .recip()
+            .within(-1.0, 1.0, op)?
+            .increasing(f64::asin),
+        arccot => unary()?.recip().increasing(f64::atan),
+        arcsinh => unary()?.increasing(f64::asinh),
+        arccosh => unary()?
+            .within(1.0, f64::INFINITY, op)?
+            .increasing(f64::acosh),
+        arctanh => unary()?.within(-1.0, 1.0, op)?.increasing(f64::atanh),
+        arcsech => unary()?
+            .recip()
+            .within(1.0, f64::INFINITY, op)?
+            .increasing(f64::acosh),
+        arccsch => unary()?.recip().increasing(f64::asinh),
+        arccoth => unary()?
+            .recip()
+            .within(-1.0, 1.0, op)?
+            .increasing(f64::atanh),
+        not => {
+            let (certain, possible) = unary()?.truths();
+            Bounds::truth(!possible, !certain)
+        }
+        and => Bounds::truth(truths.iter().all(|t| t.0), truths.iter().all(|t| t.1)),
+        or => Bounds::truth(truths.iter().any(|t| t.0), truths.iter().any(|t| t.1)),
+        // Only known once every argument is
+        xor if truths.iter().all(|t| t.0 == t.1) => {
+            let odd = truths.iter().filter(|t| t.0).count() % 2 == 1;
+            Bounds::truth(odd, odd)
+        }
+        xor => Bounds::truth(false, true),
+        equivalent => {
+            arity(2, any)?;
+            chain(values, |a, b| {
+                let (a, b) = (a.truths(), b.truths());
+                let known = a.0 == a.1 && b.0 == b.1;
+                (known && a.0 == b.0, !known || a.0 == b.0)
+            })
+        }
+        eq => chain(values, equal),
+        neq => {
+            let (a, b) = binary()?;
+            let (certain, possible) = equal(&a, &b);
+            Bounds::truth(!possible, !certain)
+        }
+        lt => chain(values, |a, b| (a.hi < b.lo, a.lo < b.hi)),
+        leq => chain(values, |a, b| (a.hi <= b.lo, a.lo <= b.hi)),
+        gt => chain(values, |a, b| (a.lo > b.hi, a.hi > b.lo)),
+        geq => chain(values, |a, b| (a.lo >= b.hi, a.hi >= b.lo)),
+        _ => return Err(EvalError::UnsupportedOperator(op.to_string())),
+    };
+    Ok(value)
+}
+
+fn eval_node(node: &MathNode, env: &HashMap<String, Bounds>) -> Result<Bounds, EvalError> {
+    match node {
+        MathNode::Root { children, .. } => {
+            let mut expressions = children.iter().filter(|c| !c.is_markup());
+            match (expressions.next(), expressions.next()) {
+                (Some(expression), None) => eval_node(expression, env),
+                _ => Err(EvalError::UnsupportedNode(
+                    "a <math> element without exactly one expression".to_owned(),
+                )),
+            }
+        }
+        MathNode::Apply { children, .. } if node.bound_variables().is_empty() => {
+            match children.first() {
+                Some(MathNode::Op(op)) => {
+                    let values = node
+                        .operands()
+                        .into_iter()
+                        .map(|a| eval_node(a, env))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    match (op, node.degree(), node.logbase()) {
+                        (BuiltinOp::root, Some(_), _) | (BuiltinOp::log, _, Some(_)) => Err(
+                            EvalError::UnsupportedOperator(format!("{} with a qualifier", op)),
+                        ),
+                        _ => eval_op(op, &values),
+                    }
+                }
+                _ => node.eval(&HashMap::new()).map(Bounds::point),
+            }
+        }
+        MathNode::Ci { children, .. } => {
+            let name = crate::text_content(children);
+            env.get(&name)
+                .cloned()
+                .ok_or(EvalError::UnboundVariable(name))
+        }
+        MathNode::Cn { num_type, .. } => number(num_type).map(Bounds::point),
+        MathNode::Constant(constant) => constant_symbol(constant).map(Bounds::point),
+        // Any of the pieces could be the one that applies
+        MathNode::Piecewise { pieces, otherwise } => pieces
+            .iter()
+            .map(|(value, _)| value)
+            .chain(otherwise.as_deref())
+            .map(|value| eval_node(value, env))
+            .reduce(|a, b| Ok(a?.hull(&b?)))
+            .unwrap_or_else(|| {
+                Err(EvalError::UnsupportedNode(
+                    "an empty <piecewise>".to_owned(),
+                ))
+            }),
+        MathNode::Semantics { primary, .. } => eval_node(primary, env),
+        // Nothing else depends on the environment, so the interpreter knows what to do
+        _ => node.eval(&HashMap::new()).map(Bounds::point),
+    }
+}
+
+impl MathNode {
+    /// Evaluates the expression with each identifier bound to a range of
+    /// values, giving a range that holds every value the expression can take.
+    /// Arguments outside an operator's domain, where `eval` gives NaN, are left out
+    pub fn eval_bounds(&self, env: &HashMap<String, Bounds>) -> Result<Bounds, EvalError> {
+        eval_node(self, env)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Bounds;
+    use crate::error::EvalError;
+    use crate::math;
+    use std::collections::HashMap;
+
+    /// Whether `bounds` holds `[lo, hi]` and is hardly any wider
+    fn encloses(bounds: Result<Bounds, EvalError>, lo: f64, hi: f64) -> bool {
+        matches!(bounds, Ok(b) if b.lo <= lo && lo - b.lo < 1e-12 && hi <= b.hi && b.hi - hi < 1e-12)
+    }
+
+    #[test]
+    fn test_eval_bounds() {
+        let env: HashMap<String, Bounds> = vec![
+            ("Vmax", Bounds::new(9.0, 11.0)),
+            ("S", Bounds::new(1.0, 2.0)),
+            ("Km", Bounds::point(2.0)),
+            ("t", Bounds::new(0.0, 4.0)),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_owned(), v))
+        .collect();
+        let law = math!(Vmax * S / (Km + S));
+        assert!(encloses(law.eval_bounds(&env), 2.25, 22.0 / 3.0));
+        assert!(encloses(
+            math!(power(S - 2, 2) + cos(t)).eval_bounds(&env),
+            -1.0,
+            2.0
+        ));
+        assert_eq!(
+            math!(1 / (t - 1))
+                .eval_bounds(&env)
+                .map(|b| b.contains(1e300)),
+            Ok(true)
+        );
+        let zero = math!(0 * (1 / (t - 1))).eval_bounds(&env).unwrap();
+        assert!(zero.lo <= zero.hi && zero.contains(0.0));
+        assert_eq!(
+            math!(lt(S, t + 3)).eval_bounds(&env),
+            Ok(Bounds::new(1.0, 1.0))
+        );
+        assert_eq!(math!(lt(S, t)).eval_bounds(&env), Ok(Bounds::new(0.0, 1.0)));
+        assert!(math!(tan(t)).eval_bounds(&env).unwrap().hi.is_infinite());
+        // quotient(-0.5, 2) is -0
+        assert_eq!(
+            math!(1 / quotient(S - 1.5, 2))
+                .eval_bounds(&env)
+                .map(|b| b.contains(f64::NEG_INFINITY)),
+            Ok(true)
+        );
+
+        let env: HashMap<String, Bounds> = vec![("x".to_owned(), Bounds::new(-1.0, 2.0))]
+            .into_iter()
+            .collect();
+        assert_eq!(math!(abs(x)).eval_bounds(&env), Ok(Bounds::new(0.0, 2.0)));
+        assert!(encloses(math!(root(x)).eval_bounds(&env), 0.0, 2f64.sqrt()));
+        assert!(math!(ln(x - 3)).eval_bounds(&env).is_err());
+    }
+    #[test]
+    fn test_bounds_hold_eval() {
+        let expressions = [
+            math!(abs(power(x, 0.5))),
+            math!(power(x, y) / y),
+            math!(tan(x) - sec(y)),
+            math!(rem(7 * x, y) + quotient(x, -y)),
+            math!(coth(x) * arccot(y)),
+            math!(mean(x, y, 2) + sdev(x, y)),
+            math!(and(lt(x, y), leq(y, 2))),
+        ];
+        for expression in &expressions {
+            for &(x, y) in &[(3.0, 0.7), (0.1, 1.3), (2.5, 2.5)] {
+                let env: HashMap<String, Bounds> = vec![
+                    ("x".to_owned(), Bounds::new(x, x + 0.5)),
+                    ("y".to_owned(), Bounds::new(y, y + 0.25)),
+                ]
+                .into_iter()
+                .collect();
+                let bounds = expression.eval_bounds(&env).unwrap();
+                for &(dx, dy) in &[(0.0, 0.0), (0.5, 0.25), (0.2, 0.1), (0.5, 0.0)] {
+                    let point: HashMap<String, f64> =
+                        vec![("x".to_owned(), x + dx), ("y".to_owned(), y + dy)]
+                            .into_iter()
+                            .collect();
+                    let value = expression.eval(&point).unwrap();
+                    assert!(
+                        bounds.contains(value),
+                        "{} = {} at {:?}",
+                        expression,
+                        value,
+                        point
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use super::{BuiltinOp, ConstantSymbol, MathNode, NumType};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// Builds a `MathNode` from a Rust-like expression, e.g. `math!(a * x + sin(b))`.
+/// Identifiers become `<ci>`, literals `<cn>` (or `<true/>`/`<false/>`), and
+/// `name(args...)` applies the `BuiltinOp` of that name, e.g. `power(x, 2)`.
+/// `+ - * /` follow the usual precedence.
+#[macro_export]
+macro_rules! math {
+    // Splits the arguments of a call on its top-level commas
+    (@args [$($done:expr),*] []) => { vec![$($done),*] };
+    (@args [$($done:expr),*] [$($arg:tt)+]) => {
+        vec![$($done,)* $crate::math!($($arg)+)]
+    };
+    (@args [$($done:expr),*] [$($arg:tt)+] , $($rest:tt)*) => {
+        $crate::math!(@args [$($done,)* $crate::math!($($arg)+)] [] $($rest)*)
+    };
+    (@args [$($done:expr),*] [$($arg:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::math!(@args [$($done),*] [$($arg)* $next] $($rest)*)
+    };
+    // Rewrites one token at a time, leaving the operators to Rust
+    (@expr [$($out:tt)*]) => { $($out)* };
+    (@expr [$($out:tt)*] $f:ident ( $($args:tt)* ) $($rest:tt)*) => {
+        $crate::math!(@expr [$($out)* $crate::MathNode::apply(
+            $crate::BuiltinOp::$f,
+            $crate::math!(@args [] [] $($args)*)
+        )] $($rest)*)
+    };
+    (@expr [$($out:tt)*] ( $($inner:tt)+ ) $($rest:tt)*) => {
+        $crate::math!(@expr [$($out)* ($crate::math!($($inner)+))] $($rest)*)
+    };
+    // Before literals, which would swallow the `-` of `a -1`
+    (@expr [$($out:tt)*] - $($rest:tt)*) => {
+        $crate::math!(@expr [$($out)* -] $($rest)*)
+    };
+    (@expr [$($out:tt)*] $value:literal $($rest:tt)*) => {
+        $crate::math!(@expr [$($out)* $crate::MathNode::from($value)] $($rest)*)
+    };
+    (@expr [$($out:tt)*] $name:ident $($rest:tt)*) => {
+        $crate::math!(@expr [$($out)* $crate::MathNode::ci(stringify!($name))] $($rest)*)
+    };
+    (@expr [$($out:tt)*] $op:tt $($rest:tt)*) => {
+        $crate::math!(@expr [$($out)* $op] $($rest)*)
+    };
+    ($($tokens:tt)+) => { $crate::math!(@expr [] $($tokens)+) };
+}
+
+/// Constructors for building expressions in code, e.g. to write them back into a model
+impl MathNode {
+    /// `<apply>` of a builtin operator
+    pub fn apply(op: BuiltinOp, operands: impl IntoIterator<Item = MathNode>) -> MathNode {
+        MathNode::call(MathNode::Op(op), operands)
+    }
+    /// `<apply>` of anything else, e.g. a user defined function
+    pub fn call(head: MathNode, operands: impl IntoIterator<Item = MathNode>) -> MathNode {
+        let mut children = vec![head];
+        children.extend(operands);
+        MathNode::Apply {
+            children,
+            attributes: Vec::new(),
+        }
+    }
+    /// `<ci>` identifier
+    pub fn ci(name: impl Into<String>) -> MathNode {
+        MathNode::Ci {
+            children: vec![MathNode::Text(name.into())],
+            ci_type: None,
+            attributes: Vec::new(),
+        }
+    }
+    pub(crate) fn number(num_type: NumType) -> MathNode {
+        MathNode::Cn {
+            num_type,
+            base: 10,
+            definition_url: None,
+            encoding: None,
+            units: None,
+            units_namespace: None,
+            attributes: Vec::new(),
+        }
+    }
+    /// `<cn type="real">`
+    pub fn cn(value: f64) -> MathNode {
+        MathNode::number(NumType::Real(value))
+    }
+    /// `<cn type="integer">`
+    pub fn integer(value: i64) -> MathNode {
+        MathNode::number(NumType::Integer(value))
+    }
+    /// `<cn type="rational">`
+    pub fn rational(numerator: i64, denominator: i64) -> MathNode {
+        MathNode::number(NumType::Rational(numerator, denominator))
+    }
+    /// An empty constant element such as `<pi/>`
+    pub fn constant(constant: ConstantSymbol) -> MathNode {
+        MathNode::Constant(constant)
+    }
+    /// `<lambda>` binding `vars` in `body`
+    pub fn lambda<S: Into<String>>(vars: impl IntoIterator<Item = S>, body: MathNode) -> MathNode {
+        MathNode::Lambda {
+            bound_vars: vars.into_iter().map(Into::into).collect(),
+            body: Box::new(body),
+        }
+    }
+    /// `<piecewise>` from `(value, condition)` pieces and an optional fallback
+    pub fn piecewise(
+        pieces: impl IntoIterator<Item = (MathNode, MathNode)>,
+        otherwise: Option<MathNode>,
+    ) -> MathNode {
+        MathNode::Piecewise {
+            pieces: pieces.into_iter().collect(),
+            otherwise: otherwise.map(Box::new),
+        }
+    }
+    /// Wraps the expression in a `<math>` element, ready to be written out
+    pub fn math(self) -> MathNode {
+        MathNode::Root {
+            children: vec![self],
+            attributes: Vec::new(),
+        }
+    }
+}
+
+impl From<i32> for MathNode {
+    fn from(value: i32) -> Self {
+        MathNode::integer(value.into())
+    }
+}
+impl From<i64> for MathNode {
+    fn from(value: i64) -> Self {
+        MathNode::integer(value)
+    }
+}
+impl From<f64> for MathNode {
+    fn from(value: f64) -> Self {
+        MathNode::cn(value)
+    }
+}
+impl From<bool> for MathNode {
+    fn from(value: bool) -> Self {
+        MathNode::Constant(if value {
+            ConstantSymbol::True
+        } else {
+            ConstantSymbol::False
+        })
+    }
+}
+
+macro_rules! binary_op {
+    ($trait:ident, $method:ident, $op:ident) => {
+        impl $trait for MathNode {
+            type Output = MathNode;
+            fn $method(self, rhs: MathNode) -> MathNode {
+                MathNode::apply(BuiltinOp::$op, vec![self, rhs])
+            }
+        }
+    };
+}
+binary_op!(Add, add, plus);
+binary_op!(Sub, sub, minus);
+binary_op!(Mul, mul, times);
+binary_op!(Div, div, divide);
+
+impl Neg for MathNode {
+    type Output = MathNode;
+    fn neg(self) -> MathNode {
+        MathNode::apply(BuiltinOp::minus, vec![self])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::BuiltinOp::*;
+    use crate::{parse_document, MathNode};
+
+    #[test]
+    fn test_builder() {
+        // Michaelis-Menten: Vmax * S / (Km + S)
+        let built = MathNode::apply(
+            divide,
+            vec![
+                MathNode::apply(times, vec![MathNode::ci("Vmax"), MathNode::ci("S")]),
+                MathNode::apply(plus, vec![MathNode::ci("Km"), MathNode::integer(2)]),
+            ],
+        )
+        .math();
+        assert_eq!(
+            built.to_mathml(),
+            r#"<math xmlns="http://www.w3.org/1998/Math/MathML"><apply><divide/><apply><times/><ci>Vmax</ci><ci>S</ci></apply><apply><plus/><ci>Km</ci><cn type="integer">2</cn></apply></apply></math>"#
+        );
+        assert_eq!(parse_document(&built.to_mathml()).unwrap(), built);
+
+        let step = MathNode::piecewise(
+            vec![(
+                MathNode::cn(1.0),
+                MathNode::apply(gt, vec![MathNode::ci("x"), MathNode::cn(0.0)]),
+            )],
+            Some(MathNode::cn(0.0)),
+        );
+        assert_eq!(step.to_string(), "piecewise(1, x > 0, 0)");
+    }
+    #[test]
+    fn test_math_macro() {
+        let built = crate::math!(a * x + sin(b) - 2 / (k + 1.5));
+        let expected = MathNode::apply(
+            minus,
+            vec![
+                MathNode::apply(
+                    plus,
+                    vec![
+                        MathNode::apply(times, vec![MathNode::ci("a"), MathNode::ci("x")]),
+                        MathNode::apply(sin, vec![MathNode::ci("b")]),
+                    ],
+                ),
+                MathNode::apply(
+                    divide,
+                    vec![
+                        MathNode::integer(2),
+                        MathNode::apply(plus, vec![MathNode::ci("k"), MathNode::cn(1.5)]),
+                    ],
+                ),
+            ],
+        );
+        assert_eq!(built, expected);
+        assert_eq!(
+            crate::math!(-power(x, 2) + max(a, b - 1, c)).to_string(),
+            "-x^2 + max(a, b - 1, c)"
+        );
+    }
+}
@@ -0,0 +1,432 @@
+use super::{BuiltinOp, MathNode, SbmlSymbol};
+use crate::error::EvalError;
+use crate::eval::{aggregates, constant_symbol, eval_op, number};
+use crate::ops::parenthesize;
+
+/// Precedences of C's operators, higher binds tighter
+const PREC_TERNARY: u8 = 1;
+const PREC_OR: u8 = 2;
+const PREC_AND: u8 = 3;
+const PREC_XOR: u8 = 4;
+const PREC_EQUALITY: u8 = 5;
+const PREC_RELATION: u8 = 6;
+const PREC_ADD: u8 = 7;
+const PREC_MUL: u8 = 8;
+const PREC_UNARY: u8 = 9;
+const PREC_ATOM: u8 = 10;
+
+/// Identifiers that C reserves
+const KEYWORDS: &str = "auto break case char const continue default do double else enum extern \
+                        float for goto if inline int long register restrict return short signed \
+                        sizeof static struct switch typedef union unsigned void volatile while \
+                        _Bool _Complex _Imaginary";
+
+/// A C expression together with the precedence of its outermost operator
+#[derive(Clone)]
+struct C {
+    text: String,
+    precedence: u8,
+}
+
+impl C {
+    fn new(text: String, precedence: u8) -> Self {
+        C { text, precedence }
+    }
+    fn atom(text: String) -> Self {
+        C::new(text, PREC_ATOM)
+    }
+    fn wrap(self, min_precedence: u8) -> String {
+        parenthesize(self.text, self.precedence, min_precedence)
+    }
+}
+
+/// A `double` literal, so that e.g. `1 / 2` isn't integer division
+fn literal(value: f64) -> C {
+    if value.is_nan() {
+        C::atom("NAN".to_owned())
+    } else if value == f64::INFINITY {
+        C::atom("INFINITY".to_owned())
+    } else if value == f64::NEG_INFINITY {
+        C::new("-INFINITY".to_owned(), PREC_UNARY)
+    } else {
+        let text = format!("{:?}", value);
+        let precedence = if text.starts_with('-') {
+            PREC_UNARY
+        } else {
+            PREC_ATOM
+        };
+        C::new(text, precedence)
+    }
+}
+
+fn identifier(name: &str) -> Result<C, EvalError> {
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !KEYWORDS.split_whitespace().any(|k| k == name);
+    if !valid {
+        return Err(EvalError::CodeGeneration(format!(
+            "'{}' is not a C identifier",
+            name
+        )));
+    }
+    Ok(C::atom(name.to_owned()))
+}
+
+fn call(name: &str, args: Vec<C>) -> C {
+    let args: Vec<String> = args.into_iter().map(|a| a.text).collect();
+    C::atom(format!("{}({})", name, args.join(", ")))
+}
+
+/// Joins left associative operands, parenthesizing the right ones so that C
+/// evaluates them in the order of the tree
+fn left_assoc(args: Vec<C>, symbol: &str, precedence: u8) -> C {
+    let text = args
+        .into_iter()
+        .enumerate()
+        .map(|(i, a)| a.wrap(if i == 0 { precedence } else { precedence + 1 }))
+        .collect::<Vec<_>>()
+        .join(symbol);
+    C::new(text, precedence)
+}
+
+/// `a rel b && b rel c`, the meaning of chained relations
+fn chain(args: Vec<C>, symbol: &str, precedence: u8) -> C {
+    let mut pairs: Vec<C> = args
+        .windows(2)
+        .map(|w| {
+            let (a, b) = (w[0].clone(), w[1].clone());
+            C::new(
+                format!("{}{}{}", a.wrap(precedence), symbol, b.wrap(precedence + 1)),
+                precedence,
+            )
+        })
+        .collect();
+    match pairs.len() {
+        0 => literal(1.0),
+        1 => pairs.remove(0),
+        _ => left_assoc(pairs, " && ", PREC_AND),
+    }
+}
+
+fn reciprocal(x: C) -> C {
+    C::new(format!("1.0 / {}", x.wrap(PREC_MUL + 1)), PREC_MUL)
+}
+
+struct Emitter<'a> {
+    /// The parameters of `to_c_function`, which are the only identifiers allowed
+    variables: Option<&'a [&'a str]>,
+}
+
+impl Emitter<'_> {
+    fn variable(&self, name: &str) -> Result<C, EvalError> {
+        match self.variables {
+            Some(variables) if !variables.contains(&name) => {
+                Err(EvalError::UnboundVariable(name.to_owned()))
+            }
+            _ => identifier(name),
+        }
+    }
+    fn emit(&self, node: &MathNode) -> Result<C, EvalError> {
+        match node {
+            MathNode::Root { children, .. } => {
+                let mut expressions = children.iter().filter(|c| !c.is_markup());
+                match (expressions.next(), expressions.next()) {
+                    (Some(expression), None) => self.emit(expression),
+                    _ => Err(EvalError::UnsupportedNode(
+                        "a <math> element without exactly one expression".to_owned(),
+                    )),
+                }
+            }
+            MathNode::Apply { children, .. } if node.bound_variables().is_empty() => {
+                match children.first() {
+                    Some(MathNode::Op(op)) => self.apply(op, node),
+                    // Calls of function definitions, which the C code has to provide
+                    Some(MathNode::Ci { children, .. }) => {
+                        let args = node
+                            .operands()
+                            .into_iter()
+                            .map(|a| self.emit(a))
+                            .collect::<Result<_, _>>()?;
+                        let name = identifier(&crate::text_content(children))?;
+                        Ok(call(&name.text, args))
+                    }
+                    _ => Err(EvalError::UnsupportedNode(
+                        "an application of a non-builtin function".to_owned(),
+                    )),
+                }
+            }
+            MathNode::Apply { .. } => Err(EvalError::CodeGeneration(
+                "operators over bound variables have no C equivalent".to_owned(),
+            )),
+            MathNode::Ci { children, .. } => self.variable(&crate::text_content(children)),
+            MathNode::Csymbol {
+                sbml: Some(SbmlSymbol::Time),
+                name,
+                ..
+            } => self.variable(name),
+            MathNode::Cn { num_type, .. } => Ok(literal(number(num_type)?)),
+            MathNode::Constant(constant) => Ok(literal(constant_symbol(constant)?)),
+            MathNode::Semantics { primary, .. } => self.emit(primary),
+            MathNode::Piecewise { pieces, otherwise } => {
+                // Nested ternaries, which C evaluates lazily from the first piece
+                let mut text = String::new();
+                for (value, condition) in pieces {
+                    text.push_str(&format!(
+                        "{} ? {} : ",
+                        self.emit(condition)?.wrap(PREC_OR),
+                        self.emit(value)?.wrap(PREC_TERNARY)
+                    ));
+                }
+                let last = match otherwise {
+                    Some(otherwise) => self.emit(otherwise)?,
+                    None => literal(f64::NAN),
+                };
+                text.push_str(&last.wrap(PREC_TERNARY));
+                Ok(C::new(text, PREC_TERNARY))
+            }
+            _ => node.eval(&Default::default()).map(literal),
+        }
+    }
+    fn apply(&self, op: &BuiltinOp, node: &MathNode) -> Result<C, EvalError> {
+        use BuiltinOp::*;
+        let mut args = Vec::new();
+        for operand in node.operands() {
+            match operand {
+                MathNode::Set(items) | MathNode::List { items, .. } if aggregates(op) => {
+                    for item in items {
+                        args.push(self.emit(item)?);
+                    }
+                }
+                _ => args.push(self.emit(operand)?),
+            }
+        }
+        // Arity and support only depend on the operator, so check them as the interpreter would
+        eval_op(op, &vec![1.0; args.len()])?;
+        match (op, node.degree(), node.logbase()) {
+            (root, Some(degree), _) => {
+                let x = args.remove(0);
+                return Ok(match degree {
+                    MathNode::Cn { num_type, .. } if number(num_type) == Ok(2.0) => {
+                        call("sqrt", vec![x])
+                    }
+                    MathNode::Cn { num_type, .. } if number(num_type) == Ok(3.0) => {
+                        call("cbrt", vec![x])
+                    }
+                    _ => call("pow", vec![x, reciprocal(self.emit(degree)?)]),
+                });
+            }
+            (log, _, Some(base)) => {
+                let x = call("log10", args);
+                let base = call("log10", vec![self.emit(base)?]);
+                return Ok(left_assoc(vec![x, base], " / ", PREC_MUL));
+            }
+            _ => {}
+        }
+        let value = match op {
+            plus if args.is_empty() => literal(0.0),
+            times if args.is_empty() => literal(1.0),
+            plus => left_assoc(args, " + ", PREC_ADD),
+            times => left_assoc(args, " * ", PREC_MUL),
+            minus if args.len() == 1 => C::new(
+                format!("-{}", args.remove(0).wrap(PREC_UNARY + 1)),
+                PREC_UNARY,
+            ),
+            minus => left_assoc(args, " - ", PREC_ADD),
+            divide => left_assoc(args, " / ", PREC_MUL),
+            power => call("pow", args),
+            rem => call("fmod", args),
+            quotient => call("trunc", vec![left_assoc(args, " / ", PREC_MUL)]),
+            factorof => {
+                let (a, b) = (args[0].clone(), args[1].clone());
+                C::new(
+                    format!(
+                        "{} != 0.0 && {} == 0.0",
+                        a.clone().wrap(PREC_EQUALITY + 1),
+                        call("fmod", vec![b, a]).text
+                    ),
+                    PREC_AND,
+                )
+            }
+            implies => {
+                let b = args.remove(1);
+                C::new(
+                    format!(
+                        "!{} || {}",
+                        args.remove(0).wrap(PREC_UNARY + 1),
+                        b.wrap(PREC_OR + 1)
+                    ),
+                    PREC_OR,
+                )
+            }
+            root => call("sqrt", args),
+            abs => call("fabs", args),
+            floor => call("floor", args),
+            ceiling => call("ceil", args),
+            factorial => call(
+                "tgamma",
+                vec![left_assoc(
+                    vec![args.remove(0), literal(1.0)],
+                    " + ",
+                    PREC_ADD,
+                )],
+            ),
+            exp => call("exp", args),
+            ln => call("log", args),
+            log => call("log10", args),
+            sin => call("sin", args),
+            cos => call("cos", args),
+            tan => call("tan", args),
+            sec => reciprocal(call("cos", args)),
+            csc => reciprocal(call("sin", args)),
+            cot => reciprocal(call("tan", args)),
+            sinh => call("sinh", args),
+            cosh => call("cosh", args),
+            tanh => call("tanh", args),
+            sech => reciprocal(call("cosh", args)),
+            csch => reciprocal(call("sinh", args)),
+            coth => reciprocal(call("tanh", args)),
+            arcsin => call("asin", args),
+            arccos => call("acos", args),
+            arctan => call("atan", args),
+            arcsec => call("acos", vec![reciprocal(args.remove(0))]),
+            arccsc => call("asin", vec![reciprocal(args.remove(0))]),
+            arccot => call("atan", vec![reciprocal(args.remove(0))]),
+            arcsinh => call("asinh", args),
+            arccosh => call("acosh", args),
+            arctanh => call("atanh", args),
+            arcsech => call("acosh", vec![reciprocal(args.remove(0))]),
+            arccsch => call("asinh", vec![reciprocal(args.remove(0))]),
+            arccoth => call("atanh", vec![reciprocal(args.remove(0))]),
+            not => C::new(
+                format!("!{}", args.remove(0).wrap(PREC_UNARY + 1)),
+                PREC_UNARY,
+            ),
+            and if args.is_empty() => literal(1.0),
+            or | xor if args.is_empty() => literal(0.0),
+            and => left_assoc(args, " && ", PREC_AND),
+            or => left_assoc(args, " || ", PREC_OR),
+            xor => {
+                let truths = args
+                    .into_iter()
+                    .map(|a| C::new(format!("{} != 0.0", a.wrap(PREC_EQUALITY)), PREC_EQUALITY))
+                    .collect();
+                left_assoc(truths, " ^ ", PREC_XOR)
+            }
+            equivalent => {
+                let truths = args
+                    .into_iter()
+                    .map(|a| C::new(format!("!{}", a.wrap(PREC_UNARY + 1)), PREC_UNARY))
+                    .collect();
+                chain(truths, " == ", PREC_EQUALITY)
+            }
+            eq => chain(args, " == ", PREC_EQUALITY),
+            neq => left_assoc(args, " != ", PREC_EQUALITY),
+            gt => chain(args, " > ", PREC_RELATION),
+            lt => chain(args, " < ", PREC_RELATION),
+            geq => chain(args, " >= ", PREC_RELATION),
+            leq => chain(args, " <= ", PREC_RELATION),
+            max | min => {
+                let name = if *op == max { "fmax" } else { "fmin" };
+                let mut args = args.into_iter();
+                let first = args.next().unwrap_or_else(|| literal(f64::NAN));
+                args.fold(first, |acc, a| call(name, vec![acc, a]))
+            }
+            mean => {
+                let count = literal(args.len() as f64);
+                left_assoc(
+                    vec![left_assoc(args, " + ", PREC_ADD), count],
+                    " / ",
+                    PREC_MUL,
+                )
+            }
+            _ => {
+                return Err(EvalError::CodeGeneration(format!(
+                    "'{}' has no C equivalent",
+                    op
+                )))
+            }
+        };
+        Ok(value)
+    }
+}
+
+impl MathNode {
+    /// Writes the expression in C99, e.g. `pow(x, 2.0) + fmax(a, b)`, for
+    /// embedding in generated code such as the right-hand side of a CVODE
+    /// model. It needs `<math.h>`. Values match those of `CompiledExpr`, with
+    /// booleans as 0 and 1 and NaN where no piece applies, except that roots
+    /// of negative numbers with an odd degree other than 3 are NaN and
+    /// factorials of non-integers are the gamma function's. Identifiers are
+    /// written as they are and must be valid in C
+    pub fn to_c_expression(&self) -> Result<String, EvalError> {
+        Ok(Emitter { variables: None }.emit(self)?.text)
+    }
+    /// Writes a C99 function `double name(double v1, double v2, ...)`
+    /// returning the expression, see `to_c_expression`. Identifiers other than
+    /// `variables` and the names of called functions are unbound variables
+    pub fn to_c_function(&self, name: &str, variables: &[&str]) -> Result<String, EvalError> {
+        let name = identifier(name)?;
+        let parameters = variables
+            .iter()
+            .map(|v| Ok(format!("double {}", identifier(v)?.text)))
+            .collect::<Result<Vec<_>, EvalError>>()?;
+        let body = Emitter {
+            variables: Some(variables),
+        }
+        .emit(self)?;
+        Ok(format!(
+            "double {}({})\n{{\n    return {};\n}}\n",
+            name.text,
+            if parameters.is_empty() {
+                "void".to_owned()
+            } else {
+                parameters.join(", ")
+            },
+            body.text
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::EvalError;
+    use crate::parse_document;
+
+    #[test]
+    fn test_c_code() {
+        let test = r#"<apply><plus/>
+                        <apply><divide/><apply><times/><ci>Vmax</ci><ci>S</ci></apply><apply><plus/><ci>Km</ci><ci>S</ci></apply></apply>
+                        <apply><power/><apply><minus/><ci>S</ci></apply><cn>2</cn></apply>
+                        <apply><root/><degree><cn>3</cn></degree><ci>S</ci></apply>
+                        <apply><min/><ci>S</ci><cn>1</cn><pi/></apply>
+                        <apply><minus/><ci>S</ci><apply><minus/><ci>Km</ci><cn>-1</cn></apply></apply>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(
+            parsed.to_c_expression().unwrap(),
+            "Vmax * S / (Km + S) + pow(-S, 2.0) + cbrt(S) + fmin(fmin(S, 1.0), 3.141592653589793) + (S - (Km - -1.0))"
+        );
+
+        let test = r#"<piecewise>
+                        <piece><cn>0</cn><apply><lt/><cn>0</cn><ci>t</ci><cn>5</cn></apply></piece>
+                        <piece><apply><sec/><ci>t</ci></apply><apply><and/><apply><not/><ci>on</ci></apply><true/></apply></piece>
+                      </piecewise>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(
+            parsed.to_c_expression().unwrap(),
+            "0.0 < t && t < 5.0 ? 0.0 : !on && 1.0 ? 1.0 / cos(t) : NAN"
+        );
+        assert_eq!(
+            parsed.to_c_function("rate", &["t", "on"]).unwrap(),
+            "double rate(double t, double on)\n{\n    return 0.0 < t && t < 5.0 ? 0.0 : !on && 1.0 ? 1.0 / cos(t) : NAN;\n}\n"
+        );
+        assert_eq!(
+            parsed.to_c_function("rate", &["t"]),
+            Err(EvalError::UnboundVariable("on".to_owned()))
+        );
+        assert!(parsed.to_c_function("double", &["t", "on"]).is_err());
+    }
+}
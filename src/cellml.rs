@@ -0,0 +1,135 @@
+use super::{ConstantSymbol, MathNode, NumType};
+use crate::error::ParseError;
+
+/// The operators CellML 2.0 allows in its MathML subset
+const CELLML_OPERATORS: &str = "eq neq gt lt geq leq and or xor not plus minus times divide \
+                                power root abs exp ln log floor ceiling min max rem diff sin \
+                                cos tan sec csc cot sinh cosh tanh sech csch coth arcsin arccos \
+                                arctan arcsec arccsc arccot arcsinh arccosh arctanh arcsech \
+                                arccsch arccoth";
+
+/// Whether `ns` is the namespace of some CellML version, e.g.
+/// `http://www.cellml.org/cellml/2.0#`
+pub(crate) fn is_cellml_namespace(ns: &str) -> bool {
+    ns.starts_with("http://www.cellml.org/cellml/")
+}
+
+/// What about the node CellML doesn't allow, if anything
+fn violation(node: &MathNode) -> Option<String> {
+    let name = match node {
+        MathNode::Root { .. }
+        | MathNode::Apply { .. }
+        | MathNode::Ci { .. }
+        | MathNode::Text(_)
+        | MathNode::Piecewise { .. }
+        | MathNode::Bvar { degree: None, .. }
+        | MathNode::Degree(_)
+        | MathNode::Logbase(_)
+        | MathNode::Comment(_)
+        | MathNode::PI(_, _) => return None,
+        MathNode::Op(op)
+            if CELLML_OPERATORS
+                .split_whitespace()
+                .any(|o| o == op.to_string()) =>
+        {
+            return None
+        }
+        MathNode::Op(op) => format!("<{}/>", op),
+        MathNode::Constant(constant) => match constant {
+            ConstantSymbol::ImaginaryI | ConstantSymbol::EulerGamma => format!("<{}/>", constant),
+            _ => return None,
+        },
+        MathNode::Cn {
+            num_type,
+            base,
+            units,
+            ..
+        } => {
+            return match (num_type, base, units) {
+                (NumType::Real(_), 10, Some(_)) | (NumType::ENotation(_, _), 10, Some(_)) => None,
+                (_, _, None) => Some("a <cn> without units".to_owned()),
+                _ => Some("a <cn> that isn't a base 10 real or e-notation".to_owned()),
+            };
+        }
+        MathNode::Bvar { .. } => "a <bvar> with a <degree>".to_owned(),
+        MathNode::Csymbol { .. } => "<csymbol>".to_owned(),
+        MathNode::Lambda { .. } => "<lambda>".to_owned(),
+        MathNode::Bind { .. } => "<bind>".to_owned(),
+        MathNode::Lowlimit(_) => "<lowlimit>".to_owned(),
+        MathNode::Uplimit(_) => "<uplimit>".to_owned(),
+        MathNode::Condition(_) => "<condition>".to_owned(),
+        MathNode::Interval { .. } => "<interval>".to_owned(),
+        MathNode::Matrix(_) => "<matrix>".to_owned(),
+        MathNode::Vector(_) => "<vector>".to_owned(),
+        MathNode::Set(_) => "<set>".to_owned(),
+        MathNode::List { .. } => "<list>".to_owned(),
+        MathNode::NumberSet(set) => format!("<{}/>", set),
+        MathNode::Semantics { .. } => "<semantics>".to_owned(),
+        MathNode::Presentation(_) => "presentation markup".to_owned(),
+        MathNode::Unknown { name, .. } => format!("<{}>", name),
+        MathNode::Cs(_) => "<cs>".to_owned(),
+        MathNode::Cbytes(_) => "<cbytes>".to_owned(),
+        MathNode::Cerror(_) => "<cerror>".to_owned(),
+        MathNode::Share(_) => "<share>".to_owned(),
+        MathNode::Error { message, .. } => return Some(message.clone()),
+    };
+    Some(format!("{} is not allowed in CellML", name))
+}
+
+impl MathNode {
+    /// Checks the tree only uses the MathML subset CellML 2.0 allows: its
+    /// arithmetic, relational, logical and trigonometric operators, `<diff/>`
+    /// and `<piecewise>`, with every `<cn>` a base 10 real or e-notation
+    /// carrying `cellml:units`. Fails with the first thing outside of it
+    pub fn validate_cellml(&self) -> Result<(), ParseError> {
+        match self.iter().find_map(violation) {
+            Some(message) => Err(ParseError::InvalidStructure(message)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{parse_document, MathNode};
+
+    #[test]
+    fn test_cellml() {
+        let test = r#"<math xmlns="http://www.w3.org/1998/Math/MathML" xmlns:cellml="http://www.cellml.org/cellml/2.0#">
+                        <apply><eq/>
+                          <apply><diff/><bvar><ci>t</ci></bvar><ci>V</ci></apply>
+                          <apply><times/><cn cellml:units="per_ms">-0.1</cn><apply><exp/><ci>V</ci></apply></apply>
+                        </apply>
+                      </math>"#;
+        let parsed = parse_document(test).unwrap();
+        let cn = parsed
+            .iter()
+            .find(|n| matches!(n, MathNode::Cn { .. }))
+            .unwrap();
+        match cn {
+            MathNode::Cn {
+                units,
+                units_namespace,
+                attributes,
+                ..
+            } => {
+                assert_eq!(units.as_deref(), Some("per_ms"));
+                assert_eq!(
+                    units_namespace.as_deref(),
+                    Some("http://www.cellml.org/cellml/2.0#")
+                );
+                assert!(attributes.is_empty());
+            }
+            _ => unreachable!(),
+        }
+        assert!(parsed.validate_cellml().is_ok());
+        let written = parsed.to_mathml();
+        assert!(written.contains(
+            r#"<cn type="real" xmlns:cellml="http://www.cellml.org/cellml/2.0#" cellml:units="per_ms">-0.1</cn>"#
+        ));
+        assert_eq!(parse_document(&written).unwrap(), parsed);
+
+        let test = r#"<apply><plus/><cn>1</cn><apply><sum/><ci>x</ci></apply></apply>"#;
+        assert!(parse_document(test).unwrap().validate_cellml().is_err());
+    }
+}
@@ -0,0 +1,362 @@
+use super::{BuiltinOp, MathNode, SbmlSymbol};
+use crate::error::EvalError;
+use crate::eval::{aggregates, constant_symbol, eval_op, nth_root, number};
+use std::collections::HashMap;
+
+/// One step of a compiled expression, working on a stack of values
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Instr {
+    Const(f64),
+    /// Pushes the value at this position of the `eval` arguments
+    Var(usize),
+    /// Pops `arity` values and pushes the result of the operator
+    Apply(BuiltinOp, usize),
+    /// Pops the degree, then the radicand
+    NthRoot,
+    /// Pops the base, then the argument
+    LogBase,
+    /// Pops the otherwise value if there is one, then a value and a condition
+    /// for each piece, pushing the value of the first piece whose condition
+    /// holds. Every branch is computed, so that the code stays straight
+    Piecewise(usize, bool),
+}
+
+/// How many rows `eval_batch` works on at a time, few enough for the stack of
+/// columns to stay in cache
+const BATCH: usize = 256;
+
+/// Replaces `a` with `f(a, b)`, row by row
+fn zip_with(a: &mut [f64], b: &[f64], f: impl Fn(f64, f64) -> f64) {
+    for (x, y) in a.iter_mut().zip(b) {
+        *x = f(*x, *y);
+    }
+}
+
+/// Applies `op` to the `arity` columns from `base` on, leaving the result in
+/// the column at `base`
+fn apply_columns(op: &BuiltinOp, stack: &mut [Vec<f64>], base: usize, arity: usize, len: usize) {
+    use BuiltinOp::*;
+    if arity == 2 {
+        let (lhs, rhs) = stack[base..].split_at_mut(1);
+        let (a, b) = (&mut lhs[0][..len], &rhs[0][..len]);
+        match op {
+            plus => return zip_with(a, b, |x, y| x + y),
+            minus => return zip_with(a, b, |x, y| x - y),
+            times => return zip_with(a, b, |x, y| x * y),
+            divide => return zip_with(a, b, |x, y| x / y),
+            power => return zip_with(a, b, f64::powf),
+            _ => {}
+        }
+    }
+    let mut args = vec![0.0; arity];
+    let results: Vec<f64> = (0..len)
+        .map(|row| {
+            for (k, value) in args.iter_mut().enumerate() {
+                *value = stack[base + k][row];
+            }
+            eval_op(op, &args).unwrap_or(f64::NAN)
+        })
+        .collect();
+    stack[base][..len].copy_from_slice(&results);
+}
+
+/// An expression flattened to a sequence of stack operations, for evaluating it
+/// many times (e.g. once per solver step) without walking the tree each time
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompiledExpr {
+    pub(crate) code: Vec<Instr>,
+    pub(crate) max_stack: usize,
+}
+
+struct Compiler<'a> {
+    variables: &'a [&'a str],
+    code: Vec<Instr>,
+    depth: usize,
+    max_stack: usize,
+}
+
+impl Compiler<'_> {
+    fn emit(&mut self, instr: Instr) {
+        self.depth = match &instr {
+            Instr::Const(_) | Instr::Var(_) => self.depth + 1,
+            Instr::Apply(_, arity) => self.depth + 1 - arity,
+            Instr::NthRoot | Instr::LogBase => self.depth - 1,
+            Instr::Piecewise(pieces, otherwise) => {
+                self.depth + 1 - 2 * pieces - *otherwise as usize
+            }
+        };
+        self.max_stack = self.max_stack.max(self.depth);
+        self.code.push(instr);
+    }
+    fn variable(&mut self, name: &str) -> Result<(), EvalError> {
+        match self.variables.iter().position(|v| *v == name) {
+            Some(index) => {
+                self.emit(Instr::Var(index));
+                Ok(())
+            }
+            None => Err(EvalError::UnboundVariable(name.to_owned())),
+        }
+    }
+    fn constant(&mut self, value: Result<f64, EvalError>) -> Result<(), EvalError> {
+        self.emit(Instr::Const(value?));
+        Ok(())
+    }
+    fn compile(&mut self, node: &MathNode) -> Result<(), EvalError> {
+        match node {
+            MathNode::Root { children, .. } => {
+                let mut expressions = children.iter().filter(|c| !c.is_markup());
+                match (expressions.next(), expressions.next()) {
+                    (Some(expression), None) => self.compile(expression),
+                    _ => Err(EvalError::UnsupportedNode(
+                        "a <math> element without exactly one expression".to_owned(),
+                    )),
+                }
+            }
+            MathNode::Apply { children, .. } if node.bound_variables().is_empty() => {
+                let op = match children.first() {
+                    Some(MathNode::Op(op)) => *op,
+                    _ => return node.eval(&HashMap::new()).map(|_| ()),
+                };
+                let mut arity = 0;
+                for operand in node.operands() {
+                    match operand {
+                        MathNode::Set(items) | MathNode::List { items, .. } if aggregates(&op) => {
+                            for item in items {
+                                self.compile(item)?;
+                            }
+                            arity += items.len();
+                        }
+                        _ => {
+                            self.compile(operand)?;
+                            arity += 1;
+                        }
+                    }
+                }
+                // Arity and support only depend on the operator, so check them once here
+                eval_op(&op, &vec![1.0; arity])?;
+                match (op, node.degree(), node.logbase()) {
+                    (BuiltinOp::root, Some(degree), _) => {
+                        self.compile(degree)?;
+                        self.emit(Instr::NthRoot);
+                    }
+                    (BuiltinOp::log, _, Some(base)) => {
+                        self.compile(base)?;
+                        self.emit(Instr::LogBase);
+                    }
+                    _ => self.emit(Instr::Apply(op, arity)),
+                }
+                Ok(())
+            }
+            MathNode::Ci { children, .. } => self.variable(&crate::text_content(children)),
+            MathNode::Cn { num_type, .. } => self.constant(number(num_type)),
+            MathNode::Constant(constant) => self.constant(constant_symbol(constant)),
+            MathNode::Csymbol {
+                sbml: Some(SbmlSymbol::Time),
+                name,
+                ..
+            } => self.variable(name),
+            MathNode::Semantics { primary, .. } => self.compile(primary),
+            MathNode::Piecewise { pieces, otherwise } => {
+                for (value, condition) in pieces {
+                    self.compile(value)?;
+                    self.compile(condition)?;
+                }
+                if let Some(otherwise) = otherwise {
+                    self.compile(otherwise)?;
+                }
+                self.emit(Instr::Piecewise(pieces.len(), otherwise.is_some()));
+                Ok(())
+            }
+            // Everything else can't be evaluated, let the interpreter say why
+            _ => self.constant(node.eval(&HashMap::new())),
+        }
+    }
+}
+
+impl CompiledExpr {
+    /// Compiles `node`, where identifiers are looked up by their position in
+    /// `variables`. Fails on anything `MathNode::eval` couldn't evaluate
+    pub fn compile(node: &MathNode, variables: &[&str]) -> Result<CompiledExpr, EvalError> {
+        let mut compiler = Compiler {
+            variables,
+            code: Vec::new(),
+            depth: 0,
+            max_stack: 0,
+        };
+        compiler.compile(node)?;
+        Ok(CompiledExpr {
+            code: compiler.code,
+            max_stack: compiler.max_stack,
+        })
+    }
+    /// Evaluates the expression, `values` holding the variables in the order
+    /// given to `compile`. Booleans are 1.0 and 0.0 as with `MathNode::eval`
+    pub fn eval(&self, values: &[f64]) -> f64 {
+        let mut stack: Vec<f64> = Vec::with_capacity(self.max_stack);
+        for instr in &self.code {
+            match instr {
+                Instr::Const(value) => stack.push(*value),
+                Instr::Var(index) => stack.push(values[*index]),
+                Instr::Apply(op, arity) => {
+                    let args = stack.split_off(stack.len() - arity);
+                    stack.push(eval_op(op, &args).unwrap_or(f64::NAN));
+                }
+                Instr::NthRoot => {
+                    let degree = stack.pop().unwrap_or(f64::NAN);
+                    let x = stack.pop().unwrap_or(f64::NAN);
+                    stack.push(nth_root(x, degree));
+                }
+                Instr::LogBase => {
+                    let base = stack.pop().unwrap_or(f64::NAN);
+                    let x = stack.pop().unwrap_or(f64::NAN);
+                    stack.push(x.log10() / base.log10());
+                }
+                Instr::Piecewise(pieces, otherwise) => {
+                    // Without an otherwise, no piece applying is a NaN
+                    let fallback = if *otherwise {
+                        stack.pop().unwrap_or(f64::NAN)
+                    } else {
+                        f64::NAN
+                    };
+                    let branches = stack.split_off(stack.len() - 2 * pieces);
+                    let value = branches
+                        .chunks(2)
+                        .find(|piece| piece[1] != 0.0)
+                        .map_or(fallback, |piece| piece[0]);
+                    stack.push(value);
+                }
+            }
+        }
+        stack.pop().unwrap_or(f64::NAN)
+    }
+    /// Evaluates the expression for many inputs at once, e.g. for a parameter
+    /// scan. `inputs` holds a column of values per variable, in the order given
+    /// to `compile`, and row `i` of the columns gives `out[i]`. Each instruction
+    /// runs over a batch of rows in a loop of its own, so this is much faster
+    /// than calling `eval` per row
+    ///
+    /// Panics if there are fewer columns than variables, or a column is
+    /// shorter than `out`
+    pub fn eval_batch(&self, inputs: &[&[f64]], out: &mut [f64]) {
+        assert!(
+            inputs.iter().all(|column| column.len() >= out.len()),
+            "every input column needs a value for each output"
+        );
+        let mut stack = vec![vec![0.0; BATCH]; self.max_stack];
+        for start in (0..out.len()).step_by(BATCH) {
+            let len = BATCH.min(out.len() - start);
+            let mut top = 0;
+            for instr in &self.code {
+                match instr {
+                    Instr::Const(value) => {
+                        stack[top][..len].fill(*value);
+                        top += 1;
+                    }
+                    Instr::Var(index) => {
+                        stack[top][..len].copy_from_slice(&inputs[*index][start..start + len]);
+                        top += 1;
+                    }
+                    Instr::Apply(op, arity) => {
+                        top -= arity;
+                        apply_columns(op, &mut stack, top, *arity, len);
+                        top += 1;
+                    }
+                    Instr::NthRoot | Instr::LogBase => {
+                        top -= 1;
+                        let (lhs, rhs) = stack[top - 1..].split_at_mut(1);
+                        let (x, y) = (&mut lhs[0][..len], &rhs[0][..len]);
+                        match instr {
+                            Instr::NthRoot => zip_with(x, y, nth_root),
+                            _ => zip_with(x, y, |x, base| x.log10() / base.log10()),
+                        }
+                    }
+                    Instr::Piecewise(pieces, otherwise) => {
+                        let fallback = if *otherwise {
+                            top -= 1;
+                            Some(top)
+                        } else {
+                            None
+                        };
+                        top -= 2 * pieces;
+                        let results: Vec<f64> = (0..len)
+                            .map(|row| {
+                                (0..*pieces)
+                                    .map(|p| top + 2 * p)
+                                    .find(|&column| stack[column + 1][row] != 0.0)
+                                    .or(fallback)
+                                    .map_or(f64::NAN, |column| stack[column][row])
+                            })
+                            .collect();
+                        stack[top][..len].copy_from_slice(&results);
+                        top += 1;
+                    }
+                }
+            }
+            out[start..start + len].copy_from_slice(&stack[0][..len]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CompiledExpr;
+    use crate::error::EvalError;
+    use crate::parse_document;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_compiled_expr() {
+        let test = r#"<apply><plus/>
+                        <apply><divide/><apply><times/><ci>Vmax</ci><ci>S</ci></apply><apply><plus/><ci>Km</ci><ci>S</ci></apply></apply>
+                        <apply><root/><degree><cn>3</cn></degree><ci>S</ci></apply>
+                        <apply><log/><logbase><cn>2</cn></logbase><apply><max/><set><cn>1</cn><ci>Km</ci></set></apply></apply>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        let compiled = CompiledExpr::compile(&parsed, &["S", "Vmax", "Km"]).unwrap();
+        for s in &[-8.0, 0.0, 2.0, 27.0] {
+            let env: HashMap<String, f64> = vec![("S", *s), ("Vmax", 10.0), ("Km", 4.0)]
+                .into_iter()
+                .map(|(k, v)| (k.to_owned(), v))
+                .collect();
+            approx::assert_abs_diff_eq!(
+                compiled.eval(&[*s, 10.0, 4.0]),
+                parsed.eval(&env).unwrap()
+            );
+        }
+        assert_eq!(
+            CompiledExpr::compile(&parsed, &["S", "Vmax"]),
+            Err(EvalError::UnboundVariable("Km".to_owned()))
+        );
+
+        let test = r#"<piecewise>
+                        <piece><ci>x</ci><apply><gt/><ci>x</ci><cn>0</cn></apply></piece>
+                        <otherwise><apply><minus/><ci>x</ci></apply></otherwise>
+                      </piecewise>"#;
+        let compiled = CompiledExpr::compile(&parse_document(test).unwrap(), &["x"]).unwrap();
+        assert_eq!(compiled.eval(&[3.0]), 3.0);
+        assert_eq!(compiled.eval(&[-2.0]), 2.0);
+    }
+    #[test]
+    fn test_eval_batch() {
+        let test = r#"<apply><plus/>
+                        <apply><divide/><apply><times/><ci>Vmax</ci><ci>S</ci></apply><apply><plus/><ci>Km</ci><ci>S</ci></apply></apply>
+                        <apply><root/><degree><cn>3</cn></degree><ci>S</ci></apply>
+                        <apply><max/><cn>1</cn><ci>Km</ci><cn>2.5</cn></apply>
+                        <piecewise>
+                          <piece><cn>1</cn><apply><gt/><ci>S</ci><cn>100</cn></apply></piece>
+                          <otherwise><apply><minus/><ci>S</ci></apply></otherwise>
+                        </piecewise>
+                      </apply>"#;
+        let compiled =
+            CompiledExpr::compile(&parse_document(test).unwrap(), &["S", "Vmax", "Km"]).unwrap();
+        let rows = 1000;
+        let s: Vec<f64> = (0..rows).map(|i| i as f64 / 4.0 - 20.0).collect();
+        let vmax: Vec<f64> = (0..rows).map(|i| (i % 7) as f64).collect();
+        let km = vec![3.3; rows];
+        let mut out = vec![0.0; rows];
+        compiled.eval_batch(&[&s, &vmax, &km], &mut out);
+        for i in 0..rows {
+            approx::assert_abs_diff_eq!(out[i], compiled.eval(&[s[i], vmax[i], km[i]]));
+        }
+    }
+}
@@ -0,0 +1,611 @@
+use super::{BuiltinOp, ConstantSymbol, MathNode, NumType, NumberSet, PresentationNode};
+use crate::ops::{is_relation, PREC_ATOM, PREC_POWER, PREC_UNARY};
+use PresentationNode::*;
+
+/// Invisible operator placed between a function name and its argument list
+const FUNCTION_APPLICATION: &str = "\u{2061}";
+
+/// A converted subtree together with the precedence of its outermost operator
+struct Converted {
+    node: PresentationNode,
+    precedence: u8,
+}
+
+impl Converted {
+    fn new(node: PresentationNode, precedence: u8) -> Self {
+        Converted { node, precedence }
+    }
+    fn atom(node: PresentationNode) -> Self {
+        Converted::new(node, PREC_ATOM)
+    }
+    /// Returns the node, fenced with parentheses if it binds looser than `min_precedence`
+    fn wrap(self, min_precedence: u8) -> PresentationNode {
+        if self.precedence < min_precedence {
+            fenced("(", self.node, ")")
+        } else {
+            self.node
+        }
+    }
+}
+
+fn mo(text: &str) -> PresentationNode {
+    Mo(text.to_owned())
+}
+
+fn fenced(open: &str, node: PresentationNode, close: &str) -> PresentationNode {
+    Mrow(vec![mo(open), node, mo(close)])
+}
+
+/// The glyph used for operators written infix between their arguments
+pub(crate) fn infix_glyph(op: &BuiltinOp) -> Option<&'static str> {
+    use BuiltinOp::*;
+    let glyph = match op {
+        plus => "+",
+        minus => "\u{2212}",
+        times | scalarproduct => "\u{22C5}",
+        rem => "mod",
+        and => "\u{2227}",
+        or => "\u{2228}",
+        xor => "\u{22BB}",
+        implies => "\u{21D2}",
+        equivalent => "\u{2261}",
+        approx => "\u{2248}",
+        eq => "=",
+        neq => "\u{2260}",
+        gt => ">",
+        lt => "<",
+        geq => "\u{2265}",
+        leq => "\u{2264}",
+        union => "\u{222A}",
+        intersect => "\u{2229}",
+        setdiff => "\u{2216}",
+        cartesianproduct | vectorproduct => "\u{00D7}",
+        outerproduct => "\u{2297}",
+        compose => "\u{2218}",
+        r#in => "\u{2208}",
+        notin => "\u{2209}",
+        subset => "\u{2286}",
+        prsubset => "\u{2282}",
+        notsubset => "\u{2288}",
+        notprsubset => "\u{2284}",
+        tendsto => "\u{2192}",
+        factorof => "\u{2223}",
+        _ => return None,
+    };
+    Some(glyph)
+}
+
+/// The glyph used for prefix operators and big operators
+pub(crate) fn prefix_glyph(op: &BuiltinOp) -> Option<&'static str> {
+    use BuiltinOp::*;
+    let glyph = match op {
+        not => "\u{00AC}",
+        sum => "\u{2211}",
+        product => "\u{220F}",
+        int => "\u{222B}",
+        forall => "\u{2200}",
+        exists => "\u{2203}",
+        grad => "\u{2207}",
+        partialdiff => "\u{2202}",
+        _ => return None,
+    };
+    Some(glyph)
+}
+
+/// Glyphs for the constant symbols. `e` and `i` use the double-struck letters
+/// so they don't read as variables
+pub(crate) fn constant(constant: &ConstantSymbol) -> &'static str {
+    match constant {
+        ConstantSymbol::Pi => "\u{03C0}",
+        ConstantSymbol::ExponentialE => "\u{2147}",
+        ConstantSymbol::ImaginaryI => "\u{2148}",
+        ConstantSymbol::EulerGamma => "\u{03B3}",
+        ConstantSymbol::Infinity => "\u{221E}",
+        ConstantSymbol::NotANumber => "NaN",
+        ConstantSymbol::True => "true",
+        ConstantSymbol::False => "false",
+    }
+}
+
+pub(crate) fn number_set(set: &NumberSet) -> &'static str {
+    match set {
+        NumberSet::Integers => "\u{2124}",
+        NumberSet::Reals => "\u{211D}",
+        NumberSet::Rationals => "\u{211A}",
+        NumberSet::NaturalNumbers => "\u{2115}",
+        NumberSet::Complexes => "\u{2102}",
+        NumberSet::Primes => "\u{2119}",
+        NumberSet::EmptySet => "\u{2205}",
+    }
+}
+
+fn signed_number(value: String) -> Converted {
+    match value.strip_prefix('-') {
+        Some(abs) => Converted::new(Mrow(vec![mo("\u{2212}"), Mn(abs.to_owned())]), PREC_UNARY),
+        None => Converted::atom(Mn(value)),
+    }
+}
+
+fn number(num_type: &NumType) -> Converted {
+    match num_type {
+        NumType::Real(r) => signed_number(r.to_string()),
+        NumType::Integer(i) => signed_number(i.to_string()),
+        NumType::Rational(a, b) => Converted::atom(Mfrac(
+            Box::new(Mn(a.to_string())),
+            Box::new(Mn(b.to_string())),
+        )),
+        NumType::ComplexCartesian(a, b) => Converted::new(
+            Mrow(vec![
+                Mn(a.to_string()),
+                mo("+"),
+                Mn(b.to_string()),
+                mo("\u{2062}"),
+                Mi("i".to_owned()),
+            ]),
+            5,
+        ),
+        NumType::ComplexPolar(a, b) => Converted::new(
+            Mrow(vec![
+                Mn(a.to_string()),
+                mo("\u{2062}"),
+                Msup(
+                    Box::new(Mi("e".to_owned())),
+                    Box::new(Mrow(vec![
+                        Mi("i".to_owned()),
+                        mo("\u{2062}"),
+                        Mn(b.to_string()),
+                    ])),
+                ),
+            ]),
+            6,
+        ),
+        NumType::Constant(c) => Converted::atom(Mi(c.symbol().to_owned())),
+        NumType::ENotation(a, b) => Converted::new(
+            Mrow(vec![
+                Mn(a.to_string()),
+                mo("\u{00D7}"),
+                Msup(Box::new(Mn("10".to_owned())), Box::new(Mn(b.to_string()))),
+            ]),
+            6,
+        ),
+        NumType::Other { text, .. } => signed_number(text.clone()),
+        #[cfg(feature = "bigint")]
+        NumType::BigInteger(i) => signed_number(i.to_string()),
+    }
+}
+
+/// Joins nodes with a separator operator, e.g. `a , b , c`
+fn separated(nodes: Vec<PresentationNode>, separator: &str) -> Vec<PresentationNode> {
+    let mut row = Vec::with_capacity(nodes.len() * 2);
+    for (i, node) in nodes.into_iter().enumerate() {
+        if i > 0 {
+            row.push(mo(separator));
+        }
+        row.push(node);
+    }
+    row
+}
+
+fn function_call(name: PresentationNode, args: Vec<PresentationNode>) -> Converted {
+    Converted::atom(Mrow(vec![
+        name,
+        mo(FUNCTION_APPLICATION),
+        fenced("(", Mrow(separated(args, ",")), ")"),
+    ]))
+}
+
+/// The total order of a derivative: the `<degree>` of the `<apply>` if given,
+/// otherwise the sum of the degrees of its bound variables
+pub(crate) enum Order<'a> {
+    Degree(&'a MathNode),
+    /// The number of bound variables, none of which has a degree
+    Count(usize),
+    /// The degrees of the bound variables, `None` for a first derivative
+    Sum(Vec<Option<&'a MathNode>>),
+}
+
+/// The qualifiers of an operator with bound variables: sums, products,
+/// integrals, quantifiers and derivatives
+pub(crate) struct Binder<'a> {
+    pub(crate) bvars: Vec<(&'a str, Option<&'a MathNode>)>,
+    pub(crate) limits: Option<(&'a MathNode, &'a MathNode)>,
+    pub(crate) condition: Option<&'a MathNode>,
+    /// `None` unless it is a derivative of a higher order
+    pub(crate) order: Option<Order<'a>>,
+}
+
+impl<'a> Binder<'a> {
+    pub(crate) fn of(apply: &'a MathNode) -> Self {
+        let bvars = apply.bvars();
+        let degrees: Vec<Option<&MathNode>> = bvars.iter().map(|(_, d)| *d).collect();
+        let order = match (apply.degree(), degrees.as_slice()) {
+            (Some(degree), _) | (None, &[Some(degree)]) => Some(Order::Degree(degree)),
+            (None, [None]) => None,
+            _ if degrees.iter().all(Option::is_none) => Some(Order::Count(degrees.len())),
+            _ => Some(Order::Sum(degrees)),
+        };
+        Binder {
+            bvars,
+            limits: apply.limits(),
+            condition: apply.condition(),
+            order,
+        }
+    }
+}
+
+fn convert_order(order: Order) -> PresentationNode {
+    match order {
+        Order::Degree(degree) => convert(degree).node,
+        Order::Count(count) => Mn(count.to_string()),
+        Order::Sum(degrees) => Mrow(separated(
+            degrees
+                .iter()
+                .map(|d| d.map_or_else(|| Mn("1".to_owned()), |d| convert(d).node))
+                .collect(),
+            "+",
+        )),
+    }
+}
+
+fn convert_binder(op: &BuiltinOp, apply: &MathNode, body: Converted) -> Converted {
+    use BuiltinOp::*;
+    let binder = Binder::of(apply);
+    let vars: Vec<PresentationNode> = binder
+        .bvars
+        .iter()
+        .map(|(name, _)| Mi(name.to_string()))
+        .collect();
+    let condition = binder.condition.map(|c| convert(c).node);
+    match op {
+        sum | product | int => {
+            let glyph = Box::new(mo(prefix_glyph(op).unwrap_or_default()));
+            let symbol = match (binder.limits, condition) {
+                (Some((low, high)), _) => {
+                    let low = match op {
+                        int => convert(low).node,
+                        _ => {
+                            let mut row = separated(vars.clone(), ",");
+                            row.extend(vec![mo("="), convert(low).node]);
+                            Mrow(row)
+                        }
+                    };
+                    Msubsup(glyph, Box::new(low), Box::new(convert(high).node))
+                }
+                (None, Some(condition)) => Msub(glyph, Box::new(condition)),
+                (None, None) if *op == int => *glyph,
+                (None, None) => Msub(glyph, Box::new(Mrow(separated(vars.clone(), ",")))),
+            };
+            let mut row = vec![symbol, body.wrap(PREC_UNARY)];
+            if *op == int {
+                for var in vars {
+                    row.push(Mrow(vec![mo("\u{2146}"), var]));
+                }
+            }
+            Converted::new(Mrow(row), PREC_UNARY)
+        }
+        forall | exists => {
+            let mut row = vec![mo(prefix_glyph(op).unwrap_or_default())];
+            row.extend(separated(vars, ","));
+            if let Some(condition) = condition {
+                row.extend(vec![mo("|"), condition]);
+            }
+            row.extend(vec![mo(":"), body.node]);
+            Converted::new(Mrow(row), 0)
+        }
+        _ => {
+            let d = || {
+                if *op == diff {
+                    Mi("d".to_owned())
+                } else {
+                    mo("\u{2202}")
+                }
+            };
+            let numerator = match binder.order {
+                Some(order) => Msup(Box::new(d()), Box::new(convert_order(order))),
+                None => d(),
+            };
+            let denominator = binder
+                .bvars
+                .iter()
+                .zip(vars)
+                .map(|((_, degree), var)| match degree {
+                    Some(degree) => Mrow(vec![
+                        d(),
+                        Msup(Box::new(var), Box::new(convert(degree).node)),
+                    ]),
+                    None => Mrow(vec![d(), var]),
+                })
+                .collect();
+            let denominator = Box::new(Mrow(denominator));
+            if body.precedence >= PREC_ATOM {
+                Converted::atom(Mfrac(
+                    Box::new(Mrow(vec![numerator, body.node])),
+                    denominator,
+                ))
+            } else {
+                Converted::new(
+                    Mrow(vec![
+                        Mfrac(Box::new(numerator), denominator),
+                        body.wrap(PREC_ATOM),
+                    ]),
+                    PREC_UNARY,
+                )
+            }
+        }
+    }
+}
+
+fn convert_op(op: &BuiltinOp, apply: &MathNode, args: &[&MathNode]) -> Converted {
+    use BuiltinOp::*;
+    let prec = op.precedence();
+    let operand = |i: usize| {
+        args.get(i)
+            .map(|a| convert(a))
+            .unwrap_or_else(|| Converted::atom(Mrow(vec![])))
+    };
+    let all = |min_prec: u8| {
+        args.iter()
+            .map(|a| convert(a).wrap(min_prec))
+            .collect::<Vec<_>>()
+    };
+    let boxed = |i: usize| Box::new(operand(i).node);
+    match op {
+        minus if args.len() == 1 => Converted::new(
+            Mrow(vec![mo("\u{2212}"), operand(0).wrap(PREC_POWER)]),
+            PREC_UNARY,
+        ),
+        minus => Converted::new(
+            Mrow(vec![
+                operand(0).wrap(prec),
+                mo("\u{2212}"),
+                operand(1).wrap(prec + 1),
+            ]),
+            prec,
+        ),
+        sum | product | int | forall | exists | diff | partialdiff
+            if !apply.bound_variables().is_empty() =>
+        {
+            convert_binder(op, apply, operand(0))
+        }
+        divide => Converted::atom(Mfrac(boxed(0), boxed(1))),
+        power => Converted::new(
+            Msup(Box::new(operand(0).wrap(PREC_ATOM)), boxed(1)),
+            PREC_POWER,
+        ),
+        root => Converted::atom(Msqrt(vec![operand(0).node])),
+        exp => Converted::new(Msup(Box::new(Mi("e".to_owned())), boxed(0)), PREC_POWER),
+        factorial => Converted::new(Mrow(vec![operand(0).wrap(PREC_ATOM), mo("!")]), prec),
+        abs | card => Converted::atom(fenced("|", operand(0).node, "|")),
+        floor => Converted::atom(fenced("\u{230A}", operand(0).node, "\u{230B}")),
+        ceiling => Converted::atom(fenced("\u{2308}", operand(0).node, "\u{2309}")),
+        quotient => Converted::atom(fenced("\u{230A}", Mfrac(boxed(0), boxed(1)), "\u{230B}")),
+        transpose => Converted::new(
+            Msup(
+                Box::new(operand(0).wrap(PREC_ATOM)),
+                Box::new(Mi("T".to_owned())),
+            ),
+            PREC_POWER,
+        ),
+        inverse => Converted::new(
+            Msup(
+                Box::new(operand(0).wrap(PREC_ATOM)),
+                Box::new(Mrow(vec![mo("\u{2212}"), Mn("1".to_owned())])),
+            ),
+            PREC_POWER,
+        ),
+        diff => Converted::new(
+            Msup(
+                Box::new(operand(0).wrap(PREC_ATOM)),
+                Box::new(mo("\u{2032}")),
+            ),
+            PREC_POWER,
+        ),
+        selector => Converted::atom(Msub(
+            Box::new(operand(0).wrap(PREC_ATOM)),
+            Box::new(Mrow(separated(
+                args.iter().skip(1).map(|a| convert(a).node).collect(),
+                ",",
+            ))),
+        )),
+        _ => {
+            if let Some(glyph) = infix_glyph(op) {
+                let min_prec = if is_relation(op) { prec + 1 } else { prec };
+                Converted::new(Mrow(separated(all(min_prec), glyph)), prec)
+            } else if let Some(glyph) = prefix_glyph(op) {
+                let mut row = vec![mo(glyph)];
+                row.extend(all(PREC_ATOM));
+                Converted::new(Mrow(row), PREC_UNARY)
+            } else {
+                function_call(Mi(op.to_string()), all(0))
+            }
+        }
+    }
+}
+
+fn convert(node: &MathNode) -> Converted {
+    match node {
+        MathNode::Root { children, .. } => Converted::atom(Mrow(
+            children
+                .iter()
+                .filter(|c| !matches!(c, MathNode::Comment(_) | MathNode::PI(_, _)))
+                .map(|c| convert(c).node)
+                .collect(),
+        )),
+        MathNode::Apply { children, .. } => {
+            let operands = node.operands();
+            let first_operand = || {
+                operands
+                    .first()
+                    .map(|a| convert(a).node)
+                    .unwrap_or_else(|| Mrow(vec![]))
+            };
+            match (children.first(), node.degree(), node.logbase()) {
+                (Some(MathNode::Op(BuiltinOp::root)), Some(degree), _) => Converted::atom(Mroot(
+                    Box::new(first_operand()),
+                    Box::new(convert(degree).node),
+                )),
+                (Some(MathNode::Op(BuiltinOp::log)), _, Some(base)) => function_call(
+                    Msub(Box::new(Mi("log".to_owned())), Box::new(convert(base).node)),
+                    operands.iter().map(|a| convert(a).node).collect(),
+                ),
+                (Some(MathNode::Op(op)), _, _) => convert_op(op, node, &operands),
+                (Some(head), _, _) => function_call(
+                    convert(head).wrap(PREC_ATOM),
+                    operands.iter().map(|a| convert(a).node).collect(),
+                ),
+                (None, _, _) => Converted::atom(Mrow(vec![])),
+            }
+        }
+        MathNode::Op(op) => Converted::atom(match infix_glyph(op).or_else(|| prefix_glyph(op)) {
+            Some(glyph) => mo(glyph),
+            None => Mi(op.to_string()),
+        }),
+        MathNode::Constant(c) => Converted::atom(Mi(constant(c).to_owned())),
+        MathNode::NumberSet(set) => Converted::atom(Mi(number_set(set).to_owned())),
+        MathNode::Text(t) | MathNode::Cs(t) => Converted::atom(Mtext(t.clone())),
+        MathNode::Ci { children, .. } => match children.as_slice() {
+            [MathNode::Presentation(p)] => Converted::atom(p.clone()),
+            _ => Converted::atom(Mi(crate::text_content(children))),
+        },
+        MathNode::Csymbol { name, .. } => Converted::atom(Mi(name.clone())),
+        MathNode::Cn { num_type, .. } => number(num_type),
+        MathNode::Comment(_) | MathNode::PI(_, _) => Converted::atom(Mrow(vec![])),
+        MathNode::Error { .. } | MathNode::Cerror(_) | MathNode::Cbytes(_) | MathNode::Share(_) => {
+            Converted::atom(Mtext("?".to_owned()))
+        }
+        MathNode::Unknown { name, children, .. } => function_call(
+            Mi(name.clone()),
+            children.iter().map(|c| convert(c).node).collect(),
+        ),
+        MathNode::Presentation(node) => Converted::atom(node.clone()),
+        MathNode::Piecewise { pieces, otherwise } => {
+            let mut rows: Vec<PresentationNode> = pieces
+                .iter()
+                .map(|(value, condition)| {
+                    Mrow(vec![
+                        convert(value).node,
+                        Mtext("if".to_owned()),
+                        convert(condition).node,
+                    ])
+                })
+                .collect();
+            if let Some(otherwise) = otherwise {
+                rows.push(Mrow(vec![
+                    convert(otherwise).node,
+                    Mtext("otherwise".to_owned()),
+                ]));
+            }
+            Converted::atom(Mrow(vec![mo("{"), Mrow(separated(rows, ";"))]))
+        }
+        MathNode::Bind { .. } => convert(&node.to_apply()),
+        MathNode::Lambda { bound_vars, body } => {
+            let vars = bound_vars.iter().map(|v| Mi(v.clone())).collect();
+            let vars = match bound_vars.len() {
+                1 => Mrow(vars),
+                _ => fenced("(", Mrow(separated(vars, ",")), ")"),
+            };
+            Converted::new(Mrow(vec![vars, mo("\u{21A6}"), convert(body).node]), 0)
+        }
+        MathNode::Degree(inner)
+        | MathNode::Logbase(inner)
+        | MathNode::Lowlimit(inner)
+        | MathNode::Uplimit(inner)
+        | MathNode::Condition(inner) => convert(inner),
+        MathNode::Bvar { name, .. } => Converted::atom(Mi(name.clone())),
+        MathNode::Semantics { primary, .. } => convert(primary),
+        MathNode::Set(items) => Converted::atom(fenced(
+            "{",
+            Mrow(separated(
+                items.iter().map(|c| convert(c).node).collect(),
+                ",",
+            )),
+            "}",
+        )),
+        MathNode::List { items, .. } => Converted::atom(fenced(
+            "[",
+            Mrow(separated(
+                items.iter().map(|c| convert(c).node).collect(),
+                ",",
+            )),
+            "]",
+        )),
+        MathNode::Matrix(rows) => Converted::atom(fenced(
+            "(",
+            Mtable(
+                rows.iter()
+                    .map(|row| row.iter().map(|c| convert(c).node).collect())
+                    .collect(),
+            ),
+            ")",
+        )),
+        MathNode::Vector(children) => Converted::atom(fenced(
+            "(",
+            Mtable(children.iter().map(|c| vec![convert(c).node]).collect()),
+            ")",
+        )),
+        MathNode::Interval { closure, low, high } => {
+            let (open, close) = closure.brackets();
+            Converted::atom(fenced(
+                open,
+                Mrow(vec![convert(low).node, mo(","), convert(high).node]),
+                close,
+            ))
+        }
+    }
+}
+
+impl MathNode {
+    /// Convert content markup into the equivalent presentation markup
+    pub fn to_presentation(&self) -> PresentationNode {
+        convert(self).node
+    }
+    /// Convert content markup into a Presentation MathML document, ready for MathJax
+    pub fn to_presentation_mathml(&self) -> String {
+        MathNode::Presentation(self.to_presentation())
+            .math()
+            .to_mathml()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parse_document;
+
+    #[test]
+    fn test_to_presentation() {
+        let test = r#"<apply><times/>
+                        <apply><plus/><ci>a</ci><ci>b</ci></apply>
+                        <apply><power/><ci>x</ci><cn type="integer">2</cn></apply>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(
+            parsed.to_presentation_mathml(),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mrow><mrow><mo>(</mo><mrow><mi>a</mi><mo>+</mo><mi>b</mi></mrow><mo>)</mo></mrow><mo>\u{22C5}</mo><msup><mi>x</mi><mn>2</mn></msup></mrow></math>"
+        );
+    }
+    #[test]
+    fn test_to_presentation_functions() {
+        let test = r#"<apply><leq/>
+                        <apply><divide/><apply><sin/><ci>t</ci></apply><cn>2</cn></apply>
+                        <ci>y</ci>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(
+            parsed.to_presentation().to_mathml(),
+            "<mrow><mfrac><mrow><mi>sin</mi><mo>\u{2061}</mo><mrow><mo>(</mo><mrow><mi>t</mi></mrow><mo>)</mo></mrow></mrow><mn>2</mn></mfrac><mo>\u{2264}</mo><mi>y</mi></mrow>"
+        );
+    }
+    #[test]
+    fn test_to_presentation_binders() {
+        let test = r#"<apply><sum/>
+                        <bvar><ci>i</ci></bvar>
+                        <lowlimit><cn>1</cn></lowlimit><uplimit><ci>n</ci></uplimit>
+                        <ci>i</ci>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(
+            parsed.to_presentation().to_mathml(),
+            "<mrow><msubsup><mo>\u{2211}</mo><mrow><mi>i</mi><mo>=</mo><mn>1</mn></mrow><mi>n</mi></msubsup><mi>i</mi></mrow>"
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use crate::error::ParseError;
+use crate::infix::invalid;
+
+/// A token of the LaTeX and AsciiMath parsers
+pub(crate) trait Token: Copy + PartialEq {
+    /// The token after the last one of the input
+    const END: Self;
+    /// The token as error messages show it, e.g. `'+'`
+    fn describe(self) -> String;
+}
+
+/// The tokens of a formula, each with its byte offset, and the one to read
+/// next. The last token is `Token::END`, which is never read past
+pub(crate) struct Cursor<T> {
+    tokens: Vec<(T, usize)>,
+    next: usize,
+    /// Where `next` was before the last `advance`, which `back` returns to
+    previous: usize,
+}
+
+impl<T: Token> Cursor<T> {
+    pub(crate) fn new(tokens: Vec<(T, usize)>) -> Self {
+        Cursor {
+            tokens,
+            next: 0,
+            previous: 0,
+        }
+    }
+    pub(crate) fn peek(&self) -> T {
+        self.tokens[self.next].0
+    }
+    /// The token after the next one
+    pub(crate) fn peek_second(&self) -> T {
+        self.tokens
+            .get(self.next + 1)
+            .map_or(T::END, |(token, _)| *token)
+    }
+    pub(crate) fn position(&self) -> usize {
+        self.tokens[self.next].1
+    }
+    /// Where the last token read starts
+    pub(crate) fn previous_position(&self) -> usize {
+        self.tokens[self.previous].1
+    }
+    /// Puts `token` in place of the next one, e.g. what is left of it after
+    /// reading part of it
+    pub(crate) fn replace(&mut self, token: T, position: usize) {
+        self.tokens[self.next] = (token, position);
+    }
+    pub(crate) fn advance(&mut self) -> T {
+        let token = self.peek();
+        self.previous = self.next;
+        if token != T::END {
+            self.next += 1;
+        }
+        token
+    }
+    /// Undoes the last `advance`, which didn't move past the end of the input
+    pub(crate) fn back(&mut self) {
+        self.next = self.previous;
+    }
+    pub(crate) fn eat(&mut self, token: T) -> bool {
+        let found = self.peek() == token;
+        if found {
+            self.advance();
+        }
+        found
+    }
+    pub(crate) fn expect(&mut self, token: T) -> Result<(), ParseError> {
+        if self.eat(token) {
+            return Ok(());
+        }
+        self.error(&token.describe())
+    }
+    pub(crate) fn error<R>(&self, expected: &str) -> Result<R, ParseError> {
+        Err(invalid(
+            self.position(),
+            format!("expected {}, found {}", expected, self.peek().describe()),
+        ))
+    }
+}
@@ -0,0 +1,129 @@
+use super::MathNode;
+use std::collections::HashMap;
+
+/// A temporary holding the value of a shared subexpression. Inside a `Dag` it
+/// is referred to by a `<ci>` named after it, e.g. `_t0`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TempId(pub usize);
+
+impl TempId {
+    /// The identifier standing for the temporary
+    pub fn name(&self) -> String {
+        format!("_t{}", self.0)
+    }
+}
+
+/// An expression with its repeated subexpressions computed once, as let bindings.
+/// Each binding only refers to the temporaries defined before it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dag {
+    pub bindings: Vec<(TempId, MathNode)>,
+    pub root: MathNode,
+}
+
+impl Dag {
+    /// Substitutes the temporaries back, giving the original tree
+    pub fn inline(&self) -> MathNode {
+        let mut values: HashMap<String, MathNode> = HashMap::new();
+        for (id, value) in &self.bindings {
+            let value = value.substitute(&values);
+            values.insert(id.name(), value);
+        }
+        self.root.substitute(&values)
+    }
+}
+
+/// Subexpressions under a binder can refer to its variables, so they stay put
+fn is_opaque(node: &MathNode) -> bool {
+    matches!(node, MathNode::Lambda { .. }) || !node.bound_variables().is_empty()
+}
+
+fn count<'a>(node: &'a MathNode, counts: &mut HashMap<&'a MathNode, usize>) {
+    if let MathNode::Apply { .. } = node {
+        let seen = counts.entry(node).or_insert(0);
+        *seen += 1;
+        // Repeats get replaced whole, what is inside them only counts once
+        if *seen > 1 {
+            return;
+        }
+    }
+    if !is_opaque(node) {
+        for child in node.children() {
+            count(child, counts);
+        }
+    }
+}
+
+struct Hoister<'a> {
+    counts: HashMap<&'a MathNode, usize>,
+    assigned: HashMap<MathNode, TempId>,
+    bindings: Vec<(TempId, MathNode)>,
+}
+
+impl Hoister<'_> {
+    fn hoist(&mut self, mut node: MathNode) -> MathNode {
+        if let Some(id) = self.assigned.get(&node) {
+            return MathNode::ci(id.name());
+        }
+        let shared = self.counts.get(&node).is_some_and(|n| *n > 1);
+        let original = if shared { Some(node.clone()) } else { None };
+        if !is_opaque(&node) {
+            for child in node.children_mut() {
+                let taken = std::mem::replace(child, MathNode::Text(String::new()));
+                *child = self.hoist(taken);
+            }
+        }
+        match original {
+            Some(original) => {
+                let id = TempId(self.bindings.len());
+                self.assigned.insert(original, id);
+                self.bindings.push((id, node));
+                MathNode::ci(id.name())
+            }
+            None => node,
+        }
+    }
+}
+
+impl MathNode {
+    /// Finds the `<apply>` subtrees that occur more than once and binds each to
+    /// a temporary, so they only need to be evaluated or generated once
+    pub fn to_dag(&self) -> Dag {
+        let mut counts = HashMap::new();
+        count(self, &mut counts);
+        let mut hoister = Hoister {
+            counts,
+            assigned: HashMap::new(),
+            bindings: Vec::new(),
+        };
+        let root = hoister.hoist(self.clone());
+        Dag {
+            bindings: hoister.bindings,
+            root,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TempId;
+    use crate::{math, MathNode};
+
+    #[test]
+    fn test_to_dag() {
+        let law = math!(k1 * (s / (km + s)) + k2 * (s / (km + s)) * exp(k1 * (s / (km + s))));
+        let dag = law.to_dag();
+        let names: Vec<String> = dag
+            .bindings
+            .iter()
+            .map(|(id, value)| format!("{} = {}", id.name(), value))
+            .collect();
+        assert_eq!(names, vec!["_t0 = s / (km + s)", "_t1 = k1 * _t0"]);
+        assert_eq!(dag.root.to_string(), "_t1 + k2 * _t0 * exp(_t1)");
+        assert_eq!(dag.inline(), law);
+
+        let unshared = math!(a + b);
+        assert_eq!(unshared.to_dag().root, unshared);
+        assert_eq!(TempId(3).name(), MathNode::ci("_t3").as_ci().unwrap());
+    }
+}
@@ -0,0 +1,169 @@
+use super::MathNode;
+use crate::error::EvalError;
+use crate::scope::function_name;
+use std::collections::HashMap;
+
+/// The calls in `node` of the functions named in `definitions`, with their
+/// number of arguments
+fn calls<'a>(node: &'a MathNode, definitions: &HashMap<String, MathNode>) -> Vec<(&'a str, usize)> {
+    node.iter()
+        .filter_map(|n| match n {
+            MathNode::Apply { children, .. } => {
+                let head = children.iter().find(|c| !c.is_markup())?;
+                let name = function_name(head).filter(|name| definitions.contains_key(*name))?;
+                Some((name, n.operands().len()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// The number of arguments `lambda` takes
+fn arity(name: &str, lambda: &MathNode) -> Result<usize, EvalError> {
+    match lambda.to_apply() {
+        MathNode::Lambda { bound_vars, .. } => Ok(bound_vars.len()),
+        _ => Err(EvalError::UnsupportedNode(format!(
+            "the definition of '{}', which isn't a <lambda>",
+            name
+        ))),
+    }
+}
+
+/// Follows the calls from `name`, failing on one back into `path`
+fn check_cycles<'a>(
+    name: &'a str,
+    definitions: &'a HashMap<String, MathNode>,
+    path: &mut Vec<&'a str>,
+    done: &mut Vec<&'a str>,
+) -> Result<(), EvalError> {
+    if done.contains(&name) {
+        return Ok(());
+    }
+    if let Some(start) = path.iter().position(|p| *p == name) {
+        let mut cycle: Vec<String> = path[start..].iter().map(|p| p.to_string()).collect();
+        cycle.push(name.to_owned());
+        return Err(EvalError::RecursiveDefinition(cycle));
+    }
+    path.push(name);
+    for (callee, _) in calls(&definitions[name], definitions) {
+        check_cycles(callee, definitions, path, done)?;
+    }
+    path.pop();
+    done.push(name);
+    Ok(())
+}
+
+/// The function definitions of a model, e.g. SBML's `<functionDefinition>`s,
+/// checked for recursion and with the calls between them inlined, ready to be
+/// inlined into the model's expressions
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionDefinitions {
+    /// Each function's `<lambda>`, calling none of the others
+    lambdas: HashMap<String, MathNode>,
+    arities: HashMap<String, usize>,
+}
+
+impl FunctionDefinitions {
+    /// Takes the `<lambda>` of each function by name. Fails on a definition
+    /// that isn't a `<lambda>`, on a call with the wrong number of arguments
+    /// and on functions that call themselves, directly or through others,
+    /// which SBML doesn't allow
+    pub fn new(definitions: HashMap<String, MathNode>) -> Result<Self, EvalError> {
+        let arities = definitions
+            .iter()
+            .map(|(name, lambda)| Ok((name.clone(), arity(name, lambda)?)))
+            .collect::<Result<HashMap<_, _>, EvalError>>()?;
+        let mut names: Vec<&String> = definitions.keys().collect();
+        names.sort();
+        let mut done = Vec::new();
+        for name in names {
+            check_cycles(name, &definitions, &mut Vec::new(), &mut done)?;
+        }
+        for lambda in definitions.values() {
+            check_arities(lambda, &definitions, &arities)?;
+        }
+        let lambdas = definitions
+            .iter()
+            .map(|(name, lambda)| (name.clone(), lambda.inline_functions(&definitions)))
+            .collect();
+        Ok(FunctionDefinitions { lambdas, arities })
+    }
+    /// Replaces every call of a defined function in `node` by the function's
+    /// body, failing on a call with the wrong number of arguments
+    pub fn inline(&self, node: &MathNode) -> Result<MathNode, EvalError> {
+        check_arities(node, &self.lambdas, &self.arities)?;
+        Ok(node.inline_functions(&self.lambdas))
+    }
+}
+
+fn check_arities(
+    node: &MathNode,
+    definitions: &HashMap<String, MathNode>,
+    arities: &HashMap<String, usize>,
+) -> Result<(), EvalError> {
+    for (name, found) in calls(node, definitions) {
+        let expected = arities[name];
+        if found != expected {
+            return Err(EvalError::WrongArity {
+                op: name.to_owned(),
+                expected: expected.to_string(),
+                found,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::FunctionDefinitions;
+    use crate::error::EvalError;
+    use crate::{parse_infix, MathNode};
+    use std::collections::HashMap;
+
+    fn definitions(lambdas: Vec<(&str, &[&str], &str)>) -> HashMap<String, MathNode> {
+        lambdas
+            .into_iter()
+            .map(|(name, vars, body)| {
+                let lambda = MathNode::lambda(vars.to_vec(), parse_infix(body).unwrap());
+                (name.to_owned(), lambda)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_function_definitions() {
+        let functions = FunctionDefinitions::new(definitions(vec![
+            ("mm", &["V", "S", "K"], "V * S / (K + S)"),
+            ("inhibited", &["V", "S", "K", "I"], "mm(V, S, K) / (1 + I)"),
+        ]))
+        .unwrap();
+        let law = parse_infix("inhibited(Vmax, A, Km, B)").unwrap();
+        assert_eq!(
+            functions.inline(&law).unwrap().to_string(),
+            "Vmax * A / (Km + A) / (1 + B)"
+        );
+        assert_eq!(
+            functions
+                .inline(&parse_infix("mm(x)").unwrap())
+                .unwrap_err()
+                .to_string(),
+            "operator 'mm' expects 3 arguments, found 1"
+        );
+
+        let recursive = FunctionDefinitions::new(definitions(vec![
+            ("f", &["x"], "g(x) + 1"),
+            ("g", &["x"], "h(x, x)"),
+            ("h", &["x", "y"], "f(x * y)"),
+        ]));
+        assert_eq!(
+            recursive,
+            Err(EvalError::RecursiveDefinition(vec![
+                "f".to_owned(),
+                "g".to_owned(),
+                "h".to_owned(),
+                "f".to_owned(),
+            ]))
+        );
+    }
+}
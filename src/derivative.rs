@@ -0,0 +1,273 @@
+use super::{BuiltinOp, Dag, MathNode};
+use crate::error::EvalError;
+
+fn apply(op: BuiltinOp, operands: Vec<MathNode>) -> MathNode {
+    MathNode::apply(op, operands)
+}
+
+fn unsupported(what: impl std::fmt::Display) -> EvalError {
+    EvalError::UnsupportedNode(format!("the derivative of {}", what))
+}
+
+/// `d(node)/d(var)`, without simplifying
+fn derive(node: &MathNode, var: &str) -> Result<MathNode, EvalError> {
+    use BuiltinOp as Op;
+    if !node.variables().contains(var) {
+        return Ok(MathNode::integer(0));
+    }
+    if node.as_ci() == Some(var) {
+        return Ok(MathNode::integer(1));
+    }
+    let op = match node {
+        MathNode::Root { .. } => {
+            let mut math = node.clone();
+            for child in math.children_mut().into_iter().filter(|c| !c.is_markup()) {
+                *child = derive(child, var)?;
+            }
+            return Ok(math);
+        }
+        MathNode::Semantics { primary, .. } => return derive(primary, var),
+        MathNode::Piecewise { pieces, otherwise } => {
+            return Ok(MathNode::Piecewise {
+                pieces: pieces
+                    .iter()
+                    .map(|(value, condition)| Ok((derive(value, var)?, condition.clone())))
+                    .collect::<Result<_, EvalError>>()?,
+                otherwise: match otherwise {
+                    Some(value) => Some(Box::new(derive(value, var)?)),
+                    None => None,
+                },
+            });
+        }
+        MathNode::Apply { children, .. } if node.bound_variables().is_empty() => {
+            match children.first() {
+                Some(MathNode::Op(op)) => *op,
+                _ => return Err(unsupported("an application of a non-builtin function")),
+            }
+        }
+        _ => return Err(unsupported(format!("'{}'", node))),
+    };
+    let operands: Vec<MathNode> = node.operands().into_iter().cloned().collect();
+    let d = |i: usize| derive(&operands[i], var);
+    let u = || operands[0].clone();
+    let unary = operands.len() == 1;
+    let derivative = match op {
+        Op::plus => apply(
+            Op::plus,
+            (0..operands.len()).map(d).collect::<Result<_, _>>()?,
+        ),
+        Op::minus => apply(
+            Op::minus,
+            (0..operands.len()).map(d).collect::<Result<_, _>>()?,
+        ),
+        // The product rule, leaving out the factors that don't depend on `var`
+        Op::times => {
+            let mut terms = Vec::new();
+            for (i, factor) in operands.iter().enumerate() {
+                if !factor.variables().contains(var) {
+                    continue;
+                }
+                let mut factors = operands.clone();
+                factors[i] = derive(factor, var)?;
+                terms.push(apply(Op::times, factors));
+            }
+            apply(Op::plus, terms)
+        }
+        Op::divide if operands.len() == 2 => {
+            let v = operands[1].clone();
+            if v.variables().contains(var) {
+                let numerator = apply(
+                    Op::minus,
+                    vec![
+                        apply(Op::times, vec![d(0)?, v.clone()]),
+                        apply(Op::times, vec![u(), d(1)?]),
+                    ],
+                );
+                let square = apply(Op::power, vec![v, MathNode::integer(2)]);
+                apply(Op::divide, vec![numerator, square])
+            } else {
+                apply(Op::divide, vec![d(0)?, v])
+            }
+        }
+        Op::power if operands.len() == 2 => {
+            let v = operands[1].clone();
+            let base_varies = operands[0].variables().contains(var);
+            let exponent_varies = v.variables().contains(var);
+            match (base_varies, exponent_varies) {
+                (true, false) => {
+                    let lowered = apply(Op::minus, vec![v.clone(), MathNode::integer(1)]);
+                    apply(
+                        Op::times,
+                        vec![v, apply(Op::power, vec![u(), lowered]), d(0)?],
+                    )
+                }
+                (false, _) => apply(
+                    Op::times,
+                    vec![node.clone(), apply(Op::ln, vec![u()]), d(1)?],
+                ),
+                (true, true) => {
+                    let inner = apply(
+                        Op::plus,
+                        vec![
+                            apply(Op::times, vec![d(1)?, apply(Op::ln, vec![u()])]),
+                            apply(Op::divide, vec![apply(Op::times, vec![v, d(0)?]), u()]),
+                        ],
+                    );
+                    apply(Op::times, vec![node.clone(), inner])
+                }
+            }
+        }
+        Op::exp if unary => apply(Op::times, vec![node.clone(), d(0)?]),
+        Op::ln if unary => apply(Op::divide, vec![d(0)?, u()]),
+        Op::log if unary => {
+            let base = node
+                .logbase()
+                .cloned()
+                .unwrap_or_else(|| MathNode::integer(10));
+            let scale = apply(Op::times, vec![u(), apply(Op::ln, vec![base])]);
+            apply(Op::divide, vec![d(0)?, scale])
+        }
+        // u^(1/n) gives du / (n * u^((n - 1) / n))
+        Op::root if unary => match node.degree() {
+            None => apply(
+                Op::divide,
+                vec![
+                    d(0)?,
+                    apply(Op::times, vec![MathNode::integer(2), node.clone()]),
+                ],
+            ),
+            Some(n) => {
+                let lowered = apply(
+                    Op::divide,
+                    vec![
+                        apply(Op::minus, vec![n.clone(), MathNode::integer(1)]),
+                        n.clone(),
+                    ],
+                );
+                let scale = apply(
+                    Op::times,
+                    vec![n.clone(), apply(Op::power, vec![u(), lowered])],
+                );
+                apply(Op::divide, vec![d(0)?, scale])
+            }
+        },
+        Op::abs if unary => apply(
+            Op::times,
+            vec![d(0)?, apply(Op::divide, vec![u(), node.clone()])],
+        ),
+        Op::sin if unary => apply(Op::times, vec![apply(Op::cos, vec![u()]), d(0)?]),
+        Op::cos if unary => apply(Op::times, vec![-apply(Op::sin, vec![u()]), d(0)?]),
+        Op::tan | Op::tanh | Op::cot if unary => {
+            let (of, negative) = match op {
+                Op::tan => (Op::cos, false),
+                Op::tanh => (Op::cosh, false),
+                _ => (Op::sin, true),
+            };
+            let square = apply(Op::power, vec![apply(of, vec![u()]), MathNode::integer(2)]);
+            let quotient = apply(Op::divide, vec![d(0)?, square]);
+            if negative {
+                -quotient
+            } else {
+                quotient
+            }
+        }
+        Op::sec if unary => apply(
+            Op::times,
+            vec![node.clone(), apply(Op::tan, vec![u()]), d(0)?],
+        ),
+        Op::csc if unary => -apply(
+            Op::times,
+            vec![node.clone(), apply(Op::cot, vec![u()]), d(0)?],
+        ),
+        Op::sinh if unary => apply(Op::times, vec![apply(Op::cosh, vec![u()]), d(0)?]),
+        Op::cosh if unary => apply(Op::times, vec![apply(Op::sinh, vec![u()]), d(0)?]),
+        Op::arcsin | Op::arccos if unary => {
+            let square = apply(Op::power, vec![u(), MathNode::integer(2)]);
+            let rest = apply(Op::minus, vec![MathNode::integer(1), square]);
+            let quotient = apply(Op::divide, vec![d(0)?, apply(Op::root, vec![rest])]);
+            if op == Op::arccos {
+                -quotient
+            } else {
+                quotient
+            }
+        }
+        Op::arctan if unary => {
+            let square = apply(Op::power, vec![u(), MathNode::integer(2)]);
+            let sum = apply(Op::plus, vec![MathNode::integer(1), square]);
+            apply(Op::divide, vec![d(0)?, sum])
+        }
+        _ => return Err(unsupported(format!("'{}'", op))),
+    };
+    Ok(derivative)
+}
+
+impl MathNode {
+    /// The derivative by `var`, simplified. Other identifiers are constants.
+    /// Fails on what has no derivative that can be written in MathML, like
+    /// `floor`, or isn't differentiated symbolically, like `<sum>`
+    pub fn derivative(&self, var: &str) -> Result<MathNode, EvalError> {
+        Ok(derive(self, var)?.simplify())
+    }
+}
+
+/// The matrix of the derivatives of each of `exprs` (by row) by each of
+/// `vars` (by column), e.g. of an ODE system's right hand sides by its species
+pub fn jacobian(exprs: &[MathNode], vars: &[&str]) -> Result<Vec<Vec<MathNode>>, EvalError> {
+    exprs
+        .iter()
+        .map(|expr| vars.iter().map(|var| expr.derivative(var)).collect())
+        .collect()
+}
+
+/// `jacobian`, with the subexpressions the entries have in common computed
+/// once: the root of the `Dag` is a `<matrix>` of the entries
+pub fn jacobian_dag(exprs: &[MathNode], vars: &[&str]) -> Result<Dag, EvalError> {
+    Ok(MathNode::Matrix(jacobian(exprs, vars)?).to_dag())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{jacobian, jacobian_dag};
+    use crate::{parse_infix, MathNode};
+
+    #[test]
+    fn test_derivative() {
+        let derivative = |text: &str| {
+            parse_infix(text)
+                .unwrap()
+                .derivative("x")
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(derivative("3 * x^2 + k * x + c"), "3 * 2 * x + k");
+        assert_eq!(
+            derivative("Vmax * x / (Km + x)"),
+            "(Vmax * (Km + x) - Vmax * x) / (Km + x)^2"
+        );
+        assert_eq!(derivative("exp(-k * x)"), "exp(-k * x) * -k");
+        assert_eq!(
+            derivative("sin(x) * ln(x)"),
+            "cos(x) * ln(x) + sin(x) * 1 / x"
+        );
+        assert!(parse_infix("floor(x)").unwrap().derivative("x").is_err());
+    }
+
+    #[test]
+    fn test_jacobian() {
+        let rates = vec![
+            parse_infix("-k1 * A * B").unwrap(),
+            parse_infix("k1 * A * B - k2 * B").unwrap(),
+        ];
+        let entries = jacobian(&rates, &["A", "B"]).unwrap();
+        let shown: Vec<Vec<String>> = entries
+            .iter()
+            .map(|row| row.iter().map(MathNode::to_string).collect())
+            .collect();
+        assert_eq!(
+            shown,
+            vec![vec!["-k1 * B", "-k1 * A"], vec!["k1 * B", "k1 * A - k2"],]
+        );
+        let dag = jacobian_dag(&rates, &["A", "B"]).unwrap();
+        assert_eq!(dag.inline(), MathNode::Matrix(entries));
+    }
+}
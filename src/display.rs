@@ -0,0 +1,359 @@
+use super::{BuiltinOp, ConstantSymbol, MathNode, NamedConstant, NumType, PresentationNode};
+use crate::entities;
+use crate::ops::{is_relation, parenthesize, PREC_ATOM, PREC_POWER, PREC_UNARY};
+use crate::strict::encode_base64;
+use std::fmt;
+
+/// A piece of infix text together with the precedence of its outermost operator
+struct Infix {
+    text: String,
+    precedence: u8,
+}
+
+impl Infix {
+    fn new(text: String, precedence: u8) -> Self {
+        Infix { text, precedence }
+    }
+    fn atom(text: String) -> Self {
+        Infix::new(text, PREC_ATOM)
+    }
+    fn wrap(self, min_precedence: u8) -> String {
+        parenthesize(self.text, self.precedence, min_precedence)
+    }
+}
+
+/// Turns a preserved entity like `&tau;` into the bare entity name
+fn plain(text: &str) -> &str {
+    entities::entity_name(text).unwrap_or(text)
+}
+
+fn number(num_type: &NumType) -> Infix {
+    let signed = |text: String, negative: bool| {
+        Infix::new(text, if negative { PREC_UNARY } else { PREC_ATOM })
+    };
+    match num_type {
+        NumType::Real(r) => signed(r.to_string(), *r < 0.0),
+        NumType::Integer(i) => signed(i.to_string(), *i < 0),
+        NumType::Rational(a, b) => Infix::new(format!("{}/{}", a, b), 6),
+        NumType::ComplexCartesian(a, b) => Infix::new(format!("{} + {}i", a, b), 5),
+        NumType::ComplexPolar(a, b) => Infix::new(format!("{} * e^(i * {})", a, b), 6),
+        NumType::Constant(c) => Infix::atom(named_constant(c).to_owned()),
+        NumType::ENotation(a, b) => signed(format!("{}e{}", a, b), *a < 0.0),
+        NumType::Other { text, .. } => signed(text.clone(), text.starts_with('-')),
+        #[cfg(feature = "bigint")]
+        NumType::BigInteger(i) => signed(i.to_string(), i.sign() == num_bigint::Sign::Minus),
+    }
+}
+
+fn constant(constant: &ConstantSymbol) -> &'static str {
+    match constant {
+        ConstantSymbol::Pi => "pi",
+        ConstantSymbol::ExponentialE => "e",
+        ConstantSymbol::ImaginaryI => "i",
+        ConstantSymbol::EulerGamma => "gamma",
+        ConstantSymbol::Infinity => "inf",
+        ConstantSymbol::NotANumber => "NaN",
+        ConstantSymbol::True => "true",
+        ConstantSymbol::False => "false",
+    }
+}
+
+fn named_constant(constant: &NamedConstant) -> &str {
+    match constant {
+        NamedConstant::Pi => "pi",
+        NamedConstant::Tau => "tau",
+        NamedConstant::E => "e",
+        NamedConstant::Gamma => "gamma",
+        NamedConstant::GoldenRatio => "phi",
+        NamedConstant::Custom(text) => plain(text),
+    }
+}
+
+/// The symbol of operators written infix between their arguments
+fn infix_symbol(op: &BuiltinOp) -> Option<&'static str> {
+    use BuiltinOp::*;
+    let symbol = match op {
+        plus => " + ",
+        minus => " - ",
+        times => " * ",
+        divide => " / ",
+        rem => " % ",
+        and => " && ",
+        or => " || ",
+        xor => " xor ",
+        implies => " => ",
+        equivalent => " <=> ",
+        approx => " ~ ",
+        eq => " == ",
+        neq => " != ",
+        gt => " > ",
+        lt => " < ",
+        geq => " >= ",
+        leq => " <= ",
+        _ => return None,
+    };
+    Some(symbol)
+}
+
+fn call(name: &str, args: Vec<String>) -> Infix {
+    Infix::atom(format!("{}({})", name, args.join(", ")))
+}
+
+fn list(nodes: &[MathNode]) -> Vec<String> {
+    nodes.iter().map(|n| render(n).text).collect()
+}
+
+/// Operators with bound variables are written as calls, the body first and then
+/// each variable with its range, e.g. `sum(i^2, i = 1..n)`
+fn binder(op: &BuiltinOp, apply: &MathNode, args: &[&MathNode]) -> Infix {
+    let mut parts: Vec<String> = args.iter().map(|a| render(a).text).collect();
+    let limits = apply
+        .limits()
+        .map(|(low, high)| format!(" = {}..{}", render(low).text, render(high).text));
+    for (name, degree) in apply.bvars() {
+        let var = match degree {
+            Some(degree) => format!("{}^{}", name, render(degree).wrap(PREC_ATOM)),
+            None => name.to_owned(),
+        };
+        parts.push(format!("{}{}", var, limits.clone().unwrap_or_default()));
+    }
+    if let Some(condition) = apply.condition() {
+        parts.push(render(condition).text);
+    }
+    call(&op.to_string(), parts)
+}
+
+fn render_op(op: &BuiltinOp, apply: &MathNode, args: &[&MathNode]) -> Infix {
+    use BuiltinOp::*;
+    let prec = op.precedence();
+    let operand = |i: usize| {
+        args.get(i)
+            .map(|a| render(a))
+            .unwrap_or_else(|| Infix::atom(String::new()))
+    };
+    let all = |min_prec: u8| {
+        args.iter()
+            .map(|a| render(a).wrap(min_prec))
+            .collect::<Vec<_>>()
+    };
+    if !apply.bound_variables().is_empty() {
+        return binder(op, apply, args);
+    }
+    match (op, apply.degree(), apply.logbase()) {
+        (root, Some(degree), _) => {
+            return call("root", vec![render(degree).text, operand(0).text]);
+        }
+        (log, _, Some(base)) => return call("log", vec![render(base).text, operand(0).text]),
+        _ => {}
+    }
+    match op {
+        minus if args.len() == 1 => {
+            Infix::new(format!("-{}", operand(0).wrap(PREC_POWER)), PREC_UNARY)
+        }
+        minus | divide => Infix::new(
+            format!(
+                "{}{}{}",
+                operand(0).wrap(prec),
+                infix_symbol(op).unwrap_or_default(),
+                operand(1).wrap(prec + 1)
+            ),
+            prec,
+        ),
+        // Exponentiation is right associative
+        power => Infix::new(
+            format!(
+                "{}^{}",
+                operand(0).wrap(PREC_POWER + 1),
+                operand(1).wrap(PREC_POWER)
+            ),
+            PREC_POWER,
+        ),
+        not => Infix::new(format!("!{}", operand(0).wrap(PREC_UNARY)), PREC_UNARY),
+        factorial => Infix::new(format!("{}!", operand(0).wrap(PREC_ATOM)), prec),
+        root => call("sqrt", all(0)),
+        _ => match infix_symbol(op) {
+            Some(symbol) if is_relation(op) => Infix::new(all(prec + 1).join(symbol), prec),
+            Some(symbol) => Infix::new(all(prec).join(symbol), prec),
+            None => call(&op.to_string(), all(0)),
+        },
+    }
+}
+
+/// Presentation markup has no operator structure, so its tokens are written in order
+fn presentation(node: &PresentationNode) -> String {
+    use PresentationNode::*;
+    let row = |children: &[PresentationNode]| {
+        children
+            .iter()
+            .map(presentation)
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    match node {
+        Mi(text) | Mn(text) | Mo(text) | Mtext(text) => plain(text).to_owned(),
+        Mspace => String::new(),
+        Mglyph { alt, .. } => plain(alt).to_owned(),
+        Mrow(children) => row(children),
+        Mfrac(a, b) => format!("({}) / ({})", presentation(a), presentation(b)),
+        Msqrt(children) => format!("sqrt({})", row(children)),
+        Mroot(base, index) => format!("root({}, {})", presentation(index), presentation(base)),
+        Msup(base, sup) => format!("{}^({})", presentation(base), presentation(sup)),
+        Msub(base, sub) => format!("{}_({})", presentation(base), presentation(sub)),
+        Msubsup(base, sub, sup) => format!(
+            "{}_({})^({})",
+            presentation(base),
+            presentation(sub),
+            presentation(sup)
+        ),
+        Mfenced {
+            open,
+            close,
+            children,
+        } => format!(
+            "{}{}{}",
+            open,
+            children
+                .iter()
+                .map(presentation)
+                .collect::<Vec<_>>()
+                .join(", "),
+            close
+        ),
+        Mtable(rows) => rows
+            .iter()
+            .map(|r| {
+                format!(
+                    "[{}]",
+                    r.iter().map(presentation).collect::<Vec<_>>().join(", ")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+    }
+}
+
+fn render(node: &MathNode) -> Infix {
+    match node {
+        MathNode::Root { children, .. } => Infix::atom(
+            children
+                .iter()
+                .filter(|c| !c.is_markup())
+                .map(|c| render(c).text)
+                .collect::<Vec<_>>()
+                .join("; "),
+        ),
+        MathNode::Apply { children, .. } => {
+            let operands = node.operands();
+            match children.first() {
+                Some(MathNode::Op(op)) => render_op(op, node, &operands),
+                Some(head) => Infix::atom(format!(
+                    "{}({})",
+                    render(head).wrap(PREC_ATOM),
+                    operands
+                        .iter()
+                        .map(|a| render(a).text)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+                None => Infix::atom(String::new()),
+            }
+        }
+        MathNode::Op(op) => Infix::atom(op.to_string()),
+        MathNode::Text(t) => Infix::atom(plain(t).to_owned()),
+        MathNode::Ci { children, .. } => {
+            Infix::atom(plain(&crate::text_content(children)).to_owned())
+        }
+        MathNode::Csymbol { name, .. } => Infix::atom(name.clone()),
+        MathNode::Cn { num_type, .. } => number(num_type),
+        MathNode::Constant(c) => Infix::atom(constant(c).to_owned()),
+        MathNode::NumberSet(set) => Infix::atom(set.to_string()),
+        MathNode::Comment(_) | MathNode::PI(_, _) => Infix::atom(String::new()),
+        MathNode::Error { .. } => Infix::atom("?".to_owned()),
+        MathNode::Cs(text) => Infix::atom(format!("{:?}", text)),
+        MathNode::Cbytes(bytes) => call("cbytes", vec![encode_base64(bytes)]),
+        MathNode::Cerror(children) => call("cerror", list(children)),
+        MathNode::Share(href) => call("share", vec![href.clone()]),
+        MathNode::Unknown { name, children, .. } => call(name, list(children)),
+        MathNode::Presentation(node) => Infix::atom(presentation(node)),
+        MathNode::Piecewise { pieces, otherwise } => {
+            let mut args = Vec::new();
+            for (value, condition) in pieces {
+                args.push(render(value).text);
+                args.push(render(condition).text);
+            }
+            if let Some(otherwise) = otherwise {
+                args.push(render(otherwise).text);
+            }
+            call("piecewise", args)
+        }
+        MathNode::Bind { .. } => render(&node.to_apply()),
+        MathNode::Lambda { bound_vars, body } => {
+            let mut args = bound_vars.clone();
+            args.push(render(body).text);
+            call("lambda", args)
+        }
+        MathNode::Degree(inner)
+        | MathNode::Logbase(inner)
+        | MathNode::Lowlimit(inner)
+        | MathNode::Uplimit(inner)
+        | MathNode::Condition(inner) => render(inner),
+        MathNode::Bvar { name, .. } => Infix::atom(name.clone()),
+        MathNode::Interval { closure, low, high } => {
+            let (open, close) = closure.brackets();
+            Infix::atom(format!(
+                "{}{}, {}{}",
+                open,
+                render(low).text,
+                render(high).text,
+                close
+            ))
+        }
+        MathNode::Matrix(rows) => call(
+            "matrix",
+            rows.iter()
+                .map(|row| format!("[{}]", list(row).join(", ")))
+                .collect(),
+        ),
+        MathNode::Vector(children) => call("vector", list(children)),
+        MathNode::Set(items) => Infix::atom(format!("{{{}}}", list(items).join(", "))),
+        MathNode::List { items, .. } => Infix::atom(format!("[{}]", list(items).join(", "))),
+        MathNode::Semantics { primary, .. } => render(primary),
+    }
+}
+
+impl fmt::Display for MathNode {
+    /// Writes the expression in infix notation, e.g. `a * x + b`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&render(self).text)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parse_document;
+
+    #[test]
+    fn test_display() {
+        let test = r#"<apply><plus/>
+                        <apply><times/><ci>a</ci><ci>x</ci></apply>
+                        <apply><divide/><ci>b</ci><apply><times/><ci>c</ci><ci>d</ci></apply></apply>
+                        <apply><power/><apply><minus/><ci>x</ci></apply><cn>2</cn></apply>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(parsed.to_string(), "a * x + b / (c * d) + (-x)^2");
+
+        let test = r#"<piecewise>
+                        <piece><apply><sin/><ci>x</ci></apply><apply><lt/><ci>x</ci><cn>0</cn></apply></piece>
+                        <otherwise><apply><root/><degree><cn>3</cn></degree><ci>x</ci></apply></otherwise>
+                      </piecewise>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(parsed.to_string(), "piecewise(sin(x), x < 0, root(3, x))");
+
+        let test = r#"<apply><sum/><bvar><ci>i</ci></bvar>
+                        <lowlimit><cn>1</cn></lowlimit><uplimit><ci>n</ci></uplimit>
+                        <apply><power/><ci>i</ci><cn>2</cn></apply>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(parsed.to_string(), "sum(i^2, i = 1..n)");
+    }
+}
@@ -0,0 +1,140 @@
+use super::MathNode;
+
+/// Escapes a label for a double quoted DOT string
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// The label of a node, and whether it's drawn as a leaf
+fn label(node: &MathNode) -> (String, bool) {
+    match node {
+        MathNode::Root { .. } => ("math".to_owned(), false),
+        MathNode::Apply { children, .. } => match children.first() {
+            Some(MathNode::Op(op)) => (op.to_string(), false),
+            _ => ("apply".to_owned(), false),
+        },
+        MathNode::Op(op) => (op.to_string(), true),
+        MathNode::Ci { children, .. } => (crate::text_content(children), true),
+        MathNode::Csymbol { name, .. } => (name.trim().to_owned(), true),
+        MathNode::Cn { .. } | MathNode::Constant(_) | MathNode::NumberSet(_) => {
+            (node.to_string(), true)
+        }
+        MathNode::Text(text) => (text.clone(), true),
+        MathNode::Comment(_) | MathNode::PI(_, _) => (String::new(), true),
+        MathNode::Error { message, .. } => (format!("error: {}", message), true),
+        MathNode::Unknown { name, .. } => (format!("<{}>", name), false),
+        MathNode::Cs(text) => (format!("{:?}", text), true),
+        MathNode::Cbytes(bytes) => (format!("{} bytes", bytes.len()), true),
+        MathNode::Cerror(_) => ("cerror".to_owned(), false),
+        MathNode::Bind { bound_vars, .. } => (format!("bind {}", bound_vars.join(", ")), false),
+        MathNode::Share(href) => (format!("share {}", href), true),
+        MathNode::Presentation(_) => ("presentation".to_owned(), true),
+        MathNode::Piecewise { .. } => ("piecewise".to_owned(), false),
+        MathNode::Lambda { bound_vars, .. } => (format!("lambda {}", bound_vars.join(", ")), false),
+        MathNode::Degree(_) => ("degree".to_owned(), false),
+        MathNode::Logbase(_) => ("logbase".to_owned(), false),
+        MathNode::Bvar { name, degree } => (format!("bvar {}", name), degree.is_none()),
+        MathNode::Lowlimit(_) => ("lowlimit".to_owned(), false),
+        MathNode::Uplimit(_) => ("uplimit".to_owned(), false),
+        MathNode::Condition(_) => ("condition".to_owned(), false),
+        MathNode::Interval { closure, .. } => {
+            let (open, close) = closure.brackets();
+            (format!("interval {}{}", open, close), false)
+        }
+        MathNode::Matrix(rows) => (
+            format!(
+                "matrix {}×{}",
+                rows.len(),
+                rows.first().map_or(0, |row| row.len())
+            ),
+            false,
+        ),
+        MathNode::Vector(_) => ("vector".to_owned(), false),
+        MathNode::Set(_) => ("set".to_owned(), false),
+        MathNode::List { .. } => ("list".to_owned(), false),
+        MathNode::Semantics { .. } => ("semantics".to_owned(), false),
+    }
+}
+
+struct Graph {
+    text: String,
+    nodes: usize,
+}
+
+impl Graph {
+    /// Adds the node and its subtree, returning the node's id
+    fn add(&mut self, node: &MathNode) -> usize {
+        let id = self.nodes;
+        self.nodes += 1;
+        let (label, leaf) = label(node);
+        let shape = if leaf { ", shape=box" } else { "" };
+        self.text += &format!("  n{} [label=\"{}\"{}];\n", id, escape(&label), shape);
+
+        let mut children = node.children();
+        // The operator is the label of its `<apply>`
+        if let (MathNode::Apply { .. }, Some(MathNode::Op(_))) = (node, children.first()) {
+            children.remove(0);
+        }
+        let pieces = match node {
+            MathNode::Piecewise { pieces, .. } => pieces.len(),
+            _ => 0,
+        };
+        for (i, child) in children.into_iter().enumerate() {
+            if child.is_markup() {
+                continue;
+            }
+            let child_id = self.add(child);
+            // Tell the values of pieces from their conditions
+            let edge = match i {
+                i if i < 2 * pieces && i % 2 == 1 => " [label=\"if\"]",
+                i if i == 2 * pieces && pieces > 0 => " [label=\"otherwise\"]",
+                _ => "",
+            };
+            self.text += &format!("  n{} -> n{}{};\n", id, child_id, edge);
+        }
+        id
+    }
+}
+
+impl MathNode {
+    /// Writes the tree as a Graphviz digraph, with operators labelling the
+    /// `<apply>`s and tokens like `<ci>` and `<cn>` drawn as boxes. Operands
+    /// are laid out in order, e.g. for `dot -Tsvg`
+    pub fn to_dot(&self) -> String {
+        let mut graph = Graph {
+            text: "digraph {\n  ordering=out;\n".to_owned(),
+            nodes: 0,
+        };
+        graph.add(self);
+        graph.text + "}\n"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parse_document;
+
+    #[test]
+    fn test_to_dot() {
+        let test =
+            r#"<apply><divide/><ci>V</ci><apply><plus/><ci>K"m</ci><cn>1</cn></apply></apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(
+            parsed.to_dot(),
+            "digraph {\n  ordering=out;\n  n0 [label=\"divide\"];\n  n1 [label=\"V\", shape=box];\n  \
+             n0 -> n1;\n  n2 [label=\"plus\"];\n  n3 [label=\"K\\\"m\", shape=box];\n  n2 -> n3;\n  \
+             n4 [label=\"1\", shape=box];\n  n2 -> n4;\n  n0 -> n2;\n}\n"
+        );
+
+        let test = r#"<piecewise>
+                        <piece><cn>0</cn><apply><lt/><ci>t</ci><cn>0</cn></apply></piece>
+                        <otherwise><ci>t</ci></otherwise>
+                      </piecewise>"#;
+        let dot = parse_document(test).unwrap().to_dot();
+        assert!(dot.contains("n0 -> n2 [label=\"if\"];"));
+        assert!(dot.contains("n0 -> n5 [label=\"otherwise\"];"));
+    }
+}
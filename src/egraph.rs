@@ -0,0 +1,495 @@
+use super::{BuiltinOp, MathNode, Pattern, SimplifyRules};
+use std::collections::{HashMap, HashSet};
+
+/// How many rounds of rewriting `saturate` runs at most
+const MAX_ITERATIONS: usize = 12;
+/// The size the e-graph may grow to before `saturate` stops rewriting
+const MAX_NODES: usize = 1_000;
+
+/// Rewrites that hold for every expression
+const ALGEBRA: &[(&str, &str)] = &[
+    ("_a + _b", "_b + _a"),
+    ("_a * _b", "_b * _a"),
+    ("(_a + _b) + _c", "_a + (_b + _c)"),
+    ("_a + (_b + _c)", "(_a + _b) + _c"),
+    ("(_a * _b) * _c", "_a * (_b * _c)"),
+    ("_a * (_b * _c)", "(_a * _b) * _c"),
+];
+
+/// The rewrites of `SimplifyRules::identities`, and factoring
+const IDENTITIES: &[(&str, &str)] = &[
+    ("_a + 0", "_a"),
+    ("_a * 1", "_a"),
+    ("--_a", "_a"),
+    ("_a - 0", "_a"),
+    ("0 - _a", "-_a"),
+    ("_a / 1", "_a"),
+    ("_a^1", "_a"),
+    ("_a^0", "1"),
+    ("1^_a", "1"),
+    ("ln(exp(_a))", "_a"),
+    ("_b * _a + _c * _a", "(_b + _c) * _a"),
+    ("_a + _a", "2 * _a"),
+];
+
+/// The rewrites of `SimplifyRules::unsafe_float`
+const UNSAFE_FLOAT: &[(&str, &str)] = &[
+    ("_a * 0", "0"),
+    ("_a - _a", "0"),
+    ("_a / _a", "1"),
+    ("exp(ln(_a))", "_a"),
+];
+
+type Id = usize;
+type Subst = HashMap<String, Id>;
+/// The cheapest node of each class, with its cost and the order of its
+/// children that breaks ties
+type Choices<'a> = HashMap<Id, (usize, Vec<(bool, Id)>, &'a ENode)>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Head {
+    Op(BuiltinOp),
+    /// An index into `EGraph::leaves`
+    Leaf(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ENode {
+    head: Head,
+    children: Vec<Id>,
+}
+
+/// The left hand or right hand side of a rule
+enum Pat {
+    Var(String),
+    Op(BuiltinOp, Vec<Pat>),
+    Leaf(MathNode),
+}
+
+/// The operator and operands of an `<apply>` of a builtin operator without
+/// qualifiers, the only nodes the e-graph looks into
+fn plain_apply(node: &MathNode) -> Option<(BuiltinOp, Vec<&MathNode>)> {
+    let children = match node {
+        MathNode::Apply { children, .. } => children,
+        _ => return None,
+    };
+    let operands = node.operands();
+    match children.iter().find(|c| !c.is_markup()) {
+        Some(MathNode::Op(op))
+            if children.iter().filter(|c| !c.is_markup()).count() == operands.len() + 1 =>
+        {
+            Some((*op, operands))
+        }
+        _ => None,
+    }
+}
+
+/// Whether `leaf` matches `expected`, comparing numbers by value
+fn same_leaf(leaf: &MathNode, expected: &MathNode) -> bool {
+    match (leaf, expected) {
+        (MathNode::Cn { .. }, MathNode::Cn { .. }) => {
+            let empty = HashMap::new();
+            match (leaf.eval(&empty), expected.eval(&empty)) {
+                (Ok(x), Ok(y)) => x == y,
+                _ => leaf == expected,
+            }
+        }
+        _ => leaf == expected,
+    }
+}
+
+impl Pat {
+    fn parse(text: &str) -> Pat {
+        let pattern = Pattern::parse(text).expect("the built in rules parse");
+        Pat::from_pattern(&pattern)
+    }
+    fn from_pattern(pattern: &Pattern) -> Pat {
+        match pattern {
+            Pattern::Any(name) => Pat::Var(name.clone()),
+            Pattern::Apply(head, operands) => match &**head {
+                Pattern::Exact(MathNode::Op(op)) => {
+                    Pat::Op(*op, operands.iter().map(Pat::from_pattern).collect())
+                }
+                _ => unreachable!("the built in rules only apply operators"),
+            },
+            Pattern::Exact(node) => Pat::from_node(node),
+            _ => unreachable!("the built in rules only use `Any` wildcards"),
+        }
+    }
+    fn from_node(node: &MathNode) -> Pat {
+        match plain_apply(node) {
+            Some((op, operands)) => Pat::Op(op, operands.into_iter().map(Pat::from_node).collect()),
+            None => Pat::Leaf(node.clone()),
+        }
+    }
+}
+
+/// Equivalence classes of expressions, kept closed under congruence: two
+/// applications of one operator to operands of the same classes are in the
+/// same class
+struct EGraph {
+    /// The union-find forest of class ids
+    parents: Vec<Id>,
+    /// The nodes of each class, kept for the roots of `parents`
+    classes: Vec<Vec<ENode>>,
+    memo: HashMap<ENode, Id>,
+    /// Everything the e-graph doesn't look into: numbers, identifiers,
+    /// constants and nodes other than operator applications
+    leaves: Vec<MathNode>,
+}
+
+impl EGraph {
+    fn new() -> Self {
+        EGraph {
+            parents: Vec::new(),
+            classes: Vec::new(),
+            memo: HashMap::new(),
+            leaves: Vec::new(),
+        }
+    }
+    fn find(&self, mut id: Id) -> Id {
+        while self.parents[id] != id {
+            id = self.parents[id];
+        }
+        id
+    }
+    fn roots(&self) -> Vec<Id> {
+        (0..self.parents.len())
+            .filter(|&id| self.find(id) == id)
+            .collect()
+    }
+    fn size(&self) -> usize {
+        self.memo.len()
+    }
+    fn canonical(&self, node: &ENode) -> ENode {
+        ENode {
+            head: node.head.clone(),
+            children: node.children.iter().map(|&c| self.find(c)).collect(),
+        }
+    }
+    fn add(&mut self, node: ENode) -> Id {
+        let node = self.canonical(&node);
+        if let Some(&id) = self.memo.get(&node) {
+            return self.find(id);
+        }
+        let id = self.parents.len();
+        self.parents.push(id);
+        self.classes.push(vec![node.clone()]);
+        self.memo.insert(node, id);
+        id
+    }
+    fn add_leaf(&mut self, leaf: MathNode) -> Id {
+        let index = match self.leaves.iter().position(|l| *l == leaf) {
+            Some(index) => index,
+            None => {
+                self.leaves.push(leaf);
+                self.leaves.len() - 1
+            }
+        };
+        self.add(ENode {
+            head: Head::Leaf(index),
+            children: Vec::new(),
+        })
+    }
+    /// Adds an application, nesting `plus` and `times` of more than two
+    /// operands to the left so that associativity can regroup them
+    fn add_op(&mut self, op: BuiltinOp, children: Vec<Id>) -> Id {
+        if matches!(op, BuiltinOp::plus | BuiltinOp::times) && children.len() > 2 {
+            let mut children = children.into_iter();
+            let first = children.next().unwrap_or_default();
+            return children.fold(first, |lhs, rhs| self.add_op(op, vec![lhs, rhs]));
+        }
+        self.add(ENode {
+            head: Head::Op(op),
+            children,
+        })
+    }
+    fn add_expr(&mut self, node: &MathNode, rules: &SimplifyRules) -> Id {
+        match plain_apply(node) {
+            Some((op, operands)) => {
+                let children = operands
+                    .into_iter()
+                    .map(|o| self.add_expr(o, rules))
+                    .collect();
+                self.add_op(op, children)
+            }
+            None => self.add_leaf(node.saturate_with(rules)),
+        }
+    }
+    fn union(&mut self, a: Id, b: Id) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+        self.parents[b] = a;
+        let nodes = std::mem::take(&mut self.classes[b]);
+        self.classes[a].extend(nodes);
+        true
+    }
+    /// Restores congruence after unions, merging the classes that now hold
+    /// the same node
+    fn rebuild(&mut self) {
+        loop {
+            self.memo.clear();
+            let mut merges = Vec::new();
+            for id in self.roots() {
+                let mut seen = HashSet::new();
+                let nodes: Vec<ENode> = self.classes[id]
+                    .iter()
+                    .map(|n| self.canonical(n))
+                    .filter(|n| seen.insert(n.clone()))
+                    .collect();
+                for node in &nodes {
+                    match self.memo.get(node) {
+                        Some(&other) => merges.push((other, id)),
+                        None => {
+                            self.memo.insert(node.clone(), id);
+                        }
+                    }
+                }
+                self.classes[id] = nodes;
+            }
+            if merges.is_empty() {
+                return;
+            }
+            for (a, b) in merges {
+                self.union(a, b);
+            }
+        }
+    }
+    /// Every way `pat` matches something in class `id`, extending `subst`
+    fn ematch(&self, pat: &Pat, id: Id, subst: &Subst, found: &mut Vec<Subst>) {
+        let id = self.find(id);
+        match pat {
+            Pat::Var(name) => match subst.get(name) {
+                Some(&bound) if self.find(bound) != id => {}
+                Some(_) => found.push(subst.clone()),
+                None => {
+                    let mut extended = subst.clone();
+                    extended.insert(name.clone(), id);
+                    found.push(extended);
+                }
+            },
+            Pat::Leaf(expected) => {
+                let matches = self.classes[id].iter().any(|n| match n.head {
+                    Head::Leaf(index) => same_leaf(&self.leaves[index], expected),
+                    _ => false,
+                });
+                if matches {
+                    found.push(subst.clone());
+                }
+            }
+            Pat::Op(op, pats) => {
+                for node in &self.classes[id] {
+                    if node.head != Head::Op(*op) || node.children.len() != pats.len() {
+                        continue;
+                    }
+                    let mut substs = vec![subst.clone()];
+                    for (pat, &child) in pats.iter().zip(&node.children) {
+                        let mut next = Vec::new();
+                        for subst in &substs {
+                            self.ematch(pat, child, subst, &mut next);
+                        }
+                        substs = next;
+                    }
+                    found.extend(substs);
+                }
+            }
+        }
+    }
+    fn instantiate(&mut self, pat: &Pat, subst: &Subst) -> Id {
+        match pat {
+            Pat::Var(name) => subst[name],
+            Pat::Leaf(leaf) => self.add_leaf(leaf.clone()),
+            Pat::Op(op, pats) => {
+                let children = pats.iter().map(|p| self.instantiate(p, subst)).collect();
+                self.add_op(*op, children)
+            }
+        }
+    }
+    /// The number or truth value class `id` holds, if any
+    fn constant(&self, id: Id) -> Option<&MathNode> {
+        self.classes[self.find(id)]
+            .iter()
+            .find_map(|n| match n.head {
+                Head::Leaf(index) => match &self.leaves[index] {
+                    leaf @ MathNode::Cn { .. } => Some(leaf),
+                    _ => None,
+                },
+                _ => None,
+            })
+    }
+    /// The applications whose operands are all numbers, with their value
+    fn folds(&self) -> Vec<(Id, MathNode)> {
+        let mut folds = Vec::new();
+        for id in self.roots() {
+            for node in &self.classes[id] {
+                let op = match node.head {
+                    Head::Op(op) => op,
+                    _ => continue,
+                };
+                let operands: Option<Vec<MathNode>> = node
+                    .children
+                    .iter()
+                    .map(|&c| self.constant(c).cloned())
+                    .collect();
+                if let Some(operands) = operands {
+                    let applied = MathNode::apply(op, operands.clone());
+                    if let Some(folded) = crate::simplify::fold(&op, &applied, &operands) {
+                        folds.push((id, folded));
+                    }
+                }
+            }
+        }
+        folds
+    }
+    /// Applies every rule everywhere it matches, until nothing changes or a
+    /// limit is reached
+    fn saturate(&mut self, rules: &[(Pat, Pat)], fold_constants: bool) {
+        for _ in 0..MAX_ITERATIONS {
+            let mut matches = Vec::new();
+            for id in self.roots() {
+                for (lhs, rhs) in rules {
+                    let mut found = Vec::new();
+                    self.ematch(lhs, id, &Subst::new(), &mut found);
+                    matches.extend(found.into_iter().map(|subst| (id, rhs, subst)));
+                }
+            }
+            let mut changed = false;
+            for (id, rhs, subst) in matches {
+                if self.size() > MAX_NODES {
+                    break;
+                }
+                let rewritten = self.instantiate(rhs, &subst);
+                changed |= self.union(id, rewritten);
+            }
+            if fold_constants {
+                for (id, folded) in self.folds() {
+                    let folded = self.add_leaf(folded);
+                    changed |= self.union(id, folded);
+                }
+            }
+            self.rebuild();
+            if !changed || self.size() > MAX_NODES {
+                return;
+            }
+        }
+    }
+    /// The expression of class `root` with the fewest nodes
+    fn extract(&self, root: Id) -> MathNode {
+        let mut best: Choices = HashMap::new();
+        let roots = self.roots();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &id in &roots {
+                for node in &self.classes[id] {
+                    let cost: Option<usize> = node
+                        .children
+                        .iter()
+                        .map(|&c| best.get(&self.find(c)).map(|(cost, _, _)| *cost))
+                        .sum();
+                    let cost = match cost {
+                        Some(cost) => cost + 1,
+                        None => continue,
+                    };
+                    // Ties go to numbers first, then to what was added first
+                    let order: Vec<(bool, Id)> = node
+                        .children
+                        .iter()
+                        .map(|&c| (self.constant(c).is_none(), self.find(c)))
+                        .collect();
+                    let better = best.get(&id).is_none_or(|(current, current_order, _)| {
+                        (cost, &order) < (*current, current_order)
+                    });
+                    if better {
+                        best.insert(id, (cost, order, node));
+                        changed = true;
+                    }
+                }
+            }
+        }
+        self.build(&best, root)
+    }
+    fn build(&self, best: &Choices, id: Id) -> MathNode {
+        let node = best[&self.find(id)].2;
+        let op = match node.head {
+            Head::Leaf(index) => return self.leaves[index].clone(),
+            Head::Op(op) => op,
+        };
+        let mut operands = Vec::new();
+        for &child in &node.children {
+            let child = self.build(best, child);
+            // Flattens the nesting `add_op` introduced
+            match plain_apply(&child) {
+                Some((inner, inner_operands))
+                    if inner == op && matches!(op, BuiltinOp::plus | BuiltinOp::times) =>
+                {
+                    operands.extend(inner_operands.into_iter().cloned())
+                }
+                _ => operands.push(child),
+            }
+        }
+        MathNode::apply(op, operands)
+    }
+}
+
+impl MathNode {
+    /// Simplifies by equality saturation: puts the tree into an e-graph,
+    /// applies commutativity, associativity and the rewrites of `rules`
+    /// everywhere at once until nothing new comes out, and picks the smallest
+    /// expression found. Slower than `simplify_with`, but it finds
+    /// simplifications that need a detour, e.g. `2 * x + 3 * x` to `5 * x`
+    pub fn saturate_with(&self, rules: &SimplifyRules) -> MathNode {
+        if plain_apply(self).is_none() {
+            let mut node = self.clone();
+            for child in node.children_mut() {
+                *child = child.saturate_with(rules);
+            }
+            return node;
+        }
+        let mut texts = ALGEBRA.to_vec();
+        if rules.identities {
+            texts.extend_from_slice(IDENTITIES);
+        }
+        if rules.unsafe_float {
+            texts.extend_from_slice(UNSAFE_FLOAT);
+        }
+        let parsed: Vec<(Pat, Pat)> = texts
+            .iter()
+            .map(|(lhs, rhs)| (Pat::parse(lhs), Pat::parse(rhs)))
+            .collect();
+        let mut egraph = EGraph::new();
+        let root = egraph.add_expr(self, rules);
+        egraph.saturate(&parsed, rules.fold_constants);
+        egraph.extract(root)
+    }
+    /// `saturate_with` the default `SimplifyRules`
+    pub fn saturate(&self) -> MathNode {
+        self.saturate_with(&SimplifyRules::default())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{math, parse_infix, SimplifyRules};
+
+    #[test]
+    fn test_saturate() {
+        assert_eq!(math!(2 * x + 3 * x).saturate().to_string(), "5 * x");
+        assert_eq!(
+            parse_infix("(a + 0) * 1 + (b + 2) + 1")
+                .unwrap()
+                .saturate()
+                .to_string(),
+            "3 + a + b"
+        );
+        let test = parse_infix("(x - x) * y + z / z").unwrap();
+        assert_eq!(test.saturate(), test);
+        let rules = SimplifyRules {
+            unsafe_float: true,
+            ..SimplifyRules::default()
+        };
+        assert_eq!(test.saturate_with(&rules).to_string(), "1");
+    }
+}
@@ -0,0 +1,227 @@
+use crate::{Arity, BuiltinOp, Span};
+use std::fmt;
+
+/// Everything that can go wrong while turning a document into a `MathNode` tree
+#[derive(Debug)]
+pub enum ParseError {
+    /// The input is not well-formed XML
+    Xml(roxmltree::Error),
+    /// A tag that is not part of the supported MathML subset
+    UnknownElement(String),
+    /// A required attribute was not present on an element
+    MissingAttribute { element: String, attribute: String },
+    /// An attribute was present but its value could not be used
+    InvalidAttribute {
+        element: String,
+        attribute: String,
+        value: String,
+    },
+    /// The text of a `<cn>` element could not be parsed as the declared type
+    InvalidNumber { num_type: String, text: String },
+    /// A `<cn>` element declared a `type` we don't know about
+    UnknownNumberType(String),
+    /// An element did not have the children or text it needs
+    InvalidStructure(String),
+    /// An element from another XML vocabulary where MathML was expected
+    ForeignElement { name: String, namespace: String },
+    /// Elements were nested deeper than `ParserOptions::max_depth` allows
+    TooDeep(usize),
+    /// An infix formula that `parse_infix` couldn't read, at this byte offset
+    InvalidFormula { position: usize, message: String },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Xml(e) => write!(f, "invalid XML: {}", e),
+            ParseError::UnknownElement(name) => write!(f, "unknown element <{}>", name),
+            ParseError::MissingAttribute { element, attribute } => write!(
+                f,
+                "element <{}> is missing required attribute '{}'",
+                element, attribute
+            ),
+            ParseError::InvalidAttribute {
+                element,
+                attribute,
+                value,
+            } => write!(
+                f,
+                "invalid value '{}' for attribute '{}' of <{}>",
+                value, attribute, element
+            ),
+            ParseError::InvalidNumber { num_type, text } => {
+                write!(
+                    f,
+                    "cannot parse '{}' as a number of type {}",
+                    text, num_type
+                )
+            }
+            ParseError::UnknownNumberType(t) => write!(f, "unknown number type '{}'", t),
+            ParseError::InvalidStructure(msg) => write!(f, "invalid structure: {}", msg),
+            ParseError::ForeignElement { name, namespace } => write!(
+                f,
+                "element <{}> is in namespace '{}', not MathML",
+                name, namespace
+            ),
+            ParseError::TooDeep(max) => write!(f, "elements nested deeper than {} levels", max),
+            ParseError::InvalidFormula { position, message } => {
+                write!(f, "invalid formula at offset {}: {}", position, message)
+            }
+        }
+    }
+}
+
+/// A problem that `parse_document_recovering` skipped over
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDiagnostic {
+    pub message: String,
+    pub span: Span,
+}
+
+/// A problem `MathNode::validate` found with an `<apply>`, which `path` leads
+/// to from the root as indices into `children()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// An `<apply>` with nothing in it
+    EmptyApply { path: Vec<usize> },
+    /// An `<apply>` starting with something that can't be applied, e.g. the
+    /// `<cn>` of `<apply><cn>2</cn><ci>x</ci></apply>`
+    NotAnOperator { path: Vec<usize>, found: String },
+    /// An operator applied to too few or too many operands
+    WrongArity {
+        path: Vec<usize>,
+        op: BuiltinOp,
+        expected: Arity,
+        found: usize,
+    },
+}
+
+impl ValidationError {
+    /// Where the `<apply>` is, as indices into `children()` from the root
+    pub fn path(&self) -> &[usize] {
+        match self {
+            ValidationError::EmptyApply { path }
+            | ValidationError::NotAnOperator { path, .. }
+            | ValidationError::WrongArity { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "at {:?}: ", self.path())?;
+        match self {
+            ValidationError::EmptyApply { .. } => write!(f, "<apply> is empty"),
+            ValidationError::NotAnOperator { found, .. } => {
+                write!(
+                    f,
+                    "'{}' is applied but isn't an operator or function",
+                    found
+                )
+            }
+            ValidationError::WrongArity {
+                op,
+                expected,
+                found,
+                ..
+            } => write!(
+                f,
+                "operator '{}' expects {} arguments, found {}",
+                op, expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseError::Xml(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<roxmltree::Error> for ParseError {
+    fn from(e: roxmltree::Error) -> Self {
+        ParseError::Xml(e)
+    }
+}
+
+/// Everything that can go wrong while evaluating a `MathNode` tree to a number
+#[derive(Debug, PartialEq)]
+pub enum EvalError {
+    /// An identifier that is not present in the environment
+    UnboundVariable(String),
+    /// An operator the evaluator has no numeric meaning for
+    UnsupportedOperator(String),
+    /// A node that cannot be evaluated to a number, like presentation markup
+    UnsupportedNode(String),
+    /// A `<cn type="constant">` whose value we don't know
+    UnknownConstant(String),
+    /// An operator was applied to the wrong number of arguments
+    WrongArity {
+        op: String,
+        expected: String,
+        found: usize,
+    },
+    /// Exact evaluation divided by zero, which has no rational result
+    DivisionByZero,
+    /// The limits of a `<sum>` or `<product>` aren't integers or span too many
+    /// terms, or those of an `<int>` aren't finite
+    InvalidLimits(String),
+    /// A `<sum>`, `<product>` or `<int>` overflowed or became NaN
+    NonFinite(String),
+    /// Numerical integration didn't reach the tolerance it was given
+    NotConverged(String),
+    /// No condition of a `<piecewise>` holds and it has no `<otherwise>`
+    NoPieceApplies,
+    /// Compiling to machine code failed, e.g. on an unsupported machine
+    CodeGeneration(String),
+    /// Functions that call themselves, which SBML doesn't allow: the names
+    /// along the cycle, starting and ending with the same one
+    RecursiveDefinition(Vec<String>),
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalError::UnboundVariable(name) => write!(f, "unbound variable '{}'", name),
+            EvalError::UnsupportedOperator(op) => {
+                write!(f, "operator '{}' cannot be evaluated numerically", op)
+            }
+            EvalError::UnsupportedNode(node) => write!(f, "cannot evaluate {}", node),
+            EvalError::UnknownConstant(c) => write!(f, "unknown constant '{}'", c),
+            EvalError::WrongArity {
+                op,
+                expected,
+                found,
+            } => write!(
+                f,
+                "operator '{}' expects {} arguments, found {}",
+                op, expected, found
+            ),
+            EvalError::DivisionByZero => write!(f, "division by zero"),
+            EvalError::InvalidLimits(reason) => write!(f, "invalid limits: {}", reason),
+            EvalError::NonFinite(op) => write!(f, "{} is not finite", op),
+            EvalError::NotConverged(op) => write!(f, "{} did not converge", op),
+            EvalError::CodeGeneration(reason) => write!(f, "code generation failed: {}", reason),
+            EvalError::RecursiveDefinition(cycle) => write!(
+                f,
+                "function '{}' is defined recursively: {}",
+                cycle[0],
+                cycle.join(" -> ")
+            ),
+            EvalError::NoPieceApplies => {
+                write!(
+                    f,
+                    "no condition of the piecewise holds and it has no otherwise"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
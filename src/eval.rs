@@ -0,0 +1,798 @@
+use super::{BuiltinOp, ConstantSymbol, IntervalClosure, MathNode, NumType, SbmlSymbol};
+use crate::error::EvalError;
+use crate::ops::Arity;
+use crate::quadrature::{self, IntegrationOptions};
+use std::collections::HashMap;
+
+fn truth(value: bool) -> f64 {
+    if value {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+fn is_true(value: f64) -> bool {
+    value != 0.0
+}
+
+pub(crate) fn check_arity(
+    op: &BuiltinOp,
+    values: &[f64],
+    min: usize,
+    max: usize,
+) -> Result<(), EvalError> {
+    if values.len() < min || values.len() > max {
+        return Err(EvalError::WrongArity {
+            op: op.to_string(),
+            expected: Arity {
+                min,
+                max: Some(max).filter(|max| *max != usize::MAX),
+            }
+            .to_string(),
+            found: values.len(),
+        });
+    }
+    Ok(())
+}
+
+pub(crate) fn constant_symbol(constant: &ConstantSymbol) -> Result<f64, EvalError> {
+    match constant {
+        ConstantSymbol::Pi => Ok(std::f64::consts::PI),
+        ConstantSymbol::ExponentialE => Ok(std::f64::consts::E),
+        ConstantSymbol::EulerGamma => Ok(0.577_215_664_901_532_9),
+        ConstantSymbol::Infinity => Ok(f64::INFINITY),
+        ConstantSymbol::NotANumber => Ok(f64::NAN),
+        ConstantSymbol::True => Ok(truth(true)),
+        ConstantSymbol::False => Ok(truth(false)),
+        ConstantSymbol::ImaginaryI => Err(EvalError::UnsupportedNode("<imaginaryi/>".to_owned())),
+    }
+}
+
+pub(crate) fn number(num_type: &NumType) -> Result<f64, EvalError> {
+    match num_type {
+        NumType::Real(r) => Ok(*r),
+        NumType::Integer(i) => Ok(*i as f64),
+        NumType::Rational(a, b) => Ok(*a as f64 / *b as f64),
+        NumType::ENotation(mantissa, exponent) => Ok(mantissa * 10f64.powi(*exponent as i32)),
+        NumType::Constant(constant) => constant
+            .value()
+            .ok_or_else(|| EvalError::UnknownConstant(constant.symbol().to_owned())),
+        // Non-standard types like `double` are usually plain numbers
+        NumType::Other { type_name, text } => text
+            .parse()
+            .map_err(|_| EvalError::UnsupportedNode(format!("a number of type {}", type_name))),
+        #[cfg(feature = "bigint")]
+        NumType::BigInteger(i) => Ok(i.to_string().parse().unwrap_or(f64::NAN)),
+        NumType::ComplexCartesian(_, _) | NumType::ComplexPolar(_, _) => {
+            Err(EvalError::UnsupportedNode("a complex number".to_owned()))
+        }
+    }
+}
+
+pub(crate) fn factorial(n: f64) -> f64 {
+    if n < 0.0 || n.fract() != 0.0 {
+        return f64::NAN;
+    }
+    // 171! is already larger than the largest f64
+    if n > 170.0 {
+        return f64::INFINITY;
+    }
+    (1..=n as u64).map(|i| i as f64).product()
+}
+
+fn gcd(a: f64, b: f64) -> f64 {
+    if a.fract() != 0.0 || b.fract() != 0.0 {
+        return f64::NAN;
+    }
+    let (mut a, mut b) = (a.abs() as u64, b.abs() as u64);
+    while b != 0 {
+        let r = a % b;
+        a = b;
+        b = r;
+    }
+    a as f64
+}
+
+pub(crate) fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Population variance of the arguments
+fn variance(values: &[f64]) -> f64 {
+    let m = mean(values);
+    values.iter().map(|v| (v - m).powi(2)).sum::<f64>() / values.len() as f64
+}
+
+pub(crate) fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    }
+}
+
+/// Most frequent argument, the first one seen wins ties
+fn mode(values: &[f64]) -> f64 {
+    let mut best = (values[0], 0);
+    for v in values {
+        let count = values.iter().filter(|w| *w == v).count();
+        if count > best.1 {
+            best = (*v, count);
+        }
+    }
+    best.0
+}
+
+/// Relations are n-ary in MathML: `<lt/> a b c` means `a < b < c`
+fn chain(values: &[f64], relation: impl Fn(f64, f64) -> bool) -> f64 {
+    truth(values.windows(2).all(|w| relation(w[0], w[1])))
+}
+
+/// Operators that reduce any number of arguments to a single value
+pub(crate) fn aggregates(op: &BuiltinOp) -> bool {
+    use BuiltinOp::*;
+    matches!(
+        op,
+        max | min | gcd | lcm | mean | sdev | variance | median | mode
+    )
+}
+
+pub(crate) fn eval_op(op: &BuiltinOp, values: &[f64]) -> Result<f64, EvalError> {
+    use BuiltinOp::*;
+    let any = usize::MAX;
+    // Checks arity and hands back the first argument of unary functions
+    let unary = |values: &[f64]| -> Result<f64, EvalError> {
+        check_arity(op, values, 1, 1)?;
+        Ok(values[0])
+    };
+    let value = match op {
+        plus => values.iter().sum(),
+        times => values.iter().product(),
+        minus => {
+            check_arity(op, values, 1, 2)?;
+            match values {
+                [x] => -x,
+                [a, b] => a - b,
+                _ => unreachable!(),
+            }
+        }
+        divide | power | rem | quotient | implies | factorof => {
+            check_arity(op, values, 2, 2)?;
+            let (a, b) = (values[0], values[1]);
+            match op {
+                divide => a / b,
+                power => a.powf(b),
+                rem => a % b,
+                quotient => (a / b).trunc(),
+                factorof => truth(a != 0.0 && b % a == 0.0),
+                _ => truth(!is_true(a) || is_true(b)),
+            }
+        }
+        root => unary(values)?.sqrt(),
+        abs => unary(values)?.abs(),
+        floor => unary(values)?.floor(),
+        ceiling => unary(values)?.ceil(),
+        factorial => self::factorial(unary(values)?),
+        exp => unary(values)?.exp(),
+        ln => unary(values)?.ln(),
+        log => unary(values)?.log10(),
+        sin => unary(values)?.sin(),
+        cos => unary(values)?.cos(),
+        tan => unary(values)?.tan(),
+        sec => 1.0 / unary(values)?.cos(),
+        csc => 1.0 / unary(values)?.sin(),
+        cot => 1.0 / unary(values)?.tan(),
+        sinh => unary(values)?.sinh(),
+        cosh => unary(values)?.cosh(),
+        tanh => unary(values)?.tanh(),
+        sech => 1.0 / unary(values)?.cosh(),
+        csch => 1.0 / unary(values)?.sinh(),
+        coth => 1.0 / unary(values)?.tanh(),
+        arcsin => unary(values)?.asin(),
+        arccos => unary(values)?.acos(),
+        arctan => unary(values)?.atan(),
+        arcsec => (1.0 / unary(values)?).acos(),
+        arccsc => (1.0 / unary(values)?).asin(),
+        arccot => (1.0 / unary(values)?).atan(),
+        arcsinh => unary(values)?.asinh(),
+        arccosh => unary(values)?.acosh(),
+        arctanh => unary(values)?.atanh(),
+        arcsech => (1.0 / unary(values)?).acosh(),
+        arccsch => (1.0 / unary(values)?).asinh(),
+        arccoth => (1.0 / unary(values)?).atanh(),
+        not => truth(!is_true(unary(values)?)),
+        and => truth(values.iter().all(|v| is_true(*v))),
+        or => truth(values.iter().any(|v| is_true(*v))),
+        xor => truth(values.iter().filter(|v| is_true(**v)).count() % 2 == 1),
+        equivalent => {
+            check_arity(op, values, 2, any)?;
+            chain(values, |a, b| is_true(a) == is_true(b))
+        }
+        neq => {
+            check_arity(op, values, 2, 2)?;
+            truth(values[0] != values[1])
+        }
+        eq | gt | lt | geq | leq => {
+            check_arity(op, values, 2, any)?;
+            match op {
+                eq => chain(values, |a, b| a == b),
+                gt => chain(values, |a, b| a > b),
+                lt => chain(values, |a, b| a < b),
+                geq => chain(values, |a, b| a >= b),
+                _ => chain(values, |a, b| a <= b),
+            }
+        }
+        max | min | gcd | lcm | mean | sdev | variance | median | mode => {
+            // The empty gcd and lcm are 0 and 1, the others have no value
+            let least = if matches!(op, gcd | lcm) { 0 } else { 1 };
+            check_arity(op, values, least, any)?;
+            match op {
+                max => values.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+                min => values.iter().cloned().fold(f64::INFINITY, f64::min),
+                gcd => values.iter().cloned().fold(0.0, self::gcd),
+                lcm => values.iter().cloned().fold(1.0, |a, b| {
+                    if a == 0.0 || b == 0.0 {
+                        0.0
+                    } else {
+                        (a * b).abs() / self::gcd(a, b)
+                    }
+                }),
+                mean => self::mean(values),
+                sdev => self::variance(values).sqrt(),
+                variance => self::variance(values),
+                median => self::median(values),
+                _ => self::mode(values),
+            }
+        }
+        _ => return Err(EvalError::UnsupportedOperator(op.to_string())),
+    };
+    Ok(value)
+}
+
+/// Real n-th root, which unlike `powf` is defined for negative numbers and odd n
+pub(crate) fn nth_root(x: f64, n: f64) -> f64 {
+    if x < 0.0 && n.fract() == 0.0 && n % 2.0 != 0.0 {
+        -(-x).powf(1.0 / n)
+    } else {
+        x.powf(1.0 / n)
+    }
+}
+
+/// The most terms a `<sum>` or `<product>` is evaluated over
+const MAX_TERMS: i64 = 10_000_000;
+
+/// An integer limit of a `<sum>` or `<product>`
+fn limit(
+    node: &MathNode,
+    env: &HashMap<String, f64>,
+    opts: &IntegrationOptions,
+    resolver: &dyn SymbolResolver,
+) -> Result<i64, EvalError> {
+    let value = eval_node(node, env, opts, resolver)?;
+    if value.fract() != 0.0 || value.abs() > i64::MAX as f64 {
+        return Err(EvalError::InvalidLimits(format!(
+            "{} is not an integer",
+            value
+        )));
+    }
+    Ok(value as i64)
+}
+
+/// A `<sum>` or `<product>` of its operand over the integers its bound variable
+/// ranges over, given by `<lowlimit>`/`<uplimit>` or an `<interval>`. A
+/// `<condition>` leaves out the terms it is false for
+fn eval_series(
+    op: &BuiltinOp,
+    node: &MathNode,
+    env: &HashMap<String, f64>,
+    opts: &IntegrationOptions,
+    resolver: &dyn SymbolResolver,
+) -> Result<f64, EvalError> {
+    let (var, body) = match (
+        node.bound_variables().as_slice(),
+        node.operands().as_slice(),
+    ) {
+        ([var], [body]) => (var.to_string(), *body),
+        _ => {
+            return Err(EvalError::UnsupportedOperator(format!(
+                "{} without exactly one bound variable and operand",
+                op
+            )))
+        }
+    };
+    let (low, high) = node
+        .limits()
+        .ok_or_else(|| EvalError::InvalidLimits(format!("{} without limits", op)))?;
+    let (mut low, mut high) = (
+        limit(low, env, opts, resolver)?,
+        limit(high, env, opts, resolver)?,
+    );
+    if let Some(MathNode::Interval { closure, .. }) = node.interval() {
+        let overflow =
+            || EvalError::InvalidLimits(format!("{} with an open limit at the end of i64", op));
+        if matches!(closure, IntervalClosure::Open | IntervalClosure::OpenClosed) {
+            low = low.checked_add(1).ok_or_else(overflow)?;
+        }
+        if matches!(closure, IntervalClosure::Open | IntervalClosure::ClosedOpen) {
+            high = high.checked_sub(1).ok_or_else(overflow)?;
+        }
+    }
+    if high.saturating_sub(low) >= MAX_TERMS {
+        return Err(EvalError::InvalidLimits(format!(
+            "more than {} terms",
+            MAX_TERMS
+        )));
+    }
+    let mut env = env.clone();
+    let mut total = if *op == BuiltinOp::sum { 0.0 } else { 1.0 };
+    for i in low..=high {
+        env.insert(var.clone(), i as f64);
+        if let Some(condition) = node.condition() {
+            if !is_true(eval_node(condition, &env, opts, resolver)?) {
+                continue;
+            }
+        }
+        let term = eval_node(body, &env, opts, resolver)?;
+        if *op == BuiltinOp::sum {
+            total += term;
+        } else {
+            total *= term;
+        }
+        if !total.is_finite() {
+            return Err(EvalError::NonFinite(op.to_string()));
+        }
+    }
+    Ok(total)
+}
+
+/// A definite `<int>` of its operand, over `<lowlimit>`/`<uplimit>` or an
+/// `<interval>` of its bound variable
+fn eval_integral(
+    node: &MathNode,
+    env: &HashMap<String, f64>,
+    opts: &IntegrationOptions,
+    resolver: &dyn SymbolResolver,
+) -> Result<f64, EvalError> {
+    let (var, body) = match (
+        node.bound_variables().as_slice(),
+        node.operands().as_slice(),
+    ) {
+        ([var], [body]) => (var.to_string(), *body),
+        _ => {
+            return Err(EvalError::UnsupportedOperator(
+                "int without exactly one bound variable and operand".to_owned(),
+            ))
+        }
+    };
+    let (low, high) = node
+        .limits()
+        .ok_or_else(|| EvalError::UnsupportedOperator("int without limits".to_owned()))?;
+    let (low, high) = (
+        eval_node(low, env, opts, resolver)?,
+        eval_node(high, env, opts, resolver)?,
+    );
+    let mut env = env.clone();
+    let mut integrand = |x: f64| {
+        env.insert(var.clone(), x);
+        eval_node(body, &env, opts, resolver)
+    };
+    quadrature::integrate(&mut integrand, low, high, opts)
+}
+
+/// Values for the csymbols the evaluator can't compute by itself, from the
+/// host simulator, e.g. SBML's `delay(x, tau)` and `rateOf(x)`, which depend
+/// on the history and the state of the simulation
+pub trait SymbolResolver {
+    /// The value of `csymbol` applied to `args`, unevaluated, or standing
+    /// alone when `args` is `None`
+    fn resolve(
+        &self,
+        csymbol: &MathNode,
+        args: Option<&[&MathNode]>,
+        env: &HashMap<String, f64>,
+    ) -> Result<f64, EvalError>;
+}
+
+/// Resolves no csymbol, for `eval` and `eval_with`
+struct Unresolved;
+
+impl SymbolResolver for Unresolved {
+    fn resolve(
+        &self,
+        csymbol: &MathNode,
+        args: Option<&[&MathNode]>,
+        _: &HashMap<String, f64>,
+    ) -> Result<f64, EvalError> {
+        match (csymbol, args) {
+            (MathNode::Csymbol { name, .. }, None) => {
+                Err(EvalError::UnsupportedNode(format!("the csymbol {}", name)))
+            }
+            _ => Err(EvalError::UnsupportedNode(
+                "an application of a non-builtin function".to_owned(),
+            )),
+        }
+    }
+}
+
+fn eval_node(
+    node: &MathNode,
+    env: &HashMap<String, f64>,
+    opts: &IntegrationOptions,
+    resolver: &dyn SymbolResolver,
+) -> Result<f64, EvalError> {
+    match node {
+        MathNode::Root { children, .. } => {
+            let mut expressions = children.iter().filter(|c| !c.is_markup());
+            match (expressions.next(), expressions.next()) {
+                (Some(expression), None) => eval_node(expression, env, opts, resolver),
+                _ => Err(EvalError::UnsupportedNode(
+                    "a <math> element without exactly one expression".to_owned(),
+                )),
+            }
+        }
+        MathNode::Apply { children, .. } => match children.first() {
+            Some(MathNode::Op(op @ (BuiltinOp::sum | BuiltinOp::product))) => {
+                eval_series(op, node, env, opts, resolver)
+            }
+            Some(MathNode::Op(BuiltinOp::int)) => eval_integral(node, env, opts, resolver),
+            Some(MathNode::Op(op)) if !node.bound_variables().is_empty() => Err(
+                EvalError::UnsupportedOperator(format!("{} with bound variables", op)),
+            ),
+            Some(MathNode::Op(op)) => {
+                let values = node
+                    .operands()
+                    .into_iter()
+                    .map(|a| match a {
+                        // Statistics and extrema can take their arguments as a single container
+                        MathNode::Set(items) | MathNode::List { items, .. } if aggregates(op) => {
+                            items
+                                .iter()
+                                .map(|i| eval_node(i, env, opts, resolver))
+                                .collect()
+                        }
+                        _ => eval_node(a, env, opts, resolver).map(|v| vec![v]),
+                    })
+                    .collect::<Result<Vec<Vec<f64>>, EvalError>>()?
+                    .concat();
+                match (op, node.degree(), node.logbase()) {
+                    (BuiltinOp::root, Some(degree), _) => {
+                        check_arity(op, &values, 1, 1)?;
+                        Ok(nth_root(values[0], eval_node(degree, env, opts, resolver)?))
+                    }
+                    (BuiltinOp::log, _, Some(base)) => {
+                        let base = eval_node(base, env, opts, resolver)?;
+                        Ok(eval_op(op, &values)? / base.log10())
+                    }
+                    _ => eval_op(op, &values),
+                }
+            }
+            Some(csymbol @ MathNode::Csymbol { .. }) => {
+                resolver.resolve(csymbol, Some(&node.operands()), env)
+            }
+            Some(_) => Err(EvalError::UnsupportedNode(
+                "an application of a non-builtin function".to_owned(),
+            )),
+            None => Err(EvalError::UnsupportedNode("an empty <apply>".to_owned())),
+        },
+        MathNode::Ci { children, .. } => {
+            let name = crate::text_content(children);
+            env.get(&name)
+                .cloned()
+                .ok_or(EvalError::UnboundVariable(name))
+        }
+        MathNode::Cn { num_type, .. } => number(num_type),
+        MathNode::Constant(constant) => constant_symbol(constant),
+        MathNode::NumberSet(set) => Err(EvalError::UnsupportedNode(format!("<{}/>", set))),
+        MathNode::Op(op) => Err(EvalError::UnsupportedNode(format!(
+            "the bare operator <{}/>",
+            op
+        ))),
+        MathNode::Csymbol {
+            sbml: Some(SbmlSymbol::Avogadro),
+            ..
+        } => Ok(6.022_140_76e23),
+        // The current time is bound under the name the model gave the csymbol
+        MathNode::Csymbol {
+            sbml: Some(SbmlSymbol::Time),
+            name,
+            ..
+        } => env
+            .get(name)
+            .cloned()
+            .ok_or_else(|| EvalError::UnboundVariable(name.clone())),
+        MathNode::Csymbol { .. } => resolver.resolve(node, None, env),
+        MathNode::Text(_) | MathNode::Comment(_) | MathNode::PI(_, _) => {
+            Err(EvalError::UnsupportedNode("text or markup".to_owned()))
+        }
+        MathNode::Presentation(_) => {
+            Err(EvalError::UnsupportedNode("presentation markup".to_owned()))
+        }
+        MathNode::Unknown { name, .. } => Err(EvalError::UnsupportedNode(format!("<{}>", name))),
+        MathNode::Cs(_) => Err(EvalError::UnsupportedNode("<cs>".to_owned())),
+        MathNode::Cbytes(_) => Err(EvalError::UnsupportedNode("<cbytes>".to_owned())),
+        MathNode::Cerror(_) => Err(EvalError::UnsupportedNode("<cerror>".to_owned())),
+        MathNode::Share(href) => Err(EvalError::UnsupportedNode(format!(
+            "<share href=\"{}\">, see resolve_shares",
+            href
+        ))),
+        MathNode::Error { message, .. } => Err(EvalError::UnsupportedNode(format!(
+            "an element that failed to parse ({})",
+            message
+        ))),
+        // The first piece whose condition holds, in document order, as in libSBML
+        MathNode::Piecewise { pieces, otherwise } => {
+            for (value, condition) in pieces {
+                if is_true(eval_node(condition, env, opts, resolver)?) {
+                    return eval_node(value, env, opts, resolver);
+                }
+            }
+            match otherwise {
+                Some(otherwise) => eval_node(otherwise, env, opts, resolver),
+                None => Err(EvalError::NoPieceApplies),
+            }
+        }
+        MathNode::Lambda { .. } => Err(EvalError::UnsupportedNode("<lambda>".to_owned())),
+        MathNode::Bind { .. } => eval_node(&node.to_apply(), env, opts, resolver),
+        MathNode::Interval { .. } => Err(EvalError::UnsupportedNode("<interval>".to_owned())),
+        MathNode::Matrix(_) => Err(EvalError::UnsupportedNode("<matrix>".to_owned())),
+        MathNode::Vector(_) => Err(EvalError::UnsupportedNode("<vector>".to_owned())),
+        MathNode::Semantics { primary, .. } => eval_node(primary, env, opts, resolver),
+        MathNode::Set(_) => Err(EvalError::UnsupportedNode("<set>".to_owned())),
+        MathNode::List { .. } => Err(EvalError::UnsupportedNode("<list>".to_owned())),
+        MathNode::Degree(_)
+        | MathNode::Logbase(_)
+        | MathNode::Bvar { .. }
+        | MathNode::Lowlimit(_)
+        | MathNode::Uplimit(_)
+        | MathNode::Condition(_) => Err(EvalError::UnsupportedNode(
+            "a qualifier outside of <apply>".to_owned(),
+        )),
+    }
+}
+
+impl MathNode {
+    /// Evaluate the expression to a number, looking identifiers up in `env`.
+    /// Booleans are represented as 1.0 (true) and 0.0 (false)
+    pub fn eval(&self, env: &HashMap<String, f64>) -> Result<f64, EvalError> {
+        self.eval_with(env, &IntegrationOptions::default())
+    }
+    /// Like `eval`, computing definite integrals as `opts` says
+    pub fn eval_with(
+        &self,
+        env: &HashMap<String, f64>,
+        opts: &IntegrationOptions,
+    ) -> Result<f64, EvalError> {
+        eval_node(self, env, opts, &Unresolved)
+    }
+    /// Like `eval_with`, asking `resolver` for the values of the csymbols
+    /// other than SBML's time and Avogadro's number, alone or applied
+    pub fn eval_resolving(
+        &self,
+        env: &HashMap<String, f64>,
+        opts: &IntegrationOptions,
+        resolver: &dyn SymbolResolver,
+    ) -> Result<f64, EvalError> {
+        eval_node(self, env, opts, resolver)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SymbolResolver;
+    use crate::error::EvalError;
+    use crate::{parse_document, IntegrationOptions, MathNode, SbmlSymbol};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_eval_rate_law() {
+        // Michaelis-Menten: Vmax * S / (Km + S)
+        let test = r#"<math xmlns="http://www.w3.org/1998/Math/MathML">
+                        <apply><divide/>
+                            <apply><times/><ci> Vmax </ci><ci> S </ci></apply>
+                            <apply><plus/><ci> Km </ci><ci> S </ci></apply>
+                        </apply>
+                      </math>"#;
+        let parsed = parse_document(test).unwrap();
+        let env: HashMap<String, f64> = vec![("Vmax", 10.0), ("S", 2.0), ("Km", 3.0)]
+            .into_iter()
+            .map(|(k, v)| (k.to_owned(), v))
+            .collect();
+        approx::assert_abs_diff_eq!(parsed.eval(&env).unwrap(), 4.0);
+        let env: HashMap<String, f64> = HashMap::new();
+        assert_eq!(
+            parsed.eval(&env),
+            Err(EvalError::UnboundVariable("Vmax".to_owned()))
+        );
+
+        // Exponential decay over the SBML simulation time
+        let test = r#"<apply><exp/><apply><minus/>
+                        <csymbol definitionURL="http://www.sbml.org/sbml/symbols/time"> t </csymbol>
+                      </apply></apply>"#;
+        let parsed = parse_document(test).unwrap();
+        let env: HashMap<String, f64> = vec![("t".to_owned(), 2.0)].into_iter().collect();
+        approx::assert_abs_diff_eq!(parsed.eval(&env).unwrap(), (-2f64).exp());
+    }
+    #[test]
+    fn test_eval_functions_and_logic() {
+        let env = HashMap::new();
+        let eval = |text: &str| parse_document(text).unwrap().eval(&env);
+        approx::assert_abs_diff_eq!(
+            eval(r#"<apply><sin/><cn type="constant">&pi;</cn></apply>"#).unwrap(),
+            0.0
+        );
+        assert_eq!(
+            eval(r#"<apply><max/><cn>1</cn><cn>7</cn><cn>3</cn></apply>"#),
+            Ok(7.0)
+        );
+        assert_eq!(
+            eval(r#"<apply><lt/><cn>1</cn><cn>2</cn><cn>2</cn></apply>"#),
+            Ok(0.0)
+        );
+        assert_eq!(
+            eval(
+                r#"<apply><and/><apply><geq/><cn>2</cn><cn>2</cn></apply><apply><not/><cn>0</cn></apply></apply>"#
+            ),
+            Ok(1.0)
+        );
+        assert_eq!(
+            eval(
+                r#"<apply><power/><cn type="e-notation">2<sep/>1</cn><cn type="rational">1<sep/>2</cn></apply>"#
+            ),
+            Ok(20f64.sqrt())
+        );
+        assert!(matches!(
+            eval(r#"<apply><divide/><cn>1</cn></apply>"#),
+            Err(EvalError::WrongArity { found: 1, .. })
+        ));
+        assert_eq!(
+            eval(r#"<apply><and/><true/><apply><lt/><pi/><infinity/></apply></apply>"#),
+            Ok(1.0)
+        );
+        assert_eq!(
+            eval(r#"<apply><factorial/><cn>1e15</cn></apply>"#),
+            Ok(f64::INFINITY)
+        );
+        assert_eq!(
+            eval(r#"<apply><factorof/><cn>3</cn><cn>12</cn></apply>"#),
+            Ok(1.0)
+        );
+        assert_eq!(
+            eval(r#"<apply><median/><list><cn>5</cn><cn>1</cn><cn>3</cn></list></apply>"#),
+            Ok(3.0)
+        );
+        assert_eq!(
+            eval(r#"<apply><max/><set><cn>5</cn><cn>7</cn></set><cn>6</cn></apply>"#),
+            Ok(7.0)
+        );
+    }
+    #[test]
+    fn test_eval_qualifiers() {
+        let env = HashMap::new();
+        let eval = |text: &str| parse_document(text).unwrap().eval(&env).unwrap();
+        approx::assert_abs_diff_eq!(
+            eval(r#"<apply><root/><degree><cn>3</cn></degree><cn>-27</cn></apply>"#),
+            -3.0,
+            epsilon = 1e-12
+        );
+        approx::assert_abs_diff_eq!(
+            eval(r#"<apply><log/><logbase><cn>2</cn></logbase><cn>1024</cn></apply>"#),
+            10.0,
+            epsilon = 1e-12
+        );
+        approx::assert_abs_diff_eq!(eval(r#"<apply><log/><cn>1000</cn></apply>"#), 3.0);
+    }
+    #[test]
+    fn test_eval_piecewise() {
+        let test = r#"<piecewise>
+                        <piece><cn>1</cn><apply><lt/><ci>x</ci><cn>0</cn></apply></piece>
+                        <piece><cn>2</cn><apply><lt/><ci>x</ci><cn>10</cn></apply></piece>
+                        <piece><cn>3</cn><apply><lt/><ci>x</ci><cn>5</cn></apply></piece>
+                        <otherwise><ci>x</ci></otherwise>
+                      </piecewise>"#;
+        let parsed = parse_document(test).unwrap();
+        let eval = |x: f64| parsed.eval(&vec![("x".to_owned(), x)].into_iter().collect());
+        assert_eq!(eval(-1.0), Ok(1.0));
+        // Both of the later conditions hold, the first one wins
+        assert_eq!(eval(3.0), Ok(2.0));
+        assert_eq!(eval(12.0), Ok(12.0));
+        let test = test.replace("<otherwise><ci>x</ci></otherwise>", "");
+        let parsed = parse_document(&test).unwrap();
+        let env = vec![("x".to_owned(), 12.0)].into_iter().collect();
+        assert_eq!(parsed.eval(&env), Err(EvalError::NoPieceApplies));
+    }
+    #[test]
+    fn test_eval_sum_product() {
+        let env: HashMap<String, f64> = vec![("n".to_owned(), 4.0)].into_iter().collect();
+        let eval = |text: &str| parse_document(text).unwrap().eval(&env);
+        let sum = r#"<apply><sum/><bvar><ci>i</ci></bvar>
+                        <lowlimit><cn>1</cn></lowlimit><uplimit><ci>n</ci></uplimit>
+                        <apply><power/><ci>i</ci><cn>2</cn></apply>
+                     </apply>"#;
+        assert_eq!(eval(sum), Ok(30.0));
+        let product = r#"<apply><product/><bvar><ci>k</ci></bvar>
+                            <interval closure="open-closed"><cn>0</cn><ci>n</ci></interval>
+                            <ci>k</ci>
+                         </apply>"#;
+        assert_eq!(eval(product), Ok(24.0));
+        let even = r#"<apply><sum/><bvar><ci>i</ci></bvar>
+                        <lowlimit><cn>1</cn></lowlimit><uplimit><cn>10</cn></uplimit>
+                        <condition><apply><eq/><apply><rem/><ci>i</ci><cn>2</cn></apply><cn>0</cn></apply></condition>
+                        <ci>i</ci>
+                      </apply>"#;
+        assert_eq!(eval(even), Ok(30.0));
+        assert!(matches!(
+            eval(&sum.replace("<ci>n</ci></uplimit>", "<cn>2.5</cn></uplimit>")),
+            Err(EvalError::InvalidLimits(_))
+        ));
+        let open = r#"<apply><sum/><bvar><ci>i</ci></bvar>
+                        <interval closure="open"><cn>9223372036854775807</cn><cn>9223372036854775807</cn></interval>
+                        <ci>i</ci>
+                      </apply>"#;
+        assert!(matches!(eval(open), Err(EvalError::InvalidLimits(_))));
+        assert_eq!(
+            eval(&product.replace("<ci>k</ci>\n", "<cn>1e300</cn>\n")),
+            Err(EvalError::NonFinite("product".to_owned()))
+        );
+    }
+    #[test]
+    fn test_eval_symbol_resolver() {
+        // A simulation in which S has grown as 2 * t
+        struct Linear;
+        impl SymbolResolver for Linear {
+            fn resolve(
+                &self,
+                csymbol: &MathNode,
+                args: Option<&[&MathNode]>,
+                env: &HashMap<String, f64>,
+            ) -> Result<f64, EvalError> {
+                match (csymbol, args) {
+                    (
+                        MathNode::Csymbol {
+                            sbml: Some(SbmlSymbol::Delay),
+                            ..
+                        },
+                        Some([_, tau]),
+                    ) => Ok(2.0 * (env["t"] - tau.eval(env)?)),
+                    (
+                        MathNode::Csymbol {
+                            sbml: Some(SbmlSymbol::RateOf),
+                            ..
+                        },
+                        Some([_]),
+                    ) => Ok(2.0),
+                    _ => Err(EvalError::UnsupportedNode("an unknown csymbol".to_owned())),
+                }
+            }
+        }
+        let symbol = |name: &str| {
+            format!(
+                r#"<csymbol definitionURL="http://www.sbml.org/sbml/symbols/{0}">{0}</csymbol>"#,
+                name
+            )
+        };
+        let test = format!(
+            r#"<apply><plus/>
+                 <apply>{}<ci>S</ci><apply><divide/><ci>tau</ci><cn>2</cn></apply></apply>
+                 <apply>{}<ci>S</ci></apply>
+               </apply>"#,
+            symbol("delay"),
+            symbol("rateOf")
+        );
+        let parsed = parse_document(&test).unwrap();
+        let env: HashMap<String, f64> = vec![("t".to_owned(), 5.0), ("tau".to_owned(), 4.0)]
+            .into_iter()
+            .collect();
+        let opts = IntegrationOptions::default();
+        assert_eq!(parsed.eval_resolving(&env, &opts, &Linear), Ok(8.0));
+        assert_eq!(
+            parsed.eval(&env),
+            Err(EvalError::UnsupportedNode(
+                "an application of a non-builtin function".to_owned()
+            ))
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use super::{BuiltinOp, ConstantSymbol, MathNode, NumType};
+use crate::error::EvalError;
+use crate::eval::check_arity;
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Signed, Zero};
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+fn truth(value: bool) -> BigRational {
+    if value {
+        BigRational::one()
+    } else {
+        BigRational::zero()
+    }
+}
+
+/// The most bits a power may take, so that `x^100000000` or a huge e-notation
+/// exponent fails instead of running out of memory
+const MAX_BITS: u64 = 1 << 20;
+
+fn ten_to(exponent: i64) -> Result<BigRational, EvalError> {
+    power(
+        &BigRational::from_integer(BigInt::from(10)),
+        &BigRational::from_integer(BigInt::from(exponent)),
+    )
+}
+
+/// The value of the shortest decimal that reads back as `value`, so `0.1` is 1/10
+fn decimal(value: f64) -> Result<BigRational, EvalError> {
+    if !value.is_finite() {
+        return Err(EvalError::UnsupportedNode(format!("the number {}", value)));
+    }
+    let text = value.to_string();
+    let (whole, fraction) = text.split_once('.').unwrap_or((&text, ""));
+    let digits: BigInt = format!("{}{}", whole, fraction)
+        .parse()
+        .map_err(|_| EvalError::UnsupportedNode(format!("the number {}", value)))?;
+    Ok(BigRational::from_integer(digits) * ten_to(-(fraction.len() as i64))?)
+}
+
+fn number(num_type: &NumType) -> Result<BigRational, EvalError> {
+    match num_type {
+        NumType::Integer(i) => Ok(BigRational::from_integer(BigInt::from(*i))),
+        NumType::BigInteger(i) => Ok(BigRational::from_integer(i.clone())),
+        NumType::Rational(_, 0) => Err(EvalError::DivisionByZero),
+        NumType::Rational(a, b) => Ok(BigRational::new(BigInt::from(*a), BigInt::from(*b))),
+        NumType::Real(r) => decimal(*r),
+        NumType::ENotation(mantissa, exponent) => Ok(decimal(*mantissa)? * ten_to(*exponent)?),
+        NumType::Constant(name) => Err(EvalError::UnsupportedNode(format!(
+            "the irrational constant {}",
+            name.symbol()
+        ))),
+        NumType::Other { type_name, .. } => Err(EvalError::UnsupportedNode(format!(
+            "a number of type {}",
+            type_name
+        ))),
+        NumType::ComplexCartesian(_, _) | NumType::ComplexPolar(_, _) => {
+            Err(EvalError::UnsupportedNode("a complex number".to_owned()))
+        }
+    }
+}
+
+fn power(base: &BigRational, exponent: &BigRational) -> Result<BigRational, EvalError> {
+    if !exponent.is_integer() {
+        return Err(EvalError::UnsupportedOperator(
+            "power with a fractional exponent".to_owned(),
+        ));
+    }
+    let huge = || EvalError::UnsupportedOperator("power with a huge exponent".to_owned());
+    let n: u64 = exponent
+        .to_integer()
+        .magnitude()
+        .try_into()
+        .map_err(|_| huge())?;
+    // Powers of 0, 1 and -1 stay that small
+    let bits = if base.is_zero() || base.abs().is_one() {
+        0
+    } else {
+        base.numer().bits().max(base.denom().bits())
+    };
+    if !matches!(bits.checked_mul(n), Some(total) if total <= MAX_BITS) {
+        return Err(huge());
+    }
+    let result = num_traits::pow(base.clone(), n.try_into().map_err(|_| huge())?);
+    match (exponent.is_negative(), result.is_zero()) {
+        (true, true) => Err(EvalError::DivisionByZero),
+        (true, false) => Ok(result.recip()),
+        (false, _) => Ok(result),
+    }
+}
+
+fn eval_op(op: &BuiltinOp, values: &[BigRational]) -> Result<BigRational, EvalError> {
+    use BuiltinOp::*;
+    let any = usize::MAX;
+    let arity = |least: usize, most: usize| check_arity(op, &vec![0.0; values.len()], least, most);
+    // Relations are n-ary in MathML: `<lt/> a b c` means `a < b < c`
+    let chain = |relation: fn(&BigRational, &BigRational) -> bool| {
+        arity(2, any)?;
+        Ok(truth(values.windows(2).all(|w| relation(&w[0], &w[1]))))
+    };
+    let value = match op {
+        plus => values.iter().sum(),
+        times => values.iter().product(),
+        minus => {
+            arity(1, 2)?;
+            match values {
+                [x] => -x,
+                [a, b] => a - b,
+                _ => unreachable!(),
+            }
+        }
+        divide | power => {
+            arity(2, 2)?;
+            match op {
+                divide if values[1].is_zero() => return Err(EvalError::DivisionByZero),
+                divide => &values[0] / &values[1],
+                _ => self::power(&values[0], &values[1])?,
+            }
+        }
+        abs | floor | ceiling | not => {
+            arity(1, 1)?;
+            match op {
+                abs => values[0].abs(),
+                floor => values[0].floor(),
+                ceiling => values[0].ceil(),
+                _ => truth(values[0].is_zero()),
+            }
+        }
+        max | min => {
+            arity(1, any)?;
+            let picked = match op {
+                max => values.iter().max(),
+                _ => values.iter().min(),
+            };
+            picked.cloned().unwrap_or_default()
+        }
+        and => truth(values.iter().all(|v| !v.is_zero())),
+        or => truth(values.iter().any(|v| !v.is_zero())),
+        eq => chain(|a, b| a == b)?,
+        neq => {
+            arity(2, 2)?;
+            truth(values[0] != values[1])
+        }
+        gt => chain(|a, b| a > b)?,
+        lt => chain(|a, b| a < b)?,
+        geq => chain(|a, b| a >= b)?,
+        leq => chain(|a, b| a <= b)?,
+        _ => return Err(EvalError::UnsupportedOperator(op.to_string())),
+    };
+    Ok(value)
+}
+
+fn eval_node(
+    node: &MathNode,
+    env: &HashMap<String, BigRational>,
+) -> Result<BigRational, EvalError> {
+    match node {
+        MathNode::Root { children, .. } => {
+            let mut expressions = children.iter().filter(|c| !c.is_markup());
+            match (expressions.next(), expressions.next()) {
+                (Some(expression), None) => eval_node(expression, env),
+                _ => Err(EvalError::UnsupportedNode(
+                    "a <math> element without exactly one expression".to_owned(),
+                )),
+            }
+        }
+        MathNode::Apply { children, .. } => match children.first() {
+            Some(MathNode::Op(op)) if node.operands().len() + 1 == children.len() => {
+                let values = node
+                    .operands()
+                    .into_iter()
+                    .map(|a| eval_node(a, env))
+                    .collect::<Result<Vec<_>, _>>()?;
+                eval_op(op, &values)
+            }
+            Some(MathNode::Op(op)) => Err(EvalError::UnsupportedOperator(format!(
+                "{} with qualifiers",
+                op
+            ))),
+            _ => Err(EvalError::UnsupportedNode(
+                "an application of a non-builtin function".to_owned(),
+            )),
+        },
+        MathNode::Ci { children, .. } => {
+            let name = crate::text_content(children);
+            env.get(&name)
+                .cloned()
+                .ok_or(EvalError::UnboundVariable(name))
+        }
+        MathNode::Cn { num_type, .. } => number(num_type),
+        MathNode::Constant(ConstantSymbol::True) => Ok(truth(true)),
+        MathNode::Constant(ConstantSymbol::False) => Ok(truth(false)),
+        MathNode::Semantics { primary, .. } => eval_node(primary, env),
+        // The float evaluator already knows why everything else can't be evaluated,
+        // what is left (pi, csymbols...) has no exact rational value
+        _ => Err(node
+            .eval(&HashMap::new())
+            .err()
+            .unwrap_or_else(|| EvalError::UnsupportedNode(format!("{} exactly", node)))),
+    }
+}
+
+impl MathNode {
+    /// Evaluate the expression with exact rational arithmetic, looking identifiers
+    /// up in `env`. Only operators with rational results are supported
+    pub fn eval_exact(&self, env: &HashMap<String, BigRational>) -> Result<BigRational, EvalError> {
+        eval_node(self, env)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::EvalError;
+    use crate::{math, parse_document, BigRational, MathNode};
+    use num_bigint::BigInt;
+    use std::collections::HashMap;
+
+    fn ratio(a: i64, b: i64) -> BigRational {
+        BigRational::new(BigInt::from(a), BigInt::from(b))
+    }
+
+    #[test]
+    fn test_eval_exact() {
+        let env: HashMap<String, BigRational> =
+            vec![("x".to_owned(), ratio(1, 3))].into_iter().collect();
+        // 0.1 + 0.2 is exactly 0.3 here
+        assert_eq!(math!(eq(0.1 + 0.2, 0.3)).eval_exact(&env), Ok(ratio(1, 1)));
+        assert_eq!(
+            math!(3 * x - 1 + power(x, -2)).eval_exact(&env),
+            Ok(ratio(9, 1))
+        );
+        assert_eq!(
+            (MathNode::rational(1, 7) * math!(7)).eval_exact(&env),
+            Ok(ratio(1, 1))
+        );
+        assert_eq!(
+            math!(x / (x - x)).eval_exact(&env),
+            Err(EvalError::DivisionByZero)
+        );
+        assert!(math!(sin(x)).eval_exact(&env).is_err());
+    }
+    #[test]
+    fn test_huge_powers() {
+        let env = HashMap::new();
+        let huge = parse_document(r#"<cn type="e-notation">1<sep/>99999999999</cn>"#).unwrap();
+        assert!(huge.eval_exact(&env).is_err());
+        assert!(math!(power(3, 100000000)).eval_exact(&env).is_err());
+        assert_eq!(
+            math!(power(-1, 1000000001)).eval_exact(&env),
+            Ok(ratio(-1, 1))
+        );
+        assert_eq!(math!(power(2, -10)).eval_exact(&env), Ok(ratio(1, 1024)));
+    }
+}
@@ -0,0 +1,347 @@
+use super::{BuiltinOp, MathNode, NumType};
+use crate::numeric::fold_numbers;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+/// The most terms an expansion may have, beyond which the expression is left
+/// as it is
+const MAX_TERMS: usize = 1_000;
+
+/// A number times a product of powers of other expressions
+#[derive(Debug, Clone)]
+struct Monomial {
+    coefficient: MathNode,
+    /// Bases and their exponents, in the order they first appear
+    factors: Vec<(MathNode, u32)>,
+}
+
+impl Monomial {
+    fn number(coefficient: MathNode) -> Self {
+        Monomial {
+            coefficient,
+            factors: Vec::new(),
+        }
+    }
+    fn factor(base: MathNode) -> Self {
+        Monomial {
+            coefficient: MathNode::integer(1),
+            factors: vec![(base, 1)],
+        }
+    }
+    /// The product, unless an exponent overflows
+    fn times(&self, other: &Monomial) -> Option<Monomial> {
+        let mut factors = self.factors.clone();
+        for (base, exponent) in &other.factors {
+            match factors.iter_mut().find(|(b, _)| b == base) {
+                Some((_, e)) => *e = e.checked_add(*exponent)?,
+                None => factors.push((base.clone(), *exponent)),
+            }
+        }
+        Some(Monomial {
+            coefficient: fold_numbers(BuiltinOp::times, &self.coefficient, &other.coefficient)
+                .unwrap_or_else(|| {
+                    (self.coefficient.clone() * other.coefficient.clone()).simplify()
+                }),
+            factors,
+        })
+    }
+    /// The `n`th power, unless an exponent overflows
+    fn pow(&self, n: u32) -> Option<Monomial> {
+        let factors = self
+            .factors
+            .iter()
+            .map(|(base, e)| Some((base.clone(), e.checked_mul(n)?)))
+            .collect::<Option<_>>()?;
+        let coefficient = if value(&self.coefficient) == Some(1.0) {
+            self.coefficient.clone()
+        } else {
+            MathNode::apply(
+                BuiltinOp::power,
+                vec![self.coefficient.clone(), MathNode::integer(i64::from(n))],
+            )
+            .simplify()
+        };
+        Some(Monomial {
+            coefficient,
+            factors,
+        })
+    }
+    /// The hashes of the factors, the same for alike monomials
+    fn key(&self) -> Vec<(u64, u32)> {
+        let mut key: Vec<(u64, u32)> = self
+            .factors
+            .iter()
+            .map(|(base, exponent)| {
+                let mut hasher = DefaultHasher::new();
+                base.hash(&mut hasher);
+                (hasher.finish(), *exponent)
+            })
+            .collect();
+        key.sort_unstable();
+        key
+    }
+    fn negate(mut self) -> Monomial {
+        self.coefficient = (-self.coefficient).simplify();
+        self
+    }
+    /// The monomial as an expression, and whether it is to be subtracted
+    fn to_node(&self) -> (bool, MathNode) {
+        let (negative, coefficient) = split_sign(self.coefficient.clone());
+        let mut factors: Vec<MathNode> = self
+            .factors
+            .iter()
+            .map(|(base, exponent)| match exponent {
+                1 => base.clone(),
+                _ => MathNode::apply(
+                    BuiltinOp::power,
+                    vec![base.clone(), MathNode::integer(i64::from(*exponent))],
+                ),
+            })
+            .collect();
+        if factors.is_empty() || value(&coefficient) != Some(1.0) {
+            factors.insert(0, coefficient);
+        }
+        match factors.len() {
+            1 => (negative, factors.remove(0)),
+            _ => (negative, MathNode::apply(BuiltinOp::times, factors)),
+        }
+    }
+}
+
+fn value(node: &MathNode) -> Option<f64> {
+    match node {
+        MathNode::Cn { .. } => node.eval(&HashMap::new()).ok(),
+        _ => None,
+    }
+}
+
+/// Whether `node` is negative, and its absolute value, as far as can be told
+/// from a leading minus or a negative number
+fn split_sign(node: MathNode) -> (bool, MathNode) {
+    match node {
+        MathNode::Apply { ref children, .. }
+            if children.len() == 2 && children[0] == MathNode::Op(BuiltinOp::minus) =>
+        {
+            (true, children[1].clone())
+        }
+        MathNode::Cn {
+            num_type: NumType::Integer(i),
+            ..
+        } if i < 0 => (true, MathNode::integer(-i)),
+        MathNode::Cn {
+            num_type: NumType::Real(r),
+            ..
+        } if r < 0.0 => (true, MathNode::cn(-r)),
+        node => (false, node),
+    }
+}
+
+/// Builds `a + b - c ...` from terms to add or subtract
+fn signed_sum(terms: Vec<(bool, MathNode)>) -> MathNode {
+    let mut terms = terms.into_iter();
+    let mut sum = match terms.next() {
+        Some((true, first)) => -first,
+        Some((false, first)) => first,
+        None => return MathNode::integer(0),
+    };
+    for (negative, term) in terms {
+        let op = if negative {
+            BuiltinOp::minus
+        } else {
+            BuiltinOp::plus
+        };
+        sum = MathNode::apply(op, vec![sum, term]);
+    }
+    sum
+}
+
+/// The non-negative integer `node` is, as a power to expand
+fn exponent(node: &MathNode) -> Option<u32> {
+    match node {
+        MathNode::Cn {
+            num_type: NumType::Integer(n),
+            ..
+        } if *n >= 0 && *n <= i64::from(u32::MAX) => Some(*n as u32),
+        _ => None,
+    }
+}
+
+/// `None` when the product has more than `MAX_TERMS` terms or an exponent
+/// overflows
+fn product(a: &[Monomial], b: &[Monomial]) -> Option<Vec<Monomial>> {
+    if a.len() * b.len() > MAX_TERMS {
+        return None;
+    }
+    let terms = a
+        .iter()
+        .flat_map(|x| b.iter().map(move |y| x.times(y)))
+        .collect::<Option<_>>()?;
+    Some(combine(terms))
+}
+
+fn power(base: &[Monomial], n: u32) -> Option<Vec<Monomial>> {
+    match base {
+        [single] => return Some(vec![single.pow(n)?]),
+        // A sum of two terms or more has more terms than the exponent
+        [_, _, ..] if n as usize > MAX_TERMS => return None,
+        _ => {}
+    }
+    let mut power = vec![Monomial::number(MathNode::integer(1))];
+    for _ in 0..n {
+        power = product(&power, base)?;
+    }
+    Some(power)
+}
+
+/// `node` as a sum of monomials
+fn monomials(node: &MathNode) -> Vec<Monomial> {
+    if let MathNode::Cn { .. } = node {
+        if value(node).is_some() {
+            return vec![Monomial::number(node.clone())];
+        }
+    }
+    let op = match node {
+        MathNode::Apply { children, .. } => match children.first() {
+            Some(MathNode::Op(op)) => Some(*op),
+            _ => None,
+        },
+        _ => None,
+    };
+    let operands = node.operands();
+    let terms = match (op, operands.len()) {
+        (Some(BuiltinOp::plus), _) => Some(operands.iter().flat_map(|o| monomials(o)).collect()),
+        (Some(BuiltinOp::minus), 1) => Some(
+            monomials(operands[0])
+                .into_iter()
+                .map(Monomial::negate)
+                .collect(),
+        ),
+        (Some(BuiltinOp::minus), 2) => {
+            let mut terms = monomials(operands[0]);
+            terms.extend(monomials(operands[1]).into_iter().map(Monomial::negate));
+            Some(terms)
+        }
+        (Some(BuiltinOp::times), _) => operands
+            .iter()
+            .try_fold(vec![Monomial::number(MathNode::integer(1))], |p, o| {
+                product(&p, &monomials(o))
+            }),
+        (Some(BuiltinOp::power), 2) => {
+            exponent(operands[1]).and_then(|n| power(&monomials(operands[0]), n))
+        }
+        _ => None,
+    };
+    match terms {
+        Some(terms) => combine(terms),
+        // Left as it is, with what is inside expanded
+        None => {
+            let mut atom = node.clone();
+            for child in atom.children_mut() {
+                *child = child.expand();
+            }
+            vec![Monomial::factor(atom)]
+        }
+    }
+}
+
+/// Adds up the monomials with the same factors, dropping those that cancel
+fn combine(terms: Vec<Monomial>) -> Vec<Monomial> {
+    let mut combined: Vec<Monomial> = Vec::new();
+    let mut by_key: HashMap<Vec<(u64, u32)>, Vec<usize>> = HashMap::new();
+    for term in terms {
+        let like_factors = |c: &Monomial| {
+            c.factors.len() == term.factors.len()
+                && c.factors.iter().all(|f| term.factors.contains(f))
+        };
+        let candidates = by_key.entry(term.key()).or_default();
+        match candidates.iter().find(|i| like_factors(&combined[**i])) {
+            Some(&i) => {
+                let like = &mut combined[i];
+                like.coefficient =
+                    fold_numbers(BuiltinOp::plus, &like.coefficient, &term.coefficient)
+                        .unwrap_or_else(|| (like.coefficient.clone() + term.coefficient).simplify())
+            }
+            None => {
+                candidates.push(combined.len());
+                combined.push(term)
+            }
+        }
+    }
+    combined.retain(|m| value(&m.coefficient) != Some(0.0));
+    combined
+}
+
+impl MathNode {
+    /// Multiplies out products of sums and integer powers of sums, and adds
+    /// up like terms, e.g. `x^2 + 2 * x + 1` for `(x + 1)^2`, where `a * b`
+    /// and `b * a` are alike. Products that would have more than a thousand
+    /// terms are left as they are
+    pub fn expand(&self) -> MathNode {
+        if let MathNode::Root { .. } = self {
+            let mut node = self.clone();
+            for child in node.children_mut() {
+                *child = child.expand();
+            }
+            return node;
+        }
+        signed_sum(monomials(self).iter().map(Monomial::to_node).collect())
+    }
+    /// Gathers the terms of a polynomial in `var` by power, e.g.
+    /// `(k1 + k2) * S + S^2` for `k1 * S + S^2 + k2 * S`. Expressions that
+    /// aren't polynomials in `var` are collected inside, as in `as_polynomial`
+    pub fn collect(&self, var: &str) -> MathNode {
+        let coefficients = match self.as_polynomial(var) {
+            Some(coefficients) if self.variables().contains(var) => coefficients,
+            Some(_) => return self.clone(),
+            None => {
+                let mut node = self.clone();
+                for child in node.children_mut() {
+                    *child = child.collect(var);
+                }
+                return node;
+            }
+        };
+        let terms = coefficients
+            .into_iter()
+            .enumerate()
+            .filter(|(_, c)| value(c) != Some(0.0))
+            .map(|(power, coefficient)| {
+                let factors = match power {
+                    0 => Vec::new(),
+                    _ => vec![(MathNode::ci(var), power as u32)],
+                };
+                Monomial {
+                    coefficient,
+                    factors,
+                }
+                .to_node()
+            })
+            .collect();
+        signed_sum(terms)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parse_infix;
+
+    #[test]
+    fn test_expand_collect() {
+        let expand = |text: &str| parse_infix(text).unwrap().expand().to_string();
+        assert_eq!(expand("(a + b) * (c - d)"), "a * c - a * d + b * c - b * d");
+        assert_eq!(expand("(x + 1)^2 - 2 * x"), "x^2 + 1");
+        assert_eq!(expand("a * b + b * a"), "2 * a * b");
+        assert_eq!(expand("k * (S - P) / (1 + S)"), "(k * S - k * P) / (1 + S)");
+        assert_eq!(expand("(2 * x)^3"), "8 * x^3");
+        assert_eq!(expand("x^4294967295 * x"), "x^4294967295 * x");
+        assert_eq!(expand("(x + y)^20000"), "(x + y)^20000");
+        let binomial = parse_infix("(x + 1)^200").unwrap().expand();
+        assert_eq!(binomial.to_string().matches('x').count(), 200);
+
+        let collect = |text: &str| parse_infix(text).unwrap().collect("S").to_string();
+        assert_eq!(collect("k1 * S + S^2 + k2 * S"), "(k1 + k2) * S + S^2");
+        assert_eq!(collect("v0 - k * S * S"), "v0 - k * S^2");
+        assert_eq!(collect("exp(S + S)"), "exp(2 * S)");
+    }
+}
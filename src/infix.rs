@@ -0,0 +1,747 @@
+use super::{BuiltinOp, ConstantSymbol, MathNode, NamedConstant, NumType, SbmlSymbol};
+use crate::error::ParseError;
+use crate::ops::parenthesize;
+
+/// The operators and punctuation of the infix syntax, longest first so that
+/// `<=` isn't read as `<`
+const SYMBOLS: &[&str] = &[
+    "&&", "||", "==", "!=", "<=", ">=", "+", "-", "*", "/", "%", "^", "!", "<", ">", "(", ")", ",",
+];
+
+/// The functions of libSBML's formula syntax whose name is that of the MathML
+/// element. Others, like `sqrt` or `acos`, are spelled differently
+const FUNCTIONS: &str = "abs arccos arccosh arccot arccoth arccsc arccsch arcsec arcsech arcsin \
+                         arcsinh arctan arctanh cos cosh cot coth csc csch exp factorial floor ln \
+                         power sec sech sin sinh tan tanh and or xor not eq neq geq gt leq lt plus \
+                         times minus divide max min quotient rem implies";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token<'a> {
+    Number(&'a str),
+    Name(&'a str),
+    Symbol(&'static str),
+    End,
+}
+
+pub(crate) fn invalid(position: usize, message: impl Into<String>) -> ParseError {
+    ParseError::InvalidFormula {
+        position,
+        message: message.into(),
+    }
+}
+
+/// Splits the formula into tokens, each with its byte offset
+fn tokenize(text: &str) -> Result<Vec<(Token<'_>, usize)>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let position = text.len() - rest.len();
+        let len = if c.is_ascii_digit() || c == '.' {
+            let mut len = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            // An exponent, which may be signed
+            if rest[len..].starts_with(['e', 'E']) {
+                let exponent = rest[len + 1..]
+                    .strip_prefix(['+', '-'])
+                    .unwrap_or(&rest[len + 1..]);
+                let digits = exponent
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(exponent.len());
+                if digits > 0 {
+                    len = rest.len() - exponent.len() + digits;
+                }
+            }
+            tokens.push((Token::Number(&rest[..len]), position));
+            len
+        } else if c.is_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push((Token::Name(&rest[..len]), position));
+            len
+        } else {
+            match SYMBOLS.iter().find(|s| rest.starts_with(**s)) {
+                Some(symbol) => {
+                    tokens.push((Token::Symbol(symbol), position));
+                    symbol.len()
+                }
+                None => return Err(invalid(position, format!("unexpected '{}'", c))),
+            }
+        };
+        rest = rest[len..].trim_start();
+    }
+    tokens.push((Token::End, text.len()));
+    Ok(tokens)
+}
+
+fn number(text: &str, position: usize) -> Result<MathNode, ParseError> {
+    let error = || invalid(position, format!("'{}' is not a number", text));
+    let num_type = match text.find(['e', 'E']) {
+        Some(e) => NumType::ENotation(
+            text[..e].parse().map_err(|_| error())?,
+            text[e + 1..].parse().map_err(|_| error())?,
+        ),
+        None if text.contains('.') => NumType::Real(text.parse().map_err(|_| error())?),
+        None => match text.parse() {
+            Ok(integer) => NumType::Integer(integer),
+            Err(_) => NumType::Real(text.parse().map_err(|_| error())?),
+        },
+    };
+    Ok(MathNode::number(num_type))
+}
+
+/// `-2` is read as a negative number rather than the negation of 2, as libSBML does
+pub(crate) fn negated(node: MathNode) -> MathNode {
+    match node {
+        MathNode::Cn {
+            num_type: NumType::Integer(i),
+            ..
+        } if i != i64::MIN => MathNode::integer(-i),
+        MathNode::Cn {
+            num_type: NumType::Real(r),
+            ..
+        } => MathNode::cn(-r),
+        MathNode::Cn {
+            num_type: NumType::ENotation(m, e),
+            ..
+        } => MathNode::number(NumType::ENotation(-m, e)),
+        node => MathNode::apply(BuiltinOp::minus, vec![node]),
+    }
+}
+
+fn sbml_symbol(symbol: SbmlSymbol, name: &str) -> MathNode {
+    MathNode::Csymbol {
+        definition_url: Some(symbol.url()),
+        cd: None,
+        encoding: Some("text".to_owned()),
+        name: name.to_owned(),
+        sbml: Some(symbol),
+        attributes: Vec::new(),
+    }
+}
+
+/// A name that isn't called: a constant, `time`, `avogadro` or an identifier
+fn symbol(name: &str) -> MathNode {
+    let constant = match name.to_ascii_lowercase().as_str() {
+        "true" => ConstantSymbol::True,
+        "false" => ConstantSymbol::False,
+        "pi" => ConstantSymbol::Pi,
+        "exponentiale" => ConstantSymbol::ExponentialE,
+        "inf" | "infinity" => ConstantSymbol::Infinity,
+        "nan" | "notanumber" => ConstantSymbol::NotANumber,
+        "time" => return sbml_symbol(SbmlSymbol::Time, name),
+        "avogadro" => return sbml_symbol(SbmlSymbol::Avogadro, name),
+        _ => return MathNode::ci(name),
+    };
+    MathNode::Constant(constant)
+}
+
+/// A call `name(args)`, either of a builtin function or of a function definition
+fn function(name: &str, mut args: Vec<MathNode>, position: usize) -> Result<MathNode, ParseError> {
+    use BuiltinOp::*;
+    let lowercase = name.to_ascii_lowercase();
+    let qualified = |op: BuiltinOp, qualifier: fn(Box<MathNode>) -> MathNode, mut args: Vec<_>| {
+        let first = Box::new(args.remove(0));
+        args.insert(0, qualifier(first));
+        MathNode::apply(op, args)
+    };
+    let op = match lowercase.as_str() {
+        "acos" => arccos,
+        "acosh" => arccosh,
+        "acot" => arccot,
+        "acoth" => arccoth,
+        "acsc" => arccsc,
+        "acsch" => arccsch,
+        "asec" => arcsec,
+        "asech" => arcsech,
+        "asin" => arcsin,
+        "asinh" => arcsinh,
+        "atan" => arctan,
+        "atanh" => arctanh,
+        "ceil" | "ceiling" => ceiling,
+        "pow" => power,
+        "sqrt" => root,
+        // The base-10 logarithm is MathML's `<log/>` without a `<logbase>`
+        "log10" => log,
+        "log" if args.len() == 2 => return Ok(qualified(log, MathNode::Logbase, args)),
+        // As in SBML Level 1, a logarithm without a base is the natural one
+        "log" => ln,
+        "root" if args.len() == 2 => return Ok(qualified(root, MathNode::Degree, args)),
+        "root" => root,
+        "delay" => return Ok(MathNode::call(sbml_symbol(SbmlSymbol::Delay, name), args)),
+        "rateof" => return Ok(MathNode::call(sbml_symbol(SbmlSymbol::RateOf, name), args)),
+        "piecewise" => {
+            if args.is_empty() {
+                return Err(invalid(position, "piecewise needs at least one argument"));
+            }
+            let otherwise = if args.len() % 2 == 1 {
+                args.pop()
+            } else {
+                None
+            };
+            let mut pieces = Vec::new();
+            let mut args = args.into_iter();
+            while let (Some(value), Some(condition)) = (args.next(), args.next()) {
+                pieces.push((value, condition));
+            }
+            return Ok(MathNode::piecewise(pieces, otherwise));
+        }
+        builtin if FUNCTIONS.split_whitespace().any(|f| f == builtin) => {
+            serde_plain::from_str(builtin).expect("FUNCTIONS only lists operators")
+        }
+        _ => return Ok(MathNode::call(MathNode::ci(name), args)),
+    };
+    Ok(MathNode::apply(op, args))
+}
+
+/// The binary operators, loosest first, with the operator each applies and
+/// whether a chain like `a + b + c` becomes a single n-ary application
+const LEVELS: &[&[(&str, BuiltinOp, bool)]] = &[
+    &[("&&", BuiltinOp::and, true), ("||", BuiltinOp::or, true)],
+    &[
+        ("==", BuiltinOp::eq, true),
+        ("!=", BuiltinOp::neq, false),
+        ("<", BuiltinOp::lt, true),
+        (">", BuiltinOp::gt, true),
+        ("<=", BuiltinOp::leq, true),
+        (">=", BuiltinOp::geq, true),
+    ],
+    &[("+", BuiltinOp::plus, true), ("-", BuiltinOp::minus, false)],
+    &[
+        ("*", BuiltinOp::times, true),
+        ("/", BuiltinOp::divide, false),
+        ("%", BuiltinOp::rem, false),
+    ],
+];
+
+struct Parser<'a> {
+    tokens: Vec<(Token<'a>, usize)>,
+    next: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> (Token<'a>, usize) {
+        self.tokens[self.next]
+    }
+    fn advance(&mut self) -> (Token<'a>, usize) {
+        let token = self.peek();
+        if token.0 != Token::End {
+            self.next += 1;
+        }
+        token
+    }
+    fn eat(&mut self, symbol: &'static str) -> bool {
+        let found = self.peek().0 == Token::Symbol(symbol);
+        if found {
+            self.advance();
+        }
+        found
+    }
+    fn expect(&mut self, symbol: &'static str) -> Result<(), ParseError> {
+        if self.eat(symbol) {
+            return Ok(());
+        }
+        let (token, position) = self.peek();
+        Err(invalid(
+            position,
+            format!("expected '{}', found {}", symbol, describe(token)),
+        ))
+    }
+    /// Binary operators of `LEVELS[level]` and tighter, all left associative
+    fn binary(&mut self, level: usize) -> Result<MathNode, ParseError> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        // The operator of `lhs` if it was built by this loop and can take more operands
+        let mut chained: Option<BuiltinOp> = None;
+        loop {
+            let found = match self.peek().0 {
+                Token::Symbol(symbol) => LEVELS[level].iter().find(|(s, _, _)| *s == symbol),
+                _ => None,
+            };
+            let (_, op, nary) = match found {
+                Some(found) => *found,
+                None => return Ok(lhs),
+            };
+            self.advance();
+            let rhs = self.binary(level + 1)?;
+            lhs = match lhs {
+                MathNode::Apply {
+                    mut children,
+                    attributes,
+                } if chained == Some(op) => {
+                    children.push(rhs);
+                    MathNode::Apply {
+                        children,
+                        attributes,
+                    }
+                }
+                lhs => MathNode::apply(op, vec![lhs, rhs]),
+            };
+            chained = if nary { Some(op) } else { None };
+        }
+    }
+    /// Negation and `!`, which bind looser than `^`, so `-x^2` is `-(x^2)`
+    fn unary(&mut self) -> Result<MathNode, ParseError> {
+        if self.eat("-") {
+            Ok(negated(self.unary()?))
+        } else if self.eat("+") {
+            self.unary()
+        } else if self.eat("!") {
+            Ok(MathNode::apply(BuiltinOp::not, vec![self.unary()?]))
+        } else {
+            self.power()
+        }
+    }
+    /// `^`, left associative like libSBML's, so `a^b^c` is `(a^b)^c`. The
+    /// exponent may be negated, as in `x^-1`
+    fn power(&mut self) -> Result<MathNode, ParseError> {
+        let mut base = self.primary()?;
+        while self.eat("^") {
+            let mut negations = 0;
+            while self.eat("-") {
+                negations += 1;
+            }
+            let mut exponent = self.primary()?;
+            for _ in 0..negations {
+                exponent = negated(exponent);
+            }
+            base = MathNode::apply(BuiltinOp::power, vec![base, exponent]);
+        }
+        Ok(base)
+    }
+    fn primary(&mut self) -> Result<MathNode, ParseError> {
+        match self.advance() {
+            (Token::Number(text), position) => number(text, position),
+            (Token::Name(name), position) => {
+                if !self.eat("(") {
+                    return Ok(symbol(name));
+                }
+                let mut args = Vec::new();
+                if !self.eat(")") {
+                    loop {
+                        args.push(self.binary(0)?);
+                        if self.eat(")") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+                function(name, args, position)
+            }
+            (Token::Symbol("("), _) => {
+                let inner = self.binary(0)?;
+                self.expect(")")?;
+                Ok(inner)
+            }
+            (token, position) => Err(invalid(
+                position,
+                format!("expected an expression, found {}", describe(token)),
+            )),
+        }
+    }
+}
+
+fn describe(token: Token) -> String {
+    match token {
+        Token::Number(text) | Token::Name(text) => format!("'{}'", text),
+        Token::Symbol(symbol) => format!("'{}'", symbol),
+        Token::End => "the end of the formula".to_owned(),
+    }
+}
+
+/// Parses a formula in the infix syntax of SBML Level 1 and libSBML's
+/// `SBML_parseL3Formula`, e.g. `k1*S1/(Km+S1)`, into the tree its MathML would
+/// give. It has `^`, `%`, the relations, `&&`, `||` and `!`, the constants
+/// `pi`, `exponentiale`, `true`, `false`, `inf` and `NaN`, `time` and
+/// `avogadro`, and libSBML's functions, e.g. `pow(x, 2)`, `root(3, x)`,
+/// `log(2, x)`, `piecewise(1, x > 0, 0)` or `delay(S, 5)`, whose names are
+/// case insensitive. Calls of other names apply a function definition
+pub fn parse_infix(text: &str) -> Result<MathNode, ParseError> {
+    let mut parser = Parser {
+        tokens: tokenize(text)?,
+        next: 0,
+    };
+    let node = parser.binary(0)?;
+    match parser.peek() {
+        (Token::End, _) => Ok(node),
+        (token, position) => Err(invalid(
+            position,
+            format!("expected an operator, found {}", describe(token)),
+        )),
+    }
+}
+
+/// How `MathNode::to_infix_with` writes formulas. `Default` gives libSBML's
+/// `formulaToL3String` syntax, with `&&`, `>` and `x^y`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct InfixOptions {
+    /// Write powers as `pow(x, y)` rather than `x^y`
+    pub power_function: bool,
+    /// Write logical operators and relations as functions, e.g. `and(a, b)`
+    /// and `gt(x, 0)`, and the natural logarithm as `log(x)`, as SBML Level 1
+    /// does
+    pub level1: bool,
+}
+
+impl InfixOptions {
+    /// The syntax of libSBML's `formulaToString` and SBML Level 1
+    pub fn level1() -> Self {
+        InfixOptions {
+            power_function: true,
+            level1: true,
+        }
+    }
+}
+
+/// Precedences of the infix syntax as `parse_infix` reads it, higher binds tighter
+const PREC_LOGIC: u8 = 2;
+const PREC_RELATION: u8 = 3;
+const PREC_ADD: u8 = 4;
+const PREC_MUL: u8 = 5;
+const PREC_UNARY: u8 = 6;
+const PREC_POWER: u8 = 7;
+const PREC_ATOM: u8 = 8;
+
+/// A formula together with the precedence of its outermost operator
+struct Formula {
+    text: String,
+    precedence: u8,
+}
+
+impl Formula {
+    fn new(text: String, precedence: u8) -> Self {
+        Formula { text, precedence }
+    }
+    fn atom(text: String) -> Self {
+        Formula::new(text, PREC_ATOM)
+    }
+    fn wrap(self, min_precedence: u8) -> String {
+        parenthesize(self.text, self.precedence, min_precedence)
+    }
+}
+
+fn call(name: &str, args: Vec<Formula>) -> Formula {
+    let args: Vec<String> = args.into_iter().map(|a| a.text).collect();
+    Formula::atom(format!("{}({})", name, args.join(", ")))
+}
+
+fn real(value: f64) -> Formula {
+    if value.is_nan() {
+        Formula::atom("NaN".to_owned())
+    } else if value.is_infinite() {
+        let text = if value > 0.0 { "INF" } else { "-INF" };
+        Formula::new(
+            text.to_owned(),
+            if value > 0.0 { PREC_ATOM } else { PREC_UNARY },
+        )
+    } else {
+        signed(format!("{:?}", value))
+    }
+}
+
+/// A number whose text may start with a minus
+fn signed(text: String) -> Formula {
+    let precedence = if text.starts_with('-') {
+        PREC_UNARY
+    } else {
+        PREC_ATOM
+    };
+    Formula::new(text, precedence)
+}
+
+fn literal(num_type: &NumType) -> Formula {
+    match num_type {
+        NumType::Real(r) => real(*r),
+        NumType::Integer(i) => signed(i.to_string()),
+        NumType::ENotation(m, e) => signed(format!("{}e{}", m, e)),
+        NumType::Rational(a, b) => Formula::atom(format!("({}/{})", a, b)),
+        NumType::Constant(NamedConstant::Pi) => Formula::atom("pi".to_owned()),
+        NumType::Constant(NamedConstant::E) => Formula::atom("exponentiale".to_owned()),
+        NumType::Constant(constant) => match constant.value() {
+            Some(value) => real(value),
+            None => Formula::atom(constant.symbol().to_owned()),
+        },
+        NumType::Other { text, .. } => signed(text.clone()),
+        #[cfg(feature = "bigint")]
+        NumType::BigInteger(i) => signed(i.to_string()),
+        // Complex numbers have no literal, so they're written as arithmetic
+        NumType::ComplexCartesian(a, b) => {
+            Formula::new(format!("{:?} + {:?} * sqrt(-1)", a, b), PREC_ADD)
+        }
+        NumType::ComplexPolar(a, b) => {
+            Formula::new(format!("{:?} * exp({:?} * sqrt(-1))", a, b), PREC_MUL)
+        }
+    }
+}
+
+/// The name of `op` in a function call
+fn function_name(op: &BuiltinOp, opts: &InfixOptions) -> String {
+    use BuiltinOp::*;
+    let name = match op {
+        arccos => "acos",
+        arcsin => "asin",
+        arctan => "atan",
+        ceiling => "ceil",
+        power => "pow",
+        ln if opts.level1 => "log",
+        log => "log10",
+        root => "sqrt",
+        _ => return op.to_string(),
+    };
+    name.to_owned()
+}
+
+/// The symbol of operators written between their arguments in the Level 3 syntax
+fn infix_symbol(op: &BuiltinOp) -> Option<&'static str> {
+    use BuiltinOp::*;
+    let symbol = match op {
+        plus => " + ",
+        minus => " - ",
+        times => " * ",
+        divide => " / ",
+        and => " && ",
+        or => " || ",
+        eq => " == ",
+        neq => " != ",
+        gt => " > ",
+        lt => " < ",
+        geq => " >= ",
+        leq => " <= ",
+        _ => return None,
+    };
+    Some(symbol)
+}
+
+fn formula_op(op: &BuiltinOp, apply: &MathNode, opts: &InfixOptions) -> Formula {
+    use BuiltinOp::*;
+    let args: Vec<Formula> = apply.operands().iter().map(|a| formula(a, opts)).collect();
+    match (op, apply.degree(), apply.logbase()) {
+        (root, Some(degree), _) => {
+            let mut args = args;
+            args.insert(0, formula(degree, opts));
+            return call("root", args);
+        }
+        (log, _, Some(base)) => {
+            let mut args = args;
+            args.insert(0, formula(base, opts));
+            return call("log", args);
+        }
+        _ => {}
+    }
+    let logic = matches!(op, and | or | not | eq | neq | gt | lt | geq | leq);
+    if logic && opts.level1 {
+        return call(&op.to_string(), args);
+    }
+    match (op, args.len()) {
+        (minus, 1) => {
+            let operand = args.into_iter().next().map(|a| a.wrap(PREC_UNARY + 1));
+            Formula::new(format!("-{}", operand.unwrap_or_default()), PREC_UNARY)
+        }
+        (not, 1) => {
+            let operand = args.into_iter().next().map(|a| a.wrap(PREC_UNARY + 1));
+            Formula::new(format!("!{}", operand.unwrap_or_default()), PREC_UNARY)
+        }
+        // Both operands are parenthesized when compound, as `^` associates
+        // differently in different tools
+        (power, 2) if !opts.power_function => {
+            let mut args = args.into_iter().map(|a| a.wrap(PREC_POWER + 1));
+            let (base, exponent) = (args.next(), args.next());
+            Formula::new(
+                format!(
+                    "{}^{}",
+                    base.unwrap_or_default(),
+                    exponent.unwrap_or_default()
+                ),
+                PREC_POWER,
+            )
+        }
+        (_, n) if n >= 2 => match infix_symbol(op) {
+            Some(symbol) => {
+                let precedence = match op {
+                    plus | minus => PREC_ADD,
+                    times | divide => PREC_MUL,
+                    and | or => PREC_LOGIC,
+                    _ => PREC_RELATION,
+                };
+                // Left associative, and `&&` and `||` don't mix without parentheses
+                let first_precedence = if precedence == PREC_ADD || precedence == PREC_MUL {
+                    precedence
+                } else {
+                    precedence + 1
+                };
+                let text = args
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, a)| {
+                        a.wrap(if i == 0 {
+                            first_precedence
+                        } else {
+                            precedence + 1
+                        })
+                    })
+                    .collect::<Vec<_>>()
+                    .join(symbol);
+                Formula::new(text, precedence)
+            }
+            None => call(&function_name(op, opts), args),
+        },
+        _ => call(&function_name(op, opts), args),
+    }
+}
+
+fn formula(node: &MathNode, opts: &InfixOptions) -> Formula {
+    match node {
+        MathNode::Root { children, .. } => Formula::atom(
+            children
+                .iter()
+                .filter(|c| !c.is_markup())
+                .map(|c| formula(c, opts).text)
+                .collect::<Vec<_>>()
+                .join("; "),
+        ),
+        MathNode::Apply { children, .. } if node.bound_variables().is_empty() => {
+            let args = || {
+                node.operands()
+                    .iter()
+                    .map(|a| formula(a, opts))
+                    .collect::<Vec<_>>()
+            };
+            match children.first() {
+                Some(MathNode::Op(op)) => formula_op(op, node, opts),
+                Some(head @ MathNode::Ci { .. }) | Some(head @ MathNode::Csymbol { .. }) => {
+                    call(&formula(head, opts).text, args())
+                }
+                _ => Formula::atom(node.to_string()),
+            }
+        }
+        MathNode::Ci { children, .. } => Formula::atom(crate::text_content(children)),
+        MathNode::Csymbol { sbml, name, .. } => Formula::atom(match sbml {
+            Some(SbmlSymbol::Time) => "time".to_owned(),
+            Some(SbmlSymbol::Avogadro) => "avogadro".to_owned(),
+            Some(SbmlSymbol::Delay) => "delay".to_owned(),
+            Some(SbmlSymbol::RateOf) => "rateOf".to_owned(),
+            None => name.clone(),
+        }),
+        MathNode::Cn { num_type, .. } => literal(num_type),
+        MathNode::Constant(constant) => Formula::atom(
+            match constant {
+                ConstantSymbol::Pi => "pi",
+                ConstantSymbol::ExponentialE => "exponentiale",
+                ConstantSymbol::Infinity => "INF",
+                ConstantSymbol::NotANumber => "NaN",
+                ConstantSymbol::True => "true",
+                ConstantSymbol::False => "false",
+                ConstantSymbol::EulerGamma => "0.5772156649015329",
+                ConstantSymbol::ImaginaryI => "sqrt(-1)",
+            }
+            .to_owned(),
+        ),
+        MathNode::Piecewise { pieces, otherwise } => {
+            let mut args = Vec::new();
+            for (value, condition) in pieces {
+                args.push(formula(value, opts));
+                args.push(formula(condition, opts));
+            }
+            if let Some(otherwise) = otherwise {
+                args.push(formula(otherwise, opts));
+            }
+            call("piecewise", args)
+        }
+        MathNode::Lambda { bound_vars, body } => {
+            let mut args: Vec<Formula> = bound_vars.iter().cloned().map(Formula::atom).collect();
+            args.push(formula(body, opts));
+            call("lambda", args)
+        }
+        MathNode::Semantics { primary, .. } => formula(primary, opts),
+        // No infix syntax has these, e.g. sums over bound variables or sets
+        _ => Formula::atom(node.to_string()),
+    }
+}
+
+impl MathNode {
+    /// Writes the expression as an SBML infix formula like libSBML's
+    /// `formulaToL3String`, e.g. `k1 * S1 / (Km + S1)`, which `parse_infix`,
+    /// libSBML, Antimony and COPASI read back. Things no infix syntax has,
+    /// like sums over bound variables, are written as `Display` does
+    pub fn to_infix(&self) -> String {
+        self.to_infix_with(&InfixOptions::default())
+    }
+    /// Like `to_infix`, in the syntax `opts` chooses
+    pub fn to_infix_with(&self, opts: &InfixOptions) -> String {
+        formula(self, opts).text
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_infix, InfixOptions};
+    use crate::error::ParseError;
+    use crate::parse_document;
+
+    #[test]
+    fn test_parse_infix() {
+        let expected = parse_document(
+            r#"<apply><divide/><apply><times/><ci>k1</ci><ci>S1</ci></apply><apply><plus/><ci>Km</ci><ci>S1</ci></apply></apply>"#,
+        )
+        .unwrap();
+        assert_eq!(parse_infix("k1*S1/(Km+S1)").unwrap(), expected);
+
+        let test = "-x^2 + a - b - c * 2.5e-3 + ceil(-3) + log(2, y) + Pow(x, 0.5)";
+        assert_eq!(
+            parse_infix(test).unwrap().to_string(),
+            "-x^2 + a - b - c * 2.5e-3 + ceiling(-3) + log(2, y) + x^0.5"
+        );
+        let test = "piecewise(1, time > 5 && !(S < 2 || S >= 10), 0) * f(S, delay(S, 1))";
+        assert_eq!(
+            parse_infix(test).unwrap().to_string(),
+            "piecewise(1, time > 5 && !(S < 2 || S >= 10), 0) * f(S, delay(S, 1))"
+        );
+        assert_eq!(
+            parse_infix("2^3^2").unwrap().eval(&Default::default()),
+            Ok(64.0)
+        );
+
+        match parse_infix("k1 * (S1 + ") {
+            Err(ParseError::InvalidFormula { position, .. }) => assert_eq!(position, 11),
+            other => panic!("expected an invalid formula, got {:?}", other),
+        }
+        assert!(parse_infix("a b").is_err());
+        assert!(parse_infix("a # b").is_err());
+    }
+    #[test]
+    fn test_to_infix() {
+        let test = r#"<apply><plus/>
+                        <apply><divide/><apply><times/><ci>k1</ci><ci>S1</ci></apply><apply><plus/><ci>Km</ci><ci>S1</ci></apply></apply>
+                        <apply><power/><apply><minus/><ci>x</ci></apply><cn>-2</cn></apply>
+                        <apply><minus/><ci>a</ci><apply><minus/><ci>b</ci><ci>c</ci></apply></apply>
+                        <apply><ln/><ci>x</ci></apply>
+                        <apply><log/><ci>x</ci></apply>
+                        <apply><root/><degree><cn>3</cn></degree><ci>x</ci></apply>
+                        <apply><arcsin/><ci>x</ci></apply>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        let l3 = parsed.to_infix();
+        assert_eq!(
+            l3,
+            "k1 * S1 / (Km + S1) + (-x)^(-2.0) + (a - (b - c)) + ln(x) + log10(x) + root(3.0, x) + asin(x)"
+        );
+        assert_eq!(parse_infix(&l3).unwrap(), parsed);
+        let l1 = parsed.to_infix_with(&InfixOptions::level1());
+        assert_eq!(
+            l1,
+            "k1 * S1 / (Km + S1) + pow(-x, -2.0) + (a - (b - c)) + log(x) + log10(x) + root(3.0, x) + asin(x)"
+        );
+        assert_eq!(parse_infix(&l1).unwrap(), parsed);
+
+        let test = "piecewise(1, time > 5 && !(S < 2 || S >= 10), 0.5) * f(S, delay(S, 1))";
+        let parsed = parse_infix(test).unwrap();
+        assert_eq!(parsed.to_infix(), test);
+        assert_eq!(
+            parsed.to_infix_with(&InfixOptions::level1()),
+            "piecewise(1, and(gt(time, 5), not(or(lt(S, 2), geq(S, 10)))), 0.5) * f(S, delay(S, 1))"
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use super::{BuiltinOp, MathNode};
+use crate::compile::{CompiledExpr, Instr};
+use crate::error::EvalError;
+use crate::eval::{eval_op, nth_root};
+use cranelift_codegen::ir::condcodes::FloatCC;
+use cranelift_codegen::ir::{types, AbiParam, InstBuilder, MemFlags, StackSlotData, StackSlotKind};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Linkage, Module};
+
+/// An expression compiled to machine code with Cranelift, for when even
+/// `CompiledExpr` is the bottleneck of a simulation. Arithmetic runs inline,
+/// other operators call back into the interpreter's implementation of them
+pub struct JitExpr {
+    /// Owns the code, freed on drop
+    module: Option<JITModule>,
+    function: extern "C" fn(*const f64) -> f64,
+    variables: usize,
+    /// The operators the code passes to `apply`, by address
+    _ops: Box<[BuiltinOp]>,
+}
+
+extern "C" fn apply(op: *const BuiltinOp, args: *const f64, arity: usize) -> f64 {
+    // Safety: the generated code passes an operator of `JitExpr::ops` and a
+    // stack slot holding `arity` values
+    let (op, args) = unsafe { (&*op, std::slice::from_raw_parts(args, arity)) };
+    eval_op(op, args).unwrap_or(f64::NAN)
+}
+extern "C" fn root(x: f64, degree: f64) -> f64 {
+    nth_root(x, degree)
+}
+extern "C" fn log_base(x: f64, base: f64) -> f64 {
+    x.log10() / base.log10()
+}
+
+fn codegen_error(error: impl std::fmt::Display) -> EvalError {
+    EvalError::CodeGeneration(error.to_string())
+}
+
+impl JitExpr {
+    /// Compiles `node`, where identifiers are looked up by their position in
+    /// `variables`. Fails where `CompiledExpr::compile` would, or if the
+    /// machine isn't supported by Cranelift
+    pub fn compile(node: &MathNode, variables: &[&str]) -> Result<JitExpr, EvalError> {
+        let compiled = CompiledExpr::compile(node, variables)?;
+        let ops: Box<[BuiltinOp]> = compiled
+            .code
+            .iter()
+            .filter_map(|instr| match instr {
+                Instr::Apply(op, _) => Some(*op),
+                _ => None,
+            })
+            .collect();
+
+        let mut flags = settings::builder();
+        flags
+            .set("use_colocated_libcalls", "false")
+            .map_err(codegen_error)?;
+        flags.set("is_pic", "false").map_err(codegen_error)?;
+        flags.set("opt_level", "speed").map_err(codegen_error)?;
+        let isa = cranelift_native::builder()
+            .map_err(codegen_error)?
+            .finish(settings::Flags::new(flags))
+            .map_err(codegen_error)?;
+        let mut builder = JITBuilder::with_isa(isa, default_libcall_names());
+        builder.symbol("mathml_apply", apply as *const u8);
+        builder.symbol("mathml_root", root as *const u8);
+        builder.symbol("mathml_log_base", log_base as *const u8);
+        let mut module = JITModule::new(builder);
+        let pointer = module.target_config().pointer_type();
+
+        let mut apply_signature = module.make_signature();
+        apply_signature.params.extend([AbiParam::new(pointer); 3]);
+        apply_signature.returns.push(AbiParam::new(types::F64));
+        let mut binary_signature = module.make_signature();
+        binary_signature
+            .params
+            .extend([AbiParam::new(types::F64); 2]);
+        binary_signature.returns.push(AbiParam::new(types::F64));
+        let apply_id = module
+            .declare_function("mathml_apply", Linkage::Import, &apply_signature)
+            .map_err(codegen_error)?;
+        let root_id = module
+            .declare_function("mathml_root", Linkage::Import, &binary_signature)
+            .map_err(codegen_error)?;
+        let log_base_id = module
+            .declare_function("mathml_log_base", Linkage::Import, &binary_signature)
+            .map_err(codegen_error)?;
+
+        let mut context = module.make_context();
+        context.func.signature.params.push(AbiParam::new(pointer));
+        context
+            .func
+            .signature
+            .returns
+            .push(AbiParam::new(types::F64));
+        let mut builder_context = FunctionBuilderContext::new();
+        let mut b = FunctionBuilder::new(&mut context.func, &mut builder_context);
+        let block = b.create_block();
+        b.append_block_params_for_function_params(block);
+        b.switch_to_block(block);
+        b.seal_block(block);
+        let values = b.block_params(block)[0];
+        let apply_ref = module.declare_func_in_func(apply_id, b.func);
+        let root_ref = module.declare_func_in_func(root_id, b.func);
+        let log_base_ref = module.declare_func_in_func(log_base_id, b.func);
+
+        // The stack of `CompiledExpr` becomes one of SSA values
+        let mut stack = Vec::with_capacity(compiled.max_stack);
+        let mut ops_used = 0;
+        for instr in &compiled.code {
+            let value = match instr {
+                Instr::Const(value) => b.ins().f64const(*value),
+                Instr::Var(index) => {
+                    let offset = (index * std::mem::size_of::<f64>()) as i32;
+                    b.ins()
+                        .load(types::F64, MemFlags::trusted(), values, offset)
+                }
+                Instr::Apply(op, arity) => {
+                    let args = stack.split_off(stack.len() - arity);
+                    let op_address = &ops[ops_used] as *const BuiltinOp as i64;
+                    ops_used += 1;
+                    match (op, args.as_slice()) {
+                        (BuiltinOp::plus, [a, b2]) => b.ins().fadd(*a, *b2),
+                        (BuiltinOp::minus, [a, b2]) => b.ins().fsub(*a, *b2),
+                        (BuiltinOp::minus, [a]) => b.ins().fneg(*a),
+                        (BuiltinOp::times, [a, b2]) => b.ins().fmul(*a, *b2),
+                        (BuiltinOp::divide, [a, b2]) => b.ins().fdiv(*a, *b2),
+                        _ => {
+                            let size = (args.len().max(1) * std::mem::size_of::<f64>()) as u32;
+                            let slot = b.create_sized_stack_slot(StackSlotData::new(
+                                StackSlotKind::ExplicitSlot,
+                                size,
+                                3,
+                            ));
+                            for (i, arg) in args.iter().enumerate() {
+                                b.ins().stack_store(*arg, slot, (i * 8) as i32);
+                            }
+                            let op = b.ins().iconst(pointer, op_address);
+                            let address = b.ins().stack_addr(pointer, slot, 0);
+                            let arity = b.ins().iconst(pointer, args.len() as i64);
+                            let call = b.ins().call(apply_ref, &[op, address, arity]);
+                            b.inst_results(call)[0]
+                        }
+                    }
+                }
+                Instr::NthRoot | Instr::LogBase => {
+                    let second = stack.pop().expect("compiled code keeps the stack balanced");
+                    let first = stack.pop().expect("compiled code keeps the stack balanced");
+                    let function = match instr {
+                        Instr::NthRoot => root_ref,
+                        _ => log_base_ref,
+                    };
+                    let call = b.ins().call(function, &[first, second]);
+                    b.inst_results(call)[0]
+                }
+                Instr::Piecewise(pieces, otherwise) => {
+                    let mut result = if *otherwise {
+                        stack.pop().expect("compiled code keeps the stack balanced")
+                    } else {
+                        b.ins().f64const(f64::NAN)
+                    };
+                    let branches = stack.split_off(stack.len() - 2 * pieces);
+                    let zero = b.ins().f64const(0.0);
+                    // Built from the last piece, so the first that holds wins
+                    for piece in branches.chunks(2).rev() {
+                        let holds = b.ins().fcmp(FloatCC::NotEqual, piece[1], zero);
+                        result = b.ins().select(holds, piece[0], result);
+                    }
+                    result
+                }
+            };
+            stack.push(value);
+        }
+        let result = stack.pop().expect("compiled code leaves a result");
+        b.ins().return_(&[result]);
+        b.finalize();
+
+        let id = module
+            .declare_anonymous_function(&context.func.signature)
+            .map_err(codegen_error)?;
+        module
+            .define_function(id, &mut context)
+            .map_err(codegen_error)?;
+        module.clear_context(&mut context);
+        module.finalize_definitions().map_err(codegen_error)?;
+        let code = module.get_finalized_function(id);
+        // Safety: the function was just defined with this signature
+        let function =
+            unsafe { std::mem::transmute::<*const u8, extern "C" fn(*const f64) -> f64>(code) };
+        Ok(JitExpr {
+            module: Some(module),
+            function,
+            variables: variables.len(),
+            _ops: ops,
+        })
+    }
+    /// Evaluates the expression, `values` holding the variables in the order
+    /// given to `compile`. Panics if there are fewer values than variables
+    pub fn eval(&self, values: &[f64]) -> f64 {
+        assert!(
+            values.len() >= self.variables,
+            "expected {} values, got {}",
+            self.variables,
+            values.len()
+        );
+        (self.function)(values.as_ptr())
+    }
+}
+
+impl Drop for JitExpr {
+    fn drop(&mut self) {
+        if let Some(module) = self.module.take() {
+            // Safety: `function` can't be called once the expression is dropped
+            unsafe { module.free_memory() }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::JitExpr;
+    use crate::compile::CompiledExpr;
+    use crate::parse_document;
+
+    #[test]
+    fn test_jit() {
+        let test = r#"<apply><plus/>
+                        <apply><divide/><apply><times/><ci>Vmax</ci><ci>S</ci></apply><apply><plus/><ci>Km</ci><ci>S</ci></apply></apply>
+                        <apply><root/><degree><cn>3</cn></degree><ci>S</ci></apply>
+                        <apply><log/><logbase><cn>2</cn></logbase><apply><max/><cn>1</cn><ci>Km</ci></apply></apply>
+                        <apply><minus/><apply><sin/><ci>S</ci></apply></apply>
+                        <piecewise>
+                          <piece><cn>1</cn><apply><gt/><ci>S</ci><cn>10</cn></apply></piece>
+                          <piece><cn>2</cn><apply><gt/><ci>S</ci><cn>0</cn></apply></piece>
+                        </piecewise>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        let jit = JitExpr::compile(&parsed, &["S", "Vmax", "Km"]).unwrap();
+        let compiled = CompiledExpr::compile(&parsed, &["S", "Vmax", "Km"]).unwrap();
+        for s in &[2.0, 27.0] {
+            approx::assert_abs_diff_eq!(
+                jit.eval(&[*s, 10.0, 4.0]),
+                compiled.eval(&[*s, 10.0, 4.0])
+            );
+        }
+        // No piece applies
+        assert!(jit.eval(&[-8.0, 10.0, 4.0]).is_nan());
+    }
+}
@@ -0,0 +1,730 @@
+use super::{
+    Annotation, BuiltinOp, ConstantSymbol, MathNode, NamedConstant, NumType, NumberSet,
+    PresentationNode,
+};
+use crate::content_to_presentation::{Binder, Order};
+use crate::entities;
+use crate::ops::{is_relation, PREC_ATOM, PREC_POWER, PREC_UNARY};
+
+/// A piece of rendered LaTeX together with the precedence of its outermost operator
+struct Latex {
+    text: String,
+    precedence: u8,
+}
+
+impl Latex {
+    fn new(text: String, precedence: u8) -> Self {
+        Latex { text, precedence }
+    }
+    fn atom(text: String) -> Self {
+        Latex::new(text, PREC_ATOM)
+    }
+    /// Like `parenthesize`, with `\left(` and `\right)` so that they grow with the text
+    fn wrap(self, min_precedence: u8) -> String {
+        if self.precedence < min_precedence {
+            format!("\\left({}\\right)", self.text)
+        } else {
+            self.text
+        }
+    }
+}
+
+/// Turns a preserved entity like `&tau;` into `\tau`, or escapes the text for LaTeX
+fn latex_text(text: &str) -> String {
+    match entities::entity_name(text) {
+        Some(name) => format!("\\{}", name),
+        None => text
+            .replace('\\', "\\backslash ")
+            .replace('_', "\\_")
+            .replace('{', "\\{")
+            .replace('}', "\\}"),
+    }
+}
+
+fn identifier(name: &str) -> String {
+    if name.chars().count() == 1 || entities::entity_name(name).is_some() {
+        latex_text(name)
+    } else {
+        format!("\\mathrm{{{}}}", latex_text(name))
+    }
+}
+
+fn number(num_type: &NumType) -> Latex {
+    let signed = |text: String, negative: bool| {
+        Latex::new(text, if negative { PREC_UNARY } else { PREC_ATOM })
+    };
+    match num_type {
+        NumType::Real(r) => signed(r.to_string(), *r < 0.0),
+        NumType::Integer(i) => signed(i.to_string(), *i < 0),
+        NumType::Rational(a, b) => Latex::atom(format!("\\frac{{{}}}{{{}}}", a, b)),
+        NumType::ComplexCartesian(a, b) => Latex::new(format!("{} + {}i", a, b), 5),
+        NumType::ComplexPolar(a, b) => Latex::new(format!("{} e^{{i {}}}", a, b), 6),
+        NumType::Constant(c) => Latex::atom(named_constant(c)),
+        NumType::ENotation(a, b) => Latex::new(format!("{} \\times 10^{{{}}}", a, b), 6),
+        NumType::Other { text, .. } => signed(latex_text(text), text.starts_with('-')),
+        #[cfg(feature = "bigint")]
+        NumType::BigInteger(i) => signed(i.to_string(), i.sign() == num_bigint::Sign::Minus),
+    }
+}
+
+fn constant(constant: &ConstantSymbol) -> &'static str {
+    match constant {
+        ConstantSymbol::Pi => "\\pi",
+        ConstantSymbol::ExponentialE => "e",
+        ConstantSymbol::ImaginaryI => "i",
+        ConstantSymbol::EulerGamma => "\\gamma",
+        ConstantSymbol::Infinity => "\\infty",
+        ConstantSymbol::NotANumber => "\\mathrm{NaN}",
+        ConstantSymbol::True => "\\mathrm{true}",
+        ConstantSymbol::False => "\\mathrm{false}",
+    }
+}
+
+fn named_constant(constant: &NamedConstant) -> String {
+    match constant {
+        NamedConstant::Pi => "\\pi".to_owned(),
+        NamedConstant::Tau => "\\tau".to_owned(),
+        NamedConstant::E => "e".to_owned(),
+        NamedConstant::Gamma => "\\gamma".to_owned(),
+        NamedConstant::GoldenRatio => "\\varphi".to_owned(),
+        NamedConstant::Custom(text) => latex_text(text),
+    }
+}
+
+fn number_set(set: &NumberSet) -> &'static str {
+    match set {
+        NumberSet::Integers => "\\mathbb{Z}",
+        NumberSet::Reals => "\\mathbb{R}",
+        NumberSet::Rationals => "\\mathbb{Q}",
+        NumberSet::NaturalNumbers => "\\mathbb{N}",
+        NumberSet::Complexes => "\\mathbb{C}",
+        NumberSet::Primes => "\\mathbb{P}",
+        NumberSet::EmptySet => "\\emptyset",
+    }
+}
+
+/// LaTeX for operators written as a named function, e.g. `\sin\left(x\right)`
+fn function_name(op: &BuiltinOp) -> String {
+    use BuiltinOp::*;
+    match op {
+        sin | cos | tan | sec | csc | cot | sinh | cosh | tanh | coth | arcsin | arccos
+        | arctan | ln | log | max | min | gcd | arg => format!("\\{}", op),
+        determinant => "\\det".to_owned(),
+        real => "\\Re".to_owned(),
+        imaginary => "\\Im".to_owned(),
+        _ => format!("\\operatorname{{{}}}", op),
+    }
+}
+
+/// LaTeX for operators written infix between their arguments
+fn infix_symbol(op: &BuiltinOp) -> Option<&'static str> {
+    use BuiltinOp::*;
+    let symbol = match op {
+        plus => " + ",
+        minus => " - ",
+        times => " \\cdot ",
+        rem => " \\bmod ",
+        and => " \\land ",
+        or => " \\lor ",
+        xor => " \\oplus ",
+        implies => " \\Rightarrow ",
+        equivalent => " \\equiv ",
+        approx => " \\approx ",
+        eq => " = ",
+        neq => " \\neq ",
+        gt => " > ",
+        lt => " < ",
+        geq => " \\geq ",
+        leq => " \\leq ",
+        union => " \\cup ",
+        intersect => " \\cap ",
+        setdiff => " \\setminus ",
+        cartesianproduct | vectorproduct => " \\times ",
+        scalarproduct => " \\cdot ",
+        outerproduct => " \\otimes ",
+        compose => " \\circ ",
+        r#in => " \\in ",
+        notin => " \\notin ",
+        subset => " \\subseteq ",
+        prsubset => " \\subset ",
+        notsubset => " \\nsubseteq ",
+        notprsubset => " \\not\\subset ",
+        tendsto => " \\to ",
+        factorof => " \\mid ",
+        _ => return None,
+    };
+    Some(symbol)
+}
+
+fn render_order(order: Order) -> String {
+    match order {
+        Order::Degree(degree) => render(degree).text,
+        Order::Count(count) => count.to_string(),
+        Order::Sum(degrees) => degrees
+            .iter()
+            .map(|d| d.map_or_else(|| "1".to_owned(), |d| render(d).text))
+            .collect::<Vec<_>>()
+            .join(" + "),
+    }
+}
+
+fn render_binder(op: &BuiltinOp, apply: &MathNode, body: Latex) -> Latex {
+    use BuiltinOp::*;
+    let binder = Binder::of(apply);
+    let vars: Vec<String> = binder
+        .bvars
+        .iter()
+        .map(|(name, _)| identifier(name))
+        .collect();
+    let condition = binder.condition.map(|c| render(c).text);
+    match op {
+        sum | product | int => {
+            let bounds = match (binder.limits, condition) {
+                (Some((low, high)), _) => {
+                    let low = match op {
+                        int => render(low).text,
+                        _ => format!("{} = {}", vars.join(", "), render(low).text),
+                    };
+                    format!("_{{{}}}^{{{}}}", low, render(high).text)
+                }
+                (None, Some(condition)) => format!("_{{{}}}", condition),
+                (None, None) if *op == int => String::new(),
+                (None, None) => format!("_{{{}}}", vars.join(", ")),
+            };
+            let text = match op {
+                sum => format!("\\sum{} {}", bounds, body.wrap(PREC_UNARY)),
+                product => format!("\\prod{} {}", bounds, body.wrap(PREC_UNARY)),
+                _ => format!(
+                    "\\int{} {} \\, {}",
+                    bounds,
+                    body.wrap(PREC_UNARY),
+                    vars.iter()
+                        .map(|v| format!("d{}", v))
+                        .collect::<Vec<_>>()
+                        .join(" \\, ")
+                ),
+            };
+            Latex::new(text, PREC_UNARY)
+        }
+        forall | exists => {
+            let symbol = if *op == forall {
+                "\\forall"
+            } else {
+                "\\exists"
+            };
+            let condition = condition
+                .map(|c| format!(" \\mid {}", c))
+                .unwrap_or_default();
+            Latex::new(
+                format!(
+                    "{} {}{} \\colon {}",
+                    symbol,
+                    vars.join(", "),
+                    condition,
+                    body.text
+                ),
+                0,
+            )
+        }
+        _ => {
+            let d = if *op == diff { "d" } else { "\\partial" };
+            let numerator = match binder.order {
+                Some(order) => format!("{}^{{{}}}", d, render_order(order)),
+                None => d.to_owned(),
+            };
+            let denominator = binder
+                .bvars
+                .iter()
+                .zip(&vars)
+                .map(|((_, degree), var)| match degree {
+                    Some(degree) => format!("{} {{{}}}^{{{}}}", d, var, render(degree).text),
+                    None => format!("{} {}", d, var),
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            if body.precedence >= PREC_ATOM {
+                Latex::atom(format!(
+                    "\\frac{{{} {}}}{{{}}}",
+                    numerator, body.text, denominator
+                ))
+            } else {
+                Latex::new(
+                    format!(
+                        "\\frac{{{}}}{{{}}} {}",
+                        numerator,
+                        denominator,
+                        body.wrap(PREC_ATOM)
+                    ),
+                    PREC_UNARY,
+                )
+            }
+        }
+    }
+}
+
+fn render_op(op: &BuiltinOp, apply: &MathNode, args: &[&MathNode]) -> Latex {
+    use BuiltinOp::*;
+    let prec = op.precedence();
+    let operand = |i: usize| {
+        args.get(i)
+            .map(|a| render(a))
+            .unwrap_or_else(|| Latex::atom(String::new()))
+    };
+    let all = |min_prec: u8| {
+        args.iter()
+            .map(|a| render(a).wrap(min_prec))
+            .collect::<Vec<_>>()
+    };
+    match op {
+        minus if args.len() == 1 => {
+            Latex::new(format!("-{}", operand(0).wrap(PREC_POWER)), PREC_UNARY)
+        }
+        minus => Latex::new(
+            format!("{} - {}", operand(0).wrap(prec), operand(1).wrap(prec + 1)),
+            prec,
+        ),
+        divide => Latex::atom(format!(
+            "\\frac{{{}}}{{{}}}",
+            operand(0).text,
+            operand(1).text
+        )),
+        power => Latex::new(
+            format!("{{{}}}^{{{}}}", operand(0).wrap(PREC_ATOM), operand(1).text),
+            PREC_POWER,
+        ),
+        root => Latex::atom(format!("\\sqrt{{{}}}", operand(0).text)),
+        exp => Latex::new(format!("e^{{{}}}", operand(0).text), PREC_POWER),
+        not => Latex::new(
+            format!("\\lnot {}", operand(0).wrap(PREC_UNARY)),
+            PREC_UNARY,
+        ),
+        factorial => Latex::new(format!("{}!", operand(0).wrap(PREC_ATOM)), prec),
+        abs | card => Latex::atom(format!("\\left|{}\\right|", operand(0).text)),
+        floor => Latex::atom(format!(
+            "\\left\\lfloor {} \\right\\rfloor",
+            operand(0).text
+        )),
+        ceiling => Latex::atom(format!("\\left\\lceil {} \\right\\rceil", operand(0).text)),
+        quotient => Latex::atom(format!(
+            "\\operatorname{{trunc}}\\left(\\frac{{{}}}{{{}}}\\right)",
+            operand(0).text,
+            operand(1).text
+        )),
+        conjugate => Latex::atom(format!("\\overline{{{}}}", operand(0).text)),
+        transpose => Latex::new(
+            format!("{{{}}}^{{T}}", operand(0).wrap(PREC_ATOM)),
+            PREC_POWER,
+        ),
+        inverse => Latex::new(
+            format!("{{{}}}^{{-1}}", operand(0).wrap(PREC_ATOM)),
+            PREC_POWER,
+        ),
+        grad => Latex::new(
+            format!("\\nabla {}", operand(0).wrap(PREC_ATOM)),
+            PREC_UNARY,
+        ),
+        divergence => Latex::new(
+            format!("\\nabla \\cdot {}", operand(0).wrap(PREC_ATOM)),
+            PREC_UNARY,
+        ),
+        curl => Latex::new(
+            format!("\\nabla \\times {}", operand(0).wrap(PREC_ATOM)),
+            PREC_UNARY,
+        ),
+        laplacian => Latex::new(
+            format!("\\nabla^2 {}", operand(0).wrap(PREC_ATOM)),
+            PREC_UNARY,
+        ),
+        selector => Latex::atom(format!(
+            "{{{}}}_{{{}}}",
+            operand(0).wrap(PREC_ATOM),
+            args.iter()
+                .skip(1)
+                .map(|a| render(a).text)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        sum | product | int | forall | exists | diff | partialdiff
+            if !apply.bound_variables().is_empty() =>
+        {
+            render_binder(op, apply, operand(0))
+        }
+        sum | product | int | forall | exists => {
+            let symbol = match op {
+                sum => "\\sum",
+                product => "\\prod",
+                int => "\\int",
+                forall => "\\forall",
+                _ => "\\exists",
+            };
+            Latex::new(
+                format!("{} {}", symbol, all(PREC_UNARY).join(" ")),
+                PREC_UNARY,
+            )
+        }
+        diff => Latex::new(format!("{}'", operand(0).wrap(PREC_ATOM)), PREC_POWER),
+        partialdiff => Latex::new(
+            format!("\\partial {}", operand(0).wrap(PREC_ATOM)),
+            PREC_UNARY,
+        ),
+        _ => match infix_symbol(op) {
+            Some(symbol) if is_relation(op) => Latex::new(all(prec + 1).join(symbol), prec),
+            Some(symbol) => Latex::new(all(prec).join(symbol), prec),
+            None => Latex::atom(format!(
+                "{}\\left({}\\right)",
+                function_name(op),
+                all(0).join(", ")
+            )),
+        },
+    }
+}
+
+/// Presentation markup already encodes the layout, so this is a direct translation
+fn presentation(node: &PresentationNode) -> String {
+    use PresentationNode::*;
+    let row = |children: &[PresentationNode]| {
+        children
+            .iter()
+            .map(presentation)
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    match node {
+        Mi(text) => identifier(text),
+        Mn(text) | Mtext(text) => latex_text(text),
+        Mo(text) => match text.as_str() {
+            "&" => "\\&".to_owned(),
+            "{" | "}" => format!("\\{}", text),
+            _ => latex_text(text),
+        },
+        Mspace => "\\;".to_owned(),
+        Mglyph { alt, .. } => latex_text(alt),
+        Mrow(children) => row(children),
+        Mfrac(a, b) => format!("\\frac{{{}}}{{{}}}", presentation(a), presentation(b)),
+        Msqrt(children) => format!("\\sqrt{{{}}}", row(children)),
+        Mroot(base, index) => format!("\\sqrt[{}]{{{}}}", presentation(index), presentation(base)),
+        Msup(base, sup) => format!("{{{}}}^{{{}}}", presentation(base), presentation(sup)),
+        Msub(base, sub) => format!("{{{}}}_{{{}}}", presentation(base), presentation(sub)),
+        Msubsup(base, sub, sup) => format!(
+            "{{{}}}_{{{}}}^{{{}}}",
+            presentation(base),
+            presentation(sub),
+            presentation(sup)
+        ),
+        Mtable(rows) => format!(
+            "\\begin{{matrix}} {} \\end{{matrix}}",
+            table(rows, presentation)
+        ),
+        Mfenced {
+            open,
+            close,
+            children,
+        } => format!(
+            "\\left{}{}\\right{}",
+            fence(open),
+            children
+                .iter()
+                .map(presentation)
+                .collect::<Vec<_>>()
+                .join(", "),
+            fence(close)
+        ),
+    }
+}
+
+/// Joins table cells with `&` and rows with `\\`
+fn table<T>(rows: &[Vec<T>], cell: impl Fn(&T) -> String) -> String {
+    rows.iter()
+        .map(|row| row.iter().map(&cell).collect::<Vec<_>>().join(" & "))
+        .collect::<Vec<_>>()
+        .join(" \\\\ ")
+}
+
+fn fence(symbol: &str) -> &str {
+    match symbol {
+        "{" => "\\{",
+        "}" => "\\}",
+        "" => ".",
+        other => other,
+    }
+}
+
+fn render(node: &MathNode) -> Latex {
+    match node {
+        MathNode::Root { children, .. } => Latex::atom(
+            children
+                .iter()
+                .map(|c| render(c).text)
+                .filter(|t| !t.is_empty())
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+        MathNode::Apply { children, .. } => {
+            let operands = node.operands();
+            let join = |nodes: &[&MathNode]| {
+                nodes
+                    .iter()
+                    .map(|a| render(a).text)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            match (children.first(), node.degree(), node.logbase()) {
+                (Some(MathNode::Op(BuiltinOp::root)), Some(degree), _) => Latex::atom(format!(
+                    "\\sqrt[{}]{{{}}}",
+                    render(degree).text,
+                    join(&operands)
+                )),
+                (Some(MathNode::Op(BuiltinOp::log)), _, Some(base)) => Latex::atom(format!(
+                    "\\log_{{{}}}\\left({}\\right)",
+                    render(base).text,
+                    join(&operands)
+                )),
+                (Some(MathNode::Op(op)), _, _) => render_op(op, node, &operands),
+                (Some(head), _, _) => Latex::atom(format!(
+                    "{}\\left({}\\right)",
+                    render(head).wrap(PREC_ATOM),
+                    join(&operands)
+                )),
+                (None, _, _) => Latex::atom(String::new()),
+            }
+        }
+        MathNode::Op(op) => Latex::atom(function_name(op)),
+        MathNode::Constant(c) => Latex::atom(constant(c).to_owned()),
+        MathNode::NumberSet(set) => Latex::atom(number_set(set).to_owned()),
+        MathNode::Text(t) | MathNode::Cs(t) => Latex::atom(latex_text(t)),
+        MathNode::Ci { children, .. } => match children.as_slice() {
+            [MathNode::Presentation(p)] => Latex::atom(presentation(p)),
+            _ => Latex::atom(identifier(&crate::text_content(children))),
+        },
+        MathNode::Csymbol { name, .. } => Latex::atom(identifier(name)),
+        MathNode::Cn { num_type, .. } => number(num_type),
+        MathNode::Comment(_) | MathNode::PI(_, _) => Latex::atom(String::new()),
+        MathNode::Error { .. } | MathNode::Cerror(_) | MathNode::Cbytes(_) | MathNode::Share(_) => {
+            Latex::atom("?".to_owned())
+        }
+        MathNode::Unknown { name, children, .. } => Latex::atom(format!(
+            "\\operatorname{{{}}}\\left({}\\right)",
+            latex_text(name),
+            children
+                .iter()
+                .map(|c| render(c).text)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        MathNode::Presentation(node) => Latex::atom(presentation(node)),
+        MathNode::Piecewise { pieces, otherwise } => {
+            let mut rows: Vec<String> = pieces
+                .iter()
+                .map(|(value, condition)| {
+                    format!(
+                        "{} & \\text{{if }} {}",
+                        render(value).text,
+                        render(condition).text
+                    )
+                })
+                .collect();
+            if let Some(otherwise) = otherwise {
+                rows.push(format!("{} & \\text{{otherwise}}", render(otherwise).text));
+            }
+            Latex::atom(format!(
+                "\\begin{{cases}} {} \\end{{cases}}",
+                rows.join(" \\\\ ")
+            ))
+        }
+        MathNode::Bind { .. } => render(&node.to_apply()),
+        MathNode::Lambda { bound_vars, body } => {
+            let vars: Vec<String> = bound_vars.iter().map(|v| identifier(v)).collect();
+            let vars = match vars.as_slice() {
+                [single] => single.clone(),
+                _ => format!("\\left({}\\right)", vars.join(", ")),
+            };
+            Latex::new(format!("{} \\mapsto {}", vars, render(body).text), 0)
+        }
+        MathNode::Degree(inner)
+        | MathNode::Logbase(inner)
+        | MathNode::Lowlimit(inner)
+        | MathNode::Uplimit(inner)
+        | MathNode::Condition(inner) => render(inner),
+        MathNode::Bvar { name, .. } => Latex::atom(identifier(name)),
+        MathNode::Semantics {
+            primary,
+            annotations,
+        } => {
+            // An author-supplied LaTeX alternate beats anything we could generate
+            let source = annotations.iter().find_map(|a| match a {
+                Annotation::Text { encoding, text }
+                    if matches!(
+                        encoding.as_deref(),
+                        Some("LaTeX") | Some("application/x-tex")
+                    ) =>
+                {
+                    Some(text.clone())
+                }
+                _ => None,
+            });
+            match source {
+                Some(text) => Latex::new(text, 0),
+                None => render(primary),
+            }
+        }
+        MathNode::Set(items) => Latex::atom(format!(
+            "\\left\\{{{}\\right\\}}",
+            items
+                .iter()
+                .map(|c| render(c).text)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        MathNode::List { items, .. } => Latex::atom(format!(
+            "\\left[{}\\right]",
+            items
+                .iter()
+                .map(|c| render(c).text)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        MathNode::Matrix(rows) => Latex::atom(format!(
+            "\\begin{{pmatrix}} {} \\end{{pmatrix}}",
+            table(rows, |c| render(c).text)
+        )),
+        MathNode::Vector(children) => {
+            let rows: Vec<Vec<&MathNode>> = children.iter().map(|c| vec![c]).collect();
+            Latex::atom(format!(
+                "\\begin{{pmatrix}} {} \\end{{pmatrix}}",
+                table(&rows, |c| render(c).text)
+            ))
+        }
+        MathNode::Interval { closure, low, high } => {
+            let (open, close) = closure.brackets();
+            Latex::atom(format!(
+                "\\left{}{}, {}\\right{}",
+                open,
+                render(low).text,
+                render(high).text,
+                close
+            ))
+        }
+    }
+}
+
+impl MathNode {
+    /// Render the expression as LaTeX math (without the surrounding `$`)
+    pub fn to_latex(&self) -> String {
+        render(self).text
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{parse_document, parse_infix};
+
+    #[test]
+    fn test_latex_precedence() {
+        let test = r#"<apply><times/>
+                        <apply><plus/><ci>a</ci><ci>b</ci></apply>
+                        <apply><power/><ci>x</ci><cn type="integer">2</cn></apply>
+                        <apply><minus/><ci>c</ci><apply><minus/><ci>d</ci><ci>e</ci></apply></apply>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(
+            parsed.to_latex(),
+            r"\left(a + b\right) \cdot {x}^{2} \cdot \left(c - \left(d - e\right)\right)"
+        );
+    }
+    #[test]
+    fn test_latex_functions() {
+        let test = r#"<apply><leq/>
+                        <apply><divide/>
+                            <apply><sin/><ci>theta</ci></apply>
+                            <apply><root/><ci>k1</ci></apply>
+                        </apply>
+                        <apply><minus/><apply><power/><ci>x</ci><cn>2</cn></apply></apply>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(
+            parsed.to_latex(),
+            r"\frac{\sin\left(\mathrm{theta}\right)}{\sqrt{\mathrm{k1}}} \leq -{x}^{2}"
+        );
+        // `eval` truncates toward zero, so -7 quotient 2 is -3
+        assert_eq!(
+            parse_infix("quotient(-7, 2)").unwrap().to_latex(),
+            r"\operatorname{trunc}\left(\frac{-7}{2}\right)"
+        );
+    }
+    #[test]
+    fn test_latex_presentation() {
+        let test = r#"<mrow><msup><mi>x</mi><mn>2</mn></msup><mo>+</mo><mfrac><mn>1</mn><mi>y</mi></mfrac></mrow>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(parsed.to_latex(), r"{x}^{2} + \frac{1}{y}");
+    }
+    #[test]
+    fn test_latex_piecewise() {
+        let test = r#"<piecewise>
+                        <piece><cn>1</cn><apply><lt/><ci>x</ci><cn>0</cn></apply></piece>
+                        <otherwise><ci>x</ci></otherwise>
+                      </piecewise>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(
+            parsed.to_latex(),
+            r"\begin{cases} 1 & \text{if } x < 0 \\ x & \text{otherwise} \end{cases}"
+        );
+    }
+    #[test]
+    fn test_latex_qualifiers() {
+        let test = r#"<apply><plus/>
+                        <apply><root/><degree><cn>3</cn></degree><ci>x</ci></apply>
+                        <apply><log/><logbase><cn>2</cn></logbase><ci>y</ci></apply>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(parsed.to_latex(), r"\sqrt[3]{x} + \log_{2}\left(y\right)");
+        let test = r#"<apply><times/><cn>2</cn><pi/><imaginaryi/></apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(parsed.to_latex(), r"2 \cdot \pi \cdot i");
+        let test =
+            r#"<apply><notin/><ci>x</ci><apply><setdiff/><reals/><rationals/></apply></apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(
+            parsed.to_latex(),
+            r"x \notin \mathbb{R} \setminus \mathbb{Q}"
+        );
+    }
+    #[test]
+    fn test_latex_binders() {
+        let test = r#"<apply><sum/>
+                        <bvar><ci>i</ci></bvar>
+                        <lowlimit><cn>1</cn></lowlimit><uplimit><ci>n</ci></uplimit>
+                        <apply><power/><ci>i</ci><cn>2</cn></apply>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(parsed.to_latex(), r"\sum_{i = 1}^{n} {i}^{2}");
+
+        let test = r#"<apply><int/>
+                        <bvar><ci>x</ci></bvar>
+                        <interval><cn>0</cn><cn>1</cn></interval>
+                        <apply><sin/><ci>x</ci></apply>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(parsed.to_latex(), r"\int_{0}^{1} \sin\left(x\right) \, dx");
+
+        let test = r#"<apply><diff/>
+                        <bvar><ci>x</ci><degree><cn>2</cn></degree></bvar>
+                        <ci>f</ci>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(parsed.to_latex(), r"\frac{d^{2} f}{d {x}^{2}}");
+    }
+    #[test]
+    fn test_latex_matrix() {
+        let test = r#"<apply><transpose/>
+                        <matrix>
+                            <matrixrow><cn>1</cn><cn>2</cn></matrixrow>
+                            <matrixrow><ci>a</ci><ci>b</ci></matrixrow>
+                        </matrix>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(
+            parsed.to_latex(),
+            r"{\begin{pmatrix} 1 & 2 \\ a & b \end{pmatrix}}^{T}"
+        );
+    }
+}
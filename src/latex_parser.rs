@@ -0,0 +1,675 @@
+use super::{BuiltinOp, ConstantSymbol, MathNode};
+use crate::cursor::{self, Cursor};
+use crate::entities;
+use crate::error::ParseError;
+use crate::infix::{invalid, negated};
+
+/// Symbols that stand for themselves, `\\` being the row break of `cases`
+const SYMBOLS: &[&str] = &[
+    "\\\\", "+", "-", "*", "/", "^", "_", "=", "<", ">", "(", ")", "[", "]", "{", "}", "|", ",",
+    "!", "&",
+];
+
+/// Commands that only affect the layout, and are skipped
+const LAYOUT: &str = "left right big Big bigl bigr Bigl Bigr displaystyle textstyle limits \
+                      nolimits quad qquad";
+
+/// Commands whose braced argument is text rather than math
+const TEXT_COMMANDS: &str = "mathrm mathit operatorname text begin end";
+
+const GREEK: &str = "alpha beta gamma delta epsilon varepsilon zeta eta theta vartheta iota \
+                     kappa lambda mu nu xi rho sigma tau upsilon phi varphi chi psi omega Gamma \
+                     Delta Theta Lambda Xi Pi Sigma Phi Psi Omega";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token<'a> {
+    Number(&'a str),
+    /// A single letter
+    Letter(&'a str),
+    /// `\name`, without the backslash
+    Command(&'a str),
+    /// A command of `TEXT_COMMANDS` with its argument, e.g. `\mathrm{Vmax}`
+    Text(&'a str, &'a str),
+    Symbol(&'static str),
+    End,
+}
+
+fn tokenize(text: &str) -> Result<Vec<(Token<'_>, usize)>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let position = text.len() - rest.len();
+        let len = if c.is_ascii_digit() || c == '.' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            tokens.push((Token::Number(&rest[..len]), position));
+            len
+        } else if c.is_alphabetic() {
+            tokens.push((Token::Letter(&rest[..c.len_utf8()]), position));
+            c.len_utf8()
+        } else if c == '\\' && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+            let name_len = rest[1..]
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(rest.len() - 1);
+            let name = &rest[1..=name_len];
+            let mut len = name_len + 1;
+            if TEXT_COMMANDS.split_whitespace().any(|c| c == name) {
+                let argument = rest[len..].trim_start();
+                let end = argument
+                    .strip_prefix('{')
+                    .and_then(|a| a.find('}'))
+                    .ok_or_else(|| invalid(position, format!("\\{} needs a {{...}}", name)))?;
+                tokens.push((Token::Text(name, &argument[1..=end]), position));
+                len = rest.len() - argument.len() + end + 2;
+            } else if name == "left" || name == "right" {
+                // `\left.` is an invisible delimiter
+                if rest[len..].starts_with('.') {
+                    len += 1;
+                }
+            } else if !LAYOUT.split_whitespace().any(|c| c == name) {
+                tokens.push((Token::Command(name), position));
+            }
+            len
+        } else if c == '\\' && rest[1..].starts_with([',', ';', ':', '!', ' ']) {
+            // Spacing
+            2
+        } else if c == '\\' && rest[1..].starts_with(['{', '}', '|']) {
+            tokens.push((Token::Command(&rest[1..2]), position));
+            2
+        } else {
+            match SYMBOLS.iter().find(|s| rest.starts_with(**s)) {
+                Some(symbol) => {
+                    tokens.push((Token::Symbol(symbol), position));
+                    symbol.len()
+                }
+                None => return Err(invalid(position, format!("unexpected '{}'", c))),
+            }
+        };
+        rest = rest[len..].trim_start();
+    }
+    tokens.push((Token::End, text.len()));
+    Ok(tokens)
+}
+
+/// The functions with a command of their own, e.g. `\sin x`
+fn function(command: &str) -> Option<BuiltinOp> {
+    use BuiltinOp::*;
+    let op = match command {
+        "exp" => exp,
+        "ln" => ln,
+        "log" | "lg" => log,
+        "sin" => sin,
+        "cos" => cos,
+        "tan" => tan,
+        "sec" => sec,
+        "csc" => csc,
+        "cot" => cot,
+        "sinh" => sinh,
+        "cosh" => cosh,
+        "tanh" => tanh,
+        "coth" => coth,
+        "arcsin" => arcsin,
+        "arccos" => arccos,
+        "arctan" => arctan,
+        "max" => max,
+        "min" => min,
+        "gcd" => gcd,
+        "det" => determinant,
+        _ => return None,
+    };
+    Some(op)
+}
+
+fn relation(token: Token) -> Option<BuiltinOp> {
+    use BuiltinOp::*;
+    let op = match token {
+        Token::Symbol("=") => eq,
+        Token::Command("neq") | Token::Command("ne") => neq,
+        Token::Symbol("<") | Token::Command("lt") => lt,
+        Token::Symbol(">") | Token::Command("gt") => gt,
+        Token::Command("leq") | Token::Command("le") => leq,
+        Token::Command("geq") | Token::Command("ge") => geq,
+        Token::Command("approx") => approx,
+        _ => return None,
+    };
+    Some(op)
+}
+
+impl cursor::Token for Token<'_> {
+    const END: Self = Token::End;
+    fn describe(self) -> String {
+        match self {
+            Token::Number(text) | Token::Letter(text) => format!("'{}'", text),
+            Token::Command(name) => format!("'\\{}'", name),
+            Token::Text(name, text) => format!("'\\{}{{{}}}'", name, text),
+            Token::Symbol(symbol) => format!("'{}'", symbol),
+            Token::End => "the end of the input".to_owned(),
+        }
+    }
+}
+
+/// Appends `rhs` to `lhs` if it applies `op` from the same chain, as in
+/// `a + b + c`, and otherwise applies `op` to both
+fn chain(lhs: MathNode, op: BuiltinOp, rhs: MathNode, chained: bool) -> MathNode {
+    match lhs {
+        MathNode::Apply {
+            mut children,
+            attributes,
+        } if chained && children.first() == Some(&MathNode::Op(op)) => {
+            children.push(rhs);
+            MathNode::Apply {
+                children,
+                attributes,
+            }
+        }
+        lhs => MathNode::apply(op, vec![lhs, rhs]),
+    }
+}
+
+/// The character of a Greek letter's command, e.g. `α` for `\alpha`
+fn greek(name: &str) -> Option<&'static str> {
+    if GREEK.split_whitespace().any(|g| g == name) {
+        entities::lookup(name)
+    } else {
+        None
+    }
+}
+
+struct Parser<'a> {
+    tokens: Cursor<Token<'a>>,
+    /// How many `|` of absolute values are open, which then can't start another
+    open_bars: usize,
+    /// How many integrals are open, in which `dx` ends the integrand
+    open_integrals: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn expr(&mut self) -> Result<MathNode, ParseError> {
+        let mut lhs = self.or()?;
+        loop {
+            let op = match self.tokens.peek() {
+                Token::Command("Rightarrow") | Token::Command("implies") => BuiltinOp::implies,
+                Token::Command("Leftrightarrow")
+                | Token::Command("iff")
+                | Token::Command("equiv") => BuiltinOp::equivalent,
+                _ => return Ok(lhs),
+            };
+            self.tokens.advance();
+            lhs = MathNode::apply(op, vec![lhs, self.or()?]);
+        }
+    }
+    fn or(&mut self) -> Result<MathNode, ParseError> {
+        let mut lhs = self.and()?;
+        let mut chained = false;
+        while self.tokens.eat(Token::Command("lor")) || self.tokens.eat(Token::Command("vee")) {
+            lhs = chain(lhs, BuiltinOp::or, self.and()?, chained);
+            chained = true;
+        }
+        Ok(lhs)
+    }
+    fn and(&mut self) -> Result<MathNode, ParseError> {
+        let mut lhs = self.not()?;
+        let mut chained = false;
+        while self.tokens.eat(Token::Command("land")) || self.tokens.eat(Token::Command("wedge")) {
+            lhs = chain(lhs, BuiltinOp::and, self.not()?, chained);
+            chained = true;
+        }
+        Ok(lhs)
+    }
+    /// `\lnot`, which binds looser than relations, as in `\lnot x > 1`
+    fn not(&mut self) -> Result<MathNode, ParseError> {
+        if self.tokens.eat(Token::Command("lnot")) || self.tokens.eat(Token::Command("neg")) {
+            Ok(MathNode::apply(BuiltinOp::not, vec![self.not()?]))
+        } else {
+            self.relation()
+        }
+    }
+    /// Relations, where `a < b < c` is a single `<lt/>` of three operands
+    fn relation(&mut self) -> Result<MathNode, ParseError> {
+        let mut lhs = self.additive()?;
+        let mut previous = None;
+        while let Some(op) = relation(self.tokens.peek()) {
+            self.tokens.advance();
+            let rhs = self.additive()?;
+            lhs = chain(lhs, op, rhs, previous == Some(op) && op != BuiltinOp::neq);
+            previous = Some(op);
+        }
+        Ok(lhs)
+    }
+    fn additive(&mut self) -> Result<MathNode, ParseError> {
+        let mut lhs = self.term()?;
+        let mut chained = false;
+        loop {
+            if self.tokens.eat(Token::Symbol("+")) {
+                lhs = chain(lhs, BuiltinOp::plus, self.term()?, chained);
+                chained = true;
+            } else if self.tokens.eat(Token::Symbol("-")) {
+                lhs = MathNode::apply(BuiltinOp::minus, vec![lhs, self.term()?]);
+                chained = false;
+            } else {
+                return Ok(lhs);
+            }
+        }
+    }
+    /// Products, explicit with `\cdot`, `\times` or `*` or implied by
+    /// juxtaposition as in `2 x \sin y`, divisions with `/` or `\div`, and
+    /// remainders with `\bmod`
+    fn term(&mut self) -> Result<MathNode, ParseError> {
+        let mut lhs = self.unary()?;
+        let mut chained = false;
+        loop {
+            let op = match self.tokens.peek() {
+                Token::Symbol("/") | Token::Command("div") => Some(BuiltinOp::divide),
+                Token::Command("bmod") | Token::Command("mod") => Some(BuiltinOp::rem),
+                _ => None,
+            };
+            if let Some(op) = op {
+                self.tokens.advance();
+                lhs = MathNode::apply(op, vec![lhs, self.unary()?]);
+                chained = false;
+                continue;
+            }
+            let explicit = self.tokens.eat(Token::Command("cdot"))
+                || self.tokens.eat(Token::Command("times"))
+                || self.tokens.eat(Token::Command("ast"))
+                || self.tokens.eat(Token::Symbol("*"));
+            if !explicit && (!self.starts_factor() || self.at_differential()) {
+                return Ok(lhs);
+            }
+            lhs = chain(lhs, BuiltinOp::times, self.unary()?, chained);
+            chained = true;
+        }
+    }
+    fn starts_factor(&self) -> bool {
+        match self.tokens.peek() {
+            Token::Number(_) | Token::Letter(_) => true,
+            Token::Text(command, _) => command != "end",
+            Token::Command(name) => {
+                greek(name).is_some()
+                    || function(name).is_some()
+                    || matches!(
+                        name,
+                        "pi" | "infty"
+                            | "frac"
+                            | "dfrac"
+                            | "tfrac"
+                            | "sqrt"
+                            | "sum"
+                            | "prod"
+                            | "int"
+                            | "lfloor"
+                            | "lceil"
+                    )
+            }
+            Token::Symbol("|") => self.open_bars == 0,
+            Token::Symbol(symbol) => matches!(symbol, "(" | "[" | "{"),
+            Token::End => false,
+        }
+    }
+    /// Whether the `dx` ending an integrand comes next, also as `\mathrm{d}x`
+    fn at_differential(&self) -> bool {
+        let variable = matches!(
+            self.tokens.peek_second(),
+            Token::Letter(_) | Token::Command(_)
+        );
+        self.open_integrals > 0
+            && variable
+            && matches!(
+                self.tokens.peek(),
+                Token::Letter("d") | Token::Text("mathrm", "d")
+            )
+    }
+    fn unary(&mut self) -> Result<MathNode, ParseError> {
+        if self.tokens.eat(Token::Symbol("-")) {
+            Ok(negated(self.unary()?))
+        } else if self.tokens.eat(Token::Symbol("+")) {
+            self.unary()
+        } else {
+            self.power()
+        }
+    }
+    /// `^`, right associative as `x^{y^z}` is the only way to write it
+    fn power(&mut self) -> Result<MathNode, ParseError> {
+        let base = self.postfix()?;
+        if !self.tokens.eat(Token::Symbol("^")) {
+            return Ok(base);
+        }
+        Ok(MathNode::apply(
+            BuiltinOp::power,
+            vec![base, self.script()?],
+        ))
+    }
+    /// The argument of `^`, `_`, `\frac` or `\sqrt`: a `{...}` group or a
+    /// single token, so that `x^23` is `x^2 3`
+    fn script(&mut self) -> Result<MathNode, ParseError> {
+        if let Token::Number(text) = self.tokens.peek() {
+            if text.len() > 1 {
+                let position = self.tokens.position();
+                self.tokens.replace(Token::Number(&text[1..]), position + 1);
+                return Ok(MathNode::integer(i64::from(text.as_bytes()[0] - b'0')));
+            }
+        }
+        if self.tokens.eat(Token::Symbol("-")) {
+            return Ok(negated(self.script()?));
+        }
+        self.primary()
+    }
+    fn postfix(&mut self) -> Result<MathNode, ParseError> {
+        let mut node = self.primary()?;
+        while self.tokens.eat(Token::Symbol("!")) {
+            node = MathNode::apply(BuiltinOp::factorial, vec![node]);
+        }
+        Ok(node)
+    }
+    /// An identifier, with a subscript becoming part of its name, e.g. `k_1`
+    /// or `k_{\mathrm{cat}}`
+    fn identifier(&mut self, name: &str) -> Result<MathNode, ParseError> {
+        if !self.tokens.eat(Token::Symbol("_")) {
+            return Ok(MathNode::ci(name));
+        }
+        let grouped = self.tokens.eat(Token::Symbol("{"));
+        let mut subscript = String::new();
+        loop {
+            match self.tokens.peek() {
+                Token::Number(text)
+                | Token::Letter(text)
+                | Token::Text("mathrm", text)
+                | Token::Text("mathit", text) => subscript.push_str(text),
+                Token::Command(name) if greek(name).is_some() => {
+                    subscript.push_str(greek(name).unwrap_or(name))
+                }
+                Token::Symbol("}") if grouped && !subscript.is_empty() => {
+                    self.tokens.advance();
+                    break;
+                }
+                _ => return self.tokens.error("a subscript of letters and digits"),
+            }
+            self.tokens.advance();
+            if !grouped {
+                break;
+            }
+        }
+        Ok(MathNode::ci(format!("{}_{}", name, subscript)))
+    }
+    /// The arguments of a function: a parenthesized list, or the factor right
+    /// after it, so `\sin 2x` is `\sin(2) x`
+    fn arguments(&mut self) -> Result<Vec<MathNode>, ParseError> {
+        if !self.tokens.eat(Token::Symbol("(")) {
+            return Ok(vec![self.script()?]);
+        }
+        let mut args = vec![self.expr()?];
+        while self.tokens.eat(Token::Symbol(",")) {
+            args.push(self.expr()?);
+        }
+        self.tokens.expect(Token::Symbol(")"))?;
+        Ok(args)
+    }
+    /// A function applied to its arguments, as in `\sin x`, `\log_2 x` or
+    /// `\sin^2 x`, which is the square of the sine
+    fn function(&mut self, op: BuiltinOp) -> Result<MathNode, ParseError> {
+        let base = if op == BuiltinOp::log && self.tokens.eat(Token::Symbol("_")) {
+            Some(self.script()?)
+        } else {
+            None
+        };
+        let exponent = if self.tokens.eat(Token::Symbol("^")) {
+            Some(self.script()?)
+        } else {
+            None
+        };
+        let mut args = self.arguments()?;
+        if let Some(base) = base {
+            args.insert(0, MathNode::Logbase(Box::new(base)));
+        }
+        let applied = MathNode::apply(op, args);
+        Ok(match exponent {
+            Some(exponent) => MathNode::apply(BuiltinOp::power, vec![applied, exponent]),
+            None => applied,
+        })
+    }
+    /// `\sum_{i=1}^{n} body`, `\prod` alike, or `\int_a^b body \, dx`
+    fn big_operator(&mut self, op: BuiltinOp) -> Result<MathNode, ParseError> {
+        let mut qualifiers = Vec::new();
+        let mut bvar = None;
+        if self.tokens.eat(Token::Symbol("_")) {
+            let position = self.tokens.position();
+            let lower = self.script()?;
+            if op == BuiltinOp::int {
+                qualifiers.push(MathNode::Lowlimit(Box::new(lower)));
+            } else {
+                let (name, low) = match lower {
+                    MathNode::Apply { children, .. }
+                        if children.len() == 3 && children[0] == MathNode::Op(BuiltinOp::eq) =>
+                    {
+                        let mut children = children.into_iter().skip(1);
+                        (children.next(), children.next())
+                    }
+                    lower => (Some(lower), None),
+                };
+                match name {
+                    Some(MathNode::Ci { children, .. }) => {
+                        bvar = Some(crate::text_content(&children))
+                    }
+                    _ => return Err(invalid(position, "expected a variable = a limit")),
+                }
+                if let Some(low) = low {
+                    qualifiers.push(MathNode::Lowlimit(Box::new(low)));
+                }
+            }
+        }
+        if self.tokens.eat(Token::Symbol("^")) {
+            qualifiers.push(MathNode::Uplimit(Box::new(self.script()?)));
+        }
+        let body = if op == BuiltinOp::int {
+            self.open_integrals += 1;
+            let body = self.term();
+            self.open_integrals -= 1;
+            let body = body?;
+            if !self.tokens.eat(Token::Letter("d")) && !self.tokens.eat(Token::Text("mathrm", "d"))
+            {
+                return self.tokens.error("the 'dx' of the integral");
+            }
+            bvar = match self.tokens.advance() {
+                Token::Letter(name) => Some(name.to_owned()),
+                Token::Command(name) if greek(name).is_some() => greek(name).map(str::to_owned),
+                _ => {
+                    self.tokens.back();
+                    return self.tokens.error("the variable of integration");
+                }
+            };
+            body
+        } else {
+            self.term()?
+        };
+        let mut children = vec![MathNode::Op(op)];
+        if let Some(name) = bvar {
+            children.push(MathNode::Bvar { name, degree: None });
+        }
+        children.extend(qualifiers);
+        children.push(body);
+        Ok(MathNode::Apply {
+            children,
+            attributes: Vec::new(),
+        })
+    }
+    /// `\begin{cases} value & \text{if } condition \\ value & \text{otherwise} \end{cases}`
+    fn cases(&mut self) -> Result<MathNode, ParseError> {
+        let mut pieces = Vec::new();
+        let mut otherwise = None;
+        loop {
+            let value = self.expr()?;
+            self.tokens.expect(Token::Symbol("&"))?;
+            match self.tokens.peek() {
+                Token::Text("text", text) if matches!(text.trim(), "otherwise" | "else") => {
+                    self.tokens.advance();
+                    otherwise = Some(value);
+                }
+                _ => {
+                    // The condition may be introduced by some text, e.g. `\text{if }`
+                    if let Token::Text("text", _) = self.tokens.peek() {
+                        self.tokens.advance();
+                    }
+                    pieces.push((value, self.expr()?));
+                }
+            }
+            if !self.tokens.eat(Token::Symbol("\\\\"))
+                || self.tokens.peek() == Token::Text("end", "cases")
+            {
+                break;
+            }
+        }
+        self.tokens.expect(Token::Text("end", "cases"))?;
+        Ok(MathNode::piecewise(pieces, otherwise))
+    }
+    fn group(&mut self, close: Token<'static>) -> Result<MathNode, ParseError> {
+        let inner = self.expr()?;
+        self.tokens.expect(close)?;
+        Ok(inner)
+    }
+    fn primary(&mut self) -> Result<MathNode, ParseError> {
+        let position = self.tokens.position();
+        match self.tokens.advance() {
+            Token::Number(text) => match text.parse() {
+                Ok(integer) => Ok(MathNode::integer(integer)),
+                Err(_) => Ok(MathNode::cn(text.parse().map_err(|_| {
+                    invalid(position, format!("'{}' is not a number", text))
+                })?)),
+            },
+            Token::Letter("e") if self.tokens.peek() != Token::Symbol("_") => {
+                Ok(MathNode::Constant(ConstantSymbol::ExponentialE))
+            }
+            Token::Letter(name) | Token::Text("mathrm", name) | Token::Text("mathit", name) => {
+                self.identifier(name)
+            }
+            Token::Text("operatorname", name) => match serde_plain::from_str(name) {
+                Ok(op) => self.function(op),
+                Err(_) => Ok(MathNode::call(MathNode::ci(name), self.arguments()?)),
+            },
+            Token::Text("begin", "cases") => self.cases(),
+            Token::Command("pi") => Ok(MathNode::Constant(ConstantSymbol::Pi)),
+            Token::Command("infty") => Ok(MathNode::Constant(ConstantSymbol::Infinity)),
+            Token::Command(name) if greek(name).is_some() => {
+                self.identifier(greek(name).unwrap_or(name))
+            }
+            Token::Command(name) if function(name).is_some() => {
+                self.function(function(name).unwrap_or(BuiltinOp::exp))
+            }
+            Token::Command("frac") | Token::Command("dfrac") | Token::Command("tfrac") => {
+                let numerator = self.script()?;
+                let denominator = self.script()?;
+                Ok(MathNode::apply(
+                    BuiltinOp::divide,
+                    vec![numerator, denominator],
+                ))
+            }
+            Token::Command("sqrt") => {
+                if self.tokens.eat(Token::Symbol("[")) {
+                    let degree = self.group(Token::Symbol("]"))?;
+                    let radicand = self.script()?;
+                    return Ok(MathNode::apply(
+                        BuiltinOp::root,
+                        vec![MathNode::Degree(Box::new(degree)), radicand],
+                    ));
+                }
+                Ok(MathNode::apply(BuiltinOp::root, vec![self.script()?]))
+            }
+            Token::Command("sum") => self.big_operator(BuiltinOp::sum),
+            Token::Command("prod") => self.big_operator(BuiltinOp::product),
+            Token::Command("int") => self.big_operator(BuiltinOp::int),
+            Token::Command("lfloor") => {
+                let inner = self.group(Token::Command("rfloor"))?;
+                Ok(MathNode::apply(BuiltinOp::floor, vec![inner]))
+            }
+            Token::Command("lceil") => {
+                let inner = self.group(Token::Command("rceil"))?;
+                Ok(MathNode::apply(BuiltinOp::ceiling, vec![inner]))
+            }
+            Token::Symbol("(") => self.group(Token::Symbol(")")),
+            Token::Symbol("[") => self.group(Token::Symbol("]")),
+            Token::Symbol("{") => self.group(Token::Symbol("}")),
+            Token::Symbol("|") => {
+                self.open_bars += 1;
+                let inner = self.expr();
+                self.open_bars -= 1;
+                let inner = inner?;
+                self.tokens.expect(Token::Symbol("|"))?;
+                Ok(MathNode::apply(BuiltinOp::abs, vec![inner]))
+            }
+            _ => {
+                self.tokens.back();
+                self.tokens.error("an expression")
+            }
+        }
+    }
+}
+
+/// Parses LaTeX math, e.g. `\frac{k_1 S}{K_m + S}`, into content MathML. It
+/// reads `\frac`, `\sqrt`, `\sum`, `\prod`, `\int ... \, dx`, the functions
+/// with a command like `\sin` or `\log_2`, `\operatorname`, sub- and
+/// superscripts, `\begin{cases}`, relations and logic, and what `to_latex`
+/// writes. Juxtaposition multiplies, letters are single identifiers unless
+/// in `\mathrm`, subscripts become part of the name as in `k_1`, and `e` is
+/// Euler's number. Layout like `\left` and spacing is skipped
+pub fn parse_latex(text: &str) -> Result<MathNode, ParseError> {
+    let mut parser = Parser {
+        tokens: Cursor::new(tokenize(text)?),
+        open_bars: 0,
+        open_integrals: 0,
+    };
+    let node = parser.expr()?;
+    if parser.tokens.peek() != Token::End {
+        return parser.tokens.error("an operator");
+    }
+    Ok(node)
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_latex;
+    use crate::parse_document;
+
+    #[test]
+    fn test_parse_latex() {
+        let test =
+            r"\frac{k_{cat} E S}{K_m + S} - \sqrt[3]{x^23} + \sin^2\theta + |x| \cdot \log_2(y)";
+        assert_eq!(
+            parse_latex(test).unwrap().to_string(),
+            "k_cat * E * S / (K_m + S) - root(3, x^2 * 3) + sin(θ)^2 + abs(x) * log(2, y)"
+        );
+        let test = r"\int_0^{\pi} \sin^2 x \, \mathrm{d}x \leq \sum_{i=1}^{n} i \lor \lnot a > 1";
+        assert_eq!(
+            parse_latex(test).unwrap().to_string(),
+            "int(sin(x)^2, x = 0..pi) <= sum(i, i = 1..n) || !(a > 1)"
+        );
+
+        // What `to_latex` writes reads back as the same tree
+        for test in &[
+            r#"<apply><sum/><bvar><ci>i</ci></bvar><lowlimit><cn type="integer">1</cn></lowlimit><uplimit><ci>n</ci></uplimit>
+                 <apply><power/><ci>i</ci><cn type="integer">2</cn></apply></apply>"#,
+            r#"<apply><times/><apply><plus/><ci>a</ci><ci>b</ci></apply>
+                 <apply><divide/><apply><sin/><ci>Vmax</ci></apply><apply><root/><ci>x</ci></apply></apply>
+                 <apply><minus/><ci>c</ci><apply><minus/><ci>d</ci><ci>f</ci></apply></apply></apply>"#,
+            r#"<piecewise><piece><cn type="integer">1</cn><apply><lt/><ci>x</ci><cn type="integer">0</cn></apply></piece>
+                 <otherwise><apply><log/><logbase><cn type="integer">2</cn></logbase><ci>y</ci></apply></otherwise></piecewise>"#,
+        ] {
+            let parsed = parse_document(test).unwrap();
+            assert_eq!(parse_latex(&parsed.to_latex()).unwrap(), parsed);
+        }
+        assert!(parse_latex(r"\frac{a}{").is_err());
+        assert!(parse_latex(r"\int x").is_err());
+        let error = |text: &str| parse_latex(text).unwrap_err().to_string();
+        assert_eq!(
+            error(""),
+            "invalid formula at offset 0: expected an expression, found the end of the input"
+        );
+        assert_eq!(
+            error(" "),
+            "invalid formula at offset 1: expected an expression, found the end of the input"
+        );
+        assert_eq!(
+            error("x+"),
+            "invalid formula at offset 2: expected an expression, found the end of the input"
+        );
+    }
+}
@@ -1,15 +1,106 @@
-pub use numbers::NumType;
+pub use asciimath::parse_asciimath;
+pub use attributes::{Attributes, QualifiedName};
+pub use bounds::Bounds;
+pub use compile::CompiledExpr;
+pub use dag::{Dag, TempId};
+pub use definitions::FunctionDefinitions;
+pub use derivative::{jacobian, jacobian_dag};
+pub use error::{EvalError, ParseDiagnostic, ParseError, ValidationError};
+pub use eval::SymbolResolver;
+pub use infix::{parse_infix, InfixOptions};
+#[cfg(feature = "jit")]
+pub use jit::JitExpr;
+pub use latex_parser::parse_latex;
+pub use lint::{lint, lint_document, lint_with, LintConfig, LintId, LintLevel, LintWarning};
+pub use logic::Logic;
+#[cfg(feature = "mathjson")]
+pub use mathjson::parse_mathjson;
+#[cfg(feature = "exact")]
+pub use num_rational::BigRational;
+pub use numbers::{ConstantSymbol, NamedConstant, NumType, NumberSet};
+pub use openmath::parse_openmath;
+pub use ops::{Arity, OpCategory};
+pub use options::{EntityPolicy, ParserOptions};
+pub use presentation::PresentationNode;
+pub use quadrature::{IntegrationOptions, Quadrature};
 pub use regexes::sanitize_xml;
-use roxmltree;
+pub use rewrite::{rewrite, Pattern, Rule};
 use roxmltree::Node;
 use roxmltree::NodeType;
+pub use sbml::{validate_sbml_subset, SbmlLevelVersion, SbmlSymbol};
+pub use scope::apply_lambda;
+pub use semantics::Annotation;
 use serde_derive::{Deserialize, Serialize};
-use serde_plain;
-use std::collections::HashMap;
+pub use sexpr::parse_sexpr;
+pub use simplify::SimplifyRules;
+pub use span::{Position, SourceMap, Span};
+use std::convert::TryInto;
+use std::fmt;
+pub use strict::parse_strict;
+pub use visit::{walk, IterMut, Visitor};
+mod asciimath;
+mod attributes;
+mod bind;
+mod bounds;
+mod builder;
+mod ccode;
+mod cellml;
+mod compile;
+mod content_to_presentation;
+mod cursor;
+mod dag;
+mod definitions;
+mod derivative;
+mod display;
+mod dot;
+#[cfg(feature = "egraph")]
+mod egraph;
+mod entities;
+mod error;
+mod eval;
+#[cfg(feature = "exact")]
+mod exact;
+mod expand;
+mod infix;
+#[cfg(feature = "jit")]
+mod jit;
+mod latex;
+mod latex_parser;
+mod lint;
+mod logic;
+#[cfg(feature = "mathjson")]
+mod mathjson;
+mod normal_form;
+mod normalize;
 mod numbers;
+mod numeric;
+mod openmath;
+mod ops;
+mod options;
+mod polynomial;
+mod presentation;
+mod prune;
+mod quadrature;
 mod regexes;
+mod rewrite;
+mod sbml;
+mod scope;
+mod script;
+mod semantics;
+mod sexpr;
+mod simplify;
+mod span;
+mod strict;
+mod sympy;
+mod transform;
+mod typst;
+mod unicode;
+mod validate;
+mod visit;
+mod wolfram;
+mod writer;
 
-#[derive(Deserialize, Debug, Serialize, Eq, PartialEq)]
+#[derive(Deserialize, Debug, Serialize, Eq, PartialEq, Clone, Copy, Hash)]
 #[allow(non_camel_case_types)]
 pub enum BuiltinOp {
     factorial,
@@ -106,84 +197,997 @@ pub enum BuiltinOp {
     geq,
     leq,
     root,
+    r#in,
+    notin,
+    subset,
+    prsubset,
+    notsubset,
+    notprsubset,
+    tendsto,
+    factorof,
 }
 
-#[derive(Debug, Serialize, Eq, PartialEq)]
+impl fmt::Display for BuiltinOp {
+    /// Writes the MathML tag name of the operator
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = serde_plain::to_string(self).map_err(|_| fmt::Error)?;
+        f.write_str(&name)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone, Hash)]
 pub enum MathNode {
-    Apply(Vec<MathNode>),
+    Apply {
+        children: Vec<MathNode>,
+        /// Attributes such as `id` or `xref`, as for `Cn`
+        attributes: Attributes,
+    },
     Op(BuiltinOp),
     Text(String),
-    Root(Vec<MathNode>),
-    Ci(Vec<MathNode>),
+    /// The `<math>` element
+    Root {
+        children: Vec<MathNode>,
+        /// Attributes such as `display` or `alttext`
+        attributes: Attributes,
+    },
+    Ci {
+        children: Vec<MathNode>,
+        /// The `type` attribute, e.g. `function` for `<ci type="function">f</ci>`
+        ci_type: Option<CiType>,
+        attributes: Attributes,
+    },
+    /// `<csymbol>`, either MathML2 style with a `definitionURL` or MathML3
+    /// style naming a symbol from a content dictionary (`cd`).
+    /// `sbml` is set when the URL is one of the symbols SBML defines
     Csymbol {
-        definition_url: String,
+        definition_url: Option<String>,
+        cd: Option<String>,
         encoding: Option<String>,
-        children: Vec<MathNode>,
+        name: String,
+        sbml: Option<SbmlSymbol>,
+        /// Attributes besides the ones above, e.g. `id`
+        attributes: Attributes,
     },
     Cn {
         num_type: numbers::NumType,
         base: u32,
         definition_url: Option<String>,
         encoding: Option<String>,
-        attributes: Option<HashMap<String, String>>,
+        /// The `units` attribute in the SBML or CellML namespace (of any level
+        /// and version)
+        units: Option<String>,
+        /// The namespace `units` was read under, e.g. SBML Level 3 Version 2's
+        /// or CellML 2.0's. Without one, it is written in SBML's
+        units_namespace: Option<String>,
+        /// Attributes besides the ones above, e.g. `id`
+        attributes: Attributes,
     },
     Comment(String),
     PI(String, Option<String>),
+    /// Stands in for an element that failed to parse, see `parse_document_recovering`
+    Error {
+        message: String,
+        span: Span,
+    },
+    /// An element the parser doesn't know, kept as is unless parsing strictly
+    Unknown {
+        name: String,
+        attributes: Attributes,
+        children: Vec<MathNode>,
+    },
+    Presentation(PresentationNode),
+    /// Each piece is a `(value, condition)` pair, tried in document order
+    Piecewise {
+        pieces: Vec<(MathNode, MathNode)>,
+        otherwise: Option<Box<MathNode>>,
+    },
+    /// A function of the bound variables, as used by SBML function definitions
+    Lambda {
+        bound_vars: Vec<String>,
+        body: Box<MathNode>,
+    },
+    /// `<degree>` qualifier, e.g. the 3 of a cube root
+    Degree(Box<MathNode>),
+    /// `<logbase>` qualifier of a logarithm
+    Logbase(Box<MathNode>),
+    /// `<bvar>` qualifier naming a bound variable, with the `<degree>` of a derivative
+    Bvar {
+        name: String,
+        degree: Option<Box<MathNode>>,
+    },
+    /// `<lowlimit>` qualifier
+    Lowlimit(Box<MathNode>),
+    /// `<uplimit>` qualifier
+    Uplimit(Box<MathNode>),
+    /// `<condition>` qualifier restricting the values of the bound variables
+    Condition(Box<MathNode>),
+    /// `<interval>`, either a value or the domain of a bound variable
+    Interval {
+        closure: IntervalClosure,
+        low: Box<MathNode>,
+        high: Box<MathNode>,
+    },
+    /// `<matrix>`, as the cells of its `<matrixrow>`s
+    Matrix(Vec<Vec<MathNode>>),
+    /// `<vector>`
+    Vector(Vec<MathNode>),
+    /// `<set>`
+    Set(Vec<MathNode>),
+    /// `<list>`, with the ordering given by its `order` attribute
+    List {
+        order: Option<Order>,
+        items: Vec<MathNode>,
+    },
+    /// An empty constant element such as `<pi/>` or `<true/>`
+    Constant(ConstantSymbol),
+    /// An empty number set element such as `<reals/>` or `<emptyset/>`
+    NumberSet(NumberSet),
+    /// `<semantics>`: an expression together with alternate representations of it
+    Semantics {
+        primary: Box<MathNode>,
+        annotations: Vec<Annotation>,
+    },
+    /// `<cs>`, a string literal
+    Cs(String),
+    /// `<cbytes>`, binary data decoded from its base64
+    Cbytes(Vec<u8>),
+    /// `<cerror>`: a `<csymbol>` naming an error, followed by the objects it
+    /// concerns
+    Cerror(Vec<MathNode>),
+    /// `<share>`, linking to the element whose `id` its `href` names, e.g.
+    /// `#t1`. Kept as a link until `resolve_shares`
+    Share(String),
+    /// `<bind>`, as strict content MathML writes quantifiers and `lambda`:
+    /// `binder` of the function of `bound_vars` given by `body`, restricted
+    /// by `condition`
+    Bind {
+        binder: Box<MathNode>,
+        bound_vars: Vec<String>,
+        condition: Option<Box<MathNode>>,
+        body: Box<MathNode>,
+    },
+}
+
+/// The `order` attribute of a `<list>`
+#[derive(Deserialize, Debug, Serialize, Eq, PartialEq, Clone, Copy, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Order {
+    Lexicographic,
+    Numeric,
+}
+
+/// The `type` attribute of a `<ci>`, saying what kind of object it names
+#[derive(Deserialize, Debug, Serialize, Eq, PartialEq, Clone, Copy, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum CiType {
+    Integer,
+    Rational,
+    Real,
+    Complex,
+    ComplexPolar,
+    ComplexCartesian,
+    Constant,
+    Function,
+    Vector,
+    List,
+    Set,
+    Matrix,
+}
+
+impl fmt::Display for CiType {
+    /// Writes the value of the `type` attribute
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = serde_plain::to_string(self).map_err(|_| fmt::Error)?;
+        f.write_str(&name)
+    }
+}
+
+#[derive(Deserialize, Debug, Serialize, Eq, PartialEq, Clone, Copy, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum IntervalClosure {
+    Closed,
+    Open,
+    OpenClosed,
+    ClosedOpen,
+}
+
+impl IntervalClosure {
+    /// The opening and closing brackets of an interval with this closure
+    pub fn brackets(&self) -> (&'static str, &'static str) {
+        match self {
+            IntervalClosure::Closed => ("[", "]"),
+            IntervalClosure::Open => ("(", ")"),
+            IntervalClosure::OpenClosed => ("(", "]"),
+            IntervalClosure::ClosedOpen => ("[", ")"),
+        }
+    }
+}
+
+impl MathNode {
+    /// Comments and processing instructions carry no mathematical meaning
+    pub(crate) fn is_markup(&self) -> bool {
+        matches!(self, MathNode::Comment(_) | MathNode::PI(_, _))
+    }
+    /// Qualifiers modify the operator of an `<apply>` instead of being one of its operands
+    pub fn is_qualifier(&self) -> bool {
+        matches!(
+            self,
+            MathNode::Degree(_)
+                | MathNode::Logbase(_)
+                | MathNode::Bvar { .. }
+                | MathNode::Lowlimit(_)
+                | MathNode::Uplimit(_)
+                | MathNode::Condition(_)
+        )
+    }
+    /// The operands of an `<apply>`: every child after the operator that is
+    /// neither a qualifier nor a comment. Empty for any other node.
+    /// An `<interval>` is a qualifier when the `<apply>` binds variables
+    pub fn operands(&self) -> Vec<&MathNode> {
+        match self {
+            MathNode::Apply { children, .. } => {
+                let binds = !self.bound_variables().is_empty();
+                children
+                    .iter()
+                    .skip(1)
+                    .filter(|c| !c.is_qualifier() && !c.is_markup())
+                    .filter(|c| !(binds && matches!(c, MathNode::Interval { .. })))
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+    /// The `<bvar>`s of an `<apply>` or `<bind>`, with their `<degree>`s
+    pub fn bvars(&self) -> Vec<(&str, Option<&MathNode>)> {
+        match self {
+            MathNode::Bind { bound_vars, .. } => bound_vars
+                .iter()
+                .map(|name| (name.as_str(), None))
+                .collect(),
+            MathNode::Apply { children, .. } => children
+                .iter()
+                .filter_map(|c| match c {
+                    MathNode::Bvar { name, degree } => Some((name.as_str(), degree.as_deref())),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+    /// The names of the `<bvar>`s of an `<apply>` or `<bind>`
+    pub fn bound_variables(&self) -> Vec<&str> {
+        self.bvars().into_iter().map(|(name, _)| name).collect()
+    }
+    fn qualifier<'a>(
+        &'a self,
+        pick: fn(&'a MathNode) -> Option<&'a MathNode>,
+    ) -> Option<&'a MathNode> {
+        match self {
+            MathNode::Apply { children, .. } => children.iter().find_map(pick),
+            _ => None,
+        }
+    }
+    /// The `<degree>` of an `<apply>`, e.g. of a `<root/>`
+    pub fn degree(&self) -> Option<&MathNode> {
+        self.qualifier(|c| match c {
+            MathNode::Degree(degree) => Some(degree),
+            _ => None,
+        })
+    }
+    /// The `<logbase>` of an `<apply>` of `<log/>`
+    pub fn logbase(&self) -> Option<&MathNode> {
+        self.qualifier(|c| match c {
+            MathNode::Logbase(base) => Some(base),
+            _ => None,
+        })
+    }
+    /// The `<lowlimit>` of an `<apply>`
+    pub fn lowlimit(&self) -> Option<&MathNode> {
+        self.qualifier(|c| match c {
+            MathNode::Lowlimit(limit) => Some(limit),
+            _ => None,
+        })
+    }
+    /// The `<uplimit>` of an `<apply>`
+    pub fn uplimit(&self) -> Option<&MathNode> {
+        self.qualifier(|c| match c {
+            MathNode::Uplimit(limit) => Some(limit),
+            _ => None,
+        })
+    }
+    /// The `<condition>` of an `<apply>`
+    pub fn condition(&self) -> Option<&MathNode> {
+        self.qualifier(|c| match c {
+            MathNode::Condition(condition) => Some(condition),
+            _ => None,
+        })
+    }
+    /// The `<interval>` a bound variable of an `<apply>` ranges over
+    pub fn interval(&self) -> Option<&MathNode> {
+        if self.bound_variables().is_empty() {
+            return None;
+        }
+        self.qualifier(|c| match c {
+            MathNode::Interval { .. } => Some(c),
+            _ => None,
+        })
+    }
+    /// The lower and upper limits of an `<apply>`, given either as
+    /// `<lowlimit>`/`<uplimit>` or as an `<interval>`
+    pub fn limits(&self) -> Option<(&MathNode, &MathNode)> {
+        match (self.lowlimit(), self.uplimit(), self.interval()) {
+            (Some(low), Some(high), _) => Some((low, high)),
+            (_, _, Some(MathNode::Interval { low, high, .. })) => Some((low, high)),
+            _ => None,
+        }
+    }
+}
+
+/// Joins the text children of a token element like `<ci>` or `<csymbol>`.
+/// Presentation markup such as `<msub>` counts as its `identifier_text`
+pub(crate) fn text_content(children: &[MathNode]) -> String {
+    children
+        .iter()
+        .filter_map(|c| match c {
+            MathNode::Text(t) => Some(t.clone()),
+            MathNode::Presentation(p) => Some(p.identifier_text()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+/// A parsed child, either a node or a part of its parent like a `<piece>`
+enum Parsed {
+    Node(MathNode),
+    Piece(MathNode, MathNode),
+    Otherwise(MathNode),
+    Row(Vec<MathNode>),
+}
+
+impl Parsed {
+    fn into_node(self) -> Result<MathNode, ParseError> {
+        let (tag, parent) = match self {
+            Parsed::Node(node) => return Ok(node),
+            Parsed::Piece(..) => ("piece", "piecewise"),
+            Parsed::Otherwise(_) => ("otherwise", "piecewise"),
+            Parsed::Row(_) => ("matrixrow", "matrix"),
+        };
+        Err(ParseError::InvalidStructure(format!(
+            "<{}> outside of <{}>",
+            tag, parent
+        )))
+    }
+}
+
+/// An element whose children are still being parsed
+struct Pending<'a, 'input> {
+    node: Node<'a, 'input>,
+    children: roxmltree::Children<'a, 'input>,
+    parsed: Vec<Parsed>,
 }
 
-fn has_text(math_node: &MathNode) -> bool {
-    match math_node {
-        MathNode::Text(e) if e.is_empty() => false,
+impl<'a, 'input> Pending<'a, 'input> {
+    fn new(node: Node<'a, 'input>) -> Self {
+        Pending {
+            node,
+            children: node.children(),
+            parsed: Vec::new(),
+        }
+    }
+}
+
+/// Elements that are parsed in one go instead of from their parsed children
+fn parses_whole(node: Node) -> bool {
+    let tag_name = node.tag_name().name();
+    matches!(tag_name, "cn" | "semantics" | "cs" | "cbytes" | "share")
+        || presentation::is_presentation_tag(tag_name)
+}
+/// The only children some elements can have
+fn allowed_children(tag_name: &str) -> Option<&'static [&'static str]> {
+    match tag_name {
+        "piecewise" => Some(&["piece", "otherwise"]),
+        "matrix" => Some(&["matrixrow"]),
+        _ => None,
+    }
+}
+/// Whether a child node ends up in the tree at all
+pub(crate) fn is_kept(node: Node, opts: &ParserOptions) -> bool {
+    match node.node_type() {
+        NodeType::Text => !node.text().unwrap_or_default().trim().is_empty(),
+        NodeType::Comment => opts.keep_comments,
+        NodeType::PI => opts.keep_processing_instructions,
         _ => true,
     }
 }
-fn map_children(node: Node) -> Vec<MathNode> {
-    node.children().map(parse_node).filter(has_text).collect()
+/// Fails if `child` can't appear inside `parent`
+fn check_allowed(parent: Node, child: Node) -> Result<(), ParseError> {
+    let (parent, child) = (parent.tag_name().name(), child.tag_name().name());
+    match allowed_children(parent) {
+        Some(allowed) if !allowed.contains(&child) => Err(ParseError::InvalidStructure(format!(
+            "unexpected <{}> inside <{}>",
+            child, parent
+        ))),
+        _ => Ok(()),
+    }
+}
+/// Where `parse_tree` reports the problems it skips over
+struct Recovery<'a, 't> {
+    locator: &'a span::Locator<'t>,
+    diagnostics: Vec<ParseDiagnostic>,
+}
+/// Hands `error` to `recovery` if there is one, to get a placeholder for
+/// `node`, or fails with it otherwise
+fn recover(
+    recovery: &mut Option<&mut Recovery>,
+    node: Node,
+    error: ParseError,
+) -> Result<MathNode, ParseError> {
+    let recovery = match recovery {
+        Some(recovery) => recovery,
+        None => return Err(error),
+    };
+    let span = recovery.locator.span(node);
+    let message = error.to_string();
+    recovery.diagnostics.push(ParseDiagnostic {
+        message: message.clone(),
+        span,
+    });
+    Ok(MathNode::Error { message, span })
+}
+/// Parses an element and everything below it. This walks the tree with an
+/// explicit stack, so machine-written documents nested thousands of levels
+/// deep can't overflow the call stack
+fn parse_tree(
+    root: Node,
+    opts: &ParserOptions,
+    mut recovery: Option<&mut Recovery>,
+) -> Result<MathNode, ParseError> {
+    let mut stack = vec![Pending::new(root)];
+    loop {
+        let top = stack
+            .last_mut()
+            .expect("the stack holds the root until it is done");
+        let (source, parsed) = match top.children.find(|n| is_kept(*n, opts)) {
+            Some(child) if child.is_element() => {
+                let allowed = check_allowed(top.node, child);
+                if allowed.is_ok() && !parses_whole(child) {
+                    stack.push(Pending::new(child));
+                    continue;
+                }
+                let parsed = allowed.and_then(|_| parse_element_type(child, Vec::new(), opts));
+                (child, parsed.map(Parsed::Node))
+            }
+            Some(child) => (child, parse_with(child, opts).map(Parsed::Node)),
+            None => {
+                let done = stack.pop().expect("checked above");
+                (done.node, parse_part(done.node, done.parsed, opts))
+            }
+        };
+        let parsed = match parsed {
+            Ok(parsed) => parsed,
+            Err(error) => Parsed::Node(recover(&mut recovery, source, error)?),
+        };
+        match stack.last_mut() {
+            Some(parent) => parent.parsed.push(parsed),
+            None => {
+                return parsed
+                    .into_node()
+                    .or_else(|error| recover(&mut recovery, root, error))
+            }
+        }
+    }
+}
+/// The children of an element that can only contain nodes
+fn nodes(children: Vec<Parsed>) -> Result<Vec<MathNode>, ParseError> {
+    children.into_iter().map(Parsed::into_node).collect()
+}
+/// Drops the text, comments and processing instructions between elements
+fn elements(children: Vec<Parsed>) -> Result<Vec<MathNode>, ParseError> {
+    let mut nodes = nodes(children)?;
+    nodes.retain(|n| {
+        !matches!(
+            n,
+            MathNode::Text(_) | MathNode::Comment(_) | MathNode::PI(_, _)
+        )
+    });
+    Ok(nodes)
+}
+/// The element children of a node that must have exactly `N` of them
+fn fixed_children<const N: usize>(
+    node: Node,
+    children: Vec<Parsed>,
+) -> Result<[MathNode; N], ParseError> {
+    let children = elements(children)?;
+    let found = children.len();
+    children.try_into().map_err(|_| {
+        ParseError::InvalidStructure(format!(
+            "<{}> needs exactly {} children, found {}",
+            node.tag_name().name(),
+            N,
+            found
+        ))
+    })
+}
+/// Builds an element from its parsed children
+fn parse_part(
+    node: Node,
+    children: Vec<Parsed>,
+    opts: &ParserOptions,
+) -> Result<Parsed, ParseError> {
+    Ok(match node.tag_name().name() {
+        "piece" => {
+            let [value, condition] = fixed_children(node, children)?;
+            Parsed::Piece(value, condition)
+        }
+        "otherwise" => {
+            let [value] = fixed_children(node, children)?;
+            Parsed::Otherwise(value)
+        }
+        "matrixrow" => Parsed::Row(elements(children)?),
+        _ => Parsed::Node(parse_element_type(node, children, opts)?),
+    })
+}
+fn parse_piecewise(children: Vec<Parsed>) -> Result<MathNode, ParseError> {
+    let mut pieces = Vec::new();
+    let mut otherwise = None;
+    for child in children {
+        match child {
+            Parsed::Piece(value, condition) => pieces.push((value, condition)),
+            Parsed::Otherwise(value) if otherwise.is_none() => otherwise = Some(Box::new(value)),
+            Parsed::Otherwise(_) => {
+                return Err(ParseError::InvalidStructure(
+                    "<piecewise> can only have one <otherwise>".to_owned(),
+                ))
+            }
+            // Comments and processing instructions
+            _ => {}
+        }
+    }
+    Ok(MathNode::Piecewise { pieces, otherwise })
 }
-fn parse_element_type(node: Node) -> MathNode {
+fn parse_matrix(children: Vec<Parsed>) -> Result<MathNode, ParseError> {
+    let mut rows: Vec<Vec<MathNode>> = Vec::new();
+    for child in children {
+        let row = match child {
+            Parsed::Row(row) => row,
+            _ => continue,
+        };
+        if let Some(first) = rows.first() {
+            if first.len() != row.len() {
+                return Err(ParseError::InvalidStructure(format!(
+                    "<matrix> rows must have the same length, found {} and {}",
+                    first.len(),
+                    row.len()
+                )));
+            }
+        }
+        rows.push(row);
+    }
+    Ok(MathNode::Matrix(rows))
+}
+/// The `definitionURL` attribute, also accepting the `definitionUrl` spelling
+pub(crate) fn definition_url(node: Node) -> Option<String> {
+    node.attribute("definitionURL")
+        .or_else(|| node.attribute("definitionUrl"))
+        .map(|url| url.to_owned())
+}
+fn parse_csymbol(node: Node, children: Vec<Parsed>) -> Result<MathNode, ParseError> {
+    let name = text_content(&nodes(children)?);
+    if name.is_empty() {
+        return Err(ParseError::InvalidStructure(
+            "<csymbol> is missing its name".to_owned(),
+        ));
+    }
+    let definition_url = definition_url(node);
+    Ok(MathNode::Csymbol {
+        sbml: definition_url.as_deref().and_then(SbmlSymbol::from_url),
+        definition_url,
+        cd: node.attribute("cd").map(|cd| cd.to_owned()),
+        encoding: node.attribute("encoding").map(|e| e.to_owned()),
+        name,
+        attributes: attributes::extra_attributes(
+            node,
+            &["definitionURL", "definitionUrl", "cd", "encoding"],
+        ),
+    })
+}
+fn parse_ci(node: Node, children: Vec<Parsed>) -> Result<MathNode, ParseError> {
+    let ci_type = match node.attribute("type") {
+        Some(ci_type) => {
+            Some(
+                serde_plain::from_str(ci_type).map_err(|_| ParseError::InvalidAttribute {
+                    element: "ci".to_owned(),
+                    attribute: "type".to_owned(),
+                    value: ci_type.to_owned(),
+                })?,
+            )
+        }
+        None => None,
+    };
+    Ok(MathNode::Ci {
+        children: nodes(children)?,
+        ci_type,
+        attributes: attributes::extra_attributes(node, &["type"]),
+    })
+}
+fn parse_list(node: Node, children: Vec<Parsed>) -> Result<MathNode, ParseError> {
+    let order = match node.attribute("order") {
+        Some(order) => {
+            Some(
+                serde_plain::from_str(order).map_err(|_| ParseError::InvalidAttribute {
+                    element: "list".to_owned(),
+                    attribute: "order".to_owned(),
+                    value: order.to_owned(),
+                })?,
+            )
+        }
+        None => None,
+    };
+    Ok(MathNode::List {
+        order,
+        items: elements(children)?,
+    })
+}
+fn parse_bvar(children: Vec<Parsed>) -> Result<MathNode, ParseError> {
+    let mut name = None;
+    let mut degree = None;
+    for child in elements(children)? {
+        match child {
+            MathNode::Ci { children, .. } if name.is_none() => name = Some(text_content(&children)),
+            MathNode::Degree(d) if degree.is_none() => degree = Some(d),
+            _ => {
+                return Err(ParseError::InvalidStructure(
+                    "<bvar> must contain a <ci> and optionally a <degree>".to_owned(),
+                ))
+            }
+        }
+    }
+    match name {
+        Some(name) => Ok(MathNode::Bvar { name, degree }),
+        None => Err(ParseError::InvalidStructure(
+            "<bvar> is missing its <ci>".to_owned(),
+        )),
+    }
+}
+fn parse_interval(node: Node, children: Vec<Parsed>) -> Result<MathNode, ParseError> {
+    let closure = match node.attribute("closure") {
+        Some(closure) => {
+            serde_plain::from_str(closure).map_err(|_| ParseError::InvalidAttribute {
+                element: "interval".to_owned(),
+                attribute: "closure".to_owned(),
+                value: closure.to_owned(),
+            })?
+        }
+        None => IntervalClosure::Closed,
+    };
+    let [low, high] = fixed_children(node, children)?;
+    Ok(MathNode::Interval {
+        closure,
+        low: Box::new(low),
+        high: Box::new(high),
+    })
+}
+fn parse_lambda(children: Vec<Parsed>) -> Result<MathNode, ParseError> {
+    let mut bound_vars = Vec::new();
+    let mut body = None;
+    for child in elements(children)? {
+        match child {
+            MathNode::Bvar { name, .. } if body.is_none() => bound_vars.push(name),
+            _ if body.is_none() => body = Some(Box::new(child)),
+            _ => {
+                return Err(ParseError::InvalidStructure(
+                    "<lambda> can only have one body".to_owned(),
+                ))
+            }
+        }
+    }
+    let body = body
+        .ok_or_else(|| ParseError::InvalidStructure("<lambda> is missing its body".to_owned()))?;
+    Ok(MathNode::Lambda { bound_vars, body })
+}
+/// The content elements `parse_element_type` knows besides operators,
+/// constants and number sets
+const CONTENT_TAGS: &[&str] = &[
+    "math",
+    "apply",
+    "ci",
+    "cn",
+    "piecewise",
+    "piece",
+    "otherwise",
+    "lambda",
+    "degree",
+    "logbase",
+    "bvar",
+    "lowlimit",
+    "uplimit",
+    "condition",
+    "interval",
+    "matrix",
+    "matrixrow",
+    "vector",
+    "set",
+    "list",
+    "semantics",
+    "csymbol",
+    "cs",
+    "cbytes",
+    "cerror",
+    "share",
+    "bind",
+];
+/// Whether `parse_element_type` knows an element of this name
+fn is_known_tag(tag_name: &str) -> bool {
+    CONTENT_TAGS.contains(&tag_name)
+        || presentation::is_presentation_tag(tag_name)
+        || serde_plain::from_str::<BuiltinOp>(tag_name).is_ok()
+        || serde_plain::from_str::<ConstantSymbol>(tag_name).is_ok()
+        || serde_plain::from_str::<NumberSet>(tag_name).is_ok()
+}
+/// Builds an element from its already parsed `children`, which are left empty
+/// for the elements that `parses_whole`
+fn parse_element_type(
+    node: Node,
+    children: Vec<Parsed>,
+    opts: &ParserOptions,
+) -> Result<MathNode, ParseError> {
     let tag_name = node.tag_name().name();
     // Is this a defined op?
     let maybe_op: Result<BuiltinOp, serde_plain::Error> = serde_plain::from_str(tag_name);
     if let Ok(op) = maybe_op {
-        return MathNode::Op(op);
+        return Ok(MathNode::Op(op));
+    }
+    if let Ok(constant) = serde_plain::from_str(tag_name) {
+        return Ok(MathNode::Constant(constant));
+    }
+    if let Ok(set) = serde_plain::from_str(tag_name) {
+        return Ok(MathNode::NumberSet(set));
     }
     match tag_name {
-        "apply" => MathNode::Apply(map_children(node)),
-        "ci" => MathNode::Ci(map_children(node)),
+        "math" => Ok(MathNode::Root {
+            children: nodes(children)?,
+            attributes: attributes::extra_attributes(node, &[]),
+        }),
+        "apply" => Ok(MathNode::Apply {
+            children: nodes(children)?,
+            attributes: attributes::extra_attributes(node, &[]),
+        }),
+        "ci" => parse_ci(node, children),
         "cn" => numbers::node_to_cn(node),
-        "csymbol" => MathNode::Csymbol {
-            definition_url: node.attribute("definitionUrl").unwrap().to_owned(),
-            encoding: node.attribute("encoding").map(|e| e.to_owned()),
-            children: map_children(node),
-        },
-        _ => {
-            dbg!(node);
-            panic!()
+        "piecewise" => parse_piecewise(children),
+        "lambda" => parse_lambda(children),
+        "bind" => bind::parse_bind(elements(children)?),
+        "degree" => {
+            let [degree] = fixed_children(node, children)?;
+            Ok(MathNode::Degree(Box::new(degree)))
+        }
+        "logbase" => {
+            let [base] = fixed_children(node, children)?;
+            Ok(MathNode::Logbase(Box::new(base)))
+        }
+        "bvar" => parse_bvar(children),
+        "lowlimit" => {
+            let [limit] = fixed_children(node, children)?;
+            Ok(MathNode::Lowlimit(Box::new(limit)))
+        }
+        "uplimit" => {
+            let [limit] = fixed_children(node, children)?;
+            Ok(MathNode::Uplimit(Box::new(limit)))
         }
+        "condition" => {
+            let [condition] = fixed_children(node, children)?;
+            Ok(MathNode::Condition(Box::new(condition)))
+        }
+        "interval" => parse_interval(node, children),
+        "matrix" => parse_matrix(children),
+        "vector" => Ok(MathNode::Vector(elements(children)?)),
+        "set" => Ok(MathNode::Set(elements(children)?)),
+        "list" => parse_list(node, children),
+        "semantics" => semantics::parse_semantics(node, opts),
+        "csymbol" => parse_csymbol(node, children),
+        "cs" => Ok(MathNode::Cs(node.text().unwrap_or_default().to_owned())),
+        "cbytes" => strict::parse_cbytes(node),
+        "cerror" => Ok(MathNode::Cerror(elements(children)?)),
+        "share" => Ok(MathNode::Share(openmath::attribute(node, "href")?)),
+        _ if presentation::is_presentation_tag(tag_name) => Ok(MathNode::Presentation(
+            presentation::parse_presentation(node)?,
+        )),
+        _ if opts.strict => Err(ParseError::UnknownElement(tag_name.to_owned())),
+        _ => Ok(MathNode::Unknown {
+            name: tag_name.to_owned(),
+            attributes: attributes::extra_attributes(node, &[]),
+            children: nodes(children)?,
+        }),
     }
 }
 /// Parse a single xml node into a MathML node
-
-pub fn parse_node(node: Node) -> MathNode {
+pub fn parse_node(node: Node) -> Result<MathNode, ParseError> {
+    parse_with(node, &ParserOptions::default())
+}
+/// `parse_node`, as configured by `opts`
+pub(crate) fn parse_with(node: Node, opts: &ParserOptions) -> Result<MathNode, ParseError> {
     match node.node_type() {
-        NodeType::Text => MathNode::Text(node.text().unwrap().trim().to_owned()),
-        NodeType::Element if node.tag_name().name() == "math" => MathNode::Root(map_children(node)),
-        NodeType::Root => parse_node(node.first_child().unwrap()),
-        NodeType::Element => parse_element_type(node),
-        NodeType::PI => MathNode::PI(
-            node.pi().unwrap().target.to_owned(),
-            node.pi().unwrap().value.map(|m| m.to_owned()),
-        ),
-        NodeType::Comment => MathNode::Comment(node.text().unwrap().to_owned()),
+        NodeType::Text => {
+            let text = node.text().unwrap_or_default();
+            Ok(MathNode::Text(if opts.trim_whitespace {
+                text.trim().to_owned()
+            } else {
+                text.to_owned()
+            }))
+        }
+        // Skips comments and processing instructions before the root element
+        NodeType::Root => match node.children().find(|n| n.is_element()) {
+            Some(child) => parse_with(child, opts),
+            None => Err(ParseError::InvalidStructure("empty document".to_owned())),
+        },
+        NodeType::Element if parses_whole(node) => parse_element_type(node, Vec::new(), opts),
+        NodeType::Element => parse_tree(node, opts, None),
+        NodeType::PI => match node.pi() {
+            Some(pi) => Ok(MathNode::PI(
+                pi.target.to_owned(),
+                pi.value.map(|m| m.to_owned()),
+            )),
+            None => Err(ParseError::InvalidStructure(
+                "processing instruction without a target".to_owned(),
+            )),
+        },
+        NodeType::Comment => Ok(MathNode::Comment(
+            node.text().unwrap_or_default().to_owned(),
+        )),
     }
 }
 /// Parse a string into a MathML node
-pub fn parse_document(text: &str) -> Result<MathNode, roxmltree::Error> {
+pub fn parse_document(text: &str) -> Result<MathNode, ParseError> {
+    parse_document_with_options(text, &ParserOptions::default())
+}
+/// Like `parse_document`, but a `<cn>` of a type MathML doesn't define is an
+/// error instead of a `NumType::Other`
+pub fn parse_document_strict(text: &str) -> Result<MathNode, ParseError> {
+    let options = ParserOptions {
+        strict: true,
+        ..ParserOptions::default()
+    };
+    parse_document_with_options(text, &options)
+}
+/// Parse a string into a MathML node, as configured by `options`
+pub fn parse_document_with_options(
+    text: &str,
+    options: &ParserOptions,
+) -> Result<MathNode, ParseError> {
+    let sanitized = sanitize(text, options);
+    let xml = roxmltree::Document::parse(&sanitized.text)?;
+    parse_root(xml.root_element(), options)
+}
+fn sanitize(text: &str, options: &ParserOptions) -> regexes::Sanitized {
+    match options.entities {
+        EntityPolicy::Resolve => regexes::resolve_entities(text),
+        EntityPolicy::Preserve => regexes::preserve_entities(text),
+        EntityPolicy::Error => regexes::Sanitized {
+            text: text.to_owned(),
+            anchors: Vec::new(),
+        },
+    }
+}
+/// Parses the root element of a document, with the checks `options` asks for
+fn parse_root(root: Node, options: &ParserOptions) -> Result<MathNode, ParseError> {
+    if let Some(max_depth) = options.max_depth {
+        check_depth(root, max_depth)?;
+    }
+    let parsed = parse_with(root, options)?;
+    if options.strict {
+        check_strict(&parsed)?;
+    }
+    Ok(parsed)
+}
+/// Parses a bare expression such as `<apply>...</apply>`, as often stored
+/// without a surrounding `<math>`. The result is the expression itself, not a
+/// `MathNode::Root`. The root element must be a MathML element other than
+/// `<math>`, either without a namespace or in the MathML one
+pub fn parse_fragment(text: &str) -> Result<MathNode, ParseError> {
     let sanitized = regexes::sanitize_xml(text);
     let xml = roxmltree::Document::parse(&sanitized)?;
-
-    let parsed: MathNode = parse_node(xml.root());
-    Ok(parsed)
+    let root = xml.root_element();
+    let tag_name = root.tag_name().name();
+    match root.tag_name().namespace() {
+        Some(namespace) if namespace != writer::MATHML_NS => {
+            return Err(ParseError::ForeignElement {
+                name: tag_name.to_owned(),
+                namespace: namespace.to_owned(),
+            })
+        }
+        _ if tag_name == "math" => {
+            return Err(ParseError::InvalidStructure(
+                "a fragment can't be a whole <math> element, use parse_document".to_owned(),
+            ))
+        }
+        _ if !is_known_tag(tag_name) => {
+            return Err(ParseError::UnknownElement(tag_name.to_owned()))
+        }
+        _ => {}
+    }
+    parse_with(root, &ParserOptions::default())
+}
+/// Parses every `<math>` element of a larger XML document, such as XHTML, in
+/// document order. The rest of the document is ignored
+pub fn parse_all_math(text: &str) -> Result<Vec<MathNode>, ParseError> {
+    let options = ParserOptions::default();
+    let sanitized = regexes::sanitize_xml(text);
+    let xml = roxmltree::Document::parse(&sanitized)?;
+    let is_math = |n: &Node| n.is_element() && n.tag_name().name() == "math";
+    xml.descendants()
+        .filter(|n| is_math(n) && !n.ancestors().skip(1).any(|a| is_math(&a)))
+        .map(|n| parse_with(n, &options))
+        .collect()
+}
+/// Like `parse_document_with_options`, also returning where in `text` every
+/// node came from, e.g. to point at the line of an unknown identifier
+pub fn parse_document_with_spans(
+    text: &str,
+    options: &ParserOptions,
+) -> Result<(MathNode, SourceMap), ParseError> {
+    let sanitized = sanitize(text, options);
+    let xml = roxmltree::Document::parse(&sanitized.text)?;
+    let root = xml.root_element();
+    let parsed = parse_root(root, options)?;
+    let spans = span::source_map(&parsed, root, text, &sanitized.anchors, options);
+    Ok((parsed, spans))
+}
+/// Like `parse_document_with_options`, but an element that fails to parse
+/// becomes a `MathNode::Error` instead of failing the whole document, so that
+/// every problem can be reported at once. Malformed XML is still an error
+pub fn parse_document_recovering(
+    text: &str,
+    options: &ParserOptions,
+) -> Result<(MathNode, Vec<ParseDiagnostic>), ParseError> {
+    let sanitized = sanitize(text, options);
+    let xml = roxmltree::Document::parse(&sanitized.text)?;
+    let root = xml.root_element();
+    if let Some(max_depth) = options.max_depth {
+        check_depth(root, max_depth)?;
+    }
+    let locator = span::Locator::new(text, &sanitized.anchors);
+    let mut recovery = Recovery {
+        locator: &locator,
+        diagnostics: Vec::new(),
+    };
+    let parsed = if parses_whole(root) {
+        parse_element_type(root, Vec::new(), options)
+            .or_else(|error| recover(&mut Some(&mut recovery), root, error))?
+    } else {
+        parse_tree(root, options, Some(&mut recovery))?
+    };
+    if options.strict {
+        check_strict(&parsed)?;
+    }
+    Ok((parsed, recovery.diagnostics))
+}
+/// Fails if elements are nested deeper than `max_depth`
+fn check_depth(root: Node, max_depth: usize) -> Result<(), ParseError> {
+    let mut stack = vec![(root, 1)];
+    while let Some((node, depth)) = stack.pop() {
+        if depth > max_depth {
+            return Err(ParseError::TooDeep(max_depth));
+        }
+        stack.extend(
+            node.children()
+                .filter(|n| n.is_element())
+                .map(|n| (n, depth + 1)),
+        );
+    }
+    Ok(())
+}
+fn check_strict(parsed: &MathNode) -> Result<(), ParseError> {
+    let unknown = parsed.iter().find_map(|node| match node {
+        MathNode::Cn {
+            num_type: NumType::Other { type_name, .. },
+            ..
+        } => Some(type_name.clone()),
+        _ => None,
+    });
+    match unknown {
+        Some(type_name) => Err(ParseError::UnknownNumberType(type_name)),
+        None => Ok(()),
+    }
 }
 
 #[cfg(test)]
@@ -191,6 +1195,7 @@ mod test {
     use super::MathNode::*;
     use super::*;
     use crate::numbers::NumType;
+    use std::collections::HashMap;
 
     #[test]
     fn test_simple_parsing() {
@@ -201,11 +1206,13 @@ mod test {
                       <ci> y </ci>
                     </apply></math>"#;
         let res = parse_document(test).unwrap();
-        let exp = Root(vec![Apply(vec![
-            Op(BuiltinOp::plus),
-            Ci(vec![Text("x".to_owned())]),
-            Ci(vec![Text("y".to_owned())]),
-        ])]);
+        let exp = Root {
+            children: vec![Apply {
+                children: vec![Op(BuiltinOp::plus), MathNode::ci("x"), MathNode::ci("y")],
+                attributes: Vec::new(),
+            }],
+            attributes: Vec::new(),
+        };
         assert_eq!(res, exp);
     }
     #[test]
@@ -220,15 +1227,17 @@ mod test {
                       <ci> b </ci>
                     </apply>"#;
         let res = parse_document(test).unwrap();
-        let expect = Apply(vec![
-            Op(BuiltinOp::plus),
-            Apply(vec![
-                Op(BuiltinOp::times),
-                Ci(vec![Text("a".to_owned())]),
-                Ci(vec![Text("x".to_owned())]),
-            ]),
-            Ci(vec![Text("b".to_owned())]),
-        ]);
+        let expect = Apply {
+            children: vec![
+                Op(BuiltinOp::plus),
+                Apply {
+                    children: vec![Op(BuiltinOp::times), MathNode::ci("a"), MathNode::ci("x")],
+                    attributes: Vec::new(),
+                },
+                MathNode::ci("b"),
+            ],
+            attributes: Vec::new(),
+        };
         assert_eq!(res, expect)
     }
     #[test]
@@ -253,18 +1262,68 @@ mod test {
         assert_eq!(
             parsed,
             Cn {
-                num_type: NumType::Constant("$FIXED_tau".to_string()),
+                num_type: NumType::Constant(NamedConstant::Tau),
                 base: 10,
                 definition_url: None,
                 encoding: None,
-                attributes: None
+                units: None,
+                units_namespace: None,
+                attributes: Vec::new()
             }
         )
     }
     #[test]
+    fn test_named_constant() {
+        let test = r#"<cn type="constant">  &tau; </cn>"#;
+        let parsed = parse_document(test).unwrap();
+        let options = ParserOptions {
+            entities: EntityPolicy::Preserve,
+            ..ParserOptions::default()
+        };
+        assert_eq!(parse_document_with_options(test, &options).unwrap(), parsed);
+        assert_eq!(parsed.to_mathml(), r#"<cn type="constant">τ</cn>"#);
+        assert_eq!(parsed.to_string(), "tau");
+        assert_eq!(parsed.eval(&HashMap::new()), Ok(2.0 * std::f64::consts::PI));
+        let preserved = parse_document_with_options("<ci>&alpha;</ci>", &options).unwrap();
+        assert_eq!(preserved.to_mathml(), "<ci>&alpha;</ci>");
+        let custom = parse_document(r#"<cn type="constant">c0</cn>"#).unwrap();
+        assert!(custom.eval(&HashMap::new()).is_err());
+    }
+    #[test]
+    fn test_entity_table() {
+        let parsed = parse_document("<mi>&alefsym;&infin;&ne;&Alpha;&le;</mi>").unwrap();
+        assert_eq!(parsed.to_string(), "ℵ∞≠Α≤");
+    }
+    #[test]
+    fn test_declared_entities() {
+        let test = r#"<?xml version="1.0"?>
+<!DOCTYPE math [
+  <!-- the ]> here doesn't end the DOCTYPE -->
+  <!ENTITY km "<ci>Km</ci>">
+  <!ENTITY tau "T">
+  <!ENTITY two '<cn type="integer">&#50;</cn>'>
+  <!ENTITY sum "&km; + &two;">
+]>
+<math><apply><plus/>&km;&two;<ci>&tau;</ci></apply></math>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(parsed.to_string(), "Km + 2 + T");
+        let laughs = format!(
+            "<!DOCTYPE math [<!ENTITY l0 \"lol\">{}]><math><ci>&l20;</ci></math>",
+            (1..=20)
+                .map(|i| format!(
+                    "<!ENTITY l{} \"{}\">",
+                    i,
+                    format!("&l{};", i - 1).repeat(10)
+                ))
+                .collect::<String>()
+        );
+        assert!(parse_document(&laughs).is_err());
+    }
+    #[test]
     fn test_sbml_attrs() {
         use BuiltinOp::*;
         use NumType::*;
+        const SBML_L3V2: &str = "http://www.sbml.org/sbml/level3/version2/core";
         let test = r#"                <math xmlns="http://www.w3.org/1998/Math/MathML" 
                     xmlns:sbml="http://www.sbml.org/sbml/level3/version2/core">
                     <apply>
@@ -281,39 +1340,544 @@ mod test {
                         </apply>
                     </apply>
                 </math>"#;
-        let xml = roxmltree::Document::parse(&test).unwrap();
-        let parsed: MathNode = parse_node(xml.root());
-        let units: HashMap<String, String> = vec![(
-            "http://www.sbml.org/sbml/level3/version2/core:units".to_owned(),
-            "mole".to_owned(),
-        )]
-        .into_iter()
-        .collect();
-        let expected = Root(vec![Apply(vec![
-            Op(and),
-            Apply(vec![
-                Op(lt),
-                Cn {
-                    num_type: Real(1.0),
-                    base: 10,
-                    definition_url: None,
-                    encoding: None,
-                    attributes: Some(units.clone()),
+        let xml = roxmltree::Document::parse(test).unwrap();
+        let parsed: MathNode = parse_node(xml.root()).unwrap();
+        let expected = Root {
+            children: vec![Apply {
+                children: vec![
+                    Op(and),
+                    Apply {
+                        children: vec![
+                            Op(lt),
+                            Cn {
+                                num_type: Real(1.0),
+                                base: 10,
+                                definition_url: None,
+                                encoding: None,
+                                units: Some("mole".to_owned()),
+                                units_namespace: Some(SBML_L3V2.to_owned()),
+                                attributes: Vec::new(),
+                            },
+                            MathNode::ci("S1"),
+                        ],
+                        attributes: Vec::new(),
+                    },
+                    Apply {
+                        children: vec![
+                            Op(lt),
+                            MathNode::ci("S1"),
+                            Cn {
+                                num_type: Real(100.0),
+                                base: 10,
+                                definition_url: None,
+                                encoding: None,
+                                units: Some("mole".to_owned()),
+                                units_namespace: Some(SBML_L3V2.to_owned()),
+                                attributes: Vec::new(),
+                            },
+                        ],
+                        attributes: Vec::new(),
+                    },
+                ],
+                attributes: Vec::new(),
+            }],
+            attributes: Vec::new(),
+        };
+        assert_eq!(expected, parsed);
+        let written = parsed.to_mathml();
+        assert!(written.contains(r#"<cn type="real" xmlns:sbml="http://www.sbml.org/sbml/level3/version2/core" sbml:units="mole">1</cn>"#));
+        assert_eq!(parse_document(&written).unwrap(), parsed);
+    }
+    #[test]
+    fn test_errors() {
+        let test = r#"<apply><plus/><foo/></apply>"#;
+        match parse_document_strict(test) {
+            Err(ParseError::UnknownElement(name)) => assert_eq!(name, "foo"),
+            other => panic!("expected an unknown element error, got {:?}", other),
+        }
+        let test = r#"<cn type="integer"> 12a </cn>"#;
+        match parse_document(test) {
+            Err(ParseError::InvalidNumber { num_type, text }) => {
+                assert_eq!(num_type, "integer");
+                assert_eq!(text, "12a");
+            }
+            other => panic!("expected an invalid number error, got {:?}", other),
+        }
+        let test = r#"<apply><plus/><cn type="double"> 2.5 </cn><cn>1</cn></apply>"#;
+        let lenient = parse_document(test).unwrap();
+        assert_eq!(lenient.eval(&HashMap::new()), Ok(3.5));
+        assert_eq!(parse_document(&lenient.to_mathml()).unwrap(), lenient);
+        match parse_document_strict(test) {
+            Err(ParseError::UnknownNumberType(t)) => assert_eq!(t, "double"),
+            other => panic!("expected an unknown number type error, got {:?}", other),
+        }
+        let test = r#"<csymbol cd="transc1"/>"#;
+        assert!(matches!(
+            parse_document(test),
+            Err(ParseError::InvalidStructure(_))
+        ));
+        assert!(matches!(
+            parse_document("<apply></plus>"),
+            Err(ParseError::Xml(_))
+        ));
+    }
+    #[test]
+    fn test_piecewise() {
+        let test = r#"<math xmlns="http://www.w3.org/1998/Math/MathML">
+                <piecewise>
+                    <piece>
+                        <piecewise>
+                            <piece><cn> 1 </cn><apply><gt/><ci> y </ci><cn> 0 </cn></apply></piece>
+                            <otherwise><cn> 2 </cn></otherwise>
+                        </piecewise>
+                        <apply><lt/><ci> x </ci><cn> 0 </cn></apply>
+                    </piece>
+                    <!-- fall through -->
+                    <otherwise><ci> x </ci></otherwise>
+                </piecewise>
+            </math>"#;
+        let cn = |v: f64| Cn {
+            num_type: NumType::Real(v),
+            base: 10,
+            definition_url: None,
+            encoding: None,
+            units: None,
+            units_namespace: None,
+            attributes: Vec::new(),
+        };
+        let ci = MathNode::ci;
+        let inner = Piecewise {
+            pieces: vec![(
+                cn(1.0),
+                Apply {
+                    children: vec![Op(BuiltinOp::gt), ci("y"), cn(0.0)],
+                    attributes: Vec::new(),
                 },
-                Ci(vec![Text("S1".to_owned())]),
-            ]),
-            Apply(vec![
-                Op(lt),
-                Ci(vec![Text("S1".to_owned())]),
-                Cn {
-                    num_type: Real(100.0),
-                    base: 10,
+            )],
+            otherwise: Some(Box::new(cn(2.0))),
+        };
+        let expected = Root {
+            children: vec![Piecewise {
+                pieces: vec![(
+                    inner,
+                    Apply {
+                        children: vec![Op(BuiltinOp::lt), ci("x"), cn(0.0)],
+                        attributes: Vec::new(),
+                    },
+                )],
+                otherwise: Some(Box::new(ci("x"))),
+            }],
+            attributes: Vec::new(),
+        };
+        assert_eq!(parse_document(test).unwrap(), expected);
+
+        let test = r#"<piecewise><piece><cn> 1 </cn></piece></piecewise>"#;
+        assert!(matches!(
+            parse_document(test),
+            Err(ParseError::InvalidStructure(_))
+        ));
+    }
+    #[test]
+    fn test_lambda() {
+        let test = r#"<math xmlns="http://www.w3.org/1998/Math/MathML">
+                <lambda>
+                    <bvar><ci> x </ci></bvar>
+                    <bvar><ci> y </ci></bvar>
+                    <apply><times/><ci> x </ci><ci> y </ci></apply>
+                </lambda>
+            </math>"#;
+        let expected = Root {
+            children: vec![Lambda {
+                bound_vars: vec!["x".to_owned(), "y".to_owned()],
+                body: Box::new(Apply {
+                    children: vec![Op(BuiltinOp::times), MathNode::ci("x"), MathNode::ci("y")],
+                    attributes: Vec::new(),
+                }),
+            }],
+            attributes: Vec::new(),
+        };
+        assert_eq!(parse_document(test).unwrap(), expected);
+
+        let test = r#"<lambda><bvar><ci> x </ci></bvar></lambda>"#;
+        assert!(matches!(
+            parse_document(test),
+            Err(ParseError::InvalidStructure(_))
+        ));
+    }
+    #[test]
+    fn test_degree_logbase() {
+        let test =
+            r#"<apply><root/><degree><cn type="integer"> 3 </cn></degree><ci> x </ci></apply>"#;
+        let parsed = parse_document(test).unwrap();
+        let three = || Cn {
+            num_type: NumType::Integer(3),
+            base: 10,
+            definition_url: None,
+            encoding: None,
+            units: None,
+            units_namespace: None,
+            attributes: Vec::new(),
+        };
+        assert_eq!(
+            parsed,
+            Apply {
+                children: vec![
+                    Op(BuiltinOp::root),
+                    Degree(Box::new(three())),
+                    MathNode::ci("x"),
+                ],
+                attributes: Vec::new()
+            }
+        );
+        assert_eq!(parsed.degree(), Some(&three()));
+        assert_eq!(parsed.logbase(), None);
+        assert_eq!(parsed.operands(), vec![&MathNode::ci("x")]);
+
+        let test =
+            r#"<apply><log/><logbase><cn type="integer"> 2 </cn></logbase><ci> x </ci></apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert!(parsed.logbase().is_some());
+        assert_eq!(parsed.operands().len(), 1);
+    }
+    #[test]
+    fn test_limits_and_intervals() {
+        let test = r#"<apply><sum/>
+                        <bvar><ci> i </ci></bvar>
+                        <lowlimit><cn type="integer"> 1 </cn></lowlimit>
+                        <uplimit><ci> n </ci></uplimit>
+                        <apply><power/><ci> i </ci><cn type="integer"> 2 </cn></apply>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(parsed.bound_variables(), vec!["i"]);
+        let (low, high) = parsed.limits().unwrap();
+        assert!(matches!(
+            low,
+            Cn {
+                num_type: NumType::Integer(1),
+                ..
+            }
+        ));
+        assert_eq!(high, &MathNode::ci("n"));
+        assert_eq!(parsed.operands().len(), 1);
+
+        let test = r#"<apply><int/>
+                        <bvar><ci> x </ci></bvar>
+                        <interval closure="open"><cn> 0 </cn><cn> 1 </cn></interval>
+                        <ci> f </ci>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert!(matches!(
+            parsed.interval(),
+            Some(Interval {
+                closure: IntervalClosure::Open,
+                ..
+            })
+        ));
+        assert!(parsed.limits().is_some());
+        assert_eq!(parsed.operands(), vec![&MathNode::ci("f")]);
+
+        let test = r#"<apply><forall/>
+                        <bvar><ci> x </ci></bvar>
+                        <condition><apply><gt/><ci> x </ci><cn> 0 </cn></apply></condition>
+                        <apply><gt/><apply><abs/><ci> x </ci></apply><cn> 0 </cn></apply>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert!(parsed.condition().is_some());
+        assert_eq!(parsed.operands().len(), 1);
+
+        let test = r#"<apply><diff/><bvar><ci> x </ci><degree><cn> 2 </cn></degree></bvar><ci> f </ci></apply>"#;
+        match parse_document(test).unwrap() {
+            Apply { children, .. } => assert!(matches!(
+                &children[1],
+                Bvar { name, degree: Some(_) } if name == "x"
+            )),
+            other => panic!("expected an apply, got {:?}", other),
+        }
+
+        let test = r#"<interval closure="half-open"><cn> 0 </cn><cn> 1 </cn></interval>"#;
+        assert!(matches!(
+            parse_document(test),
+            Err(ParseError::InvalidAttribute { .. })
+        ));
+    }
+    #[test]
+    fn test_matrix_vector() {
+        let test = r#"<apply><determinant/>
+                        <matrix>
+                            <matrixrow><cn> 1 </cn><ci> a </ci></matrixrow>
+                            <matrixrow><ci> b </ci><cn> 2 </cn></matrixrow>
+                        </matrix>
+                      </apply>"#;
+        match parse_document(test).unwrap() {
+            Apply { children, .. } => match &children[1] {
+                Matrix(rows) => {
+                    assert_eq!(rows.len(), 2);
+                    assert_eq!(rows[1][0], MathNode::ci("b"));
+                }
+                other => panic!("expected a matrix, got {:?}", other),
+            },
+            other => panic!("expected an apply, got {:?}", other),
+        }
+
+        let test = r#"<vector><ci> x </ci><ci> y </ci></vector>"#;
+        assert!(matches!(parse_document(test).unwrap(), Vector(v) if v.len() == 2));
+
+        let test = r#"<matrix><matrixrow><cn>1</cn></matrixrow><matrixrow/></matrix>"#;
+        assert!(matches!(
+            parse_document(test),
+            Err(ParseError::InvalidStructure(_))
+        ));
+    }
+    #[test]
+    fn test_set_list() {
+        let test = r#"<apply><union/>
+                        <set><ci> a </ci><ci> b </ci></set>
+                        <set><ci> c </ci></set>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(parsed.operands()[1], &Set(vec![MathNode::ci("c")]));
+
+        let test = r#"<list order="numeric"><cn> 2 </cn><cn> 1 </cn></list>"#;
+        assert!(matches!(
+            parse_document(test).unwrap(),
+            List { order: Some(Order::Numeric), items } if items.len() == 2
+        ));
+        let test = r#"<list order="random"><cn> 2 </cn></list>"#;
+        assert!(parse_document(test).is_err());
+    }
+    #[test]
+    fn test_constants() {
+        let test = r#"<apply><and/><true/><apply><lt/><pi/><infinity/></apply></apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(parsed.operands()[0], &Constant(ConstantSymbol::True));
+        assert_eq!(
+            parsed.operands()[1].operands(),
+            vec![
+                &Constant(ConstantSymbol::Pi),
+                &Constant(ConstantSymbol::Infinity)
+            ]
+        );
+        assert_eq!(ConstantSymbol::NotANumber.to_string(), "notanumber");
+
+        let test = r#"<apply><in/><ci> x </ci><reals/></apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert!(matches!(&parsed, Apply { children, .. } if children[0] == Op(BuiltinOp::r#in)));
+
+        let test = r#"<apply><setdiff/><reals/><rationals/></apply>"#;
+        assert_eq!(
+            parse_document(test).unwrap().operands(),
+            vec![
+                &MathNode::NumberSet(crate::NumberSet::Reals),
+                &MathNode::NumberSet(crate::NumberSet::Rationals)
+            ]
+        );
+    }
+    #[test]
+    fn test_csymbol() {
+        let test = r#"<apply><csymbol cd="transc1"> sin </csymbol><ci> x </ci></apply>"#;
+        match parse_document(test).unwrap() {
+            Apply { children, .. } => assert_eq!(
+                children[0],
+                Csymbol {
                     definition_url: None,
+                    cd: Some("transc1".to_owned()),
                     encoding: None,
-                    attributes: Some(units),
+                    name: "sin".to_owned(),
+                    sbml: None,
+                    attributes: Vec::new(),
+                }
+            ),
+            other => panic!("expected an apply, got {:?}", other),
+        }
+        let test =
+            r#"<csymbol definitionUrl="http://www.sbml.org/sbml/symbols/time"> t </csymbol>"#;
+        assert!(matches!(
+            parse_document(test).unwrap(),
+            Csymbol {
+                definition_url: Some(_),
+                cd: None,
+                ..
+            }
+        ));
+    }
+    #[test]
+    fn test_ci_type() {
+        let test =
+            r#"<apply><ci type="function"> f </ci><ci type="complex-polar"> z </ci></apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert!(matches!(
+            parsed.children()[..],
+            [
+                Ci {
+                    ci_type: Some(CiType::Function),
+                    ..
                 },
-            ]),
-        ])]);
-        assert_eq!(expected, parsed);
+                Ci {
+                    ci_type: Some(CiType::ComplexPolar),
+                    ..
+                }
+            ]
+        ));
+        assert!(parsed
+            .to_mathml()
+            .contains(r#"<ci type="complex-polar">z</ci>"#));
+        assert_eq!(parse_document(&parsed.to_mathml()).unwrap(), parsed);
+        assert!(parse_document(r#"<ci type="tensor"> T </ci>"#).is_err());
+    }
+    #[test]
+    fn test_parser_options() {
+        let test = r#"<apply><plus/><!-- rate --><?copasi key?><ci>  x </ci><cn type="constant">&tau;</cn></apply>"#;
+        let options = ParserOptions {
+            keep_comments: false,
+            keep_processing_instructions: false,
+            trim_whitespace: false,
+            ..ParserOptions::default()
+        };
+        let parsed = parse_document_with_options(test, &options).unwrap();
+        assert_eq!(parsed.children().len(), 3);
+        assert_eq!(parsed.children()[1], &MathNode::ci("  x "));
+        assert_eq!(parse_document(test).unwrap().children().len(), 5);
+
+        let options = ParserOptions {
+            entities: EntityPolicy::Error,
+            ..ParserOptions::default()
+        };
+        assert!(parse_document_with_options(test, &options).is_err());
+
+        let options = ParserOptions {
+            max_depth: Some(2),
+            ..ParserOptions::default()
+        };
+        assert!(parse_document_with_options(test, &options).is_ok());
+        let nested = "<apply><minus/><apply><minus/><ci>x</ci></apply></apply>";
+        assert!(matches!(
+            parse_document_with_options(nested, &options),
+            Err(ParseError::TooDeep(2))
+        ));
+    }
+    #[test]
+    fn test_parse_all_math() {
+        let test = r#"<html xmlns="http://www.w3.org/1999/xhtml"><body>
+                        <p>Rate <math xmlns="http://www.w3.org/1998/Math/MathML"><ci>k</ci></math></p>
+                        <!-- the second one -->
+                        <p><math xmlns="http://www.w3.org/1998/Math/MathML"><cn>2</cn></math></p>
+                      </body></html>"#;
+        let found = parse_all_math(test).unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0], MathNode::ci("k").math());
+        assert!(parse_all_math("<p>no math</p>").unwrap().is_empty());
+        // Leading comments no longer hide the root element
+        let test = "<!-- generated --><math><ci>k</ci></math>";
+        assert_eq!(parse_document(test).unwrap(), MathNode::ci("k").math());
+    }
+    #[test]
+    fn test_parse_fragment() {
+        let test = "<apply><plus/><ci>x</ci><cn>1</cn></apply>";
+        let parsed = parse_fragment(test).unwrap();
+        assert_eq!(parsed, crate::math!(x + 1.0));
+        assert!(matches!(
+            parse_fragment(
+                r#"<apply xmlns="http://www.w3.org/1998/Math/MathML"><sin/><ci>x</ci></apply>"#
+            ),
+            Ok(Apply { .. })
+        ));
+        assert!(matches!(
+            parse_fragment(r#"<p xmlns="http://www.w3.org/1999/xhtml">x</p>"#),
+            Err(ParseError::ForeignElement { name, .. }) if name == "p"
+        ));
+        assert!(matches!(
+            parse_fragment("<div><apply><plus/></apply></div>"),
+            Err(ParseError::UnknownElement(name)) if name == "div"
+        ));
+        assert!(parse_fragment("<math><ci>x</ci></math>").is_err());
+    }
+    #[test]
+    fn test_recovery() {
+        let test = r#"<math>
+            <apply><plus/><ci>x</ci><cn type="integer">1.5</cn></apply>
+            <apply><times/><foo/><degree/></apply>
+        </math>"#;
+        let strict = ParserOptions {
+            strict: true,
+            ..ParserOptions::default()
+        };
+        let (parsed, diagnostics) = parse_document_recovering(test, &strict).unwrap();
+        assert_eq!(diagnostics.len(), 3);
+        assert_eq!(diagnostics[0].span.start.line, 2);
+        assert_eq!(diagnostics[1].message, "unknown element <foo>");
+        let errors: Vec<&MathNode> = parsed
+            .iter()
+            .filter(|n| matches!(n, Error { .. }))
+            .collect();
+        assert_eq!(errors.len(), 3);
+        assert_eq!(parsed.to_string(), "x + ?; ? * ?");
+        assert!(parse_document(test).is_err());
+        assert!(
+            parse_document_recovering("<math><ci>x</cn></math>", &ParserOptions::default())
+                .is_err()
+        );
+    }
+    #[test]
+    fn test_unknown_elements() {
+        let test = r#"<math><apply><plus/><ci>x</ci><foo bar="1"><ci>y</ci></foo></apply></math>"#;
+        let parsed = parse_document(test).unwrap();
+        let unknown = parsed.iter().find(|n| matches!(n, Unknown { .. })).unwrap();
+        match unknown {
+            Unknown {
+                name,
+                attributes,
+                children,
+            } => {
+                assert_eq!(name, "foo");
+                assert_eq!(attributes[0].1, "1");
+                assert_eq!(children, &vec![MathNode::ci("y")]);
+            }
+            _ => unreachable!(),
+        }
+        assert_eq!(parsed.to_string(), "x + foo(y)");
+        assert_eq!(parse_document(&parsed.to_mathml()).unwrap(), parsed);
+    }
+    #[test]
+    fn test_deep_nesting() {
+        let depth = 5000;
+        let test = format!(
+            "{}<ci>x</ci>{}",
+            "<apply><minus/>".repeat(depth),
+            "</apply>".repeat(depth)
+        );
+        let parsed = parse_document(&test).unwrap();
+        assert_eq!(parsed.iter().filter(|n| n.as_ci().is_some()).count(), 1);
+        assert_eq!(parsed.iter().count(), 2 * depth + 1);
+    }
+    #[test]
+    fn test_serde_round_trip() {
+        let test = r#"<math xmlns="http://www.w3.org/1998/Math/MathML"
+                        xmlns:sbml="http://www.sbml.org/sbml/level3/version2/core">
+                        <apply><times/>
+                            <cn sbml:units="mole" type="e-notation"> 1.5 <sep/> 3 </cn>
+                            <csymbol definitionURL="http://www.sbml.org/sbml/symbols/time"> t </csymbol>
+                            <apply><root/><degree><cn> 3 </cn></degree><ci> x </ci></apply>
+                            <piecewise><piece><pi/><apply><lt/><ci> x </ci><cn> 0 </cn></apply></piece></piecewise>
+                            <interval closure="open"><cn> 0 </cn><infinity/></interval>
+                            <semantics><ci> y </ci><annotation encoding="LaTeX">y</annotation></semantics>
+                            <mfrac><mi>a</mi><mn>2</mn></mfrac>
+                        </apply>
+                      </math>"#;
+        let parsed = parse_document(test).unwrap();
+        let json = serde_json::to_string(&parsed).unwrap();
+        assert_eq!(serde_json::from_str::<MathNode>(&json).unwrap(), parsed);
+    }
+    #[test]
+    fn test_clone_and_hash() {
+        use std::collections::HashSet;
+        let rate = r#"<apply><times/><ci> k </ci><cn type="real"> 0.5 </cn><ci> S </ci></apply>"#;
+        let other = r#"<apply><times/><ci> k </ci><cn type="real"> -0.5 </cn><ci> S </ci></apply>"#;
+        let parsed = parse_document(rate).unwrap();
+        let mut seen = HashSet::new();
+        seen.insert(parsed.clone());
+        seen.insert(parse_document(rate).unwrap());
+        seen.insert(parse_document(other).unwrap());
+        assert_eq!(seen.len(), 2);
+        assert!(seen.contains(&parsed));
     }
 }
@@ -0,0 +1,293 @@
+use super::{BuiltinOp, MathNode, ParserOptions};
+use crate::error::ParseError;
+use roxmltree::{Node, NodeType};
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+
+/// The checks `lint` makes, named in kebab case, e.g. `empty-apply`
+#[derive(Deserialize, Debug, Serialize, Eq, PartialEq, Clone, Copy, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum LintId {
+    /// Elements deprecated since MathML 2: `<reln>`, `<fn>` and `<declare>`
+    Deprecated,
+    /// A `definitionURL` spelled with other casing, e.g. `definitionUrl`
+    DefinitionUrlCase,
+    /// An `<apply>` with nothing in it
+    EmptyApply,
+    /// Text or elements in a `<cn>` after the number, which the parser ignores,
+    /// e.g. `<cn>5<!-- -->3</cn>`. Only `lint_document` can see these
+    CnTrailingText,
+    /// An `<apply>` holding nothing but another `<apply>`
+    RedundantApply,
+}
+
+impl fmt::Display for LintId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = serde_plain::to_string(self).map_err(|_| fmt::Error)?;
+        f.write_str(&name)
+    }
+}
+
+/// What to do about a lint
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+pub enum LintLevel {
+    /// Don't report it
+    Allow,
+    Warn,
+    /// Report it as something that should fail a check
+    Deny,
+}
+
+/// The level of each lint, all `Warn` by default
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintConfig {
+    pub deprecated: LintLevel,
+    pub definition_url_case: LintLevel,
+    pub empty_apply: LintLevel,
+    pub cn_trailing_text: LintLevel,
+    pub redundant_apply: LintLevel,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        LintConfig {
+            deprecated: LintLevel::Warn,
+            definition_url_case: LintLevel::Warn,
+            empty_apply: LintLevel::Warn,
+            cn_trailing_text: LintLevel::Warn,
+            redundant_apply: LintLevel::Warn,
+        }
+    }
+}
+
+impl LintConfig {
+    pub fn level(&self, id: LintId) -> LintLevel {
+        match id {
+            LintId::Deprecated => self.deprecated,
+            LintId::DefinitionUrlCase => self.definition_url_case,
+            LintId::EmptyApply => self.empty_apply,
+            LintId::CnTrailingText => self.cn_trailing_text,
+            LintId::RedundantApply => self.redundant_apply,
+        }
+    }
+}
+
+/// Something suspicious about the node `path` leads to from the root, as
+/// indices into `children()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintWarning {
+    pub id: LintId,
+    pub level: LintLevel,
+    pub path: Vec<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for LintWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let level = match self.level {
+            LintLevel::Deny => "error",
+            _ => "warning",
+        };
+        write!(
+            f,
+            "{}[{}] at {:?}: {}",
+            level, self.id, self.path, self.message
+        )
+    }
+}
+
+/// Whether `name` is some other spelling of `definitionURL`
+fn is_misspelled_url(name: &str) -> bool {
+    name != "definitionURL" && name.eq_ignore_ascii_case("definitionURL")
+}
+
+/// The lints of `node` that don't need its source
+fn tree_lints(node: &MathNode, report: &mut dyn FnMut(LintId, String)) {
+    match node {
+        MathNode::Unknown { name, .. } if matches!(name.as_str(), "reln" | "declare") => report(
+            LintId::Deprecated,
+            format!("<{}> is deprecated since MathML 2", name),
+        ),
+        MathNode::Op(BuiltinOp::r#fn) => report(
+            LintId::Deprecated,
+            "<fn> is deprecated since MathML 2, use <ci type=\"function\">".to_owned(),
+        ),
+        MathNode::Apply { children, .. } => {
+            let mut content = children.iter().filter(|c| !c.is_markup());
+            match (content.next(), content.next()) {
+                (None, _) => report(LintId::EmptyApply, "<apply> is empty".to_owned()),
+                (Some(MathNode::Apply { .. }), None) => report(
+                    LintId::RedundantApply,
+                    "<apply> only holds another <apply>".to_owned(),
+                ),
+                _ => {}
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The lints of the preserved attributes of `node`, when its source isn't known
+fn attribute_lints(node: &MathNode, report: &mut dyn FnMut(LintId, String)) {
+    let attributes = match node {
+        MathNode::Root { attributes, .. }
+        | MathNode::Apply { attributes, .. }
+        | MathNode::Ci { attributes, .. }
+        | MathNode::Csymbol { attributes, .. }
+        | MathNode::Cn { attributes, .. }
+        | MathNode::Unknown { attributes, .. } => attributes,
+        _ => return,
+    };
+    for (name, _) in attributes {
+        if is_misspelled_url(&name.local) {
+            report(
+                LintId::DefinitionUrlCase,
+                format!("'{}' should be spelled 'definitionURL'", name.local),
+            );
+        }
+    }
+}
+
+/// The lints of the element `node` was parsed from
+fn source_lints(xml: Node, report: &mut dyn FnMut(LintId, String)) {
+    for attribute in xml.attributes() {
+        if attribute.namespace().is_none() && is_misspelled_url(attribute.name()) {
+            report(
+                LintId::DefinitionUrlCase,
+                format!("'{}' should be spelled 'definitionURL'", attribute.name()),
+            );
+        }
+    }
+    if xml.tag_name().name() != "cn" {
+        return;
+    }
+    // Each `<sep/>` starts another part, any other text is ignored
+    let mut parts = 0;
+    let mut seps = 0;
+    for child in xml.children() {
+        match child.node_type() {
+            NodeType::Text if !child.text().unwrap_or_default().trim().is_empty() => parts += 1,
+            NodeType::Element if child.tag_name().name() == "sep" => seps += 1,
+            NodeType::Element => parts += 1,
+            _ => {}
+        }
+    }
+    if parts > seps + 1 {
+        report(
+            LintId::CnTrailingText,
+            "<cn> holds more than its number, and the rest is ignored".to_owned(),
+        );
+    }
+}
+
+fn walk(
+    node: &MathNode,
+    xml: Option<Node>,
+    config: &LintConfig,
+    path: &mut Vec<usize>,
+    warnings: &mut Vec<LintWarning>,
+) {
+    let mut report = |id: LintId, message: String| {
+        let level = config.level(id);
+        if level != LintLevel::Allow {
+            warnings.push(LintWarning {
+                id,
+                level,
+                path: path.clone(),
+                message,
+            });
+        }
+    };
+    tree_lints(node, &mut report);
+    match xml {
+        Some(xml) => source_lints(xml, &mut report),
+        None => attribute_lints(node, &mut report),
+    }
+    let sources = match xml {
+        Some(xml) => crate::span::sources(node, xml, &ParserOptions::default()),
+        None => Vec::new(),
+    };
+    for (i, child) in node.children().into_iter().enumerate() {
+        path.push(i);
+        walk(child, sources.get(i).copied(), config, path, warnings);
+        path.pop();
+    }
+}
+
+/// Looks for deprecated or suspicious constructs that still parse, like
+/// `<reln>` or an empty `<apply>`, with every lint at `LintLevel::Warn`
+pub fn lint(node: &MathNode) -> Vec<LintWarning> {
+    lint_with(node, &LintConfig::default())
+}
+
+/// `lint`, with the level of each lint set by `config`
+pub fn lint_with(node: &MathNode, config: &LintConfig) -> Vec<LintWarning> {
+    let mut warnings = Vec::new();
+    walk(node, None, config, &mut Vec::new(), &mut warnings);
+    warnings
+}
+
+/// Parses `text` as `parse_document` does and lints the result, adding the
+/// lints that need the source, e.g. ignored text in a `<cn>` or a
+/// `definitionUrl` the parser accepted
+pub fn lint_document(text: &str, config: &LintConfig) -> Result<Vec<LintWarning>, ParseError> {
+    let options = ParserOptions::default();
+    let sanitized = crate::sanitize(text, &options);
+    let xml = roxmltree::Document::parse(&sanitized.text)?;
+    let root = xml.root_element();
+    let parsed = crate::parse_root(root, &options)?;
+    let mut warnings = Vec::new();
+    walk(&parsed, Some(root), config, &mut Vec::new(), &mut warnings);
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{lint, lint_document, LintConfig, LintId, LintLevel};
+    use crate::parse_document;
+
+    #[test]
+    fn test_lint() {
+        let test = r#"<math>
+                        <reln><eq/><ci>a</ci><ci>b</ci></reln>
+                        <apply><plus/><apply/><apply><apply><sin/><ci>x</ci></apply></apply></apply>
+                      </math>"#;
+        let warnings = lint(&parse_document(test).unwrap());
+        let ids: Vec<_> = warnings.iter().map(|w| (w.id, w.path.clone())).collect();
+        assert_eq!(
+            ids,
+            vec![
+                (LintId::Deprecated, vec![0]),
+                (LintId::EmptyApply, vec![1, 1]),
+                (LintId::RedundantApply, vec![1, 2]),
+            ]
+        );
+        assert_eq!(
+            warnings[1].to_string(),
+            "warning[empty-apply] at [1, 1]: <apply> is empty"
+        );
+
+        let test = r#"<apply><times/>
+                        <csymbol definitionUrl="http://www.sbml.org/sbml/symbols/time">t</csymbol>
+                        <cn>5<!-- typo -->3</cn>
+                        <apply/>
+                      </apply>"#;
+        let config = LintConfig {
+            empty_apply: LintLevel::Allow,
+            cn_trailing_text: LintLevel::Deny,
+            ..LintConfig::default()
+        };
+        let warnings = lint_document(test, &config).unwrap();
+        let ids: Vec<_> = warnings.iter().map(|w| (w.id, w.level)).collect();
+        assert_eq!(
+            ids,
+            vec![
+                (LintId::DefinitionUrlCase, LintLevel::Warn),
+                (LintId::CnTrailingText, LintLevel::Deny),
+            ]
+        );
+        assert!(lint(&parse_document(test).unwrap())
+            .iter()
+            .all(|w| w.id == LintId::EmptyApply));
+    }
+}
@@ -0,0 +1,184 @@
+use super::{BuiltinOp, ConstantSymbol, MathNode};
+use crate::error::EvalError;
+use std::collections::HashMap;
+
+/// How `MathNode::eval_bool_with` treats a variable that is missing from the
+/// environment or NaN
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Logic {
+    /// A missing variable is an error and comparisons with NaN are false
+    TwoValued,
+    /// Kleene's logic: the truth of anything depending on a missing or NaN
+    /// variable is unknown, unless the other operands decide it, as in
+    /// `false and unknown`
+    ThreeValued,
+}
+
+/// Combines the operands of `and` and `or`: `decisive` is the truth value that
+/// decides the result on its own, false for `and` and true for `or`
+fn kleene(truths: &[Option<bool>], decisive: bool) -> Option<bool> {
+    if truths.contains(&Some(decisive)) {
+        Some(decisive)
+    } else if truths.contains(&None) {
+        None
+    } else {
+        Some(!decisive)
+    }
+}
+
+/// The number `node` evaluates to, `None` when it is unknown
+fn value(
+    node: &MathNode,
+    env: &HashMap<String, f64>,
+    logic: Logic,
+) -> Result<Option<f64>, EvalError> {
+    match (node.eval(env), logic) {
+        (Ok(v), Logic::ThreeValued) if v.is_nan() => Ok(None),
+        (Err(EvalError::UnboundVariable(_)), Logic::ThreeValued) => Ok(None),
+        (result, _) => result.map(Some),
+    }
+}
+
+fn truth(
+    node: &MathNode,
+    env: &HashMap<String, f64>,
+    logic: Logic,
+) -> Result<Option<bool>, EvalError> {
+    use BuiltinOp::*;
+    let op = match node {
+        MathNode::Constant(ConstantSymbol::True) => return Ok(Some(true)),
+        MathNode::Constant(ConstantSymbol::False) => return Ok(Some(false)),
+        MathNode::Semantics { primary, .. } => return truth(primary, env, logic),
+        MathNode::Root { children, .. } => {
+            let mut expressions = children.iter().filter(|c| !c.is_markup());
+            return match (expressions.next(), expressions.next()) {
+                (Some(expression), None) => truth(expression, env, logic),
+                _ => Err(EvalError::UnsupportedNode(
+                    "a <math> element without exactly one expression".to_owned(),
+                )),
+            };
+        }
+        MathNode::Apply { children, .. } => match children.first() {
+            Some(MathNode::Op(op)) => op,
+            _ => {
+                return Err(EvalError::UnsupportedNode(
+                    "an application of a non-builtin function".to_owned(),
+                ))
+            }
+        },
+        // Any other value is true when it isn't zero, as for `eval`
+        _ => return Ok(value(node, env, logic)?.map(|v| v != 0.0)),
+    };
+    let operands = node.operands();
+    match op {
+        and | or | xor | not | implies | equivalent => {
+            let truths = operands
+                .iter()
+                .map(|o| truth(o, env, logic))
+                .collect::<Result<Vec<_>, _>>()?;
+            let arity = match op {
+                not => Some(1),
+                implies => Some(2),
+                _ => None,
+            };
+            if arity.is_some_and(|n| truths.len() != n) || (*op == equivalent && truths.len() < 2) {
+                return Err(EvalError::WrongArity {
+                    op: op.to_string(),
+                    expected: arity.map_or("at least 2".to_owned(), |n| n.to_string()),
+                    found: truths.len(),
+                });
+            }
+            Ok(match op {
+                and => kleene(&truths, false),
+                or => kleene(&truths, true),
+                not => truths[0].map(|t| !t),
+                implies => kleene(&[truths[0].map(|t| !t), truths[1]], true),
+                xor => truths.iter().try_fold(false, |acc, t| t.map(|t| acc ^ t)),
+                _ => {
+                    let pairs: Vec<Option<bool>> =
+                        truths.windows(2).map(|w| Some(w[0]? == w[1]?)).collect();
+                    kleene(&pairs, false)
+                }
+            })
+        }
+        eq | neq | gt | lt | geq | leq => {
+            let values = operands
+                .iter()
+                .map(|o| value(o, env, logic))
+                .collect::<Result<Option<Vec<f64>>, _>>()?;
+            match values {
+                Some(values) => Ok(Some(crate::eval::eval_op(op, &values)? != 0.0)),
+                None => Ok(None),
+            }
+        }
+        _ => Ok(value(node, env, logic)?.map(|v| v != 0.0)),
+    }
+}
+
+impl MathNode {
+    /// Evaluates a condition such as an SBML event trigger to true or false,
+    /// looking identifiers up in `env`. Anything but a logical operator,
+    /// relation or `<true/>`/`<false/>` is true when it evaluates to non-zero
+    pub fn eval_bool(&self, env: &HashMap<String, f64>) -> Result<bool, EvalError> {
+        Ok(truth(self, env, Logic::TwoValued)?.unwrap_or(false))
+    }
+    /// Like `eval_bool`, where `None` is the unknown truth value of
+    /// `Logic::ThreeValued`
+    pub fn eval_bool_with(
+        &self,
+        env: &HashMap<String, f64>,
+        logic: Logic,
+    ) -> Result<Option<bool>, EvalError> {
+        truth(self, env, logic)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Logic;
+    use crate::error::EvalError;
+    use crate::parse_document;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_eval_bool() {
+        // An SBML trigger: S1 below its threshold, or the time past 10
+        let test = r#"<apply><or/>
+                        <apply><lt/><ci>S1</ci><cn>5</cn></apply>
+                        <apply><and/><apply><geq/><ci>t</ci><cn>10</cn></apply><true/></apply>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        let env: HashMap<String, f64> = vec![("S1".to_owned(), 7.0), ("t".to_owned(), 12.0)]
+            .into_iter()
+            .collect();
+        assert_eq!(parsed.eval_bool(&env), Ok(true));
+
+        let early: HashMap<String, f64> = vec![("t".to_owned(), 2.0)].into_iter().collect();
+        assert_eq!(
+            parsed.eval_bool(&early),
+            Err(EvalError::UnboundVariable("S1".to_owned()))
+        );
+        assert_eq!(parsed.eval_bool_with(&early, Logic::ThreeValued), Ok(None));
+        assert_eq!(
+            parsed.eval_bool_with(&env, Logic::ThreeValued),
+            Ok(Some(true))
+        );
+        // true decides an `or` even when the other operand is unknown
+        let nan: HashMap<String, f64> = vec![("S1".to_owned(), f64::NAN), ("t".to_owned(), 12.0)]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            parsed.eval_bool_with(&nan, Logic::ThreeValued),
+            Ok(Some(true))
+        );
+
+        let test = r#"<apply><xor/><apply><not/><false/></apply><apply><neq/><ci>x</ci><cn>1</cn></apply></apply>"#;
+        let parsed = parse_document(test).unwrap();
+        let env: HashMap<String, f64> = vec![("x".to_owned(), 1.0)].into_iter().collect();
+        assert_eq!(parsed.eval_bool(&env), Ok(true));
+        assert_eq!(
+            parsed.eval_bool_with(&HashMap::new(), Logic::ThreeValued),
+            Ok(None)
+        );
+    }
+}
@@ -0,0 +1,665 @@
+use super::{BuiltinOp, ConstantSymbol, IntervalClosure, MathNode, NamedConstant, NumType};
+use crate::error::ParseError;
+use crate::numbers::NumberSet;
+use serde_json::{json, Value};
+
+/// Operators that are a MathJSON function of the same operands
+const FUNCTIONS: &[(BuiltinOp, &str)] = &[
+    (BuiltinOp::plus, "Add"),
+    (BuiltinOp::times, "Multiply"),
+    (BuiltinOp::divide, "Divide"),
+    (BuiltinOp::power, "Power"),
+    (BuiltinOp::exp, "Exp"),
+    (BuiltinOp::ln, "Ln"),
+    (BuiltinOp::abs, "Abs"),
+    (BuiltinOp::floor, "Floor"),
+    (BuiltinOp::ceiling, "Ceil"),
+    (BuiltinOp::factorial, "Factorial"),
+    (BuiltinOp::sin, "Sin"),
+    (BuiltinOp::cos, "Cos"),
+    (BuiltinOp::tan, "Tan"),
+    (BuiltinOp::sec, "Sec"),
+    (BuiltinOp::csc, "Csc"),
+    (BuiltinOp::cot, "Cot"),
+    (BuiltinOp::sinh, "Sinh"),
+    (BuiltinOp::cosh, "Cosh"),
+    (BuiltinOp::tanh, "Tanh"),
+    (BuiltinOp::sech, "Sech"),
+    (BuiltinOp::csch, "Csch"),
+    (BuiltinOp::coth, "Coth"),
+    (BuiltinOp::arcsin, "Arcsin"),
+    (BuiltinOp::arccos, "Arccos"),
+    (BuiltinOp::arctan, "Arctan"),
+    (BuiltinOp::arcsec, "Arcsec"),
+    (BuiltinOp::arccsc, "Arccsc"),
+    (BuiltinOp::arccot, "Arccot"),
+    (BuiltinOp::arcsinh, "Arsinh"),
+    (BuiltinOp::arccosh, "Arcosh"),
+    (BuiltinOp::arctanh, "Artanh"),
+    (BuiltinOp::arcsech, "Arsech"),
+    (BuiltinOp::arccsch, "Arcsch"),
+    (BuiltinOp::arccoth, "Arcoth"),
+    (BuiltinOp::max, "Max"),
+    (BuiltinOp::min, "Min"),
+    (BuiltinOp::gcd, "GCD"),
+    (BuiltinOp::lcm, "LCM"),
+    (BuiltinOp::rem, "Mod"),
+    (BuiltinOp::quotient, "Quotient"),
+    (BuiltinOp::and, "And"),
+    (BuiltinOp::or, "Or"),
+    (BuiltinOp::not, "Not"),
+    (BuiltinOp::xor, "Xor"),
+    (BuiltinOp::implies, "Implies"),
+    (BuiltinOp::equivalent, "Equivalent"),
+    (BuiltinOp::eq, "Equal"),
+    (BuiltinOp::neq, "NotEqual"),
+    (BuiltinOp::lt, "Less"),
+    (BuiltinOp::leq, "LessEqual"),
+    (BuiltinOp::gt, "Greater"),
+    (BuiltinOp::geq, "GreaterEqual"),
+    (BuiltinOp::approx, "Approx"),
+    (BuiltinOp::conjugate, "Conjugate"),
+    (BuiltinOp::arg, "Arg"),
+    (BuiltinOp::real, "Real"),
+    (BuiltinOp::imaginary, "Imaginary"),
+    (BuiltinOp::determinant, "Determinant"),
+    (BuiltinOp::transpose, "Transpose"),
+    (BuiltinOp::union, "Union"),
+    (BuiltinOp::intersect, "Intersection"),
+    (BuiltinOp::setdiff, "SetMinus"),
+    (BuiltinOp::r#in, "Element"),
+    (BuiltinOp::notin, "NotElement"),
+    (BuiltinOp::subset, "SubsetEqual"),
+    (BuiltinOp::prsubset, "Subset"),
+    (BuiltinOp::mean, "Mean"),
+    (BuiltinOp::median, "Median"),
+    (BuiltinOp::mode, "Mode"),
+    (BuiltinOp::variance, "Variance"),
+    (BuiltinOp::sdev, "StandardDeviation"),
+];
+
+const CONSTANTS: &[(ConstantSymbol, &str)] = &[
+    (ConstantSymbol::Pi, "Pi"),
+    (ConstantSymbol::ExponentialE, "ExponentialE"),
+    (ConstantSymbol::ImaginaryI, "ImaginaryUnit"),
+    (ConstantSymbol::EulerGamma, "EulerGamma"),
+    (ConstantSymbol::Infinity, "PositiveInfinity"),
+    (ConstantSymbol::NotANumber, "NaN"),
+    (ConstantSymbol::True, "True"),
+    (ConstantSymbol::False, "False"),
+];
+
+const SETS: &[(NumberSet, &str)] = &[
+    (NumberSet::Integers, "Integers"),
+    (NumberSet::Reals, "RealNumbers"),
+    (NumberSet::Rationals, "RationalNumbers"),
+    (NumberSet::NaturalNumbers, "NonNegativeIntegers"),
+    (NumberSet::Complexes, "ComplexNumbers"),
+    (NumberSet::Primes, "Primes"),
+    (NumberSet::EmptySet, "EmptySet"),
+];
+
+fn function(name: &str, args: impl IntoIterator<Item = Value>) -> Value {
+    let mut items = vec![Value::from(name)];
+    items.extend(args);
+    Value::Array(items)
+}
+
+fn float(value: f64) -> Value {
+    match value {
+        v if v == f64::INFINITY => Value::from("PositiveInfinity"),
+        v if v == f64::NEG_INFINITY => Value::from("NegativeInfinity"),
+        // Also NaN, which JSON has no number for
+        v => serde_json::Number::from_f64(v).map_or_else(|| Value::from("NaN"), Value::Number),
+    }
+}
+
+/// A MathML node MathJSON has no counterpart for, as an `Error` holding it
+fn unsupported(node: &MathNode) -> Value {
+    function(
+        "Error",
+        vec![
+            Value::from("'unsupported'"),
+            Value::from(format!("'{}'", node.to_mathml())),
+        ],
+    )
+}
+
+fn number_to_json(num_type: &NumType) -> Value {
+    match num_type {
+        NumType::Integer(i) => Value::from(*i),
+        #[cfg(feature = "bigint")]
+        NumType::BigInteger(i) => json!({ "num": i.to_string() }),
+        NumType::Real(r) => float(*r),
+        NumType::ENotation(mantissa, exponent) => {
+            json!({ "num": format!("{:?}e{}", mantissa, exponent) })
+        }
+        NumType::Rational(numerator, denominator) => function(
+            "Rational",
+            vec![Value::from(*numerator), Value::from(*denominator)],
+        ),
+        NumType::ComplexCartesian(re, im) => function("Complex", vec![float(*re), float(*im)]),
+        NumType::ComplexPolar(r, theta) => function(
+            "Multiply",
+            vec![
+                float(*r),
+                function(
+                    "Exp",
+                    vec![function(
+                        "Multiply",
+                        vec![json!("ImaginaryUnit"), float(*theta)],
+                    )],
+                ),
+            ],
+        ),
+        NumType::Constant(NamedConstant::Pi) => json!("Pi"),
+        NumType::Constant(NamedConstant::E) => json!("ExponentialE"),
+        NumType::Constant(NamedConstant::Gamma) => json!("EulerGamma"),
+        NumType::Constant(NamedConstant::GoldenRatio) => json!("GoldenRatio"),
+        NumType::Constant(NamedConstant::Tau) => function("Multiply", vec![json!(2), json!("Pi")]),
+        NumType::Constant(NamedConstant::Custom(text)) => Value::from(text.as_str()),
+        NumType::Other { text, .. } => json!({ "num": text.trim() }),
+    }
+}
+
+/// `["Limits", index, lower, upper]`, the range of a sum or integral
+fn limits(node: &MathNode) -> Value {
+    let mut items = vec![json!("Limits")];
+    items.extend(node.bound_variables().into_iter().map(Value::from));
+    if let Some((low, high)) = node.limits() {
+        items.push(low.to_mathjson());
+        items.push(high.to_mathjson());
+    }
+    Value::Array(items)
+}
+
+fn apply_to_json(node: &MathNode, head: &MathNode) -> Value {
+    let operands: Vec<Value> = node
+        .operands()
+        .into_iter()
+        .map(MathNode::to_mathjson)
+        .collect();
+    let op = match head {
+        MathNode::Op(op) => *op,
+        MathNode::Ci { children, .. } => {
+            return function(crate::text_content(children).trim(), operands)
+        }
+        MathNode::Csymbol { name, .. } => return function(name.trim(), operands),
+        _ => return unsupported(node),
+    };
+    if let Some((_, name)) = FUNCTIONS.iter().find(|(o, _)| *o == op) {
+        return function(name, operands);
+    }
+    match op {
+        BuiltinOp::minus if operands.len() == 1 => function("Negate", operands),
+        BuiltinOp::minus => function("Subtract", operands),
+        BuiltinOp::root => match node.degree() {
+            Some(degree) => function(
+                "Root",
+                operands.into_iter().chain(Some(degree.to_mathjson())),
+            ),
+            None => function("Sqrt", operands),
+        },
+        // The base comes after the argument, and defaults to 10
+        BuiltinOp::log => function(
+            "Log",
+            operands
+                .into_iter()
+                .chain(node.logbase().map(MathNode::to_mathjson)),
+        ),
+        BuiltinOp::sum | BuiltinOp::product | BuiltinOp::int => {
+            let name = match op {
+                BuiltinOp::sum => "Sum",
+                BuiltinOp::product => "Product",
+                _ => "Integrate",
+            };
+            function(name, operands.into_iter().chain(Some(limits(node))))
+        }
+        // A variable is repeated as often as its degree, as in `["D", f, "x", "x"]`
+        BuiltinOp::diff | BuiltinOp::partialdiff => {
+            let mut variables = Vec::new();
+            for (name, degree) in node.bvars() {
+                let times = match degree {
+                    Some(MathNode::Cn {
+                        num_type: NumType::Integer(n),
+                        ..
+                    }) => *n,
+                    Some(_) => return unsupported(node),
+                    None => 1,
+                };
+                for _ in 0..times {
+                    variables.push(Value::from(name));
+                }
+            }
+            function("D", operands.into_iter().chain(variables))
+        }
+        _ => unsupported(node),
+    }
+}
+
+impl MathNode {
+    /// Converts this node to MathJSON, the format of MathLive and the Compute
+    /// Engine, e.g. `["Add", "x", ["Sin", "y"]]`. Identifiers become symbols,
+    /// and what MathJSON can't express, like `<selector/>`, an `Error`
+    /// holding its MathML
+    pub fn to_mathjson(&self) -> Value {
+        match self {
+            MathNode::Root { children, .. } => {
+                let objects: Vec<_> = children.iter().filter(|c| !c.is_markup()).collect();
+                match objects.as_slice() {
+                    [object] => object.to_mathjson(),
+                    objects => function("List", objects.iter().map(|c| c.to_mathjson())),
+                }
+            }
+            MathNode::Apply { children, .. } => match children.iter().find(|c| !c.is_markup()) {
+                Some(head) => apply_to_json(self, head),
+                None => unsupported(self),
+            },
+            MathNode::Ci { children, .. } => Value::from(crate::text_content(children).trim()),
+            MathNode::Csymbol { name, .. } => Value::from(name.trim()),
+            MathNode::Cn { num_type, .. } => number_to_json(num_type),
+            MathNode::Text(text) => Value::from(format!("'{}'", text)),
+            MathNode::Constant(constant) => {
+                let (_, name) = CONSTANTS
+                    .iter()
+                    .find(|(c, _)| c == constant)
+                    .expect("every constant has a symbol");
+                Value::from(*name)
+            }
+            MathNode::NumberSet(set) => {
+                let (_, name) = SETS
+                    .iter()
+                    .find(|(s, _)| s == set)
+                    .expect("every number set has a symbol");
+                Value::from(*name)
+            }
+            // The conditions come first, and `True` marks the otherwise
+            MathNode::Piecewise { pieces, otherwise } => {
+                let mut items = Vec::new();
+                for (value, condition) in pieces {
+                    items.push(condition.to_mathjson());
+                    items.push(value.to_mathjson());
+                }
+                if let Some(otherwise) = otherwise {
+                    items.push(json!("True"));
+                    items.push(otherwise.to_mathjson());
+                }
+                function("Which", items)
+            }
+            MathNode::Lambda { bound_vars, body } => function(
+                "Function",
+                Some(body.to_mathjson())
+                    .into_iter()
+                    .chain(bound_vars.iter().map(|v| Value::from(v.as_str()))),
+            ),
+            MathNode::Interval { closure, low, high } => {
+                let open = |bound: &MathNode, is_open| match is_open {
+                    true => function("Open", vec![bound.to_mathjson()]),
+                    false => bound.to_mathjson(),
+                };
+                let (low_open, high_open) = match closure {
+                    IntervalClosure::Closed => (false, false),
+                    IntervalClosure::Open => (true, true),
+                    IntervalClosure::OpenClosed => (true, false),
+                    IntervalClosure::ClosedOpen => (false, true),
+                };
+                function("Interval", vec![open(low, low_open), open(high, high_open)])
+            }
+            MathNode::Set(items) => function("Set", items.iter().map(MathNode::to_mathjson)),
+            MathNode::List { items, .. } | MathNode::Vector(items) => {
+                function("List", items.iter().map(MathNode::to_mathjson))
+            }
+            MathNode::Matrix(rows) => function(
+                "Matrix",
+                vec![function(
+                    "List",
+                    rows.iter()
+                        .map(|row| function("List", row.iter().map(MathNode::to_mathjson))),
+                )],
+            ),
+            MathNode::Semantics { primary, .. } => primary.to_mathjson(),
+            _ => unsupported(self),
+        }
+    }
+}
+
+fn invalid<T>(json: &Value, message: &str) -> Result<T, ParseError> {
+    Err(ParseError::InvalidStructure(format!(
+        "{} in MathJSON {}",
+        message, json
+    )))
+}
+
+fn parse_number(text: &str) -> Result<MathNode, ParseError> {
+    let text = text.trim();
+    if let Ok(integer) = text.parse() {
+        return Ok(MathNode::integer(integer));
+    }
+    #[cfg(feature = "bigint")]
+    if let Ok(integer) = text.parse() {
+        return Ok(MathNode::number(NumType::BigInteger(integer)));
+    }
+    let value = match text {
+        "+Infinity" | "Infinity" => f64::INFINITY,
+        "-Infinity" => f64::NEG_INFINITY,
+        text => text.parse().map_err(|_| ParseError::InvalidNumber {
+            num_type: "real".to_owned(),
+            text: text.to_owned(),
+        })?,
+    };
+    Ok(MathNode::cn(value))
+}
+
+fn parse_symbol(name: &str) -> MathNode {
+    if let Some((constant, _)) = CONSTANTS.iter().find(|(_, n)| *n == name) {
+        return MathNode::Constant(*constant);
+    }
+    if let Some((set, _)) = SETS.iter().find(|(_, n)| *n == name) {
+        return MathNode::NumberSet(*set);
+    }
+    match name {
+        "NegativeInfinity" => MathNode::apply(
+            BuiltinOp::minus,
+            vec![MathNode::Constant(ConstantSymbol::Infinity)],
+        ),
+        "GoldenRatio" => MathNode::number(NumType::Constant(NamedConstant::GoldenRatio)),
+        _ => MathNode::ci(name),
+    }
+}
+
+/// The variable and limits of `["Limits", "i", 1, "n"]`, also accepting the
+/// `Tuple` and `Triple` of older MathJSON, or just the variable
+fn parse_limits(json: &Value) -> Result<Vec<MathNode>, ParseError> {
+    let items = match json {
+        Value::String(name) => {
+            return Ok(vec![MathNode::Bvar {
+                name: name.clone(),
+                degree: None,
+            }])
+        }
+        Value::Array(items) => items,
+        _ => return invalid(json, "expected limits"),
+    };
+    match items.as_slice() {
+        [head, Value::String(name), rest @ ..]
+            if matches!(
+                head.as_str(),
+                Some("Limits") | Some("Tuple") | Some("Triple")
+            ) =>
+        {
+            let mut qualifiers = vec![MathNode::Bvar {
+                name: name.clone(),
+                degree: None,
+            }];
+            match rest {
+                [] => {}
+                [low, high] => {
+                    qualifiers.push(MathNode::Lowlimit(Box::new(parse_mathjson(low)?)));
+                    qualifiers.push(MathNode::Uplimit(Box::new(parse_mathjson(high)?)));
+                }
+                _ => return invalid(json, "expected a lower and an upper limit"),
+            }
+            Ok(qualifiers)
+        }
+        _ => invalid(json, "expected limits"),
+    }
+}
+
+/// A bound of an interval, with whether it is `["Open", bound]`
+fn parse_bound(json: &Value) -> Result<(MathNode, bool), ParseError> {
+    match json.as_array().map(Vec::as_slice) {
+        Some([head, bound]) if head == "Open" => Ok((parse_mathjson(bound)?, true)),
+        _ => Ok((parse_mathjson(json)?, false)),
+    }
+}
+
+fn parse_function(json: &Value, name: &str, args: &[Value]) -> Result<MathNode, ParseError> {
+    let operands = || {
+        args.iter()
+            .map(parse_mathjson)
+            .collect::<Result<Vec<_>, _>>()
+    };
+    if let Some((op, _)) = FUNCTIONS.iter().find(|(_, n)| *n == name) {
+        return Ok(MathNode::apply(*op, operands()?));
+    }
+    let bound = |op: BuiltinOp| -> Result<MathNode, ParseError> {
+        let (body, limits) = match args {
+            [body, limits @ ..] if !limits.is_empty() => (body, limits),
+            _ => return invalid(json, "expected a body and its variables"),
+        };
+        let mut children = vec![MathNode::Op(op)];
+        for limit in limits {
+            children.extend(parse_limits(limit)?);
+        }
+        children.push(parse_mathjson(body)?);
+        Ok(MathNode::Apply {
+            children,
+            attributes: Vec::new(),
+        })
+    };
+    let node = match (name, args) {
+        ("Negate", _) | ("Subtract", _) => MathNode::apply(BuiltinOp::minus, operands()?),
+        ("Sqrt", _) => MathNode::apply(BuiltinOp::root, operands()?),
+        ("Root", [x, degree]) => MathNode::apply(
+            BuiltinOp::root,
+            vec![
+                MathNode::Degree(Box::new(parse_mathjson(degree)?)),
+                parse_mathjson(x)?,
+            ],
+        ),
+        ("Square", [x]) => MathNode::apply(
+            BuiltinOp::power,
+            vec![parse_mathjson(x)?, MathNode::integer(2)],
+        ),
+        ("Log", [x]) | ("Lg", [x]) => MathNode::apply(BuiltinOp::log, vec![parse_mathjson(x)?]),
+        ("Log", [x, base]) => MathNode::apply(
+            BuiltinOp::log,
+            vec![
+                MathNode::Logbase(Box::new(parse_mathjson(base)?)),
+                parse_mathjson(x)?,
+            ],
+        ),
+        ("Lb", [x]) => MathNode::apply(
+            BuiltinOp::log,
+            vec![
+                MathNode::Logbase(Box::new(MathNode::integer(2))),
+                parse_mathjson(x)?,
+            ],
+        ),
+        ("Sum", _) => bound(BuiltinOp::sum)?,
+        ("Product", _) => bound(BuiltinOp::product)?,
+        ("Integrate", _) => bound(BuiltinOp::int)?,
+        // Repeated variables are differentiated by as often
+        ("D", [body, variables @ ..]) | ("PartialDerivative", [body, variables @ ..])
+            if !variables.is_empty() =>
+        {
+            let mut bvars: Vec<(String, i64)> = Vec::new();
+            for variable in variables {
+                let variable = match variable.as_str() {
+                    Some(variable) => variable,
+                    None => return invalid(json, "expected the variables to differentiate by"),
+                };
+                match bvars.iter_mut().find(|(name, _)| name == variable) {
+                    Some((_, times)) => *times += 1,
+                    None => bvars.push((variable.to_owned(), 1)),
+                }
+            }
+            let op = match bvars.len() {
+                1 => BuiltinOp::diff,
+                _ => BuiltinOp::partialdiff,
+            };
+            let mut children = vec![MathNode::Op(op)];
+            for (name, times) in bvars {
+                let degree = match times {
+                    1 => None,
+                    n => Some(Box::new(MathNode::integer(n))),
+                };
+                children.push(MathNode::Bvar { name, degree });
+            }
+            children.push(parse_mathjson(body)?);
+            MathNode::Apply {
+                children,
+                attributes: Vec::new(),
+            }
+        }
+        ("Which", _) => {
+            if !args.len().is_multiple_of(2) {
+                return invalid(json, "expected pairs of a condition and a value");
+            }
+            let mut pieces = Vec::new();
+            let mut otherwise = None;
+            for pair in args.chunks(2) {
+                let value = parse_mathjson(&pair[1])?;
+                match &pair[0] {
+                    condition if condition == "True" => otherwise = Some(value),
+                    condition => pieces.push((value, parse_mathjson(condition)?)),
+                }
+            }
+            MathNode::piecewise(pieces, otherwise)
+        }
+        ("Function", [body, variables @ ..]) => MathNode::Lambda {
+            bound_vars: variables
+                .iter()
+                .map(|v| match v.as_str() {
+                    Some(v) => Ok(v.to_owned()),
+                    None => invalid(json, "expected the names of the parameters"),
+                })
+                .collect::<Result<_, _>>()?,
+            body: Box::new(parse_mathjson(body)?),
+        },
+        ("Rational", [numerator, denominator]) => {
+            match (numerator.as_i64(), denominator.as_i64()) {
+                (Some(numerator), Some(denominator)) => MathNode::rational(numerator, denominator),
+                _ => MathNode::apply(BuiltinOp::divide, operands()?),
+            }
+        }
+        ("Complex", [re, im]) => match (re.as_f64(), im.as_f64()) {
+            (Some(re), Some(im)) => MathNode::number(NumType::ComplexCartesian(re, im)),
+            _ => return invalid(json, "expected the real and imaginary part"),
+        },
+        ("Interval", [low, high]) => {
+            let ((low, low_open), (high, high_open)) = (parse_bound(low)?, parse_bound(high)?);
+            let closure = match (low_open, high_open) {
+                (false, false) => IntervalClosure::Closed,
+                (true, true) => IntervalClosure::Open,
+                (true, false) => IntervalClosure::OpenClosed,
+                (false, true) => IntervalClosure::ClosedOpen,
+            };
+            MathNode::Interval {
+                closure,
+                low: Box::new(low),
+                high: Box::new(high),
+            }
+        }
+        ("Set", _) => MathNode::Set(operands()?),
+        ("List", _) => MathNode::List {
+            order: None,
+            items: operands()?,
+        },
+        ("Matrix", [rows]) => match parse_mathjson(rows)? {
+            MathNode::List { items, .. } => MathNode::Matrix(
+                items
+                    .into_iter()
+                    .map(|row| match row {
+                        MathNode::List { items, .. } => Ok(items),
+                        _ => invalid(json, "expected a list of rows"),
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            _ => return invalid(json, "expected a list of rows"),
+        },
+        ("Delimiter", [inner]) => parse_mathjson(inner)?,
+        ("Error", _) => return invalid(json, "unexpected error"),
+        (name, _) => MathNode::call(MathNode::ci(name), operands()?),
+    };
+    Ok(node)
+}
+
+/// Reads MathJSON, the format of MathLive and the Compute Engine, e.g.
+/// `["Add", "x", ["Sin", "y"]]`, into content MathML. Besides the functions
+/// `to_mathjson` writes it reads their common aliases like `Square` or `Lb`
+/// and the object forms `{"num": ..}`, `{"sym": ..}`, `{"str": ..}` and
+/// `{"fn": ..}`. Functions it doesn't know become calls of an identifier
+pub fn parse_mathjson(json: &Value) -> Result<MathNode, ParseError> {
+    match json {
+        Value::Number(number) => match number.as_i64() {
+            Some(integer) => Ok(MathNode::integer(integer)),
+            None => parse_number(&number.to_string()),
+        },
+        Value::String(text) => match text.strip_prefix('\'') {
+            Some(string) => Ok(MathNode::Text(
+                string.strip_suffix('\'').unwrap_or(string).to_owned(),
+            )),
+            None => Ok(parse_symbol(text)),
+        },
+        Value::Array(items) => match items.split_first() {
+            Some((Value::String(name), args)) => parse_function(json, name, args),
+            Some((head, args)) => Ok(MathNode::call(
+                parse_mathjson(head)?,
+                args.iter()
+                    .map(parse_mathjson)
+                    .collect::<Result<Vec<_>, _>>()?,
+            )),
+            None => invalid(json, "expected a function"),
+        },
+        Value::Object(object) => match (
+            object.get("num"),
+            object.get("sym"),
+            object.get("str"),
+            object.get("fn"),
+        ) {
+            (Some(Value::String(number)), ..) => parse_number(number),
+            (Some(number), ..) => parse_mathjson(number),
+            (_, Some(Value::String(name)), ..) => Ok(parse_symbol(name)),
+            (_, _, Some(Value::String(text)), _) => Ok(MathNode::Text(text.clone())),
+            (_, _, _, Some(function)) => parse_mathjson(function),
+            _ => invalid(json, "expected num, sym, str or fn"),
+        },
+        Value::Bool(_) | Value::Null => invalid(json, "unexpected value"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_mathjson;
+    use crate::parse_document;
+    use serde_json::json;
+
+    #[test]
+    fn test_mathjson() {
+        let test = r#"<apply><plus/><ci>x</ci><apply><sin/><ci>y</ci></apply>
+                        <apply><minus/><apply><root/><ci>x</ci></apply><cn>0.5</cn></apply>
+                        <apply><log/><logbase><cn type="integer">2</cn></logbase><pi/></apply></apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(
+            parsed.to_mathjson(),
+            json!([
+                "Add",
+                "x",
+                ["Sin", "y"],
+                ["Subtract", ["Sqrt", "x"], 0.5],
+                ["Log", "Pi", 2]
+            ])
+        );
+        assert_eq!(parse_mathjson(&parsed.to_mathjson()).unwrap(), parsed);
+
+        for test in &[
+            r#"<apply><sum/><bvar><ci>i</ci></bvar><lowlimit><cn type="integer">1</cn></lowlimit><uplimit><ci>n</ci></uplimit>
+                 <apply><partialdiff/><bvar><ci>x</ci><degree><cn type="integer">2</cn></degree></bvar><bvar><ci>y</ci></bvar>
+                 <apply><ci>f</ci><ci>x</ci><ci>y</ci></apply></apply></apply>"#,
+            r#"<piecewise><piece><cn type="rational">1<sep/>2</cn><apply><lt/><ci>x</ci><cn type="integer">0</cn></apply></piece>
+                 <otherwise><apply><minus/><exponentiale/></apply></otherwise></piecewise>"#,
+            r#"<lambda><bvar><ci>x</ci></bvar><apply><in/><ci>x</ci><interval closure="open-closed"><cn type="integer">0</cn><infinity/></interval></apply></lambda>"#,
+        ] {
+            let parsed = parse_document(test).unwrap();
+            assert_eq!(parse_mathjson(&parsed.to_mathjson()).unwrap(), parsed);
+        }
+
+        let test = json!(["Multiply", {"num": "1.5e3"}, ["Square", {"sym": "x"}], ["Lb", 8]]);
+        assert_eq!(
+            parse_mathjson(&test).unwrap().to_string(),
+            "1500 * x^2 * log(2, 8)"
+        );
+        assert!(parse_mathjson(&json!(["Which", "True"])).is_err());
+    }
+}
@@ -0,0 +1,234 @@
+use super::{BuiltinOp, ConstantSymbol, MathNode};
+
+/// The operator and operands of an application of a logical operator
+fn logical(node: &MathNode) -> Option<(BuiltinOp, Vec<&MathNode>)> {
+    use BuiltinOp::*;
+    let op = match node {
+        MathNode::Apply { children, .. } => match children.first() {
+            Some(MathNode::Op(op)) if matches!(op, and | or | not | implies | xor | equivalent) => {
+                *op
+            }
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let operands = node.operands();
+    let arity_ok = match op {
+        not => operands.len() == 1,
+        implies => operands.len() == 2,
+        xor | equivalent => operands.len() >= 2,
+        _ => true,
+    };
+    if arity_ok {
+        Some((op, operands))
+    } else {
+        None
+    }
+}
+
+fn constant(value: bool) -> MathNode {
+    MathNode::from(value)
+}
+
+fn truth(node: &MathNode) -> Option<bool> {
+    match node {
+        MathNode::Constant(ConstantSymbol::True) => Some(true),
+        MathNode::Constant(ConstantSymbol::False) => Some(false),
+        _ => None,
+    }
+}
+
+/// `and` or `or` of `operands`, merging operands that are the same operator
+fn junction(op: BuiltinOp, operands: impl IntoIterator<Item = MathNode>) -> MathNode {
+    let mut flat = Vec::new();
+    for operand in operands {
+        match logical(&operand) {
+            Some((inner, inner_operands)) if inner == op => {
+                flat.extend(inner_operands.into_iter().cloned())
+            }
+            _ => flat.push(operand),
+        }
+    }
+    match flat.len() {
+        0 => constant(op == BuiltinOp::and),
+        1 => flat.remove(0),
+        _ => MathNode::apply(op, flat),
+    }
+}
+
+fn negation(node: MathNode) -> MathNode {
+    MathNode::apply(BuiltinOp::not, vec![node])
+}
+
+/// The negation normal form of `node`, or of its negation when `negated`
+fn nnf(node: &MathNode, negated: bool) -> MathNode {
+    use BuiltinOp::*;
+    if let Some(value) = truth(node) {
+        return constant(value != negated);
+    }
+    if let MathNode::Root { .. } = node {
+        let mut math = node.clone();
+        for child in math.children_mut() {
+            *child = nnf(child, negated);
+        }
+        return math;
+    }
+    let (op, operands) = match logical(node) {
+        Some(logical) => logical,
+        None if negated => return negation(node.clone()),
+        None => return node.clone(),
+    };
+    // De Morgan: a negated `and` is an `or` of negations, and the other way round
+    let (conjunction, disjunction) = if negated { (or, and) } else { (and, or) };
+    match op {
+        not => nnf(operands[0], !negated),
+        and => junction(conjunction, operands.iter().map(|o| nnf(o, negated))),
+        or => junction(disjunction, operands.iter().map(|o| nnf(o, negated))),
+        implies => junction(
+            disjunction,
+            vec![nnf(operands[0], !negated), nnf(operands[1], negated)],
+        ),
+        xor => {
+            let mut operands = operands.into_iter().cloned();
+            let first = operands.next().unwrap_or_else(|| constant(false));
+            let exclusive = operands.fold(first, |a, b| {
+                let one = MathNode::apply(and, vec![a.clone(), negation(b.clone())]);
+                let other = MathNode::apply(and, vec![negation(a), b]);
+                MathNode::apply(or, vec![one, other])
+            });
+            nnf(&exclusive, negated)
+        }
+        _ => {
+            let pairs = operands.windows(2).map(|pair| {
+                let (a, b) = (pair[0].clone(), pair[1].clone());
+                let both = MathNode::apply(and, vec![a.clone(), b.clone()]);
+                let neither = MathNode::apply(and, vec![negation(a), negation(b)]);
+                MathNode::apply(or, vec![both, neither])
+            });
+            nnf(&MathNode::apply(and, pairs), negated)
+        }
+    }
+}
+
+/// The clauses of an expression in negation normal form, as a disjunction of
+/// conjunctions for `to_dnf`, or the other way round for `to_cnf`
+fn clauses(node: &MathNode, outer: BuiltinOp, inner: BuiltinOp) -> Vec<Vec<MathNode>> {
+    if let Some(value) = truth(node) {
+        // An empty clause is the identity of `inner`, true for `and`
+        return if value == (inner == BuiltinOp::and) {
+            vec![Vec::new()]
+        } else {
+            Vec::new()
+        };
+    }
+    match logical(node) {
+        Some((op, operands)) if op == outer => operands
+            .into_iter()
+            .flat_map(|o| clauses(o, outer, inner))
+            .collect(),
+        Some((op, operands)) if op == inner => {
+            operands.into_iter().fold(vec![Vec::new()], |product, o| {
+                let factor = clauses(o, outer, inner);
+                product
+                    .iter()
+                    .flat_map(|clause| {
+                        factor.iter().map(move |other| {
+                            let mut merged = clause.clone();
+                            merged.extend(other.iter().filter(|l| !clause.contains(l)).cloned());
+                            merged
+                        })
+                    })
+                    .collect()
+            })
+        }
+        _ => vec![vec![node.clone()]],
+    }
+}
+
+/// Whether a clause holds some literal and its negation, making a conjunction
+/// false and a disjunction true
+fn complementary(clause: &[MathNode]) -> bool {
+    clause.iter().any(|literal| match logical(literal) {
+        Some((BuiltinOp::not, operands)) => clause.contains(operands[0]),
+        _ => false,
+    })
+}
+
+fn normal_form(node: &MathNode, outer: BuiltinOp, inner: BuiltinOp) -> MathNode {
+    if let MathNode::Root { .. } = node {
+        let mut math = node.clone();
+        for child in math.children_mut() {
+            *child = normal_form(child, outer, inner);
+        }
+        return math;
+    }
+    let mut kept: Vec<Vec<MathNode>> = Vec::new();
+    for clause in clauses(&nnf(node, false), outer, inner) {
+        if !complementary(&clause) && !kept.contains(&clause) {
+            kept.push(clause);
+        }
+    }
+    junction(
+        outer,
+        kept.into_iter().map(|clause| junction(inner, clause)),
+    )
+}
+
+impl MathNode {
+    /// Pushes every `not` down to the comparisons and other conditions it
+    /// applies to, by De Morgan's laws and double negation, after rewriting
+    /// `implies`, `xor` and `equivalent` with `and`, `or` and `not`. A `not`
+    /// of a comparison is kept as it is rather than flipping the comparison,
+    /// which would be wrong for NaN
+    pub fn to_nnf(&self) -> MathNode {
+        nnf(self, false)
+    }
+    /// The disjunctive normal form: an `or` of `and`s of conditions and their
+    /// negations, without contradictory or repeated terms. It can be
+    /// exponentially larger than the expression
+    pub fn to_dnf(&self) -> MathNode {
+        normal_form(self, BuiltinOp::or, BuiltinOp::and)
+    }
+    /// The conjunctive normal form: an `and` of `or`s of conditions and their
+    /// negations, without tautological or repeated clauses. It can be
+    /// exponentially larger than the expression
+    pub fn to_cnf(&self) -> MathNode {
+        normal_form(self, BuiltinOp::and, BuiltinOp::or)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parse_infix;
+
+    #[test]
+    fn test_normal_forms() {
+        let trigger = parse_infix("!(S1 < 5 || (t >= 10 && !paused))").unwrap();
+        assert_eq!(
+            trigger.to_nnf().to_string(),
+            "!(S1 < 5) && (!(t >= 10) || paused)"
+        );
+        assert_eq!(
+            trigger.to_dnf().to_string(),
+            "!(S1 < 5) && !(t >= 10) || !(S1 < 5) && paused"
+        );
+        assert_eq!(
+            parse_infix("(a || b) && (a || c)")
+                .unwrap()
+                .to_dnf()
+                .to_string(),
+            "a || a && c || b && a || b && c"
+        );
+        assert_eq!(
+            parse_infix("a && b || c").unwrap().to_cnf().to_string(),
+            "(a || c) && (b || c)"
+        );
+        assert_eq!(
+            parse_infix("implies(a, b) && (a || !a)")
+                .unwrap()
+                .to_cnf()
+                .to_string(),
+            "!a || b"
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use super::{BuiltinOp, MathNode};
+use std::collections::HashMap;
+
+/// Operators whose arguments can be regrouped and reordered freely
+fn is_commutative(op: &BuiltinOp) -> bool {
+    use BuiltinOp::*;
+    matches!(op, plus | times | and | or)
+}
+
+/// The operator and operands of an application without qualifiers
+fn plain_apply(node: &MathNode) -> Option<(BuiltinOp, &[MathNode])> {
+    match node {
+        MathNode::Apply { children, .. } => match children.split_first() {
+            Some((MathNode::Op(op), operands))
+                if operands.iter().all(|c| !c.is_qualifier() && !c.is_markup()) =>
+            {
+                Some((*op, operands))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Numbers sort first, then identifiers, then everything else
+fn rank(node: &MathNode) -> u8 {
+    match node {
+        MathNode::Cn { .. } | MathNode::Constant(_) => 0,
+        MathNode::Ci { .. } | MathNode::Csymbol { .. } => 1,
+        _ => 2,
+    }
+}
+
+/// Sort key giving a total order on expressions that only depends on their structure
+pub(crate) fn canonical_key(node: &MathNode) -> (u8, String) {
+    (rank(node), node.to_mathml())
+}
+
+fn normalize_node(node: MathNode) -> MathNode {
+    let (op, operands) = match plain_apply(&node) {
+        Some((op, operands)) if is_commutative(&op) => (op, operands),
+        _ => return node,
+    };
+    let mut flat = Vec::with_capacity(operands.len());
+    for operand in operands {
+        match plain_apply(operand) {
+            // Children are normalized first, so one level of flattening is enough
+            Some((inner, nested)) if inner == op => flat.extend(nested.iter().cloned()),
+            _ => flat.push(operand.clone()),
+        }
+    }
+    flat.sort_by_cached_key(canonical_key);
+    MathNode::apply(op, flat)
+}
+
+/// The node with every child blanked out, to compare what isn't a subtree
+fn shell(node: &MathNode) -> MathNode {
+    let mut shell = node.clone();
+    for child in shell.children_mut() {
+        *child = MathNode::Text(String::new());
+    }
+    shell
+}
+
+fn same(a: &MathNode, b: &MathNode) -> bool {
+    match (a, b) {
+        (MathNode::Cn { .. }, MathNode::Cn { .. }) => {
+            match (a.eval(&HashMap::new()), b.eval(&HashMap::new())) {
+                (Ok(x), Ok(y)) => approx::relative_eq!(x, y, max_relative = 1e-9),
+                _ => a == b,
+            }
+        }
+        _ => {
+            let (left, right) = (a.children(), b.children());
+            left.len() == right.len()
+                && shell(a) == shell(b)
+                && left.iter().zip(right).all(|(l, r)| same(l, r))
+        }
+    }
+}
+
+impl MathNode {
+    /// Rewrites the tree into a canonical form: nested `plus`, `times`, `and`
+    /// and `or` are flattened and their arguments sorted, so `b + (a + 1)` and
+    /// `(1 + b) + a` both become `1 + a + b`
+    pub fn normalize(&self) -> MathNode {
+        self.clone().map(normalize_node)
+    }
+    /// Whether both expressions are the same up to the order and grouping of
+    /// commutative operators, with numbers compared with a relative tolerance,
+    /// e.g. `2 * (k * S)` and `S * k * 2.0`
+    pub fn equivalent(&self, other: &MathNode) -> bool {
+        same(&self.normalize(), &other.normalize())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::math;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            math!(b + (a + 1)).normalize(),
+            math!((1 + b) + a).normalize()
+        );
+        assert_eq!(math!(b + (a + 1)).normalize().to_string(), "1 + a + b");
+        assert_eq!(
+            math!(k * (y * sin(x)) - (c + b)).normalize().to_string(),
+            "k * y * sin(x) - (b + c)"
+        );
+    }
+    #[test]
+    fn test_equivalent() {
+        assert!(math!(2 * (k * s)).equivalent(&math!(s * k * 2.0)));
+        assert!(math!(and(gt(x, 0), b)).equivalent(&math!(and(b, gt(x, 0)))));
+        assert!(!math!(a - b).equivalent(&math!(b - a)));
+        assert!(!math!(x * 2).equivalent(&math!(x * 2.001)));
+    }
+}
@@ -1,9 +1,11 @@
 use super::MathNode;
+use crate::attributes::extra_attributes;
+use crate::error::ParseError;
 use roxmltree::Node;
 use serde_derive::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
-use std::num::{ParseFloatError, ParseIntError};
-#[derive(Debug, Serialize, Deserialize)]
+use std::fmt;
+use std::hash::{Hash, Hasher};
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum NumType {
     Real(f64),
@@ -11,143 +13,385 @@ pub enum NumType {
     Rational(i64, i64),
     ComplexCartesian(f64, f64),
     ComplexPolar(f64, f64),
-    Constant(String),
+    Constant(NamedConstant),
     ENotation(f64, i64),
+    /// A `type` outside of the MathML ones, e.g. `double`, kept with its text
+    Other {
+        type_name: String,
+        text: String,
+    },
+    /// An integer too large for `i64`, only with the `bigint` feature
+    #[cfg(feature = "bigint")]
+    BigInteger(num_bigint::BigInt),
 }
-impl Eq for NumType {}
-impl PartialEq for NumType {
-    fn eq(&self, other: &Self) -> bool {
+/// The bits of a float that equality and hashing go by, with `-0.0` folded
+/// into `0.0` and every NaN into one
+fn float_key(value: f64) -> u64 {
+    if value == 0.0 {
+        0f64.to_bits()
+    } else if value.is_nan() {
+        f64::NAN.to_bits()
+    } else {
+        value.to_bits()
+    }
+}
+
+impl NumType {
+    /// Whether the numbers are the same, with floats compared by `same`
+    fn compare(&self, other: &Self, same: impl Fn(f64, f64) -> bool) -> bool {
         use NumType::*;
         match (self, other) {
-            (Real(r), Real(r2)) => approx::abs_diff_eq!(r, r2),
+            (Real(r), Real(r2)) => same(*r, *r2),
             (Integer(r1), Integer(r2)) => r1 == r2,
             (Rational(a, b), Rational(c, d)) => (a == c) && (b == d),
             (ComplexPolar(a, b), ComplexPolar(c, d))
-            | (ComplexCartesian(a, b), ComplexCartesian(c, d)) => {
-                approx::abs_diff_eq!(a, c) && approx::abs_diff_eq!(b, d)
-            }
+            | (ComplexCartesian(a, b), ComplexCartesian(c, d)) => same(*a, *c) && same(*b, *d),
             (Constant(a), Constant(b)) => a == b,
-            (ENotation(a, b), ENotation(c, d)) => a == c && b == d,
+            (ENotation(a, b), ENotation(c, d)) => same(*a, *c) && b == d,
+            (
+                Other { type_name, text },
+                Other {
+                    type_name: type_name2,
+                    text: text2,
+                },
+            ) => type_name == type_name2 && text == text2,
+            #[cfg(feature = "bigint")]
+            (BigInteger(a), BigInteger(b)) => a == b,
             _ => false,
         }
     }
+    /// Like `==`, with floats within `f64::EPSILON` of each other equal
+    pub fn approx_eq(&self, other: &Self) -> bool {
+        self.compare(other, |a, b| approx::abs_diff_eq!(a, b))
+    }
+}
+
+impl Eq for NumType {}
+/// Floats are equal if they have the same bits, but for `-0.0` being `0.0` and
+/// NaN being NaN, as they are hashed
+impl PartialEq for NumType {
+    fn eq(&self, other: &Self) -> bool {
+        self.compare(other, |a, b| float_key(a) == float_key(b))
+    }
+}
+
+/// The constant symbols Content MathML writes as empty elements, e.g. `<pi/>`
+#[derive(Deserialize, Debug, Serialize, Eq, PartialEq, Clone, Copy, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum ConstantSymbol {
+    Pi,
+    ExponentialE,
+    ImaginaryI,
+    EulerGamma,
+    Infinity,
+    NotANumber,
+    True,
+    False,
+}
+
+impl fmt::Display for ConstantSymbol {
+    /// Writes the MathML tag name of the constant
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = serde_plain::to_string(self).map_err(|_| fmt::Error)?;
+        f.write_str(&name)
+    }
+}
+
+/// The text of a `<cn type="constant">`, e.g. `&tau;`
+#[derive(Deserialize, Debug, Serialize, Eq, PartialEq, Clone, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum NamedConstant {
+    Pi,
+    Tau,
+    E,
+    /// The Euler-Mascheroni constant
+    Gamma,
+    GoldenRatio,
+    /// Any other text, kept as is
+    Custom(String),
+}
+
+impl NamedConstant {
+    /// The value of the constant, or `None` for a `Custom` one
+    pub fn value(&self) -> Option<f64> {
+        match self {
+            NamedConstant::Pi => Some(std::f64::consts::PI),
+            NamedConstant::Tau => Some(2.0 * std::f64::consts::PI),
+            NamedConstant::E => Some(std::f64::consts::E),
+            NamedConstant::Gamma => Some(0.577_215_664_901_532_9),
+            NamedConstant::GoldenRatio => Some(1.618_033_988_749_895),
+            NamedConstant::Custom(_) => None,
+        }
+    }
+    /// The character MathML writes the constant as, or the text of a `Custom` one
+    pub fn symbol(&self) -> &str {
+        match self {
+            NamedConstant::Pi => "\u{03C0}",
+            NamedConstant::Tau => "\u{03C4}",
+            NamedConstant::E => "\u{2147}",
+            NamedConstant::Gamma => "\u{03B3}",
+            NamedConstant::GoldenRatio => "\u{03C6}",
+            NamedConstant::Custom(text) => text,
+        }
+    }
+}
+
+/// Recognizes the constant by its character, entity or name, e.g. `π`,
+/// `&pi;` or `pi`
+impl From<&str> for NamedConstant {
+    fn from(text: &str) -> Self {
+        let name = crate::entities::entity_name(text).unwrap_or(text);
+        match name {
+            "\u{03C0}" | "pi" => NamedConstant::Pi,
+            "\u{03C4}" | "tau" => NamedConstant::Tau,
+            "\u{2147}" | "e" | "ee" | "ExponentialE" | "exponentiale" => NamedConstant::E,
+            "\u{03B3}" | "gamma" | "eulergamma" => NamedConstant::Gamma,
+            "\u{03C6}" | "\u{03D5}" | "phi" | "phiv" | "varphi" => NamedConstant::GoldenRatio,
+            _ => NamedConstant::Custom(text.to_owned()),
+        }
+    }
 }
 
-fn parse_and_trim_int(node: Node, base: u32) -> Result<i64, ParseIntError> {
-    i64::from_str_radix(node.text().unwrap().trim(), base)
+/// The number sets Content MathML writes as empty elements, e.g. `<reals/>`
+#[derive(Deserialize, Debug, Serialize, Eq, PartialEq, Clone, Copy, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum NumberSet {
+    Integers,
+    Reals,
+    Rationals,
+    NaturalNumbers,
+    Complexes,
+    Primes,
+    EmptySet,
 }
-fn parse_and_trim_float(node: Node) -> Result<f64, ParseFloatError> {
-    node.text().unwrap().trim().parse()
+
+impl fmt::Display for NumberSet {
+    /// Writes the MathML tag name of the set
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = serde_plain::to_string(self).map_err(|_| fmt::Error)?;
+        f.write_str(&name)
+    }
 }
-fn extract_enotation(node: Node) -> Result<(f64, i64), Box<dyn std::error::Error>> {
-    // We can either have 1 child (SBML) or 3 children (MathML)
-    let children_count = node.children().count();
-    match children_count {
-        3 => extract_float_int_children(node),
-        1 => {
-            let first_child: Vec<String> = node
-                .first_child()
-                .unwrap()
-                .text()
-                .unwrap()
-                .to_lowercase()
-                .split("e")
-                .map(|e| e.trim().to_owned())
-                .collect();
 
-            let exponent: f64 = first_child[0].parse().unwrap();
-            let mantissa: i64 = first_child[1].parse().unwrap();
-            Ok((exponent, mantissa))
+/// Hashes the floats by the bits equality goes by
+impl Hash for NumType {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        fn float<H: Hasher>(value: f64, state: &mut H) {
+            float_key(value).hash(state)
+        }
+        std::mem::discriminant(self).hash(state);
+        match self {
+            NumType::Real(r) => float(*r, state),
+            NumType::Integer(i) => i.hash(state),
+            NumType::Rational(a, b) => (a, b).hash(state),
+            NumType::ComplexCartesian(a, b) | NumType::ComplexPolar(a, b) => {
+                float(*a, state);
+                float(*b, state);
+            }
+            NumType::Constant(c) => c.hash(state),
+            NumType::ENotation(a, b) => {
+                float(*a, state);
+                b.hash(state);
+            }
+            NumType::Other { type_name, text } => (type_name, text).hash(state),
+            #[cfg(feature = "bigint")]
+            NumType::BigInteger(i) => i.hash(state),
         }
+    }
+}
 
-        _ => panic!("We can only ever have 3 or 1 children"),
+fn node_text<'a>(node: Node<'a, '_>) -> Result<&'a str, ParseError> {
+    node.text()
+        .ok_or_else(|| ParseError::InvalidStructure("<cn> is missing its text".to_owned()))
+}
+fn invalid_number(num_type: &str, text: &str) -> ParseError {
+    ParseError::InvalidNumber {
+        num_type: num_type.to_owned(),
+        text: text.trim().to_owned(),
+    }
+}
+fn parse_and_trim_int(node: Node, base: u32) -> Result<i64, ParseError> {
+    let text = node_text(node)?;
+    i64::from_str_radix(text.trim(), base).map_err(|_| invalid_number("integer", text))
+}
+/// Integers that overflow `i64` become a `BigInteger` with the `bigint` feature
+fn parse_integer(node: Node, base: u32) -> Result<NumType, ParseError> {
+    match parse_and_trim_int(node, base) {
+        Ok(i) => Ok(NumType::Integer(i)),
+        #[cfg(feature = "bigint")]
+        Err(e) => num_bigint::BigInt::parse_bytes(node_text(node)?.trim().as_bytes(), base)
+            .map(NumType::BigInteger)
+            .ok_or(e),
+        #[cfg(not(feature = "bigint"))]
+        Err(e) => Err(e),
     }
 }
-pub(crate) fn node_to_cn(node: Node) -> MathNode {
-    // TODO: make static
-    let ignore_attrs: HashSet<&str> = vec!["type", "base", "encoding", "definitionUrl", "units"]
-        .into_iter()
-        .collect();
+fn parse_and_trim_float(node: Node) -> Result<f64, ParseError> {
+    let text = node_text(node)?;
+    text.trim()
+        .parse()
+        .map_err(|_| invalid_number("real", text))
+}
+/// A real written with digits in `base`, e.g. `A.8` in base 16 is 10.5
+fn parse_real_in_base(node: Node, base: u32) -> Result<f64, ParseError> {
+    let text = node_text(node)?;
+    let trimmed = text.trim();
+    let (negative, digits) = match trimmed.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+    };
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        return Err(invalid_number("real", text));
+    }
+    let digit = |c: char| c.to_digit(base).ok_or_else(|| invalid_number("real", text));
+    let mut value = 0.0;
+    for c in whole.chars() {
+        value = value * base as f64 + digit(c)? as f64;
+    }
+    // Horner's rule from the last digit, which loses less precision than scaling up
+    let mut tail = 0.0;
+    for c in fraction.chars().rev() {
+        tail = (tail + digit(c)? as f64) / base as f64;
+    }
+    value += tail;
+    Ok(if negative { -value } else { value })
+}
+/// The text on either side of each `<sep/>`, ignoring comments and the
+/// whitespace around the separators
+fn sep_parts(node: Node) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    for child in node.children() {
+        if child.is_text() {
+            if let Some(part) = parts.last_mut() {
+                part.push_str(child.text().unwrap_or_default());
+            }
+        } else if child.is_element() {
+            parts.push(String::new());
+        }
+    }
+    parts.iter().map(|p| p.trim().to_owned()).collect()
+}
+fn extract_enotation(node: Node) -> Result<(f64, i64), ParseError> {
+    // Either `2 <sep/> -5` (MathML) or `2e-5` in a single text node (SBML)
+    let parts = sep_parts(node);
+    let (mantissa, exponent) = match parts.as_slice() {
+        [mantissa, exponent] => (mantissa.as_str(), exponent.as_str()),
+        [text] => {
+            let mut split = text.splitn(2, ['e', 'E']);
+            match (split.next(), split.next()) {
+                (Some(mantissa), Some(exponent)) => (mantissa.trim(), exponent.trim()),
+                _ => {
+                    return Err(ParseError::InvalidStructure(format!(
+                        "e-notation number '{}' has no exponent",
+                        text
+                    )))
+                }
+            }
+        }
+        _ => {
+            return Err(ParseError::InvalidStructure(format!(
+                "e-notation numbers need a mantissa and an exponent, found {} parts",
+                parts.len()
+            )))
+        }
+    };
+    let invalid = || invalid_number("e-notation", &parts.join(" <sep/> "));
+    Ok((
+        mantissa.parse().map_err(|_| invalid())?,
+        exponent.parse().map_err(|_| invalid())?,
+    ))
+}
+fn first_and_last_child<'a, 'input>(
+    node: Node<'a, 'input>,
+) -> Result<(Node<'a, 'input>, Node<'a, 'input>), ParseError> {
+    match (node.first_child(), node.last_child()) {
+        (Some(first), Some(last)) => Ok((first, last)),
+        _ => Err(ParseError::InvalidStructure(
+            "<cn> is missing its children".to_owned(),
+        )),
+    }
+}
+pub(crate) fn node_to_cn(node: Node) -> Result<MathNode, ParseError> {
     let num_type_str = node.attribute("type").unwrap_or("real");
-    let base: u32 = node.attribute("base").unwrap_or("10").parse().unwrap();
+    let base_str = node.attribute("base").unwrap_or("10");
+    let base: u32 = base_str
+        .trim()
+        .parse()
+        .ok()
+        .filter(|base| (2..=36).contains(base))
+        .ok_or_else(|| ParseError::InvalidAttribute {
+            element: "cn".to_owned(),
+            attribute: "base".to_owned(),
+            value: base_str.to_owned(),
+        })?;
 
     let num_type = match num_type_str {
-        "real" => NumType::Real(node.text().unwrap().trim().parse().unwrap()),
-        "integer" => NumType::Integer(parse_and_trim_int(node, base).unwrap()),
+        "real" if base != 10 => NumType::Real(parse_real_in_base(node, base)?),
+        "real" => NumType::Real(parse_and_trim_float(node)?),
+        "integer" => parse_integer(node, base)?,
         "rational" => {
-            let child1 = parse_and_trim_int(node.first_child().unwrap(), base).unwrap();
-            let child2 = parse_and_trim_int(node.children().nth_back(0).unwrap(), base).unwrap();
+            let (first, last) = first_and_last_child(node)?;
+            let child1 = parse_and_trim_int(first, base)?;
+            let child2 = parse_and_trim_int(last, base)?;
             NumType::Rational(child1, child2)
         }
         "complex-cartesian" => {
-            let (a, b) = extract_float_children(node).unwrap();
+            let (a, b) = extract_float_children(node)?;
             NumType::ComplexCartesian(a, b)
         }
         "complex-polar" => {
-            let (a, b) = extract_float_children(node).unwrap();
+            let (a, b) = extract_float_children(node)?;
             NumType::ComplexPolar(a, b)
         }
-        "constant" => NumType::Constant(
-            node.first_child()
-                .unwrap()
-                .text()
-                .unwrap()
-                .trim()
-                .to_owned(),
-        ),
+        "constant" => NumType::Constant(node_text(node)?.trim().into()),
         // This one can either be number <sep> number or just 2e-5 for SBML, we will support both
         "e-notation" => {
-            let (a, b) = extract_enotation(node).unwrap();
+            let (a, b) = extract_enotation(node)?;
             NumType::ENotation(a, b)
         }
-        _ => panic!("Unhandled number type"),
+        other => NumType::Other {
+            type_name: other.to_owned(),
+            text: node_text(node)?.trim().to_owned(),
+        },
     };
 
-    let encoding = node.attribute("encoding").map(|p| p.parse().unwrap());
-    let definition_url = node.attribute("definitionUrl").map(|p| p.parse().unwrap());
-
-    let attributes: HashMap<String, String> = node
-        .attributes()
-        .iter()
-        .filter(|n| {
-            if ignore_attrs.contains(n.name()) & n.namespace().is_none() {
-                false
-            } else {
-                true
-            }
-        })
-        .map(|a| {
-            (
-                format!(
-                    "{}:{}",
-                    a.namespace().unwrap().to_owned(),
-                    a.name().to_owned()
-                ),
-                a.value().to_owned(),
-            )
-        })
-        .collect();
-    MathNode::Cn {
+    let encoding = node.attribute("encoding").map(|p| p.to_owned());
+    let definition_url = crate::definition_url(node);
+
+    let mut units = None;
+    let mut units_namespace = None;
+    let mut attributes = extra_attributes(
+        node,
+        &["type", "base", "definitionURL", "definitionUrl", "encoding"],
+    );
+    attributes.retain(|(name, value)| match &name.namespace {
+        Some(ns)
+            if name.local == "units"
+                && (crate::sbml::is_sbml_namespace(ns)
+                    || crate::cellml::is_cellml_namespace(ns)) =>
+        {
+            units = Some(value.clone());
+            units_namespace = Some(ns.clone());
+            false
+        }
+        _ => true,
+    });
+    Ok(MathNode::Cn {
         num_type,
         base,
         definition_url,
         encoding,
-        attributes: if attributes.is_empty() {
-            None
-        } else {
-            Some(attributes)
-        },
-    }
+        units,
+        units_namespace,
+        attributes,
+    })
 }
 
-fn extract_float_children(node: Node) -> Result<(f64, f64), Box<dyn std::error::Error>> {
-    let child1 = parse_and_trim_float(node.first_child().unwrap())?;
-    let child2 = parse_and_trim_float(node.children().nth_back(0).unwrap())?;
-    Ok((child1, child2))
-}
-fn extract_float_int_children(node: Node) -> Result<(f64, i64), Box<dyn std::error::Error>> {
-    let child1 = parse_and_trim_float(node.first_child().unwrap())?;
-    let child2 = parse_and_trim_int(node.children().nth_back(0).unwrap(), 10)?;
+fn extract_float_children(node: Node) -> Result<(f64, f64), ParseError> {
+    let (first, last) = first_and_last_child(node)?;
+    let child1 = parse_and_trim_float(first)?;
+    let child2 = parse_and_trim_float(last)?;
     Ok((child1, child2))
 }
 #[cfg(test)]
@@ -156,29 +400,104 @@ mod test {
     #[test]
     fn test_number_eq() {
         use super::NumType::*;
-        assert_eq!(Constant("t".to_string()), Constant("t".to_string()));
+        assert_eq!(Constant("t".into()), Constant("t".into()));
         assert_eq!(Real(1212.212), Real(1212.212));
         assert_eq!(Integer(12), Integer(12))
     }
     #[test]
+    fn test_number_hash() {
+        use super::NumType::*;
+        use std::collections::HashSet;
+        let close = (Real(0.1 + 0.2), Real(0.3));
+        assert_ne!(close.0, close.1);
+        assert!(close.0.approx_eq(&close.1));
+        let numbers: HashSet<_> = vec![Real(0.0), Real(-0.0), Real(f64::NAN), Real(-f64::NAN)]
+            .into_iter()
+            .collect();
+        assert_eq!(numbers.len(), 2);
+    }
+    #[test]
     fn test_number_e() {
         use super::node_to_cn;
         use super::MathNode::*;
         use super::NumType::*;
         let test = r#"<cn type="e-notation"> 2e-5 </cn>"#;
         let parsed = roxmltree::Document::parse(test).unwrap();
-        let ret = node_to_cn(parsed.root().first_child().unwrap());
+        let ret = node_to_cn(parsed.root().first_child().unwrap()).unwrap();
         let expected = Cn {
             num_type: ENotation(2.0, -5),
             base: 10,
             definition_url: None,
             encoding: None,
-            attributes: None,
+            units: None,
+            units_namespace: None,
+            attributes: Vec::new(),
         };
         assert_eq!(ret, expected);
-        let test = r#"<cn type="e-notation"> 2 <sep/> -5 </cn>"#;
+        let test = r#"<cn type="e-notation">
+                        2 <!-- mantissa --> <sep/>
+                        -5
+                      </cn>"#;
         let parsed = roxmltree::Document::parse(test).unwrap();
-        let ret = node_to_cn(parsed.root().first_child().unwrap());
+        let ret = node_to_cn(parsed.root().first_child().unwrap()).unwrap();
         assert_eq!(ret, expected);
+        for malformed in &[
+            r#"<cn type="e-notation">2e</cn>"#,
+            r#"<cn type="e-notation">2</cn>"#,
+            r#"<cn type="e-notation">2 <sep/> 5 <sep/> 3</cn>"#,
+            r#"<cn type="e-notation"><sep/></cn>"#,
+        ] {
+            let parsed = roxmltree::Document::parse(malformed).unwrap();
+            assert!(node_to_cn(parsed.root().first_child().unwrap()).is_err());
+        }
+    }
+    #[test]
+    fn test_big_integer() {
+        use super::node_to_cn;
+        let test = r#"<cn type="integer"> 1180591620717411303424 </cn>"#;
+        let parsed = roxmltree::Document::parse(test).unwrap();
+        let ret = node_to_cn(parsed.root().first_child().unwrap());
+        #[cfg(feature = "bigint")]
+        {
+            let ret = ret.unwrap();
+            assert_eq!(ret.to_string(), "1180591620717411303424");
+            approx::assert_relative_eq!(ret.eval(&Default::default()).unwrap(), 2f64.powi(70));
+            assert_eq!(crate::parse_document(&ret.to_mathml()).unwrap(), ret);
+        }
+        #[cfg(not(feature = "bigint"))]
+        assert!(ret.is_err());
+    }
+    #[test]
+    fn test_number_base() {
+        use super::node_to_cn;
+        use super::NumType::*;
+        use crate::error::ParseError;
+        use crate::MathNode;
+        let parse = |text: &str| {
+            let parsed = roxmltree::Document::parse(text).unwrap();
+            node_to_cn(parsed.root().first_child().unwrap())
+        };
+        let num_type = |node: MathNode| match node {
+            MathNode::Cn { num_type, .. } => num_type,
+            other => panic!("expected a number, got {:?}", other),
+        };
+        assert_eq!(
+            num_type(parse(r#"<cn base="16"> -A.8 </cn>"#).unwrap()),
+            Real(-10.5)
+        );
+        assert_eq!(
+            num_type(parse(r#"<cn type="rational" base="2">101<sep/>11</cn>"#).unwrap()),
+            Rational(5, 3)
+        );
+        assert!(matches!(
+            parse(r#"<cn base="99">1</cn>"#),
+            Err(ParseError::InvalidAttribute { value, .. }) if value == "99"
+        ));
+        assert!(matches!(
+            parse(r#"<cn base="8">9.1</cn>"#),
+            Err(ParseError::InvalidNumber { .. })
+        ));
+        let real = parse(r#"<cn base="3">12.1</cn>"#).unwrap();
+        assert_eq!(crate::parse_document(&real.to_mathml()).unwrap(), real);
     }
 }
@@ -0,0 +1,269 @@
+use super::{BuiltinOp, MathNode, NumType};
+use std::convert::TryFrom;
+use std::ops::{Add, Div, Mul, Sub};
+
+impl NumType {
+    /// The value as a float, `None` for complex numbers and unknown constants
+    pub fn to_f64(&self) -> Option<f64> {
+        crate::eval::number(self).ok()
+    }
+    /// The value as `(real, imaginary)` parts, `None` for unknown constants
+    pub fn to_complex(&self) -> Option<(f64, f64)> {
+        match self {
+            NumType::ComplexCartesian(re, im) => Some((*re, *im)),
+            NumType::ComplexPolar(r, theta) => Some((r * theta.cos(), r * theta.sin())),
+            other => other.to_f64().map(|re| (re, 0.0)),
+        }
+    }
+    /// The value as an integer, if it is one
+    fn to_i64(&self) -> Option<i64> {
+        match self {
+            NumType::Integer(i) => Some(*i),
+            NumType::Rational(a, b) if a.checked_rem(*b) == Some(0) => a.checked_div(*b),
+            _ => None,
+        }
+    }
+}
+
+impl From<i64> for NumType {
+    fn from(value: i64) -> Self {
+        NumType::Integer(value)
+    }
+}
+impl From<f64> for NumType {
+    fn from(value: f64) -> Self {
+        NumType::Real(value)
+    }
+}
+/// Fails on complex numbers and unknown constants, handing the value back
+impl TryFrom<NumType> for f64 {
+    type Error = NumType;
+    fn try_from(value: NumType) -> Result<Self, Self::Error> {
+        value.to_f64().ok_or(value)
+    }
+}
+/// Succeeds for integers and whole rationals, handing anything else back
+impl TryFrom<NumType> for i64 {
+    type Error = NumType;
+    fn try_from(value: NumType) -> Result<Self, Self::Error> {
+        value.to_i64().ok_or(value)
+    }
+}
+
+/// How far up Integer -> Rational -> Real -> Complex an operand needs to go
+#[derive(PartialEq, PartialOrd, Clone, Copy)]
+enum Level {
+    Integer,
+    Rational,
+    Real,
+    Complex,
+}
+
+fn level(value: &NumType) -> Level {
+    match value {
+        NumType::Integer(_) => Level::Integer,
+        NumType::Rational(_, _) => Level::Rational,
+        NumType::ComplexCartesian(_, _) | NumType::ComplexPolar(_, _) => Level::Complex,
+        _ => Level::Real,
+    }
+}
+
+/// `None` when it is 2^63, which `i64` can't hold
+fn gcd(a: i64, b: i64) -> Option<i64> {
+    if b == 0 {
+        a.checked_abs()
+    } else {
+        // `i64::MIN % -1` overflows, though the remainder is 0
+        gcd(b, a.wrapping_rem(b))
+    }
+}
+
+/// A rational in lowest terms with a positive denominator, `None` on overflow
+fn rational(numerator: Option<i64>, denominator: Option<i64>) -> Option<NumType> {
+    let (a, b) = (numerator?, denominator?);
+    if b == 0 {
+        return None;
+    }
+    let divisor = gcd(a, b)?.checked_mul(b.signum())?;
+    Some(NumType::Rational(
+        a.checked_div(divisor)?,
+        b.checked_div(divisor)?,
+    ))
+}
+
+fn parts(value: &NumType) -> (i64, i64) {
+    match value {
+        NumType::Integer(i) => (*i, 1),
+        NumType::Rational(a, b) => (*a, *b),
+        _ => unreachable!("only called on integers and rationals"),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+fn integer(op: Op, a: i64, b: i64) -> Option<NumType> {
+    match op {
+        Op::Add => a.checked_add(b).map(NumType::Integer),
+        Op::Sub => a.checked_sub(b).map(NumType::Integer),
+        Op::Mul => a.checked_mul(b).map(NumType::Integer),
+        Op::Div => rational(Some(a), Some(b)),
+    }
+}
+
+fn fraction(op: Op, (a, b): (i64, i64), (c, d): (i64, i64)) -> Option<NumType> {
+    match op {
+        Op::Add | Op::Sub => {
+            let (ad, cb) = (a.checked_mul(d)?, c.checked_mul(b)?);
+            let numerator = match op {
+                Op::Add => ad.checked_add(cb),
+                _ => ad.checked_sub(cb),
+            };
+            rational(numerator, b.checked_mul(d))
+        }
+        Op::Mul => rational(a.checked_mul(c), b.checked_mul(d)),
+        Op::Div => rational(a.checked_mul(d), b.checked_mul(c)),
+    }
+}
+
+fn real(op: Op, a: f64, b: f64) -> f64 {
+    match op {
+        Op::Add => a + b,
+        Op::Sub => a - b,
+        Op::Mul => a * b,
+        Op::Div => a / b,
+    }
+}
+
+fn complex(op: Op, (a, b): (f64, f64), (c, d): (f64, f64)) -> NumType {
+    let (re, im) = match op {
+        Op::Add => (a + c, b + d),
+        Op::Sub => (a - c, b - d),
+        Op::Mul => (a * c - b * d, a * d + b * c),
+        Op::Div => {
+            let norm = c * c + d * d;
+            ((a * c + b * d) / norm, (b * c - a * d) / norm)
+        }
+    };
+    NumType::ComplexCartesian(re, im)
+}
+
+/// Promotes both operands to the higher of their levels, falling back to
+/// reals when integer arithmetic overflows or divides by zero
+fn arithmetic(op: Op, lhs: &NumType, rhs: &NumType) -> NumType {
+    let top = if level(lhs) > level(rhs) {
+        level(lhs)
+    } else {
+        level(rhs)
+    };
+    let exact = match top {
+        Level::Integer => integer(op, parts(lhs).0, parts(rhs).0),
+        Level::Rational => fraction(op, parts(lhs), parts(rhs)),
+        _ => None,
+    };
+    if let Some(exact) = exact {
+        return exact;
+    }
+    if top == Level::Complex {
+        let nan = (f64::NAN, f64::NAN);
+        return complex(
+            op,
+            lhs.to_complex().unwrap_or(nan),
+            rhs.to_complex().unwrap_or(nan),
+        );
+    }
+    NumType::Real(real(
+        op,
+        lhs.to_f64().unwrap_or(f64::NAN),
+        rhs.to_f64().unwrap_or(f64::NAN),
+    ))
+}
+
+macro_rules! arithmetic_op {
+    ($trait:ident, $method:ident, $op:ident) => {
+        impl $trait for NumType {
+            type Output = NumType;
+            fn $method(self, rhs: NumType) -> NumType {
+                arithmetic(Op::$op, &self, &rhs)
+            }
+        }
+        impl<'a> $trait<&'a NumType> for &'a NumType {
+            type Output = NumType;
+            fn $method(self, rhs: &NumType) -> NumType {
+                arithmetic(Op::$op, self, rhs)
+            }
+        }
+    };
+}
+arithmetic_op!(Add, add, Add);
+arithmetic_op!(Sub, sub, Sub);
+arithmetic_op!(Mul, mul, Mul);
+arithmetic_op!(Div, div, Div);
+
+/// `x + y` or `x * y` of two numbers, which unlike `simplify` gives up on
+/// exactness rather than leave a sum too large for an integer unevaluated
+pub(crate) fn fold_numbers(op: BuiltinOp, x: &MathNode, y: &MathNode) -> Option<MathNode> {
+    let number = |node: &MathNode| match node {
+        MathNode::Cn { num_type, .. }
+            if matches!(
+                num_type,
+                NumType::Integer(_) | NumType::Rational(_, _) | NumType::Real(_)
+            ) =>
+        {
+            Some(num_type.clone())
+        }
+        _ => None,
+    };
+    let (a, b) = (number(x)?, number(y)?);
+    Some(MathNode::number(match op {
+        BuiltinOp::plus => a + b,
+        _ => a * b,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::NumType::{self, *};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(Integer(2) + Integer(3), Integer(5));
+        assert_eq!(Integer(6) / Integer(4), Rational(3, 2));
+        assert_eq!(Rational(1, 2) - Integer(1), Rational(-1, 2));
+        assert_eq!(Rational(1, 3) * Real(1.5), Real(0.5));
+        assert_eq!(Integer(i64::MAX) + Integer(1), Real(i64::MAX as f64 + 1.0));
+        assert_eq!((Integer(1) / Integer(0)).to_f64(), Some(f64::INFINITY));
+        assert_eq!(Integer(i64::MIN) / Integer(-1), Real(-(i64::MIN as f64)));
+        assert_eq!(Integer(i64::MIN) / Integer(i64::MIN), Real(1.0));
+        assert_eq!(
+            &ComplexCartesian(1.0, 2.0) * &Integer(2),
+            ComplexCartesian(2.0, 4.0)
+        );
+        assert_eq!(
+            ComplexPolar(2.0, std::f64::consts::FRAC_PI_2).to_complex(),
+            Some((2.0 * std::f64::consts::FRAC_PI_2.cos(), 2.0))
+        );
+    }
+    #[test]
+    fn test_conversions() {
+        assert_eq!(ENotation(1.5, 2).to_f64(), Some(150.0));
+        assert_eq!(f64::try_from(Rational(1, 4)), Ok(0.25));
+        assert_eq!(i64::try_from(Rational(8, 4)), Ok(2));
+        assert_eq!(i64::try_from(Real(2.0)), Err(Real(2.0)));
+        assert_eq!(
+            i64::try_from(Rational(i64::MIN, -1)),
+            Err(Rational(i64::MIN, -1))
+        );
+        assert_eq!(
+            f64::try_from(ComplexCartesian(0.0, 1.0)),
+            Err(ComplexCartesian(0.0, 1.0))
+        );
+        assert_eq!(NumType::from(3), Integer(3));
+    }
+}
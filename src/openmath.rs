@@ -0,0 +1,986 @@
+use super::{BuiltinOp, ConstantSymbol, IntervalClosure, MathNode, NamedConstant, NumType};
+use crate::error::ParseError;
+use crate::numbers::NumberSet;
+use crate::sbml::SbmlSymbol;
+use crate::strict::{decode_base64, encode_base64};
+use crate::writer::escape;
+use roxmltree::Node;
+use std::convert::TryFrom;
+
+const OPENMATH_NS: &str = "http://www.openmath.org/OpenMath";
+
+/// The content dictionary and name of the OpenMath symbol for an operator,
+/// as in the strict Content MathML of MathML 3. `None` for those without one
+fn symbol(op: BuiltinOp) -> Option<(&'static str, &'static str)> {
+    use BuiltinOp::*;
+    let symbol = match op {
+        abs => ("arith1", "abs"),
+        divide => ("arith1", "divide"),
+        gcd => ("arith1", "gcd"),
+        lcm => ("arith1", "lcm"),
+        minus => ("arith1", "minus"),
+        plus => ("arith1", "plus"),
+        power => ("arith1", "power"),
+        product => ("arith1", "product"),
+        root => ("arith1", "root"),
+        sum => ("arith1", "sum"),
+        times => ("arith1", "times"),
+        factorial => ("integer1", "factorial"),
+        quotient => ("integer1", "quotient"),
+        rem => ("integer1", "remainder"),
+        factorof => ("integer1", "factorof"),
+        conjugate => ("complex1", "conjugate"),
+        arg => ("complex1", "argument"),
+        real => ("complex1", "real"),
+        imaginary => ("complex1", "imaginary"),
+        floor => ("rounding1", "floor"),
+        ceiling => ("rounding1", "ceiling"),
+        not => ("logic1", "not"),
+        and => ("logic1", "and"),
+        or => ("logic1", "or"),
+        xor => ("logic1", "xor"),
+        implies => ("logic1", "implies"),
+        equivalent => ("logic1", "equivalent"),
+        inverse => ("fns1", "inverse"),
+        ident => ("fns1", "identity"),
+        domain => ("fns1", "domain"),
+        codomain => ("fns1", "range"),
+        image => ("fns1", "image"),
+        compose => ("fns1", "left_compose"),
+        sin => ("transc1", "sin"),
+        cos => ("transc1", "cos"),
+        tan => ("transc1", "tan"),
+        sec => ("transc1", "sec"),
+        csc => ("transc1", "csc"),
+        cot => ("transc1", "cot"),
+        sinh => ("transc1", "sinh"),
+        cosh => ("transc1", "cosh"),
+        tanh => ("transc1", "tanh"),
+        sech => ("transc1", "sech"),
+        csch => ("transc1", "csch"),
+        coth => ("transc1", "coth"),
+        arcsin => ("transc1", "arcsin"),
+        arccos => ("transc1", "arccos"),
+        arctan => ("transc1", "arctan"),
+        arcsec => ("transc1", "arcsec"),
+        arccsc => ("transc1", "arccsc"),
+        arccot => ("transc1", "arccot"),
+        arcsinh => ("transc1", "arcsinh"),
+        arccosh => ("transc1", "arccosh"),
+        arctanh => ("transc1", "arctanh"),
+        arcsech => ("transc1", "arcsech"),
+        arccsch => ("transc1", "arccsch"),
+        arccoth => ("transc1", "arccoth"),
+        exp => ("transc1", "exp"),
+        ln => ("transc1", "ln"),
+        log => ("transc1", "log"),
+        determinant => ("linalg1", "determinant"),
+        transpose => ("linalg1", "transpose"),
+        vectorproduct => ("linalg1", "vectorproduct"),
+        scalarproduct => ("linalg1", "scalarproduct"),
+        outerproduct => ("linalg1", "outerproduct"),
+        divergence => ("veccalc1", "divergence"),
+        grad => ("veccalc1", "grad"),
+        curl => ("veccalc1", "curl"),
+        laplacian => ("veccalc1", "Laplacian"),
+        card => ("set1", "size"),
+        setdiff => ("set1", "setdiff"),
+        union => ("set1", "union"),
+        intersect => ("set1", "intersect"),
+        cartesianproduct => ("set1", "cartesian_product"),
+        r#in => ("set1", "in"),
+        notin => ("set1", "notin"),
+        subset => ("set1", "subset"),
+        prsubset => ("set1", "prsubset"),
+        notsubset => ("set1", "notsubset"),
+        notprsubset => ("set1", "notprsubset"),
+        eq => ("relation1", "eq"),
+        neq => ("relation1", "neq"),
+        gt => ("relation1", "gt"),
+        lt => ("relation1", "lt"),
+        geq => ("relation1", "geq"),
+        leq => ("relation1", "leq"),
+        approx => ("relation1", "approx"),
+        max => ("minmax1", "max"),
+        min => ("minmax1", "min"),
+        mean => ("s_data1", "mean"),
+        sdev => ("s_data1", "sdev"),
+        variance => ("s_data1", "variance"),
+        median => ("s_data1", "median"),
+        mode => ("s_data1", "mode"),
+        int => ("calculus1", "int"),
+        diff => ("calculus1", "diff"),
+        partialdiff => ("calculus1", "partialdiff"),
+        forall => ("quant1", "forall"),
+        exists => ("quant1", "exists"),
+        selector | r#fn | tendsto => return None,
+    };
+    Some(symbol)
+}
+
+fn oms(out: &mut String, cd: &str, name: &str) {
+    out.push_str(&format!(
+        "<OMS cd=\"{}\" name=\"{}\"/>",
+        escape(cd),
+        escape(name)
+    ));
+}
+
+fn omf(out: &mut String, value: f64) {
+    let dec = match value {
+        v if v == f64::INFINITY => "INF".to_owned(),
+        v if v == f64::NEG_INFINITY => "-INF".to_owned(),
+        v => format!("{:?}", v),
+    };
+    out.push_str(&format!("<OMF dec=\"{}\"/>", dec));
+}
+
+fn omv(out: &mut String, name: &str) {
+    out.push_str(&format!("<OMV name=\"{}\"/>", escape(name.trim())));
+}
+
+/// `<OMA>` of the symbol `cd`/`name` to `args`
+fn oma<'a>(out: &mut String, cd: &str, name: &str, args: impl IntoIterator<Item = &'a MathNode>) {
+    out.push_str("<OMA>");
+    oms(out, cd, name);
+    for arg in args {
+        write_object(arg, out);
+    }
+    out.push_str("</OMA>");
+}
+
+/// `<OMBIND>` of `fns1`'s `lambda`, binding `variables` in `body`
+fn lambda<'a>(out: &mut String, variables: impl IntoIterator<Item = &'a str>, body: &MathNode) {
+    out.push_str("<OMBIND>");
+    oms(out, "fns1", "lambda");
+    out.push_str("<OMBVAR>");
+    for variable in variables {
+        omv(out, variable);
+    }
+    out.push_str("</OMBVAR>");
+    write_object(body, out);
+    out.push_str("</OMBIND>");
+}
+
+/// An object OpenMath can't express, as an `<OME>` holding its MathML
+fn encoding_error(out: &mut String, node: &MathNode) {
+    out.push_str("<OME>");
+    oms(out, "moreerrors", "encodingError");
+    out.push_str(&format!(
+        "<OMSTR>{}</OMSTR></OME>",
+        escape(&node.to_mathml())
+    ));
+}
+
+fn write_number(num_type: &NumType, out: &mut String) {
+    match num_type {
+        NumType::Integer(i) => out.push_str(&format!("<OMI>{}</OMI>", i)),
+        #[cfg(feature = "bigint")]
+        NumType::BigInteger(i) => out.push_str(&format!("<OMI>{}</OMI>", i)),
+        NumType::Real(r) => omf(out, *r),
+        NumType::ENotation(mantissa, exponent) => {
+            out.push_str(&format!("<OMF dec=\"{:?}e{}\"/>", mantissa, exponent))
+        }
+        NumType::Rational(numerator, denominator) => {
+            out.push_str("<OMA>");
+            oms(out, "nums1", "rational");
+            out.push_str(&format!(
+                "<OMI>{}</OMI><OMI>{}</OMI></OMA>",
+                numerator, denominator
+            ));
+        }
+        NumType::ComplexCartesian(a, b) | NumType::ComplexPolar(a, b) => {
+            out.push_str("<OMA>");
+            match num_type {
+                NumType::ComplexPolar(..) => oms(out, "complex1", "complex_polar"),
+                _ => oms(out, "complex1", "complex_cartesian"),
+            }
+            omf(out, *a);
+            omf(out, *b);
+            out.push_str("</OMA>");
+        }
+        NumType::Constant(NamedConstant::Pi) => oms(out, "nums1", "pi"),
+        NumType::Constant(NamedConstant::E) => oms(out, "nums1", "e"),
+        NumType::Constant(NamedConstant::Gamma) => oms(out, "nums1", "gamma"),
+        NumType::Constant(constant) => match constant.value() {
+            Some(value) => omf(out, value),
+            None => omv(out, constant.symbol()),
+        },
+        NumType::Other { text, .. } => match text.trim().parse() {
+            Ok(value) => omf(out, value),
+            Err(_) => out.push_str(&format!("<OMSTR>{}</OMSTR>", escape(text))),
+        },
+    }
+}
+
+/// Writes an `<apply>`, binding its `<bvar>`s where the operator does
+fn write_apply(node: &MathNode, head: &MathNode, out: &mut String) {
+    let op = match head {
+        MathNode::Op(op) => *op,
+        MathNode::Ci { children, .. } => {
+            out.push_str("<OMA>");
+            omv(out, &crate::text_content(children));
+            for operand in node.operands() {
+                write_object(operand, out);
+            }
+            out.push_str("</OMA>");
+            return;
+        }
+        _ => {
+            out.push_str("<OMA>");
+            write_object(head, out);
+            for operand in node.operands() {
+                write_object(operand, out);
+            }
+            out.push_str("</OMA>");
+            return;
+        }
+    };
+    let (cd, name) = match symbol(op) {
+        Some(symbol) => symbol,
+        None => return encoding_error(out, node),
+    };
+    let operands = node.operands();
+    let bvars = node.bound_variables();
+    match op {
+        BuiltinOp::minus if operands.len() == 1 => oma(out, cd, "unary_minus", operands),
+        // Both take the base first, which defaults to 10 for a logarithm and
+        // 2 for a root
+        BuiltinOp::log | BuiltinOp::root => {
+            let default = MathNode::integer(if op == BuiltinOp::log { 10 } else { 2 });
+            let other = node.logbase().or_else(|| node.degree()).unwrap_or(&default);
+            match op {
+                BuiltinOp::log => oma(out, cd, name, std::iter::once(other).chain(operands)),
+                _ => oma(out, cd, name, operands.into_iter().chain(Some(other))),
+            }
+        }
+        BuiltinOp::int | BuiltinOp::sum | BuiltinOp::product if bvars.len() == 1 => {
+            let body = operands.first().copied().unwrap_or(node);
+            let (name, interval) = match op {
+                BuiltinOp::int if node.limits().is_some() => ("defint", "interval"),
+                BuiltinOp::int => ("indefint", "interval"),
+                _ => (name, "integer_interval"),
+            };
+            out.push_str("<OMA>");
+            oms(out, cd, name);
+            if let Some((low, high)) = node.limits() {
+                oma(out, "interval1", interval, vec![low, high]);
+            }
+            lambda(out, bvars, body);
+            out.push_str("</OMA>");
+        }
+        BuiltinOp::diff if bvars.len() == 1 => {
+            let body = operands.first().copied().unwrap_or(node);
+            out.push_str("<OMA>");
+            match node.bvars()[0].1 {
+                Some(degree) => {
+                    oms(out, cd, "nthdiff");
+                    write_object(degree, out);
+                }
+                None => oms(out, cd, name),
+            }
+            lambda(out, bvars, body);
+            out.push_str("</OMA>");
+        }
+        // Differentiates the function of all bound variables by the position
+        // of each variable, as many times as its degree
+        BuiltinOp::partialdiff if !bvars.is_empty() => {
+            let body = operands.first().copied().unwrap_or(node);
+            let mut indices = Vec::new();
+            for (i, (_, degree)) in node.bvars().into_iter().enumerate() {
+                let times = match degree {
+                    Some(MathNode::Cn {
+                        num_type: NumType::Integer(n),
+                        ..
+                    }) => *n,
+                    _ => 1,
+                };
+                for _ in 0..times {
+                    indices.push(MathNode::integer(i as i64 + 1));
+                }
+            }
+            out.push_str("<OMA>");
+            oms(out, cd, name);
+            oma(out, "list1", "list", &indices);
+            lambda(out, bvars, body);
+            out.push_str("</OMA>");
+        }
+        _ if !bvars.is_empty() => {
+            out.push_str("<OMBIND>");
+            oms(out, cd, name);
+            out.push_str("<OMBVAR>");
+            for variable in &bvars {
+                omv(out, variable);
+            }
+            out.push_str("</OMBVAR>");
+            // A quantifier's condition restricts what it ranges over
+            let connective = match op {
+                BuiltinOp::forall => "implies",
+                _ => "and",
+            };
+            match (node.condition(), operands.first().copied()) {
+                (Some(condition), Some(body)) => {
+                    oma(out, "logic1", connective, vec![condition, body])
+                }
+                (Some(only), None) | (None, Some(only)) => write_object(only, out),
+                (None, None) => {}
+            }
+            out.push_str("</OMBIND>");
+        }
+        _ => oma(out, cd, name, operands),
+    }
+}
+
+fn write_object(node: &MathNode, out: &mut String) {
+    match node {
+        MathNode::Root { children, .. } => {
+            let objects: Vec<_> = children.iter().filter(|c| !c.is_markup()).collect();
+            match objects.as_slice() {
+                [object] => write_object(object, out),
+                objects => oma(out, "list1", "list", objects.iter().copied()),
+            }
+        }
+        MathNode::Apply { children, .. } => match children.iter().find(|c| !c.is_markup()) {
+            Some(head) => write_apply(node, head, out),
+            None => encoding_error(out, node),
+        },
+        MathNode::Op(op) => match symbol(*op) {
+            Some((cd, name)) => oms(out, cd, name),
+            None => encoding_error(out, node),
+        },
+        MathNode::Constant(constant) => {
+            let (cd, name) = match constant {
+                ConstantSymbol::Pi => ("nums1", "pi"),
+                ConstantSymbol::ExponentialE => ("nums1", "e"),
+                ConstantSymbol::ImaginaryI => ("nums1", "i"),
+                ConstantSymbol::EulerGamma => ("nums1", "gamma"),
+                ConstantSymbol::Infinity => ("nums1", "infinity"),
+                ConstantSymbol::NotANumber => ("nums1", "NaN"),
+                ConstantSymbol::True => ("logic1", "true"),
+                ConstantSymbol::False => ("logic1", "false"),
+            };
+            oms(out, cd, name)
+        }
+        MathNode::NumberSet(set) => {
+            let (cd, name) = match set {
+                NumberSet::Integers => ("setname1", "Z"),
+                NumberSet::Reals => ("setname1", "R"),
+                NumberSet::Rationals => ("setname1", "Q"),
+                NumberSet::NaturalNumbers => ("setname1", "N"),
+                NumberSet::Complexes => ("setname1", "C"),
+                NumberSet::Primes => ("setname1", "P"),
+                NumberSet::EmptySet => ("set1", "emptyset"),
+            };
+            oms(out, cd, name)
+        }
+        MathNode::Text(text) | MathNode::Cs(text) => {
+            out.push_str(&format!("<OMSTR>{}</OMSTR>", escape(text)))
+        }
+        MathNode::Cbytes(bytes) => out.push_str(&format!("<OMB>{}</OMB>", encode_base64(bytes))),
+        MathNode::Cerror(children) => {
+            out.push_str("<OME>");
+            for child in children {
+                write_object(child, out);
+            }
+            out.push_str("</OME>");
+        }
+        MathNode::Share(href) => out.push_str(&format!("<OMR href=\"{}\"/>", escape(href))),
+        MathNode::Ci { children, .. } => omv(out, &crate::text_content(children)),
+        MathNode::Bvar { name, .. } => omv(out, name),
+        // A symbol of an unknown dictionary is split from its URL, as in
+        // `http://www.sbml.org/sbml/symbols/time`
+        MathNode::Csymbol {
+            definition_url,
+            cd,
+            name,
+            ..
+        } => match (cd, definition_url) {
+            (Some(cd), _) => oms(out, cd, name.trim()),
+            (None, Some(url)) => {
+                let url = url.trim_end_matches('/');
+                let (rest, symbol) = url.rsplit_once('/').unwrap_or(("", url));
+                let (base, cd) = rest.rsplit_once('/').unwrap_or(("", rest));
+                out.push_str(&format!(
+                    "<OMS cdbase=\"{}\" cd=\"{}\" name=\"{}\"/>",
+                    escape(base),
+                    escape(cd),
+                    escape(symbol)
+                ));
+            }
+            (None, None) => omv(out, name),
+        },
+        MathNode::Cn { num_type, .. } => write_number(num_type, out),
+        MathNode::Piecewise { pieces, otherwise } => {
+            out.push_str("<OMA>");
+            oms(out, "piece1", "piecewise");
+            for (value, condition) in pieces {
+                oma(out, "piece1", "piece", vec![value, condition]);
+            }
+            if let Some(otherwise) = otherwise {
+                oma(out, "piece1", "otherwise", Some(&**otherwise));
+            }
+            out.push_str("</OMA>");
+        }
+        MathNode::Lambda { bound_vars, body } => {
+            lambda(out, bound_vars.iter().map(String::as_str), body)
+        }
+        // Operators bind as their `<apply>` does, other binders directly
+        MathNode::Bind {
+            binder,
+            bound_vars,
+            condition,
+            body,
+        } if !matches!(**binder, MathNode::Op(_)) => {
+            out.push_str("<OMBIND>");
+            write_object(binder, out);
+            out.push_str("<OMBVAR>");
+            for variable in bound_vars {
+                omv(out, variable);
+            }
+            out.push_str("</OMBVAR>");
+            match condition {
+                Some(condition) => oma(out, "logic1", "and", vec![&**condition, &**body]),
+                None => write_object(body, out),
+            }
+            out.push_str("</OMBIND>");
+        }
+        MathNode::Bind { .. } => write_object(&node.to_apply(), out),
+        MathNode::Degree(inner)
+        | MathNode::Logbase(inner)
+        | MathNode::Lowlimit(inner)
+        | MathNode::Uplimit(inner)
+        | MathNode::Condition(inner) => write_object(inner, out),
+        MathNode::Interval { closure, low, high } => {
+            let name = match closure {
+                IntervalClosure::Closed => "interval_cc",
+                IntervalClosure::Open => "interval_oo",
+                IntervalClosure::OpenClosed => "interval_oc",
+                IntervalClosure::ClosedOpen => "interval_co",
+            };
+            oma(out, "interval1", name, vec![&**low, &**high])
+        }
+        MathNode::Matrix(rows) => {
+            out.push_str("<OMA>");
+            oms(out, "linalg2", "matrix");
+            for row in rows {
+                oma(out, "linalg2", "matrixrow", row);
+            }
+            out.push_str("</OMA>");
+        }
+        MathNode::Vector(children) => oma(out, "linalg2", "vector", children),
+        MathNode::Set(children) => oma(out, "set1", "set", children),
+        MathNode::List { items, .. } => oma(out, "list1", "list", items),
+        MathNode::Semantics { primary, .. } => write_object(primary, out),
+        MathNode::Comment(_) | MathNode::PI(_, _) => {}
+        MathNode::Error { .. } | MathNode::Unknown { .. } | MathNode::Presentation(_) => {
+            encoding_error(out, node)
+        }
+    }
+}
+
+/// The operator of an OpenMath symbol, the inverse of `symbol`
+fn builtin(cd: &str, name: &str) -> Option<BuiltinOp> {
+    let mathml = match name {
+        "unary_minus" if cd == "arith1" => return Some(BuiltinOp::minus),
+        "remainder" => "rem",
+        "argument" => "arg",
+        "identity" => "ident",
+        "range" => "codomain",
+        "left_compose" => "compose",
+        "size" => "card",
+        "cartesian_product" => "cartesianproduct",
+        "Laplacian" => "laplacian",
+        name => name,
+    };
+    let op = serde_plain::from_str(mathml).ok()?;
+    if symbol(op) == Some((cd, name)) {
+        Some(op)
+    } else {
+        None
+    }
+}
+
+pub(crate) fn attribute(node: Node, attribute: &str) -> Result<String, ParseError> {
+    match node.attribute(attribute) {
+        Some(value) => Ok(value.trim().to_owned()),
+        None => Err(ParseError::MissingAttribute {
+            element: node.tag_name().name().to_owned(),
+            attribute: attribute.to_owned(),
+        }),
+    }
+}
+
+pub(crate) fn structure<T>(message: &str) -> Result<T, ParseError> {
+    Err(ParseError::InvalidStructure(message.to_owned()))
+}
+
+fn parse_integer(text: &str) -> Result<MathNode, ParseError> {
+    let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    // Hexadecimal integers are written as `x1F` or `-x1F`
+    let parsed = match text.strip_prefix('-').unwrap_or(&text).strip_prefix('x') {
+        Some(digits) => i64::from_str_radix(digits, 16).map(|i| match text.starts_with('-') {
+            true => -i,
+            false => i,
+        }),
+        None => text.parse(),
+    };
+    if let Ok(integer) = parsed {
+        return Ok(MathNode::integer(integer));
+    }
+    #[cfg(feature = "bigint")]
+    if let Ok(integer) = text.parse() {
+        return Ok(MathNode::number(NumType::BigInteger(integer)));
+    }
+    Err(ParseError::InvalidNumber {
+        num_type: "integer".to_owned(),
+        text,
+    })
+}
+
+fn parse_float(node: Node) -> Result<MathNode, ParseError> {
+    let invalid = |text: &str| ParseError::InvalidNumber {
+        num_type: "real".to_owned(),
+        text: text.to_owned(),
+    };
+    let value = match (node.attribute("dec"), node.attribute("hex")) {
+        (None, Some(hex)) => {
+            f64::from_bits(u64::from_str_radix(hex.trim(), 16).map_err(|_| invalid(hex))?)
+        }
+        _ => {
+            let dec = attribute(node, "dec")?;
+            dec.parse().map_err(|_| invalid(&dec))?
+        }
+    };
+    Ok(MathNode::cn(value))
+}
+
+/// A symbol on its own, as an operator, constant, number set or otherwise a
+/// `<csymbol>`
+fn parse_symbol(node: Node) -> Result<MathNode, ParseError> {
+    let cd = attribute(node, "cd")?;
+    let name = attribute(node, "name")?;
+    if let Some(op) = builtin(&cd, &name) {
+        return Ok(MathNode::Op(op));
+    }
+    let constant = match (cd.as_str(), name.as_str()) {
+        ("nums1", "pi") => Some(ConstantSymbol::Pi),
+        ("nums1", "e") => Some(ConstantSymbol::ExponentialE),
+        ("nums1", "i") => Some(ConstantSymbol::ImaginaryI),
+        ("nums1", "gamma") => Some(ConstantSymbol::EulerGamma),
+        ("nums1", "infinity") => Some(ConstantSymbol::Infinity),
+        ("nums1", "NaN") => Some(ConstantSymbol::NotANumber),
+        ("logic1", "true") => Some(ConstantSymbol::True),
+        ("logic1", "false") => Some(ConstantSymbol::False),
+        _ => None,
+    };
+    if let Some(constant) = constant {
+        return Ok(MathNode::Constant(constant));
+    }
+    let set = match (cd.as_str(), name.as_str()) {
+        ("setname1", "Z") => Some(NumberSet::Integers),
+        ("setname1", "R") => Some(NumberSet::Reals),
+        ("setname1", "Q") => Some(NumberSet::Rationals),
+        ("setname1", "N") => Some(NumberSet::NaturalNumbers),
+        ("setname1", "C") => Some(NumberSet::Complexes),
+        ("setname1", "P") => Some(NumberSet::Primes),
+        ("set1", "emptyset") => Some(NumberSet::EmptySet),
+        _ => None,
+    };
+    if let Some(set) = set {
+        return Ok(MathNode::NumberSet(set));
+    }
+    // A symbol with a `cdbase` is identified by its URL, as SBML's are
+    let (definition_url, cd) = match node.attribute("cdbase") {
+        Some(base) => (Some(format!("{}/{}/{}", base.trim(), cd, name)), None),
+        None => (None, Some(cd)),
+    };
+    Ok(MathNode::Csymbol {
+        sbml: definition_url.as_deref().and_then(SbmlSymbol::from_url),
+        definition_url,
+        cd,
+        encoding: None,
+        name,
+        attributes: Vec::new(),
+    })
+}
+
+pub(crate) fn elements<'a, 'input>(
+    node: Node<'a, 'input>,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(|c| c.is_element())
+}
+
+/// The `<OMS>` at the head of an `<OMA>`, if it is one
+fn head_symbol(node: Node) -> Option<(String, String)> {
+    let head = elements(node).next()?;
+    match head.tag_name().name() {
+        "OMS" => Some((
+            head.attribute("cd")?.trim().to_owned(),
+            head.attribute("name")?.trim().to_owned(),
+        )),
+        _ => None,
+    }
+}
+
+/// The bound variables and body of a `fns1` `lambda`
+fn parse_lambda(node: MathNode) -> Result<(Vec<String>, MathNode), ParseError> {
+    match node {
+        MathNode::Lambda { bound_vars, body } => Ok((bound_vars, *body)),
+        _ => structure("expected a lambda binding the variable of a sum, product or derivative"),
+    }
+}
+
+/// An operator binding the variables of `function`, as `int` of `lambda`
+fn bind(
+    op: BuiltinOp,
+    function: MathNode,
+    degrees: Vec<Option<MathNode>>,
+    limits: Option<MathNode>,
+) -> Result<MathNode, ParseError> {
+    let (bound_vars, body) = parse_lambda(function)?;
+    let mut children = vec![MathNode::Op(op)];
+    for (i, name) in bound_vars.into_iter().enumerate() {
+        let degree = degrees.get(i).cloned().flatten().map(Box::new);
+        children.push(MathNode::Bvar { name, degree });
+    }
+    match limits {
+        Some(MathNode::Interval { low, high, .. }) => {
+            children.push(MathNode::Lowlimit(low));
+            children.push(MathNode::Uplimit(high));
+        }
+        Some(_) => return structure("expected an interval as the range of a sum or integral"),
+        None => {}
+    }
+    children.push(body);
+    Ok(MathNode::Apply {
+        children,
+        attributes: Vec::new(),
+    })
+}
+
+fn parse_application(node: Node) -> Result<MathNode, ParseError> {
+    let mut args = elements(node)
+        .map(parse_object)
+        .collect::<Result<Vec<_>, _>>()?;
+    if args.is_empty() {
+        return structure("<OMA> is missing its head");
+    }
+    let head = args.remove(0);
+    let (cd, name) = head_symbol(node).unwrap_or_default();
+    let interval = |closure| match <[MathNode; 2]>::try_from(args.clone()) {
+        Ok([low, high]) => Ok(MathNode::Interval {
+            closure,
+            low: Box::new(low),
+            high: Box::new(high),
+        }),
+        Err(_) => structure("an interval has a lower and an upper bound"),
+    };
+    let number = |arg: &MathNode| match arg {
+        MathNode::Cn {
+            num_type: NumType::Integer(i),
+            ..
+        } => Some(*i as f64),
+        MathNode::Cn {
+            num_type: NumType::Real(r),
+            ..
+        } => Some(*r),
+        _ => None,
+    };
+    let node = match (cd.as_str(), name.as_str(), args.as_slice()) {
+        // A logarithm of base 10 and a square root leave out the qualifier
+        ("transc1", "log", [base, x]) => {
+            let mut operands = vec![x.clone()];
+            if *base != MathNode::integer(10) {
+                operands.insert(0, MathNode::Logbase(Box::new(base.clone())));
+            }
+            MathNode::apply(BuiltinOp::log, operands)
+        }
+        ("arith1", "root", [x, degree]) => {
+            let mut operands = vec![x.clone()];
+            if *degree != MathNode::integer(2) {
+                operands.insert(0, MathNode::Degree(Box::new(degree.clone())));
+            }
+            MathNode::apply(BuiltinOp::root, operands)
+        }
+        (
+            "nums1",
+            "rational",
+            [MathNode::Cn {
+                num_type: NumType::Integer(numerator),
+                ..
+            }, MathNode::Cn {
+                num_type: NumType::Integer(denominator),
+                ..
+            }],
+        ) => MathNode::rational(*numerator, *denominator),
+        ("complex1", "complex_cartesian", [a, b]) | ("complex1", "complex_polar", [a, b])
+            if number(a).is_some() && number(b).is_some() =>
+        {
+            let (a, b) = (number(a).unwrap_or_default(), number(b).unwrap_or_default());
+            MathNode::number(match name.as_str() {
+                "complex_polar" => NumType::ComplexPolar(a, b),
+                _ => NumType::ComplexCartesian(a, b),
+            })
+        }
+        ("interval1", "interval", _)
+        | ("interval1", "interval_cc", _)
+        | ("interval1", "integer_interval", _) => interval(IntervalClosure::Closed)?,
+        ("interval1", "interval_oo", _) => interval(IntervalClosure::Open)?,
+        ("interval1", "interval_oc", _) => interval(IntervalClosure::OpenClosed)?,
+        ("interval1", "interval_co", _) => interval(IntervalClosure::ClosedOpen)?,
+        ("piece1", "piece", _) | ("piece1", "otherwise", _) => MathNode::call(head, args),
+        ("piece1", "piecewise", _) => {
+            let mut pieces = Vec::new();
+            let mut otherwise = None;
+            for piece in elements(node).skip(1) {
+                let piece_args = elements(piece)
+                    .skip(1)
+                    .map(parse_object)
+                    .collect::<Result<Vec<_>, _>>()?;
+                match (head_symbol(piece), <[MathNode; 2]>::try_from(piece_args)) {
+                    (Some((_, name)), Ok([value, condition])) if name == "piece" => {
+                        pieces.push((value, condition))
+                    }
+                    (Some((_, name)), Err(mut value))
+                        if name == "otherwise" && value.len() == 1 =>
+                    {
+                        otherwise = value.pop()
+                    }
+                    _ => return structure("a piecewise holds pieces and an otherwise"),
+                }
+            }
+            MathNode::piecewise(pieces, otherwise)
+        }
+        ("linalg2", "vector", _) => MathNode::Vector(args),
+        ("linalg2", "matrix", _) => MathNode::Matrix(
+            elements(node)
+                .skip(1)
+                .map(|row| elements(row).skip(1).map(parse_object).collect())
+                .collect::<Result<_, _>>()?,
+        ),
+        ("list1", "list", _) => MathNode::List {
+            order: None,
+            items: args,
+        },
+        ("set1", "set", _) => MathNode::Set(args),
+        ("calculus1", "defint", [range, function]) => bind(
+            BuiltinOp::int,
+            function.clone(),
+            Vec::new(),
+            Some(range.clone()),
+        )?,
+        ("calculus1", "int", [function]) | ("calculus1", "indefint", [function]) => {
+            bind(BuiltinOp::int, function.clone(), Vec::new(), None)?
+        }
+        ("arith1", "sum", [range, function]) | ("arith1", "product", [range, function]) => {
+            let op = builtin(&cd, &name).unwrap_or(BuiltinOp::sum);
+            bind(op, function.clone(), Vec::new(), Some(range.clone()))?
+        }
+        ("calculus1", "diff", [function]) => {
+            bind(BuiltinOp::diff, function.clone(), Vec::new(), None)?
+        }
+        ("calculus1", "nthdiff", [degree, function]) => bind(
+            BuiltinOp::diff,
+            function.clone(),
+            vec![Some(degree.clone())],
+            None,
+        )?,
+        // How often each variable is differentiated by, from its position
+        ("calculus1", "partialdiff", [MathNode::List { items, .. }, function]) => {
+            let mut degrees = Vec::new();
+            for item in items {
+                let index = match item {
+                    MathNode::Cn {
+                        num_type: NumType::Integer(i),
+                        ..
+                    } if *i >= 1 => *i as usize - 1,
+                    _ => return structure("partialdiff takes a list of variable positions"),
+                };
+                if degrees.len() <= index {
+                    degrees.resize(index + 1, 0);
+                }
+                degrees[index] += 1;
+            }
+            let degrees = degrees
+                .into_iter()
+                .map(|n| match n {
+                    1 => None,
+                    n => Some(MathNode::integer(n)),
+                })
+                .collect();
+            bind(BuiltinOp::partialdiff, function.clone(), degrees, None)?
+        }
+        _ => MathNode::call(head, args),
+    };
+    Ok(node)
+}
+
+fn parse_binding(node: Node) -> Result<MathNode, ParseError> {
+    let children: Vec<_> = elements(node).collect();
+    let (head, variables, body) = match children.as_slice() {
+        [head, variables, body] if variables.tag_name().name() == "OMBVAR" => {
+            (*head, *variables, *body)
+        }
+        _ => return structure("<OMBIND> holds a binder, an <OMBVAR> and a body"),
+    };
+    let mut bound_vars = Vec::new();
+    for variable in elements(variables) {
+        // An `<OMATTR>` attributes the `<OMV>` it ends with
+        let variable = match variable.tag_name().name() {
+            "OMATTR" => elements(variable).last().unwrap_or(variable),
+            _ => variable,
+        };
+        match variable.tag_name().name() {
+            "OMV" => bound_vars.push(attribute(variable, "name")?),
+            _ => return structure("<OMBVAR> holds <OMV>s"),
+        }
+    }
+    let body = parse_object(body)?;
+    match parse_object(head)? {
+        MathNode::Csymbol { cd, name, .. } if cd.as_deref() == Some("fns1") && name == "lambda" => {
+            Ok(MathNode::Lambda {
+                bound_vars,
+                body: Box::new(body),
+            })
+        }
+        head => {
+            let mut children = vec![head];
+            children.extend(
+                bound_vars
+                    .into_iter()
+                    .map(|name| MathNode::Bvar { name, degree: None }),
+            );
+            children.push(body);
+            Ok(MathNode::Apply {
+                children,
+                attributes: Vec::new(),
+            })
+        }
+    }
+}
+
+fn parse_object(node: Node) -> Result<MathNode, ParseError> {
+    match node.tag_name().name() {
+        "OMOBJ" => match elements(node).next() {
+            Some(object) => parse_object(object),
+            None => structure("<OMOBJ> is empty"),
+        },
+        "OMI" => parse_integer(node.text().unwrap_or_default()),
+        "OMF" => parse_float(node),
+        "OMV" => Ok(MathNode::ci(attribute(node, "name")?)),
+        "OMSTR" => Ok(MathNode::Cs(node.text().unwrap_or_default().to_owned())),
+        "OMB" => match decode_base64(node.text().unwrap_or_default()) {
+            Some(bytes) => Ok(MathNode::Cbytes(bytes)),
+            None => structure("<OMB> doesn't hold base64"),
+        },
+        "OMR" => Ok(MathNode::Share(attribute(node, "href")?)),
+        "OMS" => parse_symbol(node),
+        "OMA" => parse_application(node),
+        "OMBIND" => parse_binding(node),
+        // Attributions only annotate the object they end with
+        "OMATTR" => match elements(node).last() {
+            Some(object) if object.tag_name().name() != "OMATP" => parse_object(object),
+            _ => structure("<OMATTR> is missing its object"),
+        },
+        "OME" => Ok(MathNode::Cerror(
+            elements(node).map(parse_object).collect::<Result<_, _>>()?,
+        )),
+        name => Err(ParseError::UnknownElement(name.to_owned())),
+    }
+}
+
+impl MathNode {
+    /// Serialize this node as an OpenMath object, using the content
+    /// dictionaries MathML 3 maps its operators to, e.g. `arith1` or
+    /// `transc1`. Bound variables become `<OMBIND>`s, and anything OpenMath
+    /// has no symbol for, like `<selector/>` or presentation markup, an
+    /// `<OME>` holding its MathML
+    pub fn to_openmath(&self) -> String {
+        let mut out = format!("<OMOBJ xmlns=\"{}\" version=\"2.0\">", OPENMATH_NS);
+        write_object(self, &mut out);
+        out.push_str("</OMOBJ>");
+        out
+    }
+}
+
+/// Parses an OpenMath object in its XML encoding, e.g. `<OMOBJ><OMA><OMS
+/// cd="arith1" name="plus"/>...</OMA></OMOBJ>`, into content MathML. Symbols
+/// of the content dictionaries `to_openmath` writes become operators,
+/// constants and qualifiers again, any others a `<csymbol>` with their `cd`
+pub fn parse_openmath(text: &str) -> Result<MathNode, ParseError> {
+    let xml = roxmltree::Document::parse(text)?;
+    parse_object(xml.root_element())
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_openmath;
+    use crate::parse_document;
+
+    #[test]
+    fn test_to_openmath() {
+        let test = r#"<math><apply><plus/>
+                        <apply><sin/><ci>x</ci></apply>
+                        <apply><minus/><cn>1.5</cn></apply>
+                        <apply><log/><logbase><cn type="integer">2</cn></logbase><ci>x</ci></apply>
+                        <apply><root/><ci>x</ci></apply>
+                        <pi/>
+                      </apply></math>"#;
+        assert_eq!(
+            parse_document(test).unwrap().to_openmath(),
+            concat!(
+                r#"<OMOBJ xmlns="http://www.openmath.org/OpenMath" version="2.0"><OMA>"#,
+                r#"<OMS cd="arith1" name="plus"/>"#,
+                r#"<OMA><OMS cd="transc1" name="sin"/><OMV name="x"/></OMA>"#,
+                r#"<OMA><OMS cd="arith1" name="unary_minus"/><OMF dec="1.5"/></OMA>"#,
+                r#"<OMA><OMS cd="transc1" name="log"/><OMI>2</OMI><OMV name="x"/></OMA>"#,
+                r#"<OMA><OMS cd="arith1" name="root"/><OMV name="x"/><OMI>2</OMI></OMA>"#,
+                r#"<OMS cd="nums1" name="pi"/></OMA></OMOBJ>"#
+            )
+        );
+
+        let test = r#"<apply><int/><bvar><ci>t</ci></bvar><lowlimit><cn type="integer">0</cn></lowlimit>
+                        <uplimit><ci>T</ci></uplimit><apply><csymbol definitionURL="http://www.sbml.org/sbml/symbols/rateOf">rateOf</csymbol><ci>S</ci></apply>
+                      </apply>"#;
+        assert_eq!(
+            parse_document(test).unwrap().to_openmath(),
+            concat!(
+                r#"<OMOBJ xmlns="http://www.openmath.org/OpenMath" version="2.0"><OMA>"#,
+                r#"<OMS cd="calculus1" name="defint"/>"#,
+                r#"<OMA><OMS cd="interval1" name="interval"/><OMI>0</OMI><OMV name="T"/></OMA>"#,
+                r#"<OMBIND><OMS cd="fns1" name="lambda"/><OMBVAR><OMV name="t"/></OMBVAR>"#,
+                r#"<OMA><OMS cdbase="http://www.sbml.org/sbml" cd="symbols" name="rateOf"/><OMV name="S"/></OMA>"#,
+                r#"</OMBIND></OMA></OMOBJ>"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_openmath() {
+        let test = r#"<OMOBJ xmlns="http://www.openmath.org/OpenMath">
+                        <OMA><OMS cd="relation1" name="leq"/>
+                          <OMA><OMS cd="arith1" name="times"/><OMI>x1F</OMI><OMV name="x"/></OMA>
+                          <OMA><OMS cd="transc1" name="exp"/><OMF dec="-2.5"/></OMA>
+                        </OMA>
+                      </OMOBJ>"#;
+        assert_eq!(
+            parse_openmath(test).unwrap().to_string(),
+            "31 * x <= exp(-2.5)"
+        );
+        assert!(parse_openmath("<OMOBJ><OMS cd=\"arith1\"/></OMOBJ>").is_err());
+
+        // What `to_openmath` writes reads back as the same tree
+        for test in &[
+            r#"<apply><plus/><apply><minus/><ci>x</ci></apply><apply><log/><logbase><cn type="integer">2</cn></logbase><ci>x</ci></apply>
+                 <apply><root/><degree><cn type="integer">3</cn></degree><cn type="rational">1<sep/>2</cn></apply><exponentiale/></apply>"#,
+            r#"<apply><sum/><bvar><ci>i</ci></bvar><lowlimit><cn type="integer">1</cn></lowlimit><uplimit><ci>n</ci></uplimit>
+                 <apply><partialdiff/><bvar><ci>x</ci><degree><cn type="integer">2</cn></degree></bvar><bvar><ci>y</ci></bvar><ci>f</ci></apply></apply>"#,
+            r#"<piecewise><piece><csymbol definitionURL="http://www.sbml.org/sbml/symbols/time">time</csymbol>
+                 <apply><and/><true/><apply><in/><ci>x</ci><reals/></apply></apply></piece><otherwise><cn>0.5</cn></otherwise></piecewise>"#,
+            r#"<apply><forall/><bvar><ci>x</ci></bvar><apply><geq/><apply><abs/><ci>x</ci></apply><cn type="integer">0</cn></apply></apply>"#,
+        ] {
+            let parsed = parse_document(test).unwrap();
+            assert_eq!(parse_openmath(&parsed.to_openmath()).unwrap(), parsed);
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use super::BuiltinOp;
+use std::fmt;
+
+/// Precedence of things that never need parentheses (identifiers, function calls...)
+pub(crate) const PREC_ATOM: u8 = 10;
+/// Precedence of prefix negation, which binds tighter than products but looser than powers
+pub(crate) const PREC_UNARY: u8 = 7;
+/// Precedence of exponentiation
+pub(crate) const PREC_POWER: u8 = 8;
+
+/// Returns the text, parenthesized if it binds looser than `min_precedence`
+pub(crate) fn parenthesize(text: String, precedence: u8, min_precedence: u8) -> String {
+    if precedence < min_precedence {
+        format!("({})", text)
+    } else {
+        text
+    }
+}
+
+/// Relations chain (a < b < c) and can't nest without parentheses
+pub(crate) fn is_relation(op: &BuiltinOp) -> bool {
+    op.precedence() == BuiltinOp::eq.precedence()
+}
+
+impl BuiltinOp {
+    /// How tightly the operator binds when written infix, higher binds
+    /// tighter: from 1 for `implies` up to 9 for `factorial`, and 10 for
+    /// operators written as a function call
+    pub fn precedence(&self) -> u8 {
+        use BuiltinOp::*;
+        match self {
+            implies | equivalent => 1,
+            or | xor => 2,
+            and => 3,
+            eq | neq | gt | lt | geq | leq | approx | r#in | notin | subset | prsubset
+            | notsubset | notprsubset | tendsto | factorof => 4,
+            plus | minus | union | setdiff => 5,
+            times | divide | rem | quotient | vectorproduct | scalarproduct | outerproduct
+            | intersect | cartesianproduct | compose => 6,
+            not => PREC_UNARY,
+            power => PREC_POWER,
+            factorial => 9,
+            _ => PREC_ATOM,
+        }
+    }
+    /// The number of operands the operator takes in MathML. Qualifiers like
+    /// `<bvar>` and `<degree>` don't count, so `<int/>` and `<root/>` take one
+    pub fn arity(&self) -> Arity {
+        use BuiltinOp::*;
+        let (least, most) = match self {
+            minus => (1, Some(2)),
+            quotient | divide | power | rem | implies | approx | setdiff | vectorproduct
+            | scalarproduct | outerproduct | neq | factorof | r#in | notin | notsubset
+            | notprsubset | tendsto => (2, Some(2)),
+            selector => (2, Some(3)),
+            partialdiff => (1, Some(2)),
+            eq | gt | lt | geq | leq | equivalent | subset | prsubset => (2, None),
+            max | min | mean | sdev | variance | median | mode => (1, None),
+            plus | times | gcd | lcm | and | or | xor | union | intersect | cartesianproduct
+            | compose | r#fn => (0, None),
+            _ => (1, Some(1)),
+        };
+        Arity {
+            min: least,
+            max: most,
+        }
+    }
+    /// Whether the order of the operands doesn't change the result, e.g.
+    /// `plus` or `max` but not `minus` or `compose`
+    pub fn is_commutative(&self) -> bool {
+        use BuiltinOp::*;
+        matches!(
+            self,
+            plus | times
+                | max
+                | min
+                | gcd
+                | lcm
+                | and
+                | or
+                | xor
+                | equivalent
+                | eq
+                | neq
+                | approx
+                | union
+                | intersect
+                | mean
+                | sdev
+                | variance
+                | median
+                | mode
+                | scalarproduct
+        )
+    }
+    /// The area of mathematics the operator belongs to
+    pub fn category(&self) -> OpCategory {
+        use BuiltinOp::*;
+        match self {
+            factorial | minus | plus | times | divide | power | root | abs | floor | ceiling
+            | quotient | rem | max | min | gcd | lcm | conjugate | arg | real | imaginary => {
+                OpCategory::Arithmetic
+            }
+            sin | cos | tan | sec | csc | cot | sinh | cosh | tanh | sech | csch | coth
+            | arcsin | arccos | arctan | arcsec | arccsc | arccot | arcsinh | arccosh | arctanh
+            | arcsech | arccsch | arccoth | exp | ln | log => OpCategory::Elementary,
+            eq | neq | gt | lt | geq | leq | approx | factorof => OpCategory::Relational,
+            and | or | xor | not | implies | equivalent | forall | exists => OpCategory::Logical,
+            int | diff | partialdiff | sum | product | tendsto | divergence | grad | curl
+            | laplacian => OpCategory::Calculus,
+            union | intersect | setdiff | cartesianproduct | r#in | notin | subset | prsubset
+            | notsubset | notprsubset | card => OpCategory::Set,
+            mean | sdev | variance | median | mode => OpCategory::Statistics,
+            determinant | transpose | selector | vectorproduct | scalarproduct | outerproduct => {
+                OpCategory::LinearAlgebra
+            }
+            inverse | ident | domain | codomain | image | compose | r#fn => OpCategory::Function,
+        }
+    }
+}
+
+/// How many operands an operator takes, see `BuiltinOp::arity`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Arity {
+    pub min: usize,
+    /// `None` for operators taking any number from `min` on
+    pub max: Option<usize>,
+}
+
+impl Arity {
+    /// Whether the operator can take `count` operands
+    pub fn accepts(&self, count: usize) -> bool {
+        count >= self.min && self.max.is_none_or(|max| count <= max)
+    }
+}
+
+impl fmt::Display for Arity {
+    /// Writes the number of operands, e.g. `2`, `1 to 2` or `at least 1`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.max {
+            Some(max) if max == self.min => write!(f, "{}", max),
+            Some(max) => write!(f, "{} to {}", self.min, max),
+            None => write!(f, "at least {}", self.min),
+        }
+    }
+}
+
+/// The kinds of operator `BuiltinOp::category` tells apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OpCategory {
+    /// Arithmetic and the parts of a complex number, e.g. `plus` or `gcd`
+    Arithmetic,
+    /// Exponentials, logarithms and trigonometric functions
+    Elementary,
+    /// Comparisons, e.g. `eq` or `leq`
+    Relational,
+    /// Connectives and quantifiers, e.g. `and` or `forall`
+    Logical,
+    /// Integrals, derivatives, series, limits and vector calculus
+    Calculus,
+    /// Set operations and relations, e.g. `union` or `subset`
+    Set,
+    /// Summary statistics, e.g. `mean`
+    Statistics,
+    /// Matrix and vector operations, e.g. `determinant`
+    LinearAlgebra,
+    /// Operations on functions, e.g. `compose` or `inverse`
+    Function,
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Arity, OpCategory};
+    use crate::eval::eval_op;
+    use crate::{BuiltinOp, EvalError};
+
+    #[test]
+    fn test_op_metadata() {
+        let minus = BuiltinOp::minus.arity();
+        assert_eq!(
+            minus,
+            Arity {
+                min: 1,
+                max: Some(2)
+            }
+        );
+        assert!(minus.accepts(2) && !minus.accepts(3));
+        assert_eq!(minus.to_string(), "1 to 2");
+        assert_eq!(BuiltinOp::plus.arity().to_string(), "at least 0");
+        assert_eq!(BuiltinOp::quotient.arity().to_string(), "2");
+
+        assert!(BuiltinOp::max.is_commutative());
+        assert!(!BuiltinOp::divide.is_commutative());
+        assert!(BuiltinOp::times.precedence() > BuiltinOp::plus.precedence());
+        assert_eq!(BuiltinOp::arcsinh.category(), OpCategory::Elementary);
+        assert_eq!(BuiltinOp::subset.category(), OpCategory::Set);
+        assert_eq!(BuiltinOp::variance.category(), OpCategory::Statistics);
+    }
+
+    #[test]
+    fn test_arity_matches_eval() {
+        let names = "factorial minus abs conjugate arg real imaginary floor ceiling not inverse \
+                     ident domain codomain image sin cos tan sec csc cot sinh cosh tanh sech \
+                     csch coth arcsin arccos arctan arccosh arccot arccoth arccsc arccsch \
+                     arcsec arcsech arcsinh arctanh exp ln log determinant transpose divergence \
+                     grad curl laplacian card quotient divide power rem implies equivalent \
+                     approx setdiff vectorproduct scalarproduct outerproduct plus times max min \
+                     gcd lcm mean sdev variance median mode and or xor selector union intersect \
+                     cartesianproduct compose fn int sum product diff partialdiff forall exists \
+                     eq neq gt lt geq leq root in notin subset prsubset notsubset notprsubset \
+                     tendsto factorof";
+        let mut mismatches = Vec::new();
+        for name in names.split_whitespace() {
+            let op: BuiltinOp = serde_plain::from_str(name).unwrap();
+            for n in 0..5 {
+                let accepted = match eval_op(&op, &vec![1.0; n]) {
+                    Err(EvalError::UnsupportedOperator(_)) => break,
+                    Err(EvalError::WrongArity { .. }) => false,
+                    _ => true,
+                };
+                if op.arity().accepts(n) != accepted {
+                    mismatches.push(format!("{} of {} operands", op, n));
+                }
+            }
+        }
+        assert_eq!(mismatches, Vec::<String>::new());
+    }
+}
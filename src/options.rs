@@ -0,0 +1,45 @@
+/// What to do with named entities like `&tau;` that XML itself doesn't define
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EntityPolicy {
+    /// Replace them with the characters they stand for, e.g. `τ`
+    Resolve,
+    /// Keep them in the text, marked so that `to_mathml` writes them back out
+    /// as `&tau;` and the other writers see which entity they are
+    Preserve,
+    /// Leave them to the XML parser, which rejects the document
+    Error,
+}
+
+/// The knobs of `parse_document_with_options`. `Default` gives the behaviour
+/// of `parse_document`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ParserOptions {
+    /// Keep `<!-- -->` comments as `MathNode::Comment`
+    pub keep_comments: bool,
+    /// Keep `<?target value?>` processing instructions as `MathNode::PI`
+    pub keep_processing_instructions: bool,
+    /// Strip leading and trailing whitespace from text, e.g. `<ci> x </ci>`.
+    /// Whitespace-only text between elements is always dropped
+    pub trim_whitespace: bool,
+    /// Reject what is otherwise kept in a lenient form, like a `<cn>` of a
+    /// type MathML doesn't define
+    pub strict: bool,
+    /// The deepest element nesting accepted, or no limit. Parsing itself
+    /// doesn't recurse, but evaluating or even dropping a tree does, so
+    /// untrusted input should be given a limit
+    pub max_depth: Option<usize>,
+    pub entities: EntityPolicy,
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        ParserOptions {
+            keep_comments: true,
+            keep_processing_instructions: true,
+            trim_whitespace: true,
+            strict: false,
+            max_depth: None,
+            entities: EntityPolicy::Resolve,
+        }
+    }
+}
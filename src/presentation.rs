@@ -0,0 +1,276 @@
+use crate::error::ParseError;
+use crate::MathNode;
+use roxmltree::Node;
+use serde_derive::{Deserialize, Serialize};
+use std::convert::TryInto;
+
+/// A Presentation MathML element. Only the layout is kept, attributes are dropped
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash)]
+pub enum PresentationNode {
+    /// `<mi>`: an identifier
+    Mi(String),
+    /// `<mn>`: a number
+    Mn(String),
+    /// `<mo>`: an operator, fence or separator
+    Mo(String),
+    /// `<mtext>`: free text
+    Mtext(String),
+    /// `<mspace/>`
+    Mspace,
+    /// `<mglyph/>`: a non-standard character, `alt` is its textual fallback
+    Mglyph { src: Option<String>, alt: String },
+    /// `<mrow>`: a horizontal group
+    Mrow(Vec<PresentationNode>),
+    /// `<mfrac>`: numerator and denominator
+    Mfrac(Box<PresentationNode>, Box<PresentationNode>),
+    /// `<msqrt>`: its children form an inferred row
+    Msqrt(Vec<PresentationNode>),
+    /// `<mroot>`: base and index
+    Mroot(Box<PresentationNode>, Box<PresentationNode>),
+    /// `<msup>`: base and superscript
+    Msup(Box<PresentationNode>, Box<PresentationNode>),
+    /// `<msub>`: base and subscript
+    Msub(Box<PresentationNode>, Box<PresentationNode>),
+    /// `<msubsup>`: base, subscript and superscript
+    Msubsup(
+        Box<PresentationNode>,
+        Box<PresentationNode>,
+        Box<PresentationNode>,
+    ),
+    /// `<mfenced>`: children between an opening and closing fence
+    Mfenced {
+        open: String,
+        close: String,
+        children: Vec<PresentationNode>,
+    },
+    /// `<mtable>`: the cells of its `<mtr>` rows
+    Mtable(Vec<Vec<PresentationNode>>),
+}
+
+const PRESENTATION_TAGS: &[&str] = &[
+    "mi", "mn", "mo", "mtext", "mspace", "mglyph", "mrow", "mfrac", "msqrt", "mroot", "msup",
+    "msub", "msubsup", "mfenced", "mtable",
+];
+
+/// Whether a tag name belongs to the supported Presentation MathML subset
+pub(crate) fn is_presentation_tag(tag_name: &str) -> bool {
+    PRESENTATION_TAGS.contains(&tag_name)
+}
+
+fn token_text(node: Node) -> String {
+    node.text().unwrap_or_default().trim().to_owned()
+}
+
+fn element_children(node: Node) -> Result<Vec<PresentationNode>, ParseError> {
+    node.children()
+        .filter(|n| n.is_element())
+        .map(parse_presentation)
+        .collect()
+}
+
+/// Parses a layout element with a fixed number of children
+fn fixed_children<const N: usize>(node: Node) -> Result<[Box<PresentationNode>; N], ParseError> {
+    let children = element_children(node)?;
+    let found = children.len();
+    let boxed: Vec<Box<PresentationNode>> = children.into_iter().map(Box::new).collect();
+    boxed.try_into().map_err(|_| {
+        ParseError::InvalidStructure(format!(
+            "<{}> needs exactly {} children, found {}",
+            node.tag_name().name(),
+            N,
+            found
+        ))
+    })
+}
+
+fn expect_tag<'a, 'input>(
+    node: Node<'a, 'input>,
+    tag_name: &str,
+) -> Result<Node<'a, 'input>, ParseError> {
+    if node.tag_name().name() == tag_name {
+        Ok(node)
+    } else {
+        Err(ParseError::InvalidStructure(format!(
+            "expected <{}>, found <{}>",
+            tag_name,
+            node.tag_name().name()
+        )))
+    }
+}
+
+/// Parses the `<mtr>` rows of an `<mtable>`. Each `<mtd>` cell is an inferred row
+fn table_rows(node: Node) -> Result<Vec<Vec<PresentationNode>>, ParseError> {
+    node.children()
+        .filter(|n| n.is_element())
+        .map(|row| {
+            expect_tag(row, "mtr")?
+                .children()
+                .filter(|n| n.is_element())
+                .map(|cell| {
+                    let mut children = element_children(expect_tag(cell, "mtd")?)?;
+                    Ok(match children.len() {
+                        1 => children.remove(0),
+                        _ => PresentationNode::Mrow(children),
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+impl PresentationNode {
+    /// A plain identifier for markup used as the name of a `<ci>`, e.g. `k_1`
+    /// for `<msub><mi>k</mi><mn>1</mn></msub>`
+    pub fn identifier_text(&self) -> String {
+        use PresentationNode::*;
+        let row = |children: &[PresentationNode]| -> String {
+            children
+                .iter()
+                .map(PresentationNode::identifier_text)
+                .collect()
+        };
+        match self {
+            Mi(text) | Mn(text) | Mo(text) | Mtext(text) => text.clone(),
+            Mglyph { alt, .. } => alt.clone(),
+            Mspace => String::new(),
+            Mrow(children) | Msqrt(children) => row(children),
+            Msub(base, sub) => format!("{}_{}", base.identifier_text(), sub.identifier_text()),
+            Msup(base, sup) => format!("{}^{}", base.identifier_text(), sup.identifier_text()),
+            Msubsup(base, sub, sup) => format!(
+                "{}_{}^{}",
+                base.identifier_text(),
+                sub.identifier_text(),
+                sup.identifier_text()
+            ),
+            Mfrac(a, b) | Mroot(a, b) => a.identifier_text() + &b.identifier_text(),
+            Mfenced { children, .. } => row(children),
+            Mtable(rows) => rows.iter().map(|r| row(r)).collect(),
+        }
+    }
+}
+
+impl MathNode {
+    /// Replaces presentation markup inside every `<ci>` with its
+    /// `identifier_text`, for consumers that only handle plain names
+    pub fn flatten_identifiers(&mut self) {
+        let mut nodes = self.iter_mut();
+        while let Some(node) = nodes.next_node() {
+            if let MathNode::Ci { children, .. } = node {
+                if children
+                    .iter()
+                    .any(|c| matches!(c, MathNode::Presentation(_)))
+                {
+                    *children = vec![MathNode::Text(crate::text_content(children))];
+                }
+            }
+        }
+    }
+}
+
+/// Parse a Presentation MathML element and everything below it
+pub(crate) fn parse_presentation(node: Node) -> Result<PresentationNode, ParseError> {
+    let tag_name = node.tag_name().name();
+    let parsed = match tag_name {
+        "mi" => PresentationNode::Mi(token_text(node)),
+        "mn" => PresentationNode::Mn(token_text(node)),
+        "mo" => PresentationNode::Mo(token_text(node)),
+        "mtext" => PresentationNode::Mtext(token_text(node)),
+        "mspace" => PresentationNode::Mspace,
+        "mglyph" => PresentationNode::Mglyph {
+            src: node.attribute("src").map(|src| src.to_owned()),
+            alt: node.attribute("alt").unwrap_or_default().to_owned(),
+        },
+        "mrow" => PresentationNode::Mrow(element_children(node)?),
+        "msqrt" => PresentationNode::Msqrt(element_children(node)?),
+        "mfrac" => {
+            let [num, den] = fixed_children(node)?;
+            PresentationNode::Mfrac(num, den)
+        }
+        "mroot" => {
+            let [base, index] = fixed_children(node)?;
+            PresentationNode::Mroot(base, index)
+        }
+        "msup" => {
+            let [base, sup] = fixed_children(node)?;
+            PresentationNode::Msup(base, sup)
+        }
+        "msub" => {
+            let [base, sub] = fixed_children(node)?;
+            PresentationNode::Msub(base, sub)
+        }
+        "msubsup" => {
+            let [base, sub, sup] = fixed_children(node)?;
+            PresentationNode::Msubsup(base, sub, sup)
+        }
+        "mfenced" => PresentationNode::Mfenced {
+            open: node.attribute("open").unwrap_or("(").to_owned(),
+            close: node.attribute("close").unwrap_or(")").to_owned(),
+            children: element_children(node)?,
+        },
+        "mtable" => PresentationNode::Mtable(table_rows(node)?),
+        _ => return Err(ParseError::UnknownElement(tag_name.to_owned())),
+    };
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::PresentationNode::*;
+    use crate::parse_document;
+    use crate::MathNode::{self, Presentation, Root};
+
+    #[test]
+    fn test_presentation() {
+        let test = r#"<math xmlns="http://www.w3.org/1998/Math/MathML">
+                        <mrow>
+                            <msup><mi>x</mi><mn>2</mn></msup>
+                            <mo>+</mo>
+                            <mfrac>
+                                <mn>1</mn>
+                                <msqrt><msub><mi>k</mi><mn>1</mn></msub></msqrt>
+                            </mfrac>
+                        </mrow>
+                      </math>"#;
+        let parsed = parse_document(test).unwrap();
+        let expected = Root {
+            children: vec![Presentation(Mrow(vec![
+                Msup(Box::new(Mi("x".to_owned())), Box::new(Mn("2".to_owned()))),
+                Mo("+".to_owned()),
+                Mfrac(
+                    Box::new(Mn("1".to_owned())),
+                    Box::new(Msqrt(vec![Msub(
+                        Box::new(Mi("k".to_owned())),
+                        Box::new(Mn("1".to_owned())),
+                    )])),
+                ),
+            ]))],
+            attributes: Vec::new(),
+        };
+        assert_eq!(parsed, expected);
+    }
+    #[test]
+    fn test_presentation_arity() {
+        let test = r#"<mfrac><mn>1</mn></mfrac>"#;
+        assert!(parse_document(test).is_err());
+        let test = r#"<mtable><mtr><mi>x</mi></mtr></mtable>"#;
+        assert!(parse_document(test).is_err());
+    }
+    #[test]
+    fn test_decorated_identifiers() {
+        let test = r#"<apply><times/>
+                        <ci><msub><mi>k</mi><mi>1</mi></msub></ci>
+                        <ci><mglyph src="my-glyphs.png" alt="S-star"/></ci>
+                      </apply>"#;
+        let mut parsed = parse_document(test).unwrap();
+        assert_eq!(parsed.to_string(), "k_1 * S-star");
+        assert_eq!(parsed.to_latex(), "{k}_{1} \\cdot S-star");
+        assert_eq!(parse_document(&parsed.to_mathml()).unwrap(), parsed);
+        let env = vec![("k_1".to_owned(), 2.0), ("S-star".to_owned(), 3.0)]
+            .into_iter()
+            .collect();
+        assert_eq!(parsed.eval(&env), Ok(6.0));
+
+        parsed.flatten_identifiers();
+        assert_eq!(parsed.children()[1], &MathNode::ci("k_1"));
+    }
+}
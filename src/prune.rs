@@ -0,0 +1,151 @@
+use super::{Bounds, BuiltinOp, ConstantSymbol, MathNode};
+use std::collections::HashMap;
+
+/// Whether `a op b` holds for every value in the ranges, or for none of them
+fn compare(op: BuiltinOp, a: Bounds, b: Bounds) -> Option<bool> {
+    use BuiltinOp::*;
+    if [a.lo, a.hi, b.lo, b.hi].iter().any(|v| v.is_nan()) {
+        return None;
+    }
+    let (always, never) = match op {
+        lt => (a.hi < b.lo, a.lo >= b.hi),
+        leq => (a.hi <= b.lo, a.lo > b.hi),
+        gt => (a.lo > b.hi, a.hi <= b.lo),
+        geq => (a.lo >= b.hi, a.hi < b.lo),
+        eq => (a.lo == a.hi && a == b, a.hi < b.lo || b.hi < a.lo),
+        neq => (a.hi < b.lo || b.hi < a.lo, a.lo == a.hi && a == b),
+        _ => return None,
+    };
+    match (always, never) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    }
+}
+
+/// The truth value `condition` has whatever the identifiers in `env` are
+/// within their ranges, if that can be told
+fn decide(condition: &MathNode, env: &HashMap<String, Bounds>) -> Option<bool> {
+    use BuiltinOp::*;
+    match condition {
+        MathNode::Constant(ConstantSymbol::True) => return Some(true),
+        MathNode::Constant(ConstantSymbol::False) => return Some(false),
+        _ => {}
+    }
+    if condition.variables().is_empty() {
+        return condition.eval_bool(&HashMap::new()).ok();
+    }
+    let op = match condition {
+        MathNode::Apply { children, .. } => match children.first() {
+            Some(MathNode::Op(op)) => *op,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let operands = condition.operands();
+    match op {
+        and | or => {
+            let decisive = op == or;
+            let truths: Vec<Option<bool>> = operands.iter().map(|o| decide(o, env)).collect();
+            if truths.contains(&Some(decisive)) {
+                Some(decisive)
+            } else if truths.iter().all(|t| *t == Some(!decisive)) {
+                Some(!decisive)
+            } else {
+                None
+            }
+        }
+        not if operands.len() == 1 => decide(operands[0], env).map(|t| !t),
+        lt | leq | gt | geq | eq | neq if operands.len() >= 2 => {
+            let ranges = operands
+                .iter()
+                .map(|o| o.eval_bounds(env).ok())
+                .collect::<Option<Vec<Bounds>>>()?;
+            let truths: Vec<Option<bool>> = ranges
+                .windows(2)
+                .map(|pair| compare(op, pair[0], pair[1]))
+                .collect();
+            if truths.contains(&Some(false)) {
+                Some(false)
+            } else if truths.iter().all(|t| *t == Some(true)) {
+                Some(true)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+fn prune(node: MathNode, env: &HashMap<String, Bounds>) -> MathNode {
+    let (pieces, otherwise) = match node {
+        MathNode::Piecewise { pieces, otherwise } => (pieces, otherwise),
+        node => return node,
+    };
+    let mut kept = Vec::new();
+    let mut fallback = otherwise.clone();
+    for (value, condition) in &pieces {
+        match decide(condition, env) {
+            Some(false) => {}
+            // Nothing after a piece that always applies can be reached
+            Some(true) => {
+                fallback = Some(Box::new(value.clone()));
+                break;
+            }
+            None => kept.push((value.clone(), condition.clone())),
+        }
+    }
+    match (kept.is_empty(), fallback) {
+        (true, Some(value)) => *value,
+        // No piece can apply, so the value is undefined either way
+        (true, None) => MathNode::Piecewise { pieces, otherwise },
+        (false, fallback) => MathNode::Piecewise {
+            pieces: kept,
+            otherwise: fallback,
+        },
+    }
+}
+
+impl MathNode {
+    /// Drops the pieces of every `<piecewise>` whose condition is constant
+    /// and false, and everything after one that is constant and true, so
+    /// that generated code doesn't test them. A `<piecewise>` left with one
+    /// value is replaced by it
+    pub fn prune_piecewise(&self) -> MathNode {
+        self.prune_piecewise_with(&HashMap::new())
+    }
+    /// Like `prune_piecewise`, also deciding the comparisons that hold, or
+    /// fail, for every value of the identifiers in `env` within their ranges,
+    /// as `eval_bounds` computes them
+    pub fn prune_piecewise_with(&self, env: &HashMap<String, Bounds>) -> MathNode {
+        self.clone().map(|node| prune(node, env))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{parse_infix, Bounds};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_prune_piecewise() {
+        let prune = |text: &str| parse_infix(text).unwrap().prune_piecewise().to_string();
+        assert_eq!(prune("piecewise(1, 2 > 1, 0)"), "1");
+        assert_eq!(
+            prune("k * piecewise(a, false, b, x > 0, c, 1 == 1, d)"),
+            "k * piecewise(b, x > 0, c)"
+        );
+
+        let env: HashMap<String, Bounds> = vec![("t".to_owned(), Bounds::new(0.0, 4.0))]
+            .into_iter()
+            .collect();
+        let test = parse_infix("piecewise(a, t < 0, b, t >= 0 && t <= 10, c)").unwrap();
+        assert_eq!(test.prune_piecewise_with(&env).to_string(), "b");
+        let test = parse_infix("piecewise(a, t > 5, b, x > t, c)").unwrap();
+        assert_eq!(
+            test.prune_piecewise_with(&env).to_string(),
+            "piecewise(b, x > t, c)"
+        );
+        assert_eq!(test.prune_piecewise(), test);
+    }
+}
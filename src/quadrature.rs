@@ -0,0 +1,174 @@
+use crate::error::EvalError;
+
+/// How `<apply><int/>` is computed numerically
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Quadrature {
+    /// Simpson's rule, recursively splitting the intervals that miss the tolerance
+    AdaptiveSimpson,
+    /// Richardson extrapolation of the trapezoidal rule with ever more steps
+    Romberg,
+}
+
+/// The settings of `MathNode::eval_with` for definite integrals
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IntegrationOptions {
+    pub method: Quadrature,
+    /// The absolute error the estimate should be within
+    pub tolerance: f64,
+    /// How often an interval may be split, or how many Romberg rows are built,
+    /// before the integral is reported as not converging
+    pub max_depth: usize,
+}
+
+impl Default for IntegrationOptions {
+    fn default() -> Self {
+        IntegrationOptions {
+            method: Quadrature::AdaptiveSimpson,
+            tolerance: 1e-10,
+            max_depth: 20,
+        }
+    }
+}
+
+/// Integrates `f` from `a` to `b`, which must be finite
+pub(crate) fn integrate(
+    f: &mut dyn FnMut(f64) -> Result<f64, EvalError>,
+    a: f64,
+    b: f64,
+    opts: &IntegrationOptions,
+) -> Result<f64, EvalError> {
+    if !a.is_finite() || !b.is_finite() {
+        return Err(EvalError::InvalidLimits(
+            "an integral needs finite limits".to_owned(),
+        ));
+    }
+    if a == b {
+        return Ok(0.0);
+    }
+    let value = match opts.method {
+        Quadrature::AdaptiveSimpson => {
+            let (fa, fm, fb) = (f(a)?, f((a + b) / 2.0)?, f(b)?);
+            let whole = simpson(a, b, fa, fm, fb);
+            adaptive_simpson(
+                f,
+                (a, b),
+                (fa, fm, fb),
+                whole,
+                opts.tolerance,
+                opts.max_depth,
+            )?
+        }
+        Quadrature::Romberg => romberg(f, a, b, opts)?,
+    };
+    if !value.is_finite() {
+        return Err(EvalError::NonFinite("int".to_owned()));
+    }
+    Ok(value)
+}
+
+fn simpson(a: f64, b: f64, fa: f64, fm: f64, fb: f64) -> f64 {
+    (b - a) / 6.0 * (fa + 4.0 * fm + fb)
+}
+
+fn adaptive_simpson(
+    f: &mut dyn FnMut(f64) -> Result<f64, EvalError>,
+    (a, b): (f64, f64),
+    (fa, fm, fb): (f64, f64, f64),
+    whole: f64,
+    tolerance: f64,
+    depth: usize,
+) -> Result<f64, EvalError> {
+    let m = (a + b) / 2.0;
+    let (flm, frm) = (f((a + m) / 2.0)?, f((m + b) / 2.0)?);
+    let left = simpson(a, m, fa, flm, fm);
+    let right = simpson(m, b, fm, frm, fb);
+    let error = left + right - whole;
+    if error.abs() <= 15.0 * tolerance {
+        return Ok(left + right + error / 15.0);
+    }
+    if depth == 0 || !error.is_finite() {
+        return Err(EvalError::NotConverged("int".to_owned()));
+    }
+    Ok(
+        adaptive_simpson(f, (a, m), (fa, flm, fm), left, tolerance / 2.0, depth - 1)?
+            + adaptive_simpson(f, (m, b), (fm, frm, fb), right, tolerance / 2.0, depth - 1)?,
+    )
+}
+
+fn romberg(
+    f: &mut dyn FnMut(f64) -> Result<f64, EvalError>,
+    a: f64,
+    b: f64,
+    opts: &IntegrationOptions,
+) -> Result<f64, EvalError> {
+    let mut previous = vec![(b - a) / 2.0 * (f(a)? + f(b)?)];
+    for row in 1..=opts.max_depth.min(62) {
+        // The trapezoidal rule with 2^row steps reuses the points of the last row
+        let steps = 1u64 << row;
+        let h = (b - a) / steps as f64;
+        let mut midpoints = 0.0;
+        for k in (1..steps).step_by(2) {
+            midpoints += f(a + k as f64 * h)?;
+        }
+        let mut current = vec![previous[0] / 2.0 + h * midpoints];
+        for j in 1..=row {
+            let factor = 4f64.powi(j as i32);
+            current.push(current[j - 1] + (current[j - 1] - previous[j - 1]) / (factor - 1.0));
+        }
+        if (current[row] - previous[row - 1]).abs() <= opts.tolerance {
+            return Ok(current[row]);
+        }
+        previous = current;
+    }
+    Err(EvalError::NotConverged("int".to_owned()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_document;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_integrate() {
+        let test = r#"<apply><int/><bvar><ci>x</ci></bvar>
+                        <lowlimit><cn>0</cn></lowlimit><uplimit><pi/></uplimit>
+                        <apply><sin/><ci>x</ci></apply>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        approx::assert_abs_diff_eq!(parsed.eval(&HashMap::new()).unwrap(), 2.0, epsilon = 1e-9);
+        let romberg = IntegrationOptions {
+            method: Quadrature::Romberg,
+            ..IntegrationOptions::default()
+        };
+        approx::assert_abs_diff_eq!(
+            parsed.eval_with(&HashMap::new(), &romberg).unwrap(),
+            2.0,
+            epsilon = 1e-9
+        );
+
+        // A Gaussian, with the bound variable shadowing a free `x`
+        let test = r#"<apply><times/><ci>x</ci><apply><int/><bvar><ci>x</ci></bvar>
+                        <interval><cn>-1</cn><ci>a</ci></interval>
+                        <apply><exp/><apply><minus/><apply><power/><ci>x</ci><cn>2</cn></apply></apply></apply>
+                      </apply></apply>"#;
+        let env: HashMap<String, f64> = vec![("x".to_owned(), 2.0), ("a".to_owned(), 1.0)]
+            .into_iter()
+            .collect();
+        let parsed = parse_document(test).unwrap();
+        approx::assert_abs_diff_eq!(
+            parsed.eval(&env).unwrap(),
+            2.0 * 1.493_648_265_624_854,
+            epsilon = 1e-9
+        );
+
+        let strict = IntegrationOptions {
+            max_depth: 2,
+            ..IntegrationOptions::default()
+        };
+        assert_eq!(
+            parsed.eval_with(&env, &strict),
+            Err(EvalError::NotConverged("int".to_owned()))
+        );
+    }
+}
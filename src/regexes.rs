@@ -1,29 +1,244 @@
-/// We need to apply some replacements to account for things like
-/// https://www.tutorialspoint.com/mathml/mathml_greek_letters.htm
-/// Or else the xml parser fails :(
-/// Replaces &$STRING; with $STRING
+use crate::entities;
+use std::collections::HashMap;
 
-macro_rules! replace {
-    ($($e:ident),*) => {{
-    let mut temp_vec = Vec::new();
-            $(
-               temp_vec.push(replace_one(format!("&{};", stringify!($e)), stringify!($e)));
-            )*
-            move |x| temp_vec.iter().fold(x,|acc,next|next(acc))
-           }
-    }
+/// A document with its named entities replaced
+pub(crate) struct Sanitized {
+    pub(crate) text: String,
+    /// Where each replacement ends, as `(offset in text, offset in the original)`
+    pub(crate) anchors: Vec<(usize, usize)>,
+}
+
+/// How much text entities declared in a DOCTYPE may expand to, and how deeply
+/// they may nest, so that a few declarations can't blow up into gigabytes
+const MAX_EXPANSION: usize = 1 << 20;
+const MAX_NESTING: usize = 16;
 
+/// Replaces the named entities XML doesn't define, like `&tau;`, with what
+/// `replacement` writes given the output, the name and the text of each
+pub(crate) fn replace_entities(
+    x: &str,
+    replacement: impl Fn(&mut String, &str, &str),
+) -> Sanitized {
+    let mut scanner = Scanner {
+        replacement,
+        declared: HashMap::new(),
+        budget: MAX_EXPANSION,
+    };
+    let mut text = String::with_capacity(x.len());
+    let mut anchors = Vec::new();
+    scanner.scan(x, &mut text, Some(&mut anchors), 0);
+    Sanitized { text, anchors }
 }
-fn replace_one(from: String, to: &'static str) -> impl Fn(String) -> String + 'static {
-    move |x: String| x.replace(&from, format!("$FIXED_{}", to).as_ref())
+
+struct Scanner<F> {
+    replacement: F,
+    /// The entities declared in the DOCTYPE, with character references decoded
+    declared: HashMap<String, String>,
+    /// How many more bytes of declared entities may be expanded
+    budget: usize,
+}
+
+impl<F: Fn(&mut String, &str, &str)> Scanner<F> {
+    /// Appends the sanitized `x` to `text`. Anchors are only recorded for the
+    /// document itself, not for the text of the declared entities
+    fn scan(
+        &mut self,
+        x: &str,
+        text: &mut String,
+        mut anchors: Option<&mut Vec<(usize, usize)>>,
+        depth: usize,
+    ) {
+        let mut rest = x;
+        while let Some(pos) = rest.find(['&', '<']) {
+            text.push_str(&rest[..pos]);
+            rest = &rest[pos..];
+            if rest.starts_with('<') && !rest.starts_with("<!DOCTYPE") {
+                let len = verbatim(rest).unwrap_or(1);
+                text.push_str(&rest[..len]);
+                rest = &rest[len..];
+                continue;
+            }
+            let after = &rest[1..];
+            let name_len = after
+                .find(|c: char| !(c.is_alphanumeric() || "-._:".contains(c)))
+                .unwrap_or(after.len());
+            let name = &after[..name_len];
+            let terminated = after[name_len..].starts_with(';');
+            if rest.starts_with("<!DOCTYPE") {
+                // Dropped, as the parser rejects DTDs and its entities are expanded here
+                let (len, declared) = doctype(rest);
+                self.declared.extend(declared);
+                rest = &rest[len..];
+            } else if let Some((decoded, false, len)) = char_reference(after) {
+                let mut buf = [0; 4];
+                text.push_str(markup_escaped(decoded.encode_utf8(&mut buf)));
+                rest = &after[len..];
+            } else if let (Some(value), true) = (self.declared.get(name), terminated) {
+                if depth >= MAX_NESTING || value.len() > self.budget {
+                    // Left for the XML parser, which rejects it
+                    text.push('&');
+                    rest = after;
+                    continue;
+                }
+                self.budget -= value.len();
+                let value = value.clone();
+                self.scan(&value, text, None, depth + 1);
+                rest = &after[name_len + 1..];
+            } else if let (Some(resolved), true) = (entities::lookup(name), terminated) {
+                (self.replacement)(text, name, resolved);
+                rest = &after[name_len + 1..];
+            } else {
+                text.push('&');
+                rest = after;
+                continue;
+            }
+            if let Some(anchors) = anchors.as_deref_mut() {
+                anchors.push((text.len(), x.len() - rest.len()));
+            }
+        }
+        text.push_str(rest);
+    }
 }
-/// This function exists because the underlying XML parser will crash with certain &tag; expressions
-/// We just replace them with $FIXED_tag as text
+
+/// Replaces named entities with the characters they stand for, e.g. `&tau;`
+/// with `τ`, so that the XML parser accepts the document. Entities that stand
+/// for markup, like `&LT;`, become the XML escape for it
 pub fn sanitize_xml(x: &str) -> String {
-    let replacer = replace! {tau,alpha,beta,gamma,
-    delta,epsilon,zeta,eta,theta,iota,kappa,lambda,mu,nu,xi,
-    omicron,pi,rho,sigma,upsilon,phi,chi,psi,omega};
-    replacer(x.to_owned())
+    resolve_entities(x).text
+}
+pub(crate) fn resolve_entities(x: &str) -> Sanitized {
+    replace_entities(x, |text, _, resolved| {
+        text.push_str(markup_escaped(resolved))
+    })
+}
+fn markup_escaped(resolved: &str) -> &str {
+    match resolved {
+        "&" => "&amp;",
+        "<" => "&lt;",
+        ">" => "&gt;",
+        "\"" => "&quot;",
+        "'" => "&apos;",
+        _ => resolved,
+    }
+}
+/// The length of the markup at the start of `rest` that entities aren't
+/// replaced in: a CDATA section, comment or processing instruction.
+/// Unterminated ones run to the end, for the XML parser to reject
+fn verbatim(rest: &str) -> Option<usize> {
+    let until = |end: &str| rest.find(end).map_or(rest.len(), |pos| pos + end.len());
+    if rest.starts_with("<![CDATA[") {
+        Some(until("]]>"))
+    } else if rest.starts_with("<!--") {
+        Some(until("-->"))
+    } else if rest.starts_with("<?") {
+        Some(until("?>"))
+    } else {
+        None
+    }
+}
+/// Reads the `<!DOCTYPE ...>` at the start of `rest`, returning its length and
+/// the general entities its internal subset declares, e.g.
+/// `<!ENTITY km "K<sub>m</sub>">`. Entities with external values are skipped
+fn doctype(rest: &str) -> (usize, Vec<(String, String)>) {
+    let mut declared = Vec::new();
+    let mut in_subset = false;
+    let mut i = "<!DOCTYPE".len();
+    while let Some(c) = rest[i..].chars().next() {
+        let here = &rest[i..];
+        match c {
+            '"' | '\'' => {
+                // Quoted text can hold any of the characters looked at here
+                i += here[1..].find(c).map_or(here.len(), |end| end + 2);
+                continue;
+            }
+            '[' => in_subset = true,
+            ']' => in_subset = false,
+            '>' if !in_subset => return (i + 1, declared),
+            '<' if here.starts_with("<!--") => {
+                i += verbatim(here).unwrap_or(here.len());
+                continue;
+            }
+            '<' if here.starts_with("<!ENTITY") => {
+                if let Some((len, entity)) = entity_declaration(here) {
+                    declared.push(entity);
+                    i += len;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        i += c.len_utf8();
+    }
+    (rest.len(), declared)
+}
+/// Reads `<!ENTITY name "value"` up to the closing quote, decoding the
+/// character references of the value as XML does. Parameter entities and
+/// external ones are `None`
+fn entity_declaration(declaration: &str) -> Option<(usize, (String, String))> {
+    let body = declaration["<!ENTITY".len()..].trim_start();
+    let name_len = body.find(char::is_whitespace)?;
+    let name = &body[..name_len];
+    if name == "%" {
+        return None;
+    }
+    let literal = body[name_len..].trim_start();
+    let quote = literal.chars().next().filter(|&c| c == '"' || c == '\'')?;
+    let end = literal[1..].find(quote)?;
+    let mut value = String::new();
+    let mut rest = &literal[1..=end];
+    while let Some(pos) = rest.find('&') {
+        value.push_str(&rest[..pos]);
+        match char_reference(&rest[pos + 1..]) {
+            Some((decoded, _, len)) => {
+                value.push(decoded);
+                rest = &rest[pos + 1 + len..];
+            }
+            None => {
+                value.push('&');
+                rest = &rest[pos + 1..];
+            }
+        }
+    }
+    value.push_str(rest);
+    let len = declaration.len() - literal.len() + end + 2;
+    Some((len, (name.to_owned(), value)))
+}
+/// Decodes a reference like `#960;` or `#X3C0;` (what follows the `&`),
+/// returning the character, whether XML accepts the reference as written and
+/// its length. Unlike XML, an uppercase `X` is accepted and a code point that
+/// isn't a valid character becomes U+FFFD, as in HTML
+fn char_reference(after: &str) -> Option<(char, bool, usize)> {
+    let digits = after.strip_prefix('#')?;
+    let (radix, digits) = match digits.strip_prefix(['x', 'X']) {
+        Some(hex) => (16, hex),
+        None => (10, digits),
+    };
+    let lowercase_x = !after[1..].starts_with('X');
+    let end = digits.find(';')?;
+    if digits.starts_with('+') {
+        return None;
+    }
+    let code = u32::from_str_radix(&digits[..end], radix).ok()?;
+    let len = after.len() - digits.len() + end + 1;
+    Some(match std::char::from_u32(code) {
+        Some(c) if is_xml_char(c) => (c, lowercase_x, len),
+        _ => (std::char::REPLACEMENT_CHARACTER, false, len),
+    })
+}
+/// The `Char` production of XML 1.0
+fn is_xml_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\r' | ' '..='\u{D7FF}' | '\u{E000}'..='\u{FFFD}' | '\u{10000}'..)
+}
+/// Marks named entities with `entities::PRESERVED` in place of their `&`, so
+/// that the parsed text keeps them
+pub(crate) fn preserve_entities(x: &str) -> Sanitized {
+    // The same length, keeping the offsets of the anchors
+    let x = x.replace(entities::PRESERVED, "\u{FFFD}");
+    replace_entities(&x, |text, name, _| {
+        text.push(entities::PRESERVED);
+        text.push_str(name);
+        text.push(';');
+    })
 }
 #[cfg(test)]
 mod test {
@@ -31,12 +246,27 @@ mod test {
     #[test]
     fn test_replace() {
         let test = r#"<cn type="constant">  &tau;&bla; </cn>"#;
-        let replacer = replace! {tau,bla};
-        let output = replacer(test.to_owned());
         assert_eq!(
-            output,
-            test.replace("&tau;", "$FIXED_tau")
-                .replace("&bla;", "$FIXED_bla")
-        )
+            preserve_entities(test).text,
+            "<cn type=\"constant\">  \u{FDD0}tau;&bla; </cn>"
+        );
+        let test = "<mi>&alefsym;&infin;&ne;&Alpha;&le;&LT;&amp;&lt;&tau</mi>";
+        assert_eq!(sanitize_xml(test), "<mi>ℵ∞≠Α≤&lt;&amp;&lt;&tau</mi>");
+        let test = "<mi>&#960;&#x3C0;&#X3c0;&#X3C;&#0;&#x;&#12a;&#+1;</mi>";
+        assert_eq!(
+            sanitize_xml(test),
+            "<mi>&#960;&#x3C0;π&lt;\u{fffd}&#x;&#12a;&#+1;</mi>"
+        );
+        let test = "<mi>&pi;<![CDATA[&pi; <&pi;>]]>&pi;<![CDATA[&pi;</mi>";
+        assert_eq!(
+            sanitize_xml(test),
+            "<mi>π<![CDATA[&pi; <&pi;>]]>π<![CDATA[&pi;</mi>"
+        );
+        let valid = r#"<?xml version="1.0"?>
+<!-- &pi; -->
+<math a="&amp;&lt;"><?pi &pi;?><mi>&amp;&lt;&gt;&quot;&apos;&#960;&#x3c0;</mi></math>"#;
+        assert_eq!(sanitize_xml(valid), valid);
+        let test = r#"<!DOCTYPE math [<!ENTITY x "&#60;y>&pi;">]><mi>&x;</mi>"#;
+        assert_eq!(sanitize_xml(test), "<mi><y>π</mi>");
     }
 }
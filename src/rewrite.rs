@@ -0,0 +1,251 @@
+use super::{parse_infix, BuiltinOp, MathNode};
+use crate::error::ParseError;
+use std::collections::HashMap;
+
+/// How many times `rewrite` goes over the tree before giving up on a fixpoint
+const MAX_PASSES: usize = 100;
+
+/// An expression with wildcards in it. Each wildcard binds what it matches
+/// to its name, and a name used twice has to match equal expressions, so
+/// `_x - _x` matches `a - a` but not `a - b`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    /// Any expression
+    Any(String),
+    /// Any `<cn>`
+    Number(String),
+    /// Any `<ci>`
+    Identifier(String),
+    /// Any builtin operator, e.g. the head of `Pattern::Apply`
+    Operator(String),
+    /// An `<apply>` without qualifiers, of a head and operands matching these
+    Apply(Box<Pattern>, Vec<Pattern>),
+    /// This expression and nothing else, where numbers match by value
+    Exact(MathNode),
+}
+
+impl Pattern {
+    /// An application of `op` to operands matching `operands`
+    pub fn op(op: BuiltinOp, operands: Vec<Pattern>) -> Pattern {
+        Pattern::Apply(Box::new(Pattern::Exact(MathNode::Op(op))), operands)
+    }
+    /// Reads a pattern in the syntax of `parse_infix`, where an identifier
+    /// starting with `_` is an `Any` wildcard of the rest of its name, e.g.
+    /// `_x^2` or `_f(_x, 0)`
+    pub fn parse(text: &str) -> Result<Pattern, ParseError> {
+        Ok(Pattern::from(parse_infix(text)?))
+    }
+    fn names<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            Pattern::Any(name)
+            | Pattern::Number(name)
+            | Pattern::Identifier(name)
+            | Pattern::Operator(name) => names.push(name),
+            Pattern::Apply(head, operands) => {
+                head.names(names);
+                operands.iter().for_each(|o| o.names(names));
+            }
+            Pattern::Exact(_) => {}
+        }
+    }
+    /// Matches `node` against the pattern, adding what the wildcards bound to
+    /// `bindings`
+    fn matches(&self, node: &MathNode, bindings: &mut HashMap<String, MathNode>) -> bool {
+        let bind =
+            |name: &String, bindings: &mut HashMap<String, MathNode>| match bindings.get(name) {
+                Some(bound) => same(bound, node),
+                None => {
+                    bindings.insert(name.clone(), node.clone());
+                    true
+                }
+            };
+        match (self, node) {
+            (Pattern::Any(name), _)
+            | (Pattern::Number(name), MathNode::Cn { .. })
+            | (Pattern::Identifier(name), MathNode::Ci { .. })
+            | (Pattern::Operator(name), MathNode::Op(_)) => bind(name, bindings),
+            (Pattern::Apply(head, operands), MathNode::Apply { children, .. }) => {
+                let found = node.operands();
+                let mut content = children.iter().filter(|c| !c.is_markup());
+                match content.next() {
+                    Some(first)
+                        if found.len() == operands.len()
+                            && content.count() == found.len()
+                            && head.matches(first, bindings) =>
+                    {
+                        operands
+                            .iter()
+                            .zip(found)
+                            .all(|(p, o)| p.matches(o, bindings))
+                    }
+                    _ => false,
+                }
+            }
+            (Pattern::Exact(expected), _) => same(expected, node),
+            _ => false,
+        }
+    }
+    /// The expression the pattern stands for once its wildcards are replaced
+    /// by what they were bound to
+    fn instantiate(&self, bindings: &HashMap<String, MathNode>) -> MathNode {
+        match self {
+            Pattern::Any(name)
+            | Pattern::Number(name)
+            | Pattern::Identifier(name)
+            | Pattern::Operator(name) => bindings[name].clone(),
+            Pattern::Apply(head, operands) => MathNode::call(
+                head.instantiate(bindings),
+                operands.iter().map(|o| o.instantiate(bindings)),
+            ),
+            Pattern::Exact(node) => node.clone(),
+        }
+    }
+}
+
+/// Turns identifiers starting with `_` into `Any` wildcards, and the
+/// applications holding them into `Pattern::Apply`
+impl From<MathNode> for Pattern {
+    fn from(node: MathNode) -> Self {
+        if let Some(name) = node.as_ci().and_then(|name| name.strip_prefix('_')) {
+            return Pattern::Any(name.to_owned());
+        }
+        let is_plain_apply = matches!(&node, MathNode::Apply { children, .. }
+            if children.iter().filter(|c| !c.is_markup()).count() == node.operands().len() + 1);
+        if !is_plain_apply {
+            return Pattern::Exact(node);
+        }
+        let mut parts = node.children().into_iter().filter(|c| !c.is_markup());
+        let head = parts.next().cloned().map(Pattern::from);
+        let operands: Vec<Pattern> = parts.cloned().map(Pattern::from).collect();
+        match head {
+            Some(head) if operands.iter().chain(Some(&head)).any(has_wildcard) => {
+                Pattern::Apply(Box::new(head), operands)
+            }
+            _ => Pattern::Exact(node),
+        }
+    }
+}
+
+fn has_wildcard(pattern: &Pattern) -> bool {
+    !matches!(pattern, Pattern::Exact(_))
+}
+
+/// Whether two expressions are equal, comparing numbers by value so that
+/// `<cn>2</cn>` is `<cn type="integer">2</cn>`
+fn same(a: &MathNode, b: &MathNode) -> bool {
+    match (a, b) {
+        (MathNode::Cn { .. }, MathNode::Cn { .. }) => {
+            match (a.eval(&HashMap::new()), b.eval(&HashMap::new())) {
+                (Ok(x), Ok(y)) => x == y,
+                _ => a == b,
+            }
+        }
+        _ => a == b,
+    }
+}
+
+/// Rewrites whatever matches `pattern` into `replacement`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub pattern: Pattern,
+    pub replacement: Pattern,
+}
+
+impl Rule {
+    /// Fails when `replacement` uses a wildcard `pattern` doesn't bind
+    pub fn new(pattern: Pattern, replacement: Pattern) -> Result<Rule, ParseError> {
+        let mut bound = Vec::new();
+        pattern.names(&mut bound);
+        let mut used = Vec::new();
+        replacement.names(&mut used);
+        if let Some(name) = used.iter().find(|name| !bound.contains(name)) {
+            return Err(ParseError::InvalidStructure(format!(
+                "the replacement uses '{}', which the pattern doesn't bind",
+                name
+            )));
+        }
+        Ok(Rule {
+            pattern,
+            replacement,
+        })
+    }
+    /// A rule from two patterns in the syntax of `Pattern::parse`, e.g.
+    /// `Rule::parse("_x^2", "_x*_x")`
+    pub fn parse(pattern: &str, replacement: &str) -> Result<Rule, ParseError> {
+        Rule::new(Pattern::parse(pattern)?, Pattern::parse(replacement)?)
+    }
+    /// What `node` is rewritten to, if the rule matches it
+    pub fn apply(&self, node: &MathNode) -> Option<MathNode> {
+        let mut bindings = HashMap::new();
+        if self.pattern.matches(node, &mut bindings) {
+            Some(self.replacement.instantiate(&bindings))
+        } else {
+            None
+        }
+    }
+}
+
+/// Applies `rules` to every node of the tree, bottom-up and the first
+/// matching rule at each node, until none of them changes anything. Rules
+/// that never settle, like `_a + _b` to `_b + _a`, are cut off after a
+/// hundred passes over the tree
+pub fn rewrite(node: &MathNode, rules: &[Rule]) -> MathNode {
+    let mut current = node.clone();
+    for _ in 0..MAX_PASSES {
+        let mut changed = false;
+        current = current.map(|node| match rules.iter().find_map(|r| r.apply(&node)) {
+            Some(rewritten) if rewritten != node => {
+                changed = true;
+                rewritten
+            }
+            _ => node,
+        });
+        if !changed {
+            break;
+        }
+    }
+    current
+}
+
+#[cfg(test)]
+mod test {
+    use super::{rewrite, Pattern, Rule};
+    use crate::{parse_document, parse_infix, BuiltinOp};
+
+    #[test]
+    fn test_rewrite() {
+        let square = Rule::parse("_x^2", "_x*_x").unwrap();
+        let test = r#"<apply><plus/>
+                        <apply><power/><ci>a</ci><cn>2</cn></apply>
+                        <apply><power/><apply><power/><ci>b</ci><cn>2</cn></apply><cn>2</cn></apply>
+                      </apply>"#;
+        let rewritten = rewrite(&parse_document(test).unwrap(), &[square]);
+        assert_eq!(rewritten.to_string(), "a * a + b * b * b * b");
+
+        // A name bound twice has to match the same expression each time
+        let cancel = Rule::parse("_x - _x", "0").unwrap();
+        let test = parse_infix("(a - a) + (a - b)").unwrap();
+        assert_eq!(rewrite(&test, &[cancel]).to_string(), "0 + a - b");
+
+        // Typed wildcards, over any operator applied to a number
+        let swap = Rule::new(
+            Pattern::Apply(
+                Box::new(Pattern::Operator("f".into())),
+                vec![Pattern::Number("n".into()), Pattern::Identifier("v".into())],
+            ),
+            Pattern::Apply(
+                Box::new(Pattern::Operator("f".into())),
+                vec![Pattern::Identifier("v".into()), Pattern::Number("n".into())],
+            ),
+        )
+        .unwrap();
+        let test = parse_infix("2 * k + 3 * (x + 1)").unwrap();
+        assert_eq!(rewrite(&test, &[swap]).to_string(), "k * 2 + 3 * (x + 1)");
+
+        assert!(Rule::new(
+            Pattern::op(BuiltinOp::sin, vec![Pattern::Any("x".into())]),
+            Pattern::Any("y".into())
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,221 @@
+use super::{ConstantSymbol, MathNode, NumType};
+use crate::error::ParseError;
+use serde_derive::{Deserialize, Serialize};
+
+/// The csymbols SBML defines, identified by their `definitionURL`
+#[derive(Deserialize, Debug, Serialize, Eq, PartialEq, Clone, Copy, Hash)]
+pub enum SbmlSymbol {
+    /// The simulation time
+    Time,
+    /// `delay(x, d)`: the value of `x` at `d` time units in the past
+    Delay,
+    /// Avogadro's number
+    Avogadro,
+    /// `rateOf(x)`: the rate of change of `x` (SBML L3V2)
+    RateOf,
+}
+
+/// An SBML level and version, which decide the MathML subset it allows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SbmlLevelVersion {
+    pub level: u32,
+    pub version: u32,
+}
+
+impl SbmlLevelVersion {
+    pub fn new(level: u32, version: u32) -> Self {
+        SbmlLevelVersion { level, version }
+    }
+}
+
+/// The operators every SBML level with MathML allows
+const SBML_OPERATORS: &str = "eq neq gt lt geq leq plus minus times divide power root abs exp \
+                              ln log floor ceiling factorial and or xor not sin cos tan sec csc \
+                              cot sinh cosh tanh sech csch coth arcsin arccos arctan arcsec \
+                              arccsc arccot arcsinh arccosh arctanh arcsech arccsch arccoth";
+/// The operators SBML Level 3 Version 2 adds
+const SBML_L3V2_OPERATORS: &str = "max min quotient rem implies";
+
+const SBML_SYMBOLS: &str = "http://www.sbml.org/sbml/symbols/";
+/// The namespace `sbml:units` is written in when the document didn't say
+pub(crate) const SBML_CORE: &str = "http://www.sbml.org/sbml/level3/version1/core";
+
+/// Whether `ns` is the core namespace of some SBML level and version
+pub(crate) fn is_sbml_namespace(ns: &str) -> bool {
+    ns.starts_with("http://www.sbml.org/sbml/level")
+}
+
+impl SbmlSymbol {
+    /// Recognizes the `definitionURL` of an SBML csymbol
+    pub fn from_url(url: &str) -> Option<SbmlSymbol> {
+        match url.trim().strip_prefix(SBML_SYMBOLS)? {
+            "time" => Some(SbmlSymbol::Time),
+            "delay" => Some(SbmlSymbol::Delay),
+            "avogadro" => Some(SbmlSymbol::Avogadro),
+            "rateOf" => Some(SbmlSymbol::RateOf),
+            _ => None,
+        }
+    }
+    /// The `definitionURL` identifying this symbol
+    pub fn url(&self) -> String {
+        let name = match self {
+            SbmlSymbol::Time => "time",
+            SbmlSymbol::Delay => "delay",
+            SbmlSymbol::Avogadro => "avogadro",
+            SbmlSymbol::RateOf => "rateOf",
+        };
+        format!("{}{}", SBML_SYMBOLS, name)
+    }
+}
+
+/// What about the node `version` of SBML doesn't allow, if anything
+fn violation(node: &MathNode, version: SbmlLevelVersion) -> Option<String> {
+    let l3v2 = version >= SbmlLevelVersion::new(3, 2);
+    let name = match node {
+        MathNode::Root { .. }
+        | MathNode::Apply { .. }
+        | MathNode::Ci { .. }
+        | MathNode::Text(_)
+        | MathNode::Piecewise { .. }
+        | MathNode::Lambda { .. }
+        | MathNode::Bvar { degree: None, .. }
+        | MathNode::Degree(_)
+        | MathNode::Logbase(_)
+        | MathNode::Semantics { .. }
+        | MathNode::Comment(_)
+        | MathNode::PI(_, _) => return None,
+        MathNode::Op(op) => {
+            let allowed = |ops: &str| ops.split_whitespace().any(|o| o == op.to_string());
+            if allowed(SBML_OPERATORS) || (l3v2 && allowed(SBML_L3V2_OPERATORS)) {
+                return None;
+            }
+            format!("<{}/>", op)
+        }
+        MathNode::Constant(constant) => match constant {
+            ConstantSymbol::ImaginaryI | ConstantSymbol::EulerGamma => format!("<{}/>", constant),
+            _ => return None,
+        },
+        MathNode::Cn {
+            num_type,
+            base,
+            units,
+            ..
+        } => match num_type {
+            _ if *base != 10 => "a <cn> with a base other than 10".to_owned(),
+            _ if units.is_some() && version.level < 3 => "a <cn> with units".to_owned(),
+            NumType::Integer(_)
+            | NumType::Real(_)
+            | NumType::ENotation(_, _)
+            | NumType::Rational(_, _) => return None,
+            #[cfg(feature = "bigint")]
+            NumType::BigInteger(_) => return None,
+            _ => "a <cn> that isn't an integer, real, rational or e-notation".to_owned(),
+        },
+        MathNode::Csymbol { sbml, .. } => match sbml {
+            Some(SbmlSymbol::Time) | Some(SbmlSymbol::Delay) => return None,
+            Some(SbmlSymbol::Avogadro) if version.level >= 3 => return None,
+            Some(SbmlSymbol::RateOf) if l3v2 => return None,
+            Some(symbol) => format!("the {} csymbol", symbol.url()),
+            None => "a <csymbol> SBML doesn't define".to_owned(),
+        },
+        MathNode::Bvar { .. } => "a <bvar> with a <degree>".to_owned(),
+        MathNode::Lowlimit(_) => "<lowlimit>".to_owned(),
+        MathNode::Uplimit(_) => "<uplimit>".to_owned(),
+        MathNode::Condition(_) => "<condition>".to_owned(),
+        MathNode::Interval { .. } => "<interval>".to_owned(),
+        MathNode::Matrix(_) => "<matrix>".to_owned(),
+        MathNode::Vector(_) => "<vector>".to_owned(),
+        MathNode::Set(_) => "<set>".to_owned(),
+        MathNode::List { .. } => "<list>".to_owned(),
+        MathNode::NumberSet(set) => format!("<{}/>", set),
+        MathNode::Presentation(_) => "presentation markup".to_owned(),
+        MathNode::Cs(_) => "<cs>".to_owned(),
+        MathNode::Cbytes(_) => "<cbytes>".to_owned(),
+        MathNode::Cerror(_) => "<cerror>".to_owned(),
+        MathNode::Share(_) => "<share>".to_owned(),
+        MathNode::Bind { .. } => "<bind>".to_owned(),
+        MathNode::Unknown { name, .. } => format!("<{}>", name),
+        MathNode::Error { message, .. } => return Some(message.clone()),
+    };
+    Some(format!(
+        "{} is not allowed in SBML Level {} Version {}",
+        name, version.level, version.version
+    ))
+}
+
+/// Checks `node` only uses the MathML subset `version` of SBML allows, as
+/// libSBML does when reading a model: no `<partialdiff/>`, `<matrix>` or
+/// `<cn>` of another base, for example, and `<max/>`, `<min/>`, `<rem/>`,
+/// `<quotient/>`, `<implies/>` and `rateOf` only from Level 3 Version 2.
+/// Fails with the first thing outside of it. Level 1 has no MathML at all
+pub fn validate_sbml_subset(node: &MathNode, version: SbmlLevelVersion) -> Result<(), ParseError> {
+    if version.level < 2 {
+        return Err(ParseError::InvalidStructure(
+            "SBML Level 1 writes formulas as text, not MathML".to_owned(),
+        ));
+    }
+    match node.iter().find_map(|n| violation(n, version)) {
+        Some(message) => Err(ParseError::InvalidStructure(message)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{validate_sbml_subset, SbmlLevelVersion, SbmlSymbol};
+    use crate::{parse_document, MathNode};
+
+    #[test]
+    fn test_sbml_symbols() {
+        let test = r#"<apply><times/>
+                        <csymbol encoding="text" definitionURL="http://www.sbml.org/sbml/symbols/avogadro"> avogadro </csymbol>
+                        <csymbol definitionURL="http://www.sbml.org/sbml/symbols/time"> t </csymbol>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        let symbols: Vec<Option<SbmlSymbol>> = parsed
+            .operands()
+            .into_iter()
+            .map(|c| match c {
+                MathNode::Csymbol { sbml, .. } => *sbml,
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            symbols,
+            vec![Some(SbmlSymbol::Avogadro), Some(SbmlSymbol::Time)]
+        );
+        assert_eq!(
+            SbmlSymbol::from_url(&SbmlSymbol::RateOf.url()),
+            Some(SbmlSymbol::RateOf)
+        );
+    }
+
+    #[test]
+    fn test_validate_sbml_subset() {
+        let l3v1 = SbmlLevelVersion::new(3, 1);
+        let l3v2 = SbmlLevelVersion::new(3, 2);
+        let test = r#"<apply><times/><ci>k</ci>
+                        <apply><power/><ci>S</ci><cn type="integer">2</cn></apply>
+                        <apply><csymbol definitionURL="http://www.sbml.org/sbml/symbols/delay">delay</csymbol><ci>S</ci><cn>0.5</cn></apply>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert!(validate_sbml_subset(&parsed, l3v1).is_ok());
+        assert!(validate_sbml_subset(&parsed, SbmlLevelVersion::new(1, 2)).is_err());
+
+        let test = r#"<apply><max/><ci>a</ci><ci>b</ci></apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert!(validate_sbml_subset(&parsed, l3v2).is_ok());
+        assert_eq!(
+            validate_sbml_subset(&parsed, l3v1).unwrap_err().to_string(),
+            "invalid structure: <max/> is not allowed in SBML Level 3 Version 1"
+        );
+
+        for test in &[
+            r#"<apply><partialdiff/><bvar><ci>x</ci></bvar><ci>f</ci></apply>"#,
+            r#"<matrix><matrixrow><cn>1</cn></matrixrow></matrix>"#,
+            r#"<cn type="integer" base="16">1F</cn>"#,
+        ] {
+            assert!(validate_sbml_subset(&parse_document(test).unwrap(), l3v2).is_err());
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use super::MathNode;
+use std::collections::{HashMap, HashSet};
+
+/// Qualifiers of a binder that are evaluated outside the scope of its variables
+fn outside_scope(node: &MathNode) -> bool {
+    matches!(
+        node,
+        MathNode::Bvar { .. }
+            | MathNode::Lowlimit(_)
+            | MathNode::Uplimit(_)
+            | MathNode::Interval { .. }
+    )
+}
+
+/// Drops the bindings shadowed by `vars`, and renames the variables that would
+/// capture a free name of a replacement, e.g. `x` in `lambda(x, x + y)` when
+/// substituting `y := x`. Returns the bindings that apply inside the scope
+fn rebind(
+    vars: &mut [String],
+    in_scope: HashSet<&str>,
+    bindings: &HashMap<String, MathNode>,
+) -> HashMap<String, MathNode> {
+    let mut inner: HashMap<String, MathNode> = bindings
+        .iter()
+        .filter(|(name, _)| !vars.contains(name))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    let captured: HashSet<String> = inner.values().flat_map(MathNode::variables).collect();
+    for var in vars.iter_mut().filter(|v| captured.contains(*v)) {
+        let fresh = (1..)
+            .map(|i| format!("{}_{}", var, i))
+            .find(|f| !captured.contains(f) && !in_scope.contains(f.as_str()))
+            .unwrap_or_default();
+        inner.insert(var.clone(), MathNode::ci(fresh.clone()));
+        *var = fresh;
+    }
+    inner
+}
+
+/// The body of `lambda` with its bound variables replaced by `args`, if it is
+/// a `<lambda>` of that many variables
+fn beta(lambda: &MathNode, args: &[MathNode]) -> Option<MathNode> {
+    match lambda.to_apply() {
+        MathNode::Lambda { bound_vars, body } if bound_vars.len() == args.len() => {
+            let bindings: HashMap<String, MathNode> =
+                bound_vars.into_iter().zip(args.iter().cloned()).collect();
+            Some(body.substitute(&bindings))
+        }
+        _ => None,
+    }
+}
+
+/// Beta reduction: the body of `lambda` with each bound variable replaced by
+/// its argument, renaming the variables bound inside the body that would
+/// capture a free name of an argument. When `lambda` isn't a `<lambda>` (or
+/// a `<bind>` of `fns1`'s `lambda`) taking as many arguments, the application
+/// is returned as it is
+pub fn apply_lambda(lambda: &MathNode, args: &[MathNode]) -> MathNode {
+    beta(lambda, args).unwrap_or_else(|| MathNode::call(lambda.clone(), args.to_vec()))
+}
+
+/// The name of the function an `<ci>` or `<csymbol>` head calls
+pub(crate) fn function_name(head: &MathNode) -> Option<&str> {
+    match head {
+        MathNode::Csymbol { name, .. } => Some(name.trim()),
+        _ => head.as_ci(),
+    }
+}
+
+fn inline(node: MathNode, definitions: &HashMap<String, MathNode>, depth: usize) -> MathNode {
+    node.map(|node| {
+        let (head, args) = match &node {
+            MathNode::Apply { children, .. } => {
+                let mut content = children.iter().filter(|c| !c.is_markup());
+                let head = content.next();
+                let args: Vec<MathNode> = node.operands().into_iter().cloned().collect();
+                match head {
+                    Some(head) if content.count() == args.len() => (head, args),
+                    _ => return node,
+                }
+            }
+            _ => return node,
+        };
+        if let MathNode::Lambda { .. } | MathNode::Bind { .. } = head {
+            return beta(head, &args).unwrap_or(node);
+        }
+        let lambda = match function_name(head).and_then(|name| definitions.get(name)) {
+            Some(lambda) => lambda,
+            None => return node,
+        };
+        match beta(lambda, &args) {
+            // A body can call the other functions, but not itself, even indirectly
+            Some(body) if depth < definitions.len() => inline(body, definitions, depth + 1),
+            Some(body) => body,
+            None => node,
+        }
+    })
+}
+
+impl MathNode {
+    /// Inlines the calls of the functions in `definitions`, which maps the
+    /// names of SBML `<functionDefinition>`s to their `<lambda>`s, by beta
+    /// reduction as `apply_lambda` does, along with applications of a
+    /// `<lambda>` written in place. Calls with the wrong number of arguments
+    /// are left as they are
+    pub fn inline_functions(&self, definitions: &HashMap<String, MathNode>) -> MathNode {
+        inline(self.clone(), definitions, 0)
+    }
+    /// Replaces the free `<ci>` identifiers named in `bindings` by their values,
+    /// renaming bound variables where needed so no replacement gets captured
+    pub fn substitute(&self, bindings: &HashMap<String, MathNode>) -> MathNode {
+        let mut node = self.clone();
+        node.substitute_in_place(bindings);
+        node
+    }
+    fn substitute_in_place(&mut self, bindings: &HashMap<String, MathNode>) {
+        if bindings.is_empty() {
+            return;
+        }
+        let binder = !self.bound_variables().is_empty();
+        match self {
+            MathNode::Ci { children, .. } => {
+                if let Some(value) = bindings.get(&crate::text_content(children)) {
+                    *self = value.clone();
+                }
+            }
+            MathNode::Lambda { bound_vars, body } => {
+                let in_scope = body.iter().filter_map(MathNode::as_ci).collect();
+                let inner = rebind(bound_vars, in_scope, bindings);
+                body.substitute_in_place(&inner);
+            }
+            MathNode::Bind {
+                binder,
+                bound_vars,
+                condition,
+                body,
+            } => {
+                binder.substitute_in_place(bindings);
+                let in_scope = condition
+                    .as_deref()
+                    .into_iter()
+                    .chain(Some(&**body))
+                    .flat_map(MathNode::iter)
+                    .filter_map(MathNode::as_ci)
+                    .collect();
+                let inner = rebind(bound_vars, in_scope, bindings);
+                if let Some(condition) = condition {
+                    condition.substitute_in_place(&inner);
+                }
+                body.substitute_in_place(&inner);
+            }
+            MathNode::Apply { children, .. } if binder => {
+                let mut vars: Vec<String> = children
+                    .iter()
+                    .filter_map(|c| match c {
+                        MathNode::Bvar { name, .. } => Some(name.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                let in_scope = children
+                    .iter()
+                    .flat_map(MathNode::iter)
+                    .filter_map(MathNode::as_ci)
+                    .collect();
+                let inner = rebind(&mut vars, in_scope, bindings);
+                let mut renamed = vars.into_iter();
+                for child in children {
+                    match child {
+                        MathNode::Bvar { name, degree } => {
+                            if let Some(degree) = degree {
+                                degree.substitute_in_place(bindings);
+                            }
+                            *name = renamed.next().unwrap_or_default();
+                        }
+                        c if outside_scope(c) => c.substitute_in_place(bindings),
+                        c => c.substitute_in_place(&inner),
+                    }
+                }
+            }
+            _ => {
+                for child in self.children_mut() {
+                    child.substitute_in_place(bindings);
+                }
+            }
+        }
+    }
+    /// The names of every `<ci>` that isn't bound by an enclosing `<lambda>`,
+    /// `<bind>` or `<bvar>`, e.g. the species and parameters a kinetic law refers to
+    pub fn variables(&self) -> HashSet<String> {
+        let mut found = HashSet::new();
+        self.free_variables(&mut Vec::new(), &mut found);
+        found
+    }
+    fn free_variables<'a>(&'a self, bound: &mut Vec<&'a str>, found: &mut HashSet<String>) {
+        match self {
+            MathNode::Ci { children, .. } => {
+                let name = crate::text_content(children);
+                if !bound.contains(&name.as_str()) {
+                    found.insert(name);
+                }
+            }
+            MathNode::Lambda { bound_vars, body } => {
+                let depth = bound.len();
+                bound.extend(bound_vars.iter().map(String::as_str));
+                body.free_variables(bound, found);
+                bound.truncate(depth);
+            }
+            MathNode::Bind {
+                binder,
+                bound_vars,
+                condition,
+                body,
+            } => {
+                binder.free_variables(bound, found);
+                let depth = bound.len();
+                bound.extend(bound_vars.iter().map(String::as_str));
+                for part in condition.iter().chain(Some(body)) {
+                    part.free_variables(bound, found);
+                }
+                bound.truncate(depth);
+            }
+            MathNode::Apply { children, .. } => {
+                let vars = self.bound_variables();
+                let depth = bound.len();
+                for child in children.iter().filter(|c| outside_scope(c)) {
+                    child.free_variables(bound, found);
+                }
+                bound.extend(vars);
+                for child in children.iter().filter(|c| !outside_scope(c)) {
+                    child.free_variables(bound, found);
+                }
+                bound.truncate(depth);
+            }
+            _ => {
+                for child in self.children() {
+                    child.free_variables(bound, found);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::apply_lambda;
+    use crate::{math, parse_document, parse_infix, MathNode};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_variables() {
+        let test = r#"<math xmlns="http://www.w3.org/1998/Math/MathML">
+                        <apply><plus/>
+                            <apply><times/><ci> k1 </ci><ci> S </ci></apply>
+                            <apply><sum/><bvar><ci>i</ci></bvar>
+                                <lowlimit><cn>1</cn></lowlimit><uplimit><ci>n</ci></uplimit>
+                                <apply><times/><ci>i</ci><ci>k2</ci></apply>
+                            </apply>
+                            <apply><lambda><bvar><ci>x</ci></bvar><apply><power/><ci>x</ci><ci>h</ci></apply></lambda></apply>
+                        </apply>
+                      </math>"#;
+        let parsed = parse_document(test).unwrap();
+        let mut variables: Vec<String> = parsed.variables().into_iter().collect();
+        variables.sort();
+        assert_eq!(variables, vec!["S", "h", "k1", "k2", "n"]);
+
+        let test = r#"<bind><csymbol cd="fns1">lambda</csymbol><bvar><ci>x</ci></bvar>
+                        <apply><plus/><ci>x</ci><ci>y</ci></apply>
+                      </bind>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(parsed.bound_variables(), vec!["x"]);
+        assert_eq!(
+            parsed.variables().into_iter().collect::<Vec<_>>(),
+            vec!["y"]
+        );
+    }
+    #[test]
+    fn test_substitute() {
+        let bindings: HashMap<String, MathNode> =
+            vec![("y".to_owned(), math!(x + 1)), ("n".to_owned(), math!(m))]
+                .into_iter()
+                .collect();
+        assert_eq!(
+            math!(y * n + z).substitute(&bindings).to_string(),
+            "(x + 1) * m + z"
+        );
+
+        // The bound x is renamed rather than capturing the x of the replacement
+        let lambda = MathNode::lambda(vec!["x"], math!(x * y));
+        assert_eq!(
+            lambda.substitute(&bindings).to_string(),
+            "lambda(x_1, x_1 * (x + 1))"
+        );
+
+        let test = r#"<apply><sum/><bvar><ci>x</ci></bvar>
+                        <lowlimit><cn>1</cn></lowlimit><uplimit><ci>n</ci></uplimit>
+                        <apply><times/><ci>x</ci><ci>y</ci></apply>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(
+            parsed.substitute(&bindings).to_string(),
+            "sum(x_1 * (x + 1), x_1 = 1..m)"
+        );
+
+        let test = r#"<bind><csymbol cd="fns1">lambda</csymbol><bvar><ci>x</ci></bvar>
+                        <apply><times/><ci>x</ci><ci>y</ci></apply>
+                      </bind>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(
+            parsed.substitute(&bindings).to_string(),
+            "lambda(x_1, x_1 * (x + 1))"
+        );
+        let five: HashMap<String, MathNode> =
+            vec![("x".to_owned(), math!(5))].into_iter().collect();
+        assert_eq!(parsed.substitute(&five), parsed);
+    }
+
+    #[test]
+    fn test_inline_functions() {
+        let definitions: HashMap<String, MathNode> = vec![
+            ("f", MathNode::lambda(vec!["x", "y"], math!(x * y + k))),
+            (
+                "g",
+                MathNode::lambda(vec!["z"], parse_infix("f(z, z)").unwrap()),
+            ),
+        ]
+        .into_iter()
+        .map(|(name, lambda)| (name.to_owned(), lambda))
+        .collect();
+        let law = parse_infix("f(S, 2) + g(P) - f(S)").unwrap();
+        assert_eq!(
+            law.inline_functions(&definitions).to_string(),
+            "S * 2 + k + P * P + k - f(S)"
+        );
+
+        // The inner y is renamed rather than capturing the argument
+        let lambda = MathNode::lambda(vec!["x"], MathNode::lambda(vec!["y"], math!(x + y)));
+        assert_eq!(
+            apply_lambda(&lambda, &[math!(y)]),
+            MathNode::lambda(vec!["y_1"], math!(y + y_1))
+        );
+        assert_eq!(apply_lambda(&math!(f), &[math!(1)]).to_string(), "f(1)");
+    }
+}
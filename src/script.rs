@@ -0,0 +1,532 @@
+use super::{BuiltinOp, MathNode, SbmlSymbol};
+use crate::error::EvalError;
+use crate::eval::{aggregates, constant_symbol, eval_op, number};
+use crate::ops::parenthesize;
+
+/// Precedences shared by R and MATLAB, higher binds tighter. R's `!` binds
+/// looser than relations, MATLAB's `~` as tight as a unary minus
+const PREC_OR: u8 = 1;
+const PREC_AND: u8 = 2;
+const PREC_NOT: u8 = 3;
+const PREC_RELATION: u8 = 4;
+const PREC_ADD: u8 = 5;
+const PREC_MUL: u8 = 6;
+const PREC_UNARY: u8 = 7;
+const PREC_POWER: u8 = 8;
+const PREC_ATOM: u8 = 9;
+
+/// Identifiers R reserves, which are written in backticks
+const R_KEYWORDS: &str = "if else repeat while function for in next break TRUE FALSE NULL Inf \
+                          NaN NA NA_integer_ NA_real_ NA_character_ NA_complex_";
+
+/// Identifiers MATLAB reserves
+const MATLAB_KEYWORDS: &str = "break case catch classdef continue else elseif end for function \
+                               global if otherwise parfor persistent return spmd switch try \
+                               while";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Language {
+    R,
+    Matlab,
+}
+
+/// An expression together with the precedence of its outermost operator
+#[derive(Clone)]
+struct Code {
+    text: String,
+    precedence: u8,
+}
+
+impl Code {
+    fn new(text: String, precedence: u8) -> Self {
+        Code { text, precedence }
+    }
+    fn atom(text: String) -> Self {
+        Code::new(text, PREC_ATOM)
+    }
+    fn wrap(self, min_precedence: u8) -> String {
+        parenthesize(self.text, self.precedence, min_precedence)
+    }
+}
+
+fn call(name: &str, args: Vec<Code>) -> Code {
+    let args: Vec<String> = args.into_iter().map(|a| a.text).collect();
+    Code::atom(format!("{}({})", name, args.join(", ")))
+}
+
+/// Joins left associative operands, parenthesizing the right ones so that
+/// they're evaluated in the order of the tree
+fn left_assoc(args: Vec<Code>, symbol: &str, precedence: u8) -> Code {
+    let text = args
+        .into_iter()
+        .enumerate()
+        .map(|(i, a)| a.wrap(if i == 0 { precedence } else { precedence + 1 }))
+        .collect::<Vec<_>>()
+        .join(symbol);
+    Code::new(text, precedence)
+}
+
+struct Emitter {
+    language: Language,
+}
+
+impl Emitter {
+    fn name(&self) -> &'static str {
+        match self.language {
+            Language::R => "R",
+            Language::Matlab => "MATLAB",
+        }
+    }
+    fn unsupported<T>(&self, what: &str) -> Result<T, EvalError> {
+        Err(EvalError::CodeGeneration(format!(
+            "{} has no {} equivalent",
+            what,
+            self.name()
+        )))
+    }
+    fn literal(&self, value: f64) -> Code {
+        let text = if value.is_nan() {
+            "NaN".to_owned()
+        } else if value.is_infinite() {
+            if value > 0.0 { "Inf" } else { "-Inf" }.to_owned()
+        } else if value.fract() == 0.0 && value.abs() < 1e15 {
+            format!("{}", value)
+        } else {
+            format!("{:?}", value)
+        };
+        let precedence = if text.starts_with('-') {
+            PREC_UNARY
+        } else {
+            PREC_ATOM
+        };
+        Code::new(text, precedence)
+    }
+    fn boolean(&self, value: bool) -> Code {
+        Code::atom(
+            match (self.language, value) {
+                (Language::R, true) => "TRUE",
+                (Language::R, false) => "FALSE",
+                (Language::Matlab, true) => "true",
+                (Language::Matlab, false) => "false",
+            }
+            .to_owned(),
+        )
+    }
+    /// An identifier, in backticks where R wouldn't read it as one
+    fn identifier(&self, name: &str) -> Result<Code, EvalError> {
+        let mut chars = name.chars();
+        let first = chars.next();
+        let valid = match self.language {
+            Language::R => {
+                first.is_some_and(|c| c.is_ascii_alphabetic() || c == '.')
+                    && !(first == Some('.') && name[1..].starts_with(|c: char| c.is_ascii_digit()))
+                    && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+                    && !R_KEYWORDS.split_whitespace().any(|k| k == name)
+            }
+            Language::Matlab => {
+                first.is_some_and(|c| c.is_ascii_alphabetic())
+                    && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+                    && !MATLAB_KEYWORDS.split_whitespace().any(|k| k == name)
+            }
+        };
+        match (valid, self.language) {
+            (true, _) => Ok(Code::atom(name.to_owned())),
+            (false, Language::R) => Ok(Code::atom(format!("`{}`", name.replace('`', "\\`")))),
+            (false, Language::Matlab) => Err(EvalError::CodeGeneration(format!(
+                "'{}' is not a MATLAB identifier",
+                name
+            ))),
+        }
+    }
+    fn reciprocal(&self, x: Code) -> Code {
+        let symbol = match self.language {
+            Language::R => " / ",
+            Language::Matlab => " ./ ",
+        };
+        left_assoc(vec![self.literal(1.0), x], symbol, PREC_MUL)
+    }
+    fn binary(&self, args: Vec<Code>, r: &str, matlab: &str, precedence: u8) -> Code {
+        match self.language {
+            Language::R => left_assoc(args, r, precedence),
+            Language::Matlab => left_assoc(args, matlab, precedence),
+        }
+    }
+    /// `a rel b`. R can't chain comparisons at all, so a comparison on the
+    /// left is parenthesized too
+    fn relation(&self, args: Vec<Code>, symbol: &str) -> Code {
+        match self.language {
+            Language::R => {
+                let args: Vec<String> = args
+                    .into_iter()
+                    .map(|a| a.wrap(PREC_RELATION + 1))
+                    .collect();
+                Code::new(args.join(symbol), PREC_RELATION)
+            }
+            Language::Matlab => left_assoc(args, symbol, PREC_RELATION),
+        }
+    }
+    /// `a rel b & b rel c`, the meaning of chained relations, which neither
+    /// language chains itself
+    fn chain(&self, args: Vec<Code>, symbol: &str) -> Code {
+        let mut pairs: Vec<Code> = args
+            .windows(2)
+            .map(|w| self.relation(vec![w[0].clone(), w[1].clone()], symbol))
+            .collect();
+        match pairs.len() {
+            0 => self.boolean(true),
+            1 => pairs.remove(0),
+            _ => left_assoc(pairs, " & ", PREC_AND),
+        }
+    }
+    fn not(&self, x: Code) -> Code {
+        match self.language {
+            Language::R => Code::new(format!("!{}", x.wrap(PREC_RELATION)), PREC_NOT),
+            Language::Matlab => Code::new(format!("~{}", x.wrap(PREC_UNARY + 1)), PREC_UNARY),
+        }
+    }
+    fn emit(&self, node: &MathNode) -> Result<Code, EvalError> {
+        match node {
+            MathNode::Root { children, .. } => {
+                let mut expressions = children.iter().filter(|c| !c.is_markup());
+                match (expressions.next(), expressions.next()) {
+                    (Some(expression), None) => self.emit(expression),
+                    _ => Err(EvalError::UnsupportedNode(
+                        "a <math> element without exactly one expression".to_owned(),
+                    )),
+                }
+            }
+            MathNode::Apply { children, .. } if node.bound_variables().is_empty() => {
+                match children.first() {
+                    Some(MathNode::Op(op)) => self.apply(op, node),
+                    // Calls of function definitions, which the script has to provide
+                    Some(MathNode::Ci { children, .. }) => {
+                        let args = node
+                            .operands()
+                            .into_iter()
+                            .map(|a| self.emit(a))
+                            .collect::<Result<_, _>>()?;
+                        let name = self.identifier(&crate::text_content(children))?;
+                        Ok(call(&name.text, args))
+                    }
+                    _ => Err(EvalError::UnsupportedNode(
+                        "an application of a non-builtin function".to_owned(),
+                    )),
+                }
+            }
+            MathNode::Apply { .. } => self.unsupported("an operator over bound variables"),
+            MathNode::Ci { children, .. } => self.identifier(&crate::text_content(children)),
+            MathNode::Csymbol {
+                sbml: Some(SbmlSymbol::Time),
+                name,
+                ..
+            } => self.identifier(name.trim()),
+            MathNode::Cn { num_type, .. } => Ok(self.literal(number(num_type)?)),
+            MathNode::Constant(constant) => {
+                use crate::ConstantSymbol::*;
+                Ok(match constant {
+                    Pi => Code::atom("pi".to_owned()),
+                    ExponentialE => Code::atom("exp(1)".to_owned()),
+                    ImaginaryI => Code::atom("1i".to_owned()),
+                    True => self.boolean(true),
+                    False => self.boolean(false),
+                    _ => self.literal(constant_symbol(constant)?),
+                })
+            }
+            MathNode::Semantics { primary, .. } => self.emit(primary),
+            // R's `ifelse` takes the value of the first piece that holds, and
+            // works on vectors
+            MathNode::Piecewise { pieces, otherwise } => {
+                if self.language == Language::Matlab {
+                    return self.unsupported("a piecewise expression");
+                }
+                let mut value = match otherwise {
+                    Some(otherwise) => self.emit(otherwise)?,
+                    None => self.literal(f64::NAN),
+                };
+                for (piece, condition) in pieces.iter().rev() {
+                    value = call(
+                        "ifelse",
+                        vec![self.emit(condition)?, self.emit(piece)?, value],
+                    );
+                }
+                Ok(value)
+            }
+            _ => node
+                .eval(&Default::default())
+                .map(|value| self.literal(value)),
+        }
+    }
+    fn apply(&self, op: &BuiltinOp, node: &MathNode) -> Result<Code, EvalError> {
+        use BuiltinOp::*;
+        let r = self.language == Language::R;
+        let mut args = Vec::new();
+        for operand in node.operands() {
+            match operand {
+                MathNode::Set(items) | MathNode::List { items, .. } if aggregates(op) => {
+                    for item in items {
+                        args.push(self.emit(item)?);
+                    }
+                }
+                _ => args.push(self.emit(operand)?),
+            }
+        }
+        // Arity and support only depend on the operator, so check them as the interpreter would
+        eval_op(op, &vec![1.0; args.len()])?;
+        let raise = |base: Code, exponent: Code| {
+            // R's `^` is right associative, MATLAB's `.^` left associative,
+            // and both bind tighter than a unary minus on their left
+            let (symbol, base, exponent) = match r {
+                true => ("^", base.wrap(PREC_POWER + 1), exponent.wrap(PREC_POWER)),
+                false => (".^", base.wrap(PREC_POWER), exponent.wrap(PREC_POWER + 1)),
+            };
+            Code::new(format!("{}{}{}", base, symbol, exponent), PREC_POWER)
+        };
+        match (op, node.degree(), node.logbase()) {
+            (root, Some(degree), _) => {
+                let x = args.remove(0);
+                let degree = self.emit(degree)?;
+                return Ok(match (r, degree.text.as_str()) {
+                    (_, "2") => call("sqrt", vec![x]),
+                    (true, _) => raise(x, self.reciprocal(degree)),
+                    // The real root, as the interpreter takes of negative numbers
+                    (false, _) => call("nthroot", vec![x, degree]),
+                });
+            }
+            (log, _, Some(base)) => {
+                let base = self.emit(base)?;
+                return Ok(match r {
+                    true => call("log", vec![args.remove(0), base]),
+                    false => left_assoc(
+                        vec![call("log", args), call("log", vec![base])],
+                        " ./ ",
+                        PREC_MUL,
+                    ),
+                });
+            }
+            _ => {}
+        }
+        let value = match op {
+            plus if args.is_empty() => self.literal(0.0),
+            times if args.is_empty() => self.literal(1.0),
+            plus => left_assoc(args, " + ", PREC_ADD),
+            times => self.binary(args, " * ", " .* ", PREC_MUL),
+            minus if args.len() == 1 => Code::new(
+                format!("-{}", args.remove(0).wrap(PREC_UNARY + 1)),
+                PREC_UNARY,
+            ),
+            minus => left_assoc(args, " - ", PREC_ADD),
+            divide => self.binary(args, " / ", " ./ ", PREC_MUL),
+            power => {
+                let exponent = args.remove(1);
+                raise(args.remove(0), exponent)
+            }
+            // Truncated, unlike R's `%%`
+            rem if r => {
+                let (a, b) = (args[0].clone(), args[1].clone());
+                let whole = call("trunc", vec![left_assoc(args, " / ", PREC_MUL)]);
+                left_assoc(
+                    vec![a, left_assoc(vec![b, whole], " * ", PREC_MUL)],
+                    " - ",
+                    PREC_ADD,
+                )
+            }
+            rem => call("rem", args),
+            quotient => call(
+                if r { "trunc" } else { "fix" },
+                vec![self.binary(args, " / ", " ./ ", PREC_MUL)],
+            ),
+            factorof => {
+                let (a, b) = (args[0].clone(), args[1].clone());
+                let remainder = match r {
+                    true => {
+                        let whole = call(
+                            "trunc",
+                            vec![left_assoc(vec![b.clone(), a.clone()], " / ", PREC_MUL)],
+                        );
+                        left_assoc(
+                            vec![b, left_assoc(vec![a.clone(), whole], " * ", PREC_MUL)],
+                            " - ",
+                            PREC_ADD,
+                        )
+                    }
+                    false => call("rem", vec![b, a.clone()]),
+                };
+                left_assoc(
+                    vec![
+                        self.chain(vec![a, self.literal(0.0)], if r { " != " } else { " ~= " }),
+                        self.chain(vec![remainder, self.literal(0.0)], " == "),
+                    ],
+                    " & ",
+                    PREC_AND,
+                )
+            }
+            implies => {
+                let b = args.remove(1);
+                left_assoc(vec![self.not(args.remove(0)), b], " | ", PREC_OR)
+            }
+            root => call("sqrt", args),
+            abs => call("abs", args),
+            floor => call("floor", args),
+            ceiling => call("ceil", args),
+            factorial if r => call("factorial", args),
+            factorial => call(
+                "gamma",
+                vec![left_assoc(
+                    vec![args.remove(0), self.literal(1.0)],
+                    " + ",
+                    PREC_ADD,
+                )],
+            ),
+            exp => call("exp", args),
+            ln => call("log", args),
+            log => call("log10", args),
+            sin => call("sin", args),
+            cos => call("cos", args),
+            tan => call("tan", args),
+            sinh => call("sinh", args),
+            cosh => call("cosh", args),
+            tanh => call("tanh", args),
+            arcsin => call("asin", args),
+            arccos => call("acos", args),
+            arctan => call("atan", args),
+            arcsinh => call("asinh", args),
+            arccosh => call("acosh", args),
+            arctanh => call("atanh", args),
+            // MATLAB has these, R doesn't
+            sec | csc | cot | sech | csch | coth if !r => call(&op.to_string(), args),
+            arcsec | arccsc | arccot | arcsech | arccsch | arccoth if !r => {
+                call(&format!("a{}", &op.to_string()[3..]), args)
+            }
+            sec => self.reciprocal(call("cos", args)),
+            csc => self.reciprocal(call("sin", args)),
+            cot => self.reciprocal(call("tan", args)),
+            sech => self.reciprocal(call("cosh", args)),
+            csch => self.reciprocal(call("sinh", args)),
+            coth => self.reciprocal(call("tanh", args)),
+            arcsec => call("acos", vec![self.reciprocal(args.remove(0))]),
+            arccsc => call("asin", vec![self.reciprocal(args.remove(0))]),
+            arccot => call("atan", vec![self.reciprocal(args.remove(0))]),
+            arcsech => call("acosh", vec![self.reciprocal(args.remove(0))]),
+            arccsch => call("asinh", vec![self.reciprocal(args.remove(0))]),
+            arccoth => call("atanh", vec![self.reciprocal(args.remove(0))]),
+            not => self.not(args.remove(0)),
+            and if args.is_empty() => self.boolean(true),
+            or | xor if args.is_empty() => self.boolean(false),
+            and => left_assoc(args, " & ", PREC_AND),
+            or => left_assoc(args, " | ", PREC_OR),
+            xor => {
+                let mut args = args.into_iter();
+                let first = args.next().unwrap_or_else(|| self.boolean(false));
+                args.fold(first, |acc, a| call("xor", vec![acc, a]))
+            }
+            equivalent => {
+                let truths = args.into_iter().map(|a| self.not(a)).collect();
+                self.chain(truths, " == ")
+            }
+            eq => self.chain(args, " == "),
+            neq if r => self.relation(args, " != "),
+            neq => self.relation(args, " ~= "),
+            gt => self.chain(args, " > "),
+            lt => self.chain(args, " < "),
+            geq => self.chain(args, " >= "),
+            leq => self.chain(args, " <= "),
+            // Elementwise, so that they work on vectors as the arithmetic does
+            max if r => call("pmax", args),
+            min if r => call("pmin", args),
+            // MATLAB's take two arguments
+            max | min | gcd | lcm if !r => {
+                let name = op.to_string();
+                let mut args = args.into_iter();
+                let first = args.next().unwrap_or_else(|| self.literal(f64::NAN));
+                args.fold(first, |acc, a| call(&name, vec![acc, a]))
+            }
+            mean => {
+                let items = args
+                    .into_iter()
+                    .map(|a| a.text)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                match r {
+                    true => Code::atom(format!("mean(c({}))", items)),
+                    false => Code::atom(format!("mean([{}])", items)),
+                }
+            }
+            _ => return self.unsupported(&format!("'{}'", op)),
+        };
+        Ok(value)
+    }
+}
+
+impl MathNode {
+    /// Writes the expression in R, e.g. `Vmax * S / (Km + S)`, for analysis
+    /// scripts. It works on vectors as well, with `pmax` for `<max/>` and
+    /// `ifelse` for piecewise expressions, which are NaN where no piece
+    /// applies. Identifiers R can't read as they are are written in backticks
+    pub fn to_r(&self) -> Result<String, EvalError> {
+        Ok(Emitter {
+            language: Language::R,
+        }
+        .emit(self)?
+        .text)
+    }
+    /// Writes the expression in MATLAB or Octave, with elementwise operators
+    /// like `.*` and `.^` so that it works on vectors as well. Roots are the
+    /// real `nthroot`s the interpreter takes of negative numbers. Piecewise
+    /// expressions have no MATLAB expression syntax and are an error, as are
+    /// identifiers MATLAB can't read
+    pub fn to_matlab(&self) -> Result<String, EvalError> {
+        Ok(Emitter {
+            language: Language::Matlab,
+        }
+        .emit(self)?
+        .text)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parse_document;
+
+    #[test]
+    fn test_r_and_matlab() {
+        let test = r#"<apply><plus/>
+                        <apply><divide/><apply><times/><ci>Vmax</ci><ci>S</ci></apply><apply><plus/><ci>Km</ci><ci>S</ci></apply></apply>
+                        <apply><minus/><apply><power/><ci>S</ci><apply><power/><cn>2</cn><ci>n</ci></apply></apply></apply>
+                        <apply><root/><degree><cn>3</cn></degree><ci>S</ci></apply>
+                        <apply><log/><logbase><cn>2</cn></logbase><ci>S</ci></apply>
+                        <apply><max/><ci>S</ci><cn>1.5</cn><exponentiale/></apply>
+                        <apply><rem/><ci>S</ci><cn>4</cn></apply>
+                        <apply><csc/><ci>S</ci></apply>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(
+            parsed.to_r().unwrap(),
+            "Vmax * S / (Km + S) + -S^2^n + S^(1 / 3) + log(S, 2) + pmax(S, 1.5, exp(1)) \
+             + (S - 4 * trunc(S / 4)) + 1 / sin(S)"
+        );
+        assert_eq!(
+            parsed.to_matlab().unwrap(),
+            "Vmax .* S ./ (Km + S) + -S.^(2.^n) + nthroot(S, 3) + log(S) ./ log(2) \
+             + max(max(S, 1.5), exp(1)) + rem(S, 4) + csc(S)"
+        );
+
+        let test = r#"<piecewise>
+                        <piece><cn>0</cn><apply><lt/><cn>0</cn><ci>t</ci><cn>5</cn></apply></piece>
+                        <piece><ci>k.1</ci><apply><not/><apply><geq/><ci>t</ci><ci>if</ci></apply></apply></piece>
+                      </piecewise>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(
+            parsed.to_r().unwrap(),
+            "ifelse(0 < t & t < 5, 0, ifelse(!t >= `if`, k.1, NaN))"
+        );
+        assert!(parsed.to_matlab().is_err());
+
+        let test = r#"<apply><eq/>
+                        <apply><lt/><ci>a</ci><ci>b</ci></apply>
+                        <apply><lt/><ci>c</ci><ci>d</ci></apply>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(parsed.to_r().unwrap(), "(a < b) == (c < d)");
+        assert_eq!(parsed.to_matlab().unwrap(), "a < b == (c < d)");
+    }
+}
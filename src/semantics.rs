@@ -0,0 +1,144 @@
+use super::{parse_with, MathNode, ParserOptions};
+use crate::error::ParseError;
+use crate::writer::escape;
+use roxmltree::Node;
+use serde_derive::{Deserialize, Serialize};
+
+/// An alternate representation attached to a `<semantics>` element
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone, Hash)]
+pub enum Annotation {
+    /// `<annotation>`: the alternate as text, e.g. LaTeX source
+    Text {
+        encoding: Option<String>,
+        text: String,
+    },
+    /// `<annotation-xml>`: the alternate as markup, kept verbatim since it can
+    /// be in any vocabulary (OpenMath, another flavour of MathML...)
+    Xml {
+        encoding: Option<String>,
+        xml: String,
+    },
+}
+
+impl Annotation {
+    /// The `encoding` attribute, e.g. `"LaTeX"` or `"MathML-Presentation"`
+    pub fn encoding(&self) -> Option<&str> {
+        match self {
+            Annotation::Text { encoding, .. } | Annotation::Xml { encoding, .. } => {
+                encoding.as_deref()
+            }
+        }
+    }
+}
+
+/// Writes an element back out as XML, declaring its namespace where it changes
+fn write_xml(node: Node, parent_ns: Option<&str>, out: &mut String) {
+    if node.is_text() {
+        out.push_str(&escape(node.text().unwrap_or_default()));
+        return;
+    }
+    if !node.is_element() {
+        return;
+    }
+    let name = node.tag_name().name();
+    let ns = node.tag_name().namespace();
+    out.push('<');
+    out.push_str(name);
+    if let Some(ns) = ns.filter(|ns| Some(*ns) != parent_ns) {
+        out.push_str(&format!(" xmlns=\"{}\"", escape(ns)));
+    }
+    for attr in node.attributes().iter() {
+        out.push_str(&format!(" {}=\"{}\"", attr.name(), escape(attr.value())));
+    }
+    if !node.has_children() {
+        out.push_str("/>");
+        return;
+    }
+    out.push('>');
+    for child in node.children() {
+        write_xml(child, ns, out);
+    }
+    out.push_str(&format!("</{}>", name));
+}
+
+fn parse_annotation(node: Node) -> Result<Annotation, ParseError> {
+    let encoding = node.attribute("encoding").map(str::to_owned);
+    match node.tag_name().name() {
+        "annotation" => Ok(Annotation::Text {
+            encoding,
+            text: node.text().unwrap_or_default().trim().to_owned(),
+        }),
+        "annotation-xml" => {
+            let mut xml = String::new();
+            for child in node.children().filter(|n| n.is_element()) {
+                write_xml(child, None, &mut xml);
+            }
+            Ok(Annotation::Xml { encoding, xml })
+        }
+        other => Err(ParseError::InvalidStructure(format!(
+            "unexpected <{}> after the first child of <semantics>",
+            other
+        ))),
+    }
+}
+
+/// `<semantics>`: the first child is the expression, the rest are annotations
+pub(crate) fn parse_semantics(node: Node, opts: &ParserOptions) -> Result<MathNode, ParseError> {
+    let mut children = node.children().filter(|n| n.is_element());
+    let primary = match children.next() {
+        Some(child) if !child.tag_name().name().starts_with("annotation") => {
+            parse_with(child, opts)?
+        }
+        _ => {
+            return Err(ParseError::InvalidStructure(
+                "<semantics> must start with the annotated expression".to_owned(),
+            ))
+        }
+    };
+    Ok(MathNode::Semantics {
+        primary: Box::new(primary),
+        annotations: children.map(parse_annotation).collect::<Result<_, _>>()?,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::Annotation;
+    use crate::parse_document;
+    use crate::MathNode;
+
+    #[test]
+    fn test_semantics() {
+        let test = r#"<math xmlns="http://www.w3.org/1998/Math/MathML">
+                        <semantics>
+                            <apply><plus/><ci>x</ci><cn>1</cn></apply>
+                            <annotation encoding="LaTeX">x + 1</annotation>
+                            <annotation-xml encoding="MathML-Presentation">
+                                <mrow><mi>x</mi><mo>+</mo><mn>1</mn></mrow>
+                            </annotation-xml>
+                        </semantics>
+                      </math>"#;
+        let parsed = parse_document(test).unwrap();
+        let annotations = match &parsed {
+            MathNode::Root { children, .. } => match &children[0] {
+                MathNode::Semantics { annotations, .. } => annotations,
+                other => panic!("expected semantics, got {:?}", other),
+            },
+            other => panic!("expected a root, got {:?}", other),
+        };
+        assert_eq!(
+            annotations[0],
+            Annotation::Text {
+                encoding: Some("LaTeX".to_owned()),
+                text: "x + 1".to_owned()
+            }
+        );
+        assert_eq!(annotations[1].encoding(), Some("MathML-Presentation"));
+        assert!(matches!(
+            &annotations[1],
+            Annotation::Xml { xml, .. } if xml.starts_with("<mrow xmlns=\"http://www.w3.org/1998/Math/MathML\"><mi>x</mi>")
+        ));
+        assert_eq!(parse_document(&parsed.to_mathml()).unwrap(), parsed);
+        assert_eq!(parsed.to_latex(), "x + 1");
+    }
+}
@@ -0,0 +1,665 @@
+use super::{BuiltinOp, CiType, IntervalClosure, MathNode, NumType, Order, SbmlSymbol};
+use crate::error::ParseError;
+use crate::numbers::{ConstantSymbol, NamedConstant, NumberSet};
+
+/// Lines longer than this are broken up, one operand per line
+const WIDTH: usize = 80;
+
+/// A parsed s-expression: a bare atom like `plus` or `1.5`, a quoted string,
+/// or a parenthesized list
+#[derive(Debug, Clone, PartialEq)]
+enum Sexp {
+    Atom(String),
+    Str(String),
+    List(Vec<Sexp>),
+}
+
+fn list(head: &str, items: impl IntoIterator<Item = Sexp>) -> Sexp {
+    let mut list = vec![Sexp::Atom(head.to_owned())];
+    list.extend(items);
+    Sexp::List(list)
+}
+
+/// Whether the name reads back as the same bare atom, rather than as a
+/// number or a keyword option
+fn is_symbol(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(':')
+        && !name
+            .chars()
+            .any(|c| c.is_whitespace() || "()\";".contains(c))
+        && name.parse::<f64>().is_err()
+        && name.parse::<i64>().is_err()
+}
+
+fn name(name: &str) -> Sexp {
+    if is_symbol(name) {
+        Sexp::Atom(name.to_owned())
+    } else {
+        Sexp::Str(name.to_owned())
+    }
+}
+
+/// Appends `:key value` for each option that is set
+fn options(items: &mut Vec<Sexp>, options: &[(&str, Option<&str>)]) {
+    for (key, value) in options {
+        if let Some(value) = value {
+            items.push(Sexp::Atom(format!(":{}", key)));
+            items.push(Sexp::Str((*value).to_owned()));
+        }
+    }
+}
+
+fn number(num_type: &NumType) -> Vec<Sexp> {
+    let atom = |text: String| Sexp::Atom(text);
+    match num_type {
+        NumType::Integer(i) => vec![atom("integer".to_owned()), atom(i.to_string())],
+        NumType::Real(r) => vec![atom("real".to_owned()), atom(format!("{:?}", r))],
+        NumType::Rational(a, b) => vec![
+            atom("rational".to_owned()),
+            atom(a.to_string()),
+            atom(b.to_string()),
+        ],
+        NumType::ComplexCartesian(a, b) => vec![
+            atom("complex-cartesian".to_owned()),
+            atom(format!("{:?}", a)),
+            atom(format!("{:?}", b)),
+        ],
+        NumType::ComplexPolar(a, b) => vec![
+            atom("complex-polar".to_owned()),
+            atom(format!("{:?}", a)),
+            atom(format!("{:?}", b)),
+        ],
+        NumType::ENotation(mantissa, exponent) => vec![
+            atom("e-notation".to_owned()),
+            atom(format!("{:?}", mantissa)),
+            atom(exponent.to_string()),
+        ],
+        NumType::Constant(constant) => vec![
+            atom("constant".to_owned()),
+            Sexp::Str(constant.symbol().to_owned()),
+        ],
+        NumType::Other { type_name, text } => vec![
+            atom("other".to_owned()),
+            Sexp::Str(type_name.clone()),
+            Sexp::Str(text.clone()),
+        ],
+        #[cfg(feature = "bigint")]
+        NumType::BigInteger(i) => vec![atom("integer".to_owned()), Sexp::Str(i.to_string())],
+    }
+}
+
+fn to_sexp(node: &MathNode) -> Sexp {
+    let all = |children: &[MathNode]| -> Vec<Sexp> {
+        children
+            .iter()
+            .filter(|c| !c.is_markup())
+            .map(to_sexp)
+            .collect()
+    };
+    match node {
+        MathNode::Root { children, .. } => list("math", all(children)),
+        MathNode::Apply { children, .. } => {
+            let children = all(children);
+            match node.children().into_iter().find(|c| !c.is_markup()) {
+                Some(MathNode::Op(op)) => list(&op.to_string(), children.into_iter().skip(1)),
+                _ => list("apply", children),
+            }
+        }
+        MathNode::Op(op) => list("op", vec![Sexp::Atom(op.to_string())]),
+        MathNode::Ci {
+            children, ci_type, ..
+        } if children.iter().all(|c| matches!(c, MathNode::Text(_))) => {
+            let text = crate::text_content(children);
+            match ci_type {
+                None => name(&text),
+                Some(ci_type) => list(
+                    "ci",
+                    vec![
+                        name(&text),
+                        Sexp::Atom(":type".to_owned()),
+                        Sexp::Atom(ci_type.to_string()),
+                    ],
+                ),
+            }
+        }
+        MathNode::Csymbol {
+            definition_url,
+            cd,
+            encoding,
+            name,
+            ..
+        } => {
+            let mut items = vec![Sexp::Str(name.clone())];
+            options(
+                &mut items,
+                &[
+                    ("cd", cd.as_deref()),
+                    ("url", definition_url.as_deref()),
+                    ("encoding", encoding.as_deref()),
+                ],
+            );
+            list("csymbol", items)
+        }
+        MathNode::Cn {
+            num_type,
+            base,
+            definition_url,
+            encoding,
+            units,
+            units_namespace,
+            ..
+        } => {
+            let plain = *base == 10 && definition_url.is_none() && encoding.is_none();
+            match num_type {
+                NumType::Integer(i) if plain && units.is_none() => Sexp::Atom(i.to_string()),
+                NumType::Real(r) if plain && units.is_none() && r.is_finite() => {
+                    Sexp::Atom(format!("{:?}", r))
+                }
+                _ => {
+                    let mut items = number(num_type);
+                    if *base != 10 {
+                        items.push(Sexp::Atom(":base".to_owned()));
+                        items.push(Sexp::Atom(base.to_string()));
+                    }
+                    options(
+                        &mut items,
+                        &[
+                            ("url", definition_url.as_deref()),
+                            ("encoding", encoding.as_deref()),
+                            ("units", units.as_deref()),
+                            ("units-namespace", units_namespace.as_deref()),
+                        ],
+                    );
+                    list("cn", items)
+                }
+            }
+        }
+        MathNode::Constant(constant) => list(&constant.to_string(), None),
+        MathNode::NumberSet(set) => list(&set.to_string(), None),
+        MathNode::Piecewise { pieces, otherwise } => list(
+            "piecewise",
+            pieces
+                .iter()
+                .map(|(value, condition)| list("piece", vec![to_sexp(value), to_sexp(condition)]))
+                .chain(
+                    otherwise
+                        .iter()
+                        .map(|otherwise| list("otherwise", vec![to_sexp(otherwise)])),
+                ),
+        ),
+        MathNode::Lambda { bound_vars, body } => list(
+            "lambda",
+            vec![
+                Sexp::List(bound_vars.iter().map(|v| name(v)).collect()),
+                to_sexp(body),
+            ],
+        ),
+        MathNode::Degree(inner) => list("degree", vec![to_sexp(inner)]),
+        MathNode::Logbase(inner) => list("logbase", vec![to_sexp(inner)]),
+        MathNode::Lowlimit(inner) => list("lowlimit", vec![to_sexp(inner)]),
+        MathNode::Uplimit(inner) => list("uplimit", vec![to_sexp(inner)]),
+        MathNode::Condition(inner) => list("condition", vec![to_sexp(inner)]),
+        MathNode::Bvar { name: var, degree } => list(
+            "bvar",
+            std::iter::once(name(var)).chain(degree.iter().map(|d| to_sexp(d))),
+        ),
+        MathNode::Interval { closure, low, high } => list(
+            "interval",
+            vec![
+                Sexp::Atom(serde_plain::to_string(closure).unwrap_or_default()),
+                to_sexp(low),
+                to_sexp(high),
+            ],
+        ),
+        MathNode::Matrix(rows) => list(
+            "matrix",
+            rows.iter()
+                .map(|row| list("matrixrow", row.iter().map(to_sexp))),
+        ),
+        MathNode::Vector(items) => list("vector", all(items)),
+        MathNode::Set(items) => list("set", all(items)),
+        MathNode::List { order, items } => {
+            let order = order.iter().flat_map(|order| {
+                vec![
+                    Sexp::Atom(":order".to_owned()),
+                    Sexp::Atom(serde_plain::to_string(order).unwrap_or_default()),
+                ]
+            });
+            list("list", order.chain(all(items)))
+        }
+        MathNode::Semantics {
+            primary,
+            annotations,
+        } if annotations.is_empty() => list("semantics", vec![to_sexp(primary)]),
+        // Whatever has no s-expression of its own is kept as MathML
+        _ => list("mathml", vec![Sexp::Str(node.to_mathml())]),
+    }
+}
+
+fn flat(sexp: &Sexp) -> String {
+    match sexp {
+        Sexp::Atom(atom) => atom.clone(),
+        Sexp::Str(text) => format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"")),
+        Sexp::List(items) => format!("({})", items.iter().map(flat).collect::<Vec<_>>().join(" ")),
+    }
+}
+
+fn pretty(sexp: &Sexp, indent: usize) -> String {
+    let text = flat(sexp);
+    match sexp {
+        Sexp::List(items) if indent + text.len() > WIDTH && items.len() > 1 => {
+            let (head, rest) = match &items[0] {
+                Sexp::Atom(head) => (head.clone(), &items[1..]),
+                _ => (String::new(), &items[..]),
+            };
+            let mut text = format!("({}", head);
+            for item in rest {
+                text += &format!("\n{}{}", " ".repeat(indent + 2), pretty(item, indent + 2));
+            }
+            text + ")"
+        }
+        _ => text,
+    }
+}
+
+impl MathNode {
+    /// Writes the tree as a Lisp-style s-expression, e.g. `(plus (times a x) b)`,
+    /// one operand per line where it gets longer than 80 columns. Numbers and
+    /// csymbols keep all their attributes, in forms like `(cn rational 1 2)` and
+    /// `(csymbol "time" :url "...")`, and read back exactly with `parse_sexpr`
+    pub fn to_sexpr(&self) -> String {
+        pretty(&to_sexp(self), 0)
+    }
+}
+
+struct Reader<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, ParseError> {
+        Err(ParseError::InvalidFormula {
+            position: self.position,
+            message: message.into(),
+        })
+    }
+    /// Skips whitespace and `;` comments
+    fn skip(&mut self) {
+        loop {
+            let rest = &self.text[self.position..];
+            let trimmed = rest.trim_start();
+            self.position += rest.len() - trimmed.len();
+            if trimmed.starts_with(';') {
+                self.position += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else {
+                break;
+            }
+        }
+    }
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+    fn read(&mut self) -> Result<Sexp, ParseError> {
+        self.skip();
+        match self.peek() {
+            None => self.error("unexpected end of input"),
+            Some(')') => self.error("unexpected ')'"),
+            Some('(') => {
+                self.position += 1;
+                let mut items = Vec::new();
+                loop {
+                    self.skip();
+                    match self.peek() {
+                        Some(')') => {
+                            self.position += 1;
+                            return Ok(Sexp::List(items));
+                        }
+                        None => return self.error("unclosed '('"),
+                        _ => items.push(self.read()?),
+                    }
+                }
+            }
+            Some('"') => {
+                self.position += 1;
+                let mut text = String::new();
+                let mut chars = self.text[self.position..].char_indices();
+                while let Some((i, c)) = chars.next() {
+                    match c {
+                        '"' => {
+                            self.position += i + 1;
+                            return Ok(Sexp::Str(text));
+                        }
+                        '\\' => text.extend(chars.next().map(|(_, c)| c)),
+                        c => text.push(c),
+                    }
+                }
+                self.error("unclosed string")
+            }
+            Some(_) => {
+                let rest = &self.text[self.position..];
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || "()\";".contains(c))
+                    .unwrap_or(rest.len());
+                self.position += end;
+                Ok(Sexp::Atom(rest[..end].to_owned()))
+            }
+        }
+    }
+}
+
+/// Reads the text of an atom or string
+fn text(sexp: &Sexp) -> Result<&str, String> {
+    match sexp {
+        Sexp::Atom(text) | Sexp::Str(text) => Ok(text),
+        Sexp::List(_) => Err(format!("expected a name, found {}", flat(sexp))),
+    }
+}
+
+fn parse<T: std::str::FromStr>(sexp: &Sexp) -> Result<T, String> {
+    let text = text(sexp)?;
+    text.parse()
+        .map_err(|_| format!("'{}' is not a valid number", text))
+}
+
+fn keyword<T: serde::de::DeserializeOwned>(sexp: &Sexp, what: &str) -> Result<T, String> {
+    let text = text(sexp)?;
+    serde_plain::from_str(text).map_err(|_| format!("'{}' is not a {}", text, what))
+}
+
+/// The `:key value` options of a form
+type Options<'a> = Vec<(&'a str, &'a Sexp)>;
+
+/// Splits the arguments of a form into the positional ones and its options
+fn split_options(args: &[Sexp]) -> Result<(Vec<&Sexp>, Options<'_>), String> {
+    let mut positional = Vec::new();
+    let mut options = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg {
+            Sexp::Atom(key) if key.starts_with(':') => match args.next() {
+                Some(value) => options.push((&key[1..], value)),
+                None => return Err(format!("option {} without a value", key)),
+            },
+            _ => positional.push(arg),
+        }
+    }
+    Ok((positional, options))
+}
+
+fn option<'a>(options: &[(&str, &'a Sexp)], key: &str) -> Result<Option<&'a str>, String> {
+    options
+        .iter()
+        .find(|(k, _)| *k == key)
+        .map(|(_, value)| text(value))
+        .transpose()
+}
+
+fn one(args: &[Sexp], form: &str) -> Result<Box<MathNode>, String> {
+    match args {
+        [arg] => Ok(Box::new(from_sexp(arg)?)),
+        _ => Err(format!("({} ...) takes one argument", form)),
+    }
+}
+
+fn cn(args: &[Sexp]) -> Result<MathNode, String> {
+    let (args, options) = split_options(args)?;
+    let num_type = match (
+        text(args.first().ok_or("(cn) without a type")?)?,
+        &args[1..],
+    ) {
+        ("integer", [Sexp::Str(digits)]) => match digits.parse() {
+            Ok(i) => NumType::Integer(i),
+            #[cfg(feature = "bigint")]
+            Err(_) => NumType::BigInteger(
+                digits
+                    .parse()
+                    .map_err(|_| format!("'{}' is not a valid integer", digits))?,
+            ),
+            #[cfg(not(feature = "bigint"))]
+            Err(_) => return Err(format!("'{}' is not a valid integer", digits)),
+        },
+        ("integer", [i]) => NumType::Integer(parse(i)?),
+        ("real", [r]) => NumType::Real(parse(r)?),
+        ("rational", [a, b]) => NumType::Rational(parse(a)?, parse(b)?),
+        ("complex-cartesian", [a, b]) => NumType::ComplexCartesian(parse(a)?, parse(b)?),
+        ("complex-polar", [a, b]) => NumType::ComplexPolar(parse(a)?, parse(b)?),
+        ("e-notation", [mantissa, exponent]) => {
+            NumType::ENotation(parse(mantissa)?, parse(exponent)?)
+        }
+        ("constant", [constant]) => NumType::Constant(NamedConstant::from(text(constant)?)),
+        ("other", [type_name, value]) => NumType::Other {
+            type_name: text(type_name)?.to_owned(),
+            text: text(value)?.to_owned(),
+        },
+        (num_type, _) => return Err(format!("invalid (cn {} ...)", num_type)),
+    };
+    let owned = |key| Ok::<_, String>(option(&options, key)?.map(str::to_owned));
+    Ok(MathNode::Cn {
+        num_type,
+        base: option(&options, "base")?
+            .map(|base| base.parse().map_err(|_| format!("invalid base '{}'", base)))
+            .transpose()?
+            .unwrap_or(10),
+        definition_url: owned("url")?,
+        encoding: owned("encoding")?,
+        units: owned("units")?,
+        units_namespace: owned("units-namespace")?,
+        attributes: Vec::new(),
+    })
+}
+
+fn from_sexp(sexp: &Sexp) -> Result<MathNode, String> {
+    let items = match sexp {
+        Sexp::Atom(atom) => {
+            return Ok(match (atom.parse::<i64>(), atom.parse::<f64>()) {
+                (Ok(i), _) => MathNode::integer(i),
+                (_, Ok(r)) => MathNode::cn(r),
+                _ => MathNode::ci(atom.as_str()),
+            })
+        }
+        Sexp::Str(name) => return Ok(MathNode::ci(name.as_str())),
+        Sexp::List(items) => items,
+    };
+    let (head, args) = match items.split_first() {
+        Some((Sexp::Atom(head), args)) => (head.as_str(), args),
+        _ => {
+            return Err(format!(
+                "expected an operator at the start of {}",
+                flat(sexp)
+            ))
+        }
+    };
+    let all = |args: &[Sexp]| args.iter().map(from_sexp).collect::<Result<Vec<_>, _>>();
+    Ok(match head {
+        "math" => MathNode::Root {
+            children: all(args)?,
+            attributes: Vec::new(),
+        },
+        "apply" => MathNode::Apply {
+            children: all(args)?,
+            attributes: Vec::new(),
+        },
+        "op" => match args {
+            [op] => MathNode::Op(keyword(op, "MathML operator")?),
+            _ => return Err("(op ...) takes one operator".to_owned()),
+        },
+        "ci" => {
+            let (args, options) = split_options(args)?;
+            match args.as_slice() {
+                [name] => MathNode::Ci {
+                    children: vec![MathNode::Text(text(name)?.to_owned())],
+                    ci_type: options
+                        .iter()
+                        .find(|(k, _)| *k == "type")
+                        .map(|(_, t)| keyword::<CiType>(t, "<ci> type"))
+                        .transpose()?,
+                    attributes: Vec::new(),
+                },
+                _ => return Err("(ci ...) takes one name".to_owned()),
+            }
+        }
+        "csymbol" => {
+            let (args, options) = split_options(args)?;
+            let definition_url = option(&options, "url")?.map(str::to_owned);
+            match args.as_slice() {
+                [name] => MathNode::Csymbol {
+                    sbml: definition_url.as_deref().and_then(SbmlSymbol::from_url),
+                    definition_url,
+                    cd: option(&options, "cd")?.map(str::to_owned),
+                    encoding: option(&options, "encoding")?.map(str::to_owned),
+                    name: text(name)?.to_owned(),
+                    attributes: Vec::new(),
+                },
+                _ => return Err("(csymbol ...) takes one name".to_owned()),
+            }
+        }
+        "cn" => cn(args)?,
+        "piecewise" => {
+            let mut pieces = Vec::new();
+            let mut otherwise = None;
+            for arg in args {
+                match arg {
+                    Sexp::List(items) => match items.as_slice() {
+                        [Sexp::Atom(piece), value, condition] if piece == "piece" => {
+                            pieces.push((from_sexp(value)?, from_sexp(condition)?))
+                        }
+                        [Sexp::Atom(form), value] if form == "otherwise" => {
+                            otherwise = Some(Box::new(from_sexp(value)?))
+                        }
+                        _ => return Err(format!("invalid piece {}", flat(arg))),
+                    },
+                    _ => return Err(format!("invalid piece {}", flat(arg))),
+                }
+            }
+            MathNode::Piecewise { pieces, otherwise }
+        }
+        "lambda" => match args {
+            [Sexp::List(vars), body] => MathNode::Lambda {
+                bound_vars: vars
+                    .iter()
+                    .map(|v| text(v).map(str::to_owned))
+                    .collect::<Result<_, _>>()?,
+                body: Box::new(from_sexp(body)?),
+            },
+            _ => return Err("(lambda ...) takes a list of variables and a body".to_owned()),
+        },
+        "degree" => MathNode::Degree(one(args, head)?),
+        "logbase" => MathNode::Logbase(one(args, head)?),
+        "lowlimit" => MathNode::Lowlimit(one(args, head)?),
+        "uplimit" => MathNode::Uplimit(one(args, head)?),
+        "condition" => MathNode::Condition(one(args, head)?),
+        "semantics" => MathNode::Semantics {
+            primary: one(args, head)?,
+            annotations: Vec::new(),
+        },
+        "bvar" => match args {
+            [name] | [name, _] => MathNode::Bvar {
+                name: text(name)?.to_owned(),
+                degree: args.get(1).map(from_sexp).transpose()?.map(Box::new),
+            },
+            _ => return Err("(bvar ...) takes a name and an optional degree".to_owned()),
+        },
+        "interval" => match args {
+            [closure, low, high] => MathNode::Interval {
+                closure: keyword::<IntervalClosure>(closure, "closure")?,
+                low: Box::new(from_sexp(low)?),
+                high: Box::new(from_sexp(high)?),
+            },
+            _ => return Err("(interval ...) takes a closure and two ends".to_owned()),
+        },
+        "matrix" => MathNode::Matrix(
+            args.iter()
+                .map(|row| match row {
+                    Sexp::List(cells) if cells.first() == Some(&Sexp::Atom("matrixrow".into())) => {
+                        all(&cells[1..])
+                    }
+                    _ => Err(format!("invalid matrix row {}", flat(row))),
+                })
+                .collect::<Result<_, _>>()?,
+        ),
+        "vector" => MathNode::Vector(all(args)?),
+        "set" => MathNode::Set(all(args)?),
+        "list" => {
+            let (order, items) = match args {
+                [Sexp::Atom(key), order, items @ ..] if key == ":order" => {
+                    (Some(keyword::<Order>(order, "list order")?), items)
+                }
+                _ => (None, args),
+            };
+            MathNode::List {
+                order,
+                items: all(items)?,
+            }
+        }
+        "mathml" => match args {
+            [Sexp::Str(mathml)] => crate::parse_document(mathml).map_err(|e| e.to_string())?,
+            _ => return Err("(mathml ...) takes one string".to_owned()),
+        },
+        _ => match (
+            args.is_empty(),
+            serde_plain::from_str::<ConstantSymbol>(head),
+            serde_plain::from_str::<NumberSet>(head),
+        ) {
+            (true, Ok(constant), _) => MathNode::Constant(constant),
+            (true, _, Ok(set)) => MathNode::NumberSet(set),
+            _ => MathNode::apply(
+                keyword::<BuiltinOp>(&items[0], "MathML operator")?,
+                all(args)?,
+            ),
+        },
+    })
+}
+
+/// Reads an s-expression as written by `MathNode::to_sexpr`. Bare numbers are
+/// `<cn>`s and other bare atoms or strings `<ci>`s, and `;` starts a comment
+pub fn parse_sexpr(text: &str) -> Result<MathNode, ParseError> {
+    let mut reader = Reader { text, position: 0 };
+    let sexp = reader.read()?;
+    reader.skip();
+    if reader.position < text.len() {
+        return reader.error("unexpected text after the expression");
+    }
+    from_sexp(&sexp).map_err(|message| ParseError::InvalidFormula {
+        position: 0,
+        message,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_sexpr;
+    use crate::parse_document;
+
+    #[test]
+    fn test_sexpr() {
+        let test = r#"<apply><plus/>
+                        <apply><times/><ci>a</ci><ci>x</ci></apply>
+                        <apply><times/><cn type="rational">1<sep/>3</cn><ci>k 1</ci></apply>
+                        <cn>0.1</cn>
+                        <apply><exp/><apply><times/><apply><minus/><cn>1e-10</cn></apply>
+                            <csymbol definitionURL="http://www.sbml.org/sbml/symbols/time">t</csymbol></apply></apply>
+                        <cn sbml:units="mole" xmlns:sbml="http://www.sbml.org/sbml/level3/version1/core">2</cn>
+                        <apply><int/><bvar><ci>s</ci></bvar><lowlimit><cn>0</cn></lowlimit><uplimit><infinity/></uplimit>
+                            <piecewise><piece><pi/><apply><lt/><ci>s</ci><cn>1</cn></apply></piece><otherwise><cn>0</cn></otherwise></piecewise>
+                        </apply>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        let written = parsed.to_sexpr();
+        assert_eq!(
+            written,
+            "(plus\n  (times a x)\n  (times (cn rational 1 3) \"k 1\")\n  0.1\n  (exp\n    (times\n      \
+             (minus 1e-10)\n      (csymbol \"t\" :url \"http://www.sbml.org/sbml/symbols/time\")))\n  \
+             (cn\n    real\n    2.0\n    :units\n    \"mole\"\n    :units-namespace\n    \
+             \"http://www.sbml.org/sbml/level3/version1/core\")\n  (int\n    (bvar s)\n    (lowlimit 0.0)\n    (uplimit (infinity))\n    \
+             (piecewise (piece (pi) (lt s 1.0)) (otherwise 0.0))))"
+        );
+        assert_eq!(parse_sexpr(&written).unwrap(), parsed);
+
+        let parsed = parse_sexpr("(plus (times a x) b) ; a line").unwrap();
+        assert_eq!(parsed.to_string(), "a * x + b");
+        assert!(parse_sexpr("(plus a").is_err());
+        assert!(parse_sexpr("(frobnicate a)").is_err());
+    }
+}
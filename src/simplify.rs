@@ -0,0 +1,229 @@
+use super::{BuiltinOp, MathNode, NumType};
+use std::collections::HashMap;
+
+/// Which rewrites `MathNode::simplify_with` may apply
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimplifyRules {
+    /// Evaluate applications whose operands are all numbers, e.g. `2 * 3` to `6`.
+    /// Integer operands are only folded when the result is an integer too
+    pub fold_constants: bool,
+    /// Drop identities such as `x + 0`, `x * 1`, `x^1`, `x^0`, `--x` and `ln(exp(x))`
+    pub identities: bool,
+    /// Rewrites that hold for real numbers but not for every float, since
+    /// they lose NaNs and infinities: `x * 0`, `x - x`, `x / x` and `exp(ln(x))`
+    pub unsafe_float: bool,
+}
+
+impl Default for SimplifyRules {
+    fn default() -> Self {
+        SimplifyRules {
+            fold_constants: true,
+            identities: true,
+            unsafe_float: false,
+        }
+    }
+}
+
+fn value(node: &MathNode) -> Option<f64> {
+    match node {
+        MathNode::Cn { .. } => node.eval(&HashMap::new()).ok(),
+        _ => None,
+    }
+}
+
+fn is(node: &MathNode, expected: f64) -> bool {
+    value(node) == Some(expected)
+}
+
+/// Operators whose result is a truth value rather than a number
+fn is_boolean(op: &BuiltinOp) -> bool {
+    use BuiltinOp::*;
+    matches!(op, and | or | xor | not | implies | equivalent) || op.precedence() == eq.precedence()
+}
+
+/// The single operand of an application of `op`
+fn unwrap_unary(node: &MathNode, op: BuiltinOp) -> Option<&MathNode> {
+    match node {
+        MathNode::Apply { children, .. } if children.first() == Some(&MathNode::Op(op)) => {
+            match children.as_slice() {
+                [_, operand] => Some(operand),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+pub(crate) fn fold(op: &BuiltinOp, node: &MathNode, operands: &[MathNode]) -> Option<MathNode> {
+    if !operands.iter().all(|o| value(o).is_some()) {
+        return None;
+    }
+    let result = node.eval(&HashMap::new()).ok()?;
+    let integers = operands.iter().all(|o| {
+        matches!(
+            o,
+            MathNode::Cn {
+                num_type: NumType::Integer(_),
+                ..
+            }
+        )
+    });
+    if is_boolean(op) {
+        Some(MathNode::from(result != 0.0))
+    } else if !result.is_finite() {
+        None
+    } else if integers {
+        // Keeps 1/3 and sqrt(2) exact rather than rounding them
+        if result.fract() == 0.0 && result.abs() < 2f64.powi(53) {
+            Some(MathNode::integer(result as i64))
+        } else {
+            None
+        }
+    } else {
+        Some(MathNode::cn(result))
+    }
+}
+
+fn rewrite(
+    op: BuiltinOp,
+    mut operands: Vec<MathNode>,
+    rules: &SimplifyRules,
+) -> Result<MathNode, Vec<MathNode>> {
+    use BuiltinOp::*;
+    if rules.identities {
+        match (op, operands.as_slice()) {
+            (plus, _) | (times, _) => {
+                let neutral = if op == plus { 0.0 } else { 1.0 };
+                operands.retain(|o| !is(o, neutral));
+                match operands.len() {
+                    0 => return Ok(MathNode::integer(neutral as i64)),
+                    1 => return Ok(operands.remove(0)),
+                    _ => {}
+                }
+            }
+            (minus, [x]) => {
+                if let Some(inner) = unwrap_unary(x, minus) {
+                    return Ok(inner.clone());
+                }
+            }
+            (minus, [x, zero]) if is(zero, 0.0) => return Ok(x.clone()),
+            (minus, [zero, x]) if is(zero, 0.0) => return Ok(-x.clone()),
+            (divide, [x, one]) if is(one, 1.0) => return Ok(x.clone()),
+            (power, [x, one]) if is(one, 1.0) => return Ok(x.clone()),
+            (power, [_, zero]) if is(zero, 0.0) => return Ok(MathNode::integer(1)),
+            (power, [one, _]) if is(one, 1.0) => return Ok(MathNode::integer(1)),
+            (ln, [x]) => {
+                if let Some(inner) = unwrap_unary(x, exp) {
+                    return Ok(inner.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+    if rules.unsafe_float {
+        match (op, operands.as_slice()) {
+            (times, _) if operands.iter().any(|o| is(o, 0.0)) => return Ok(MathNode::integer(0)),
+            (minus, [a, b]) if a == b => return Ok(MathNode::integer(0)),
+            (divide, [a, b]) if a == b => return Ok(MathNode::integer(1)),
+            (exp, [x]) => {
+                if let Some(inner) = unwrap_unary(x, ln) {
+                    return Ok(inner.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+    Err(operands)
+}
+
+fn simplify_node(node: MathNode, rules: &SimplifyRules) -> MathNode {
+    let op = match &node {
+        MathNode::Apply { children, .. } => match children.first() {
+            Some(MathNode::Op(op)) => *op,
+            _ => return node,
+        },
+        _ => return node,
+    };
+    if rules.fold_constants {
+        let operands: Vec<MathNode> = node.operands().into_iter().cloned().collect();
+        if let Some(folded) = fold(&op, &node, &operands) {
+            return folded;
+        }
+    }
+    // Qualifiers change the meaning of the operands (log bases, bound variables...)
+    let children = match node {
+        MathNode::Apply { children, .. }
+            if children
+                .iter()
+                .skip(1)
+                .all(|c| !c.is_qualifier() && !c.is_markup()) =>
+        {
+            children
+        }
+        other => return other,
+    };
+    match rewrite(op, children.into_iter().skip(1).collect(), rules) {
+        Ok(simplified) => simplified,
+        Err(operands) => MathNode::apply(op, operands),
+    }
+}
+
+impl MathNode {
+    /// Folds constants and drops identities, leaving out the rewrites that can
+    /// change the result of a float computation. See `simplify_with`
+    pub fn simplify(&self) -> MathNode {
+        self.simplify_with(&SimplifyRules::default())
+    }
+    /// Rewrites the tree bottom-up with the given set of rules
+    pub fn simplify_with(&self, rules: &SimplifyRules) -> MathNode {
+        self.clone().map(|node| simplify_node(node, rules))
+    }
+    /// Substitutes the values of the identifiers in `known`, e.g. the
+    /// parameters of a rate law, then folds constants and drops the pieces of
+    /// `<piecewise>`s that can no longer apply, leaving only the other
+    /// identifiers symbolic
+    pub fn partial_eval(&self, known: &HashMap<String, f64>) -> MathNode {
+        let bindings: HashMap<String, MathNode> = known
+            .iter()
+            .map(|(name, value)| (name.clone(), MathNode::cn(*value)))
+            .collect();
+        self.substitute(&bindings).prune_piecewise().simplify()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SimplifyRules;
+    use crate::{math, parse_infix, MathNode};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_simplify() {
+        assert_eq!(math!(2 * 3 + x).simplify(), math!(6 + x));
+        assert_eq!(math!((x + 0) * 1 - -y).simplify().to_string(), "x - -y");
+        assert_eq!(math!(--power(x, 1)).simplify(), math!(x));
+        assert_eq!(math!(ln(exp(x)) + power(y, 0)).simplify(), math!(x + 1));
+        assert_eq!(math!(1 / 3 + 0.5 * 2).simplify(), math!(1 / 3 + 1.0));
+        assert_eq!(math!(gt(2, 1)).simplify(), math!(true));
+
+        // Only with floating point unsafe rewrites enabled
+        assert_eq!(math!(x * 0 + y / y).simplify(), math!(x * 0 + y / y));
+        let rules = SimplifyRules {
+            unsafe_float: true,
+            ..SimplifyRules::default()
+        };
+        assert_eq!(math!(x * 0 + y / y).simplify_with(&rules), math!(1));
+    }
+
+    #[test]
+    fn test_partial_eval() {
+        let known: HashMap<String, f64> = vec![("Vmax", 10.0), ("Km", 2.0), ("T", 310.0)]
+            .into_iter()
+            .map(|(name, value)| (name.to_owned(), value))
+            .collect();
+        let law = parse_infix("Vmax * S / (Km + S) * piecewise(1, T > 300, 0)").unwrap();
+        assert_eq!(law.partial_eval(&known).to_string(), "10 * S / (2 + S)");
+        let law = MathNode::lambda(vec!["Km"], math!(Km * S));
+        assert_eq!(law.partial_eval(&known), law);
+    }
+}
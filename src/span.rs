@@ -0,0 +1,179 @@
+use super::{is_kept, MathNode, ParserOptions};
+use roxmltree::Node;
+use serde_derive::{Deserialize, Serialize};
+
+/// A place in the source text. `line` and `column` start at 1, the column
+/// counting characters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// The source text a node was parsed from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+/// The spans of a parsed tree, one per node in the order of `MathNode::iter`
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct SourceMap {
+    spans: Vec<Span>,
+}
+
+impl SourceMap {
+    /// The spans in the order of `MathNode::iter`
+    pub fn spans(&self) -> &[Span] {
+        &self.spans
+    }
+    /// Pairs every node of `tree`, which must be the tree this map was made
+    /// for, with where it came from
+    pub fn annotate<'a>(
+        &'a self,
+        tree: &'a MathNode,
+    ) -> impl Iterator<Item = (&'a MathNode, Span)> {
+        tree.iter().zip(self.spans.iter().copied())
+    }
+}
+
+/// Turns offsets into the sanitized text back into positions in the original
+pub(crate) struct Locator<'t> {
+    original: &'t str,
+    /// Where each replaced entity ends, in the sanitized and the original text
+    anchors: &'t [(usize, usize)],
+    line_starts: Vec<usize>,
+}
+
+impl<'t> Locator<'t> {
+    pub(crate) fn new(original: &'t str, anchors: &'t [(usize, usize)]) -> Self {
+        Locator {
+            original,
+            anchors,
+            line_starts: std::iter::once(0)
+                .chain(original.match_indices('\n').map(|(i, _)| i + 1))
+                .collect(),
+        }
+    }
+    fn position(&self, sanitized_offset: usize) -> Position {
+        // The text between two replaced entities is the same in both
+        let offset = match self
+            .anchors
+            .partition_point(|&(sanitized, _)| sanitized <= sanitized_offset)
+        {
+            0 => sanitized_offset,
+            i => {
+                let (sanitized, original) = self.anchors[i - 1];
+                original + sanitized_offset - sanitized
+            }
+        };
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let line_start = self.line_starts[line - 1];
+        Position {
+            offset,
+            line,
+            column: self.original[line_start..offset].chars().count() + 1,
+        }
+    }
+    pub(crate) fn span(&self, node: Node) -> Span {
+        let range = node.range();
+        Span {
+            start: self.position(range.start),
+            end: self.position(range.end),
+        }
+    }
+}
+
+/// The source nodes of `node.children()`, given `xml` that `node` was parsed from
+pub(crate) fn sources<'a, 'input>(
+    node: &MathNode,
+    xml: Node<'a, 'input>,
+    opts: &ParserOptions,
+) -> Vec<Node<'a, 'input>> {
+    let elements = |n: Node<'a, 'input>| n.children().filter(|c| c.is_element());
+    match node {
+        MathNode::Root { .. } | MathNode::Apply { .. } | MathNode::Unknown { .. } => {
+            xml.children().filter(|c| is_kept(*c, opts)).collect()
+        }
+        MathNode::Piecewise { .. } | MathNode::Matrix(_) => {
+            elements(xml).flat_map(|part| elements(part)).collect()
+        }
+        MathNode::Lambda { .. } => elements(xml)
+            .filter(|c| c.tag_name().name() != "bvar")
+            .collect(),
+        // A condition's source is what the `<condition>` holds
+        MathNode::Bind { .. } => elements(xml)
+            .filter(|c| c.tag_name().name() != "bvar")
+            .map(|c| match c.tag_name().name() {
+                "condition" => elements(c).next().unwrap_or(c),
+                _ => c,
+            })
+            .collect(),
+        MathNode::Bvar { .. } => elements(xml)
+            .filter(|c| c.tag_name().name() == "degree")
+            .collect(),
+        MathNode::Semantics { .. } => elements(xml).take(1).collect(),
+        _ if node.children().is_empty() => Vec::new(),
+        _ => elements(xml).collect(),
+    }
+}
+
+/// Matches the parsed `tree` back up with the document it came from
+pub(crate) fn source_map(
+    tree: &MathNode,
+    root: Node,
+    original: &str,
+    anchors: &[(usize, usize)],
+    opts: &ParserOptions,
+) -> SourceMap {
+    let locator = Locator::new(original, anchors);
+    let mut spans = Vec::new();
+    let mut stack = vec![(tree, root)];
+    while let Some((node, xml)) = stack.pop() {
+        spans.push(locator.span(xml));
+        let children = node.children();
+        let sources = sources(node, xml, opts);
+        debug_assert_eq!(children.len(), sources.len());
+        stack.extend(children.into_iter().zip(sources).rev());
+    }
+    SourceMap { spans }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{parse_document_with_spans, MathNode, ParserOptions};
+
+    #[test]
+    fn test_spans() {
+        let test = "<math>\n  <apply><times/>\n    <cn type=\"constant\">&tau;</cn> <ci>S3</ci>\n  </apply>\n</math>";
+        let (parsed, spans) = parse_document_with_spans(test, &ParserOptions::default()).unwrap();
+        assert_eq!(spans.spans().len(), parsed.iter().count());
+        let (ci, span) = spans
+            .annotate(&parsed)
+            .find(|(n, _)| n.as_ci().is_some())
+            .unwrap();
+        assert_eq!(ci, &MathNode::ci("S3"));
+        assert_eq!((span.start.line, span.start.column), (3, 36));
+        assert_eq!(&test[span.start.offset..span.end.offset], "<ci>S3</ci>");
+        assert_eq!(spans.spans()[0].start.offset, 0);
+        assert_eq!(spans.spans()[0].end.offset, test.len());
+
+        let test = r#"<math><!-- every kind of child -->
+            <piecewise><piece><cn>1</cn><apply><gt/><ci>x</ci><cn>0</cn></apply></piece><otherwise><cn>0</cn></otherwise></piecewise>
+            <lambda><bvar><ci>x</ci><degree><cn>2</cn></degree></bvar><apply><sin/><ci>x</ci></apply></lambda>
+            <matrix><matrixrow><cn>1</cn><cn>2</cn></matrixrow></matrix>
+            <semantics><ci>y</ci><annotation encoding="text">y</annotation></semantics>
+            <interval><cn>0</cn><cn>1</cn></interval>
+          </math>"#;
+        let (parsed, spans) = parse_document_with_spans(test, &ParserOptions::default()).unwrap();
+        assert_eq!(spans.spans().len(), parsed.iter().count());
+        for (node, span) in spans.annotate(&parsed) {
+            let source = &test[span.start.offset..span.end.offset];
+            if let Some(name) = node.as_ci() {
+                assert_eq!(source, format!("<ci>{}</ci>", name));
+            }
+        }
+    }
+}
@@ -0,0 +1,395 @@
+use super::MathNode;
+use crate::error::ParseError;
+use crate::openmath::{attribute, elements, structure};
+use crate::writer::{escape, MATHML_NS};
+use roxmltree::Node;
+use std::collections::HashMap;
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// `bytes` in base64, as `<cbytes>` holds them
+pub(crate) fn encode_base64(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let group = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |group, (i, b)| group | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => out.push(BASE64[(group >> (18 - 6 * i) & 63) as usize] as char),
+                false => out.push('='),
+            }
+        }
+    }
+    out
+}
+
+/// The bytes of base64 `text`, ignoring whitespace. `None` if it isn't base64
+pub(crate) fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = text.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    if !digits.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut bytes = Vec::new();
+    for chunk in digits.chunks(4) {
+        let padding = chunk.iter().rev().take_while(|b| **b == b'=').count();
+        if padding > 2 {
+            return None;
+        }
+        let mut group = 0u32;
+        for digit in &chunk[..4 - padding] {
+            let value = BASE64.iter().position(|b| b == digit)?;
+            group = group << 6 | value as u32;
+        }
+        group <<= 6 * padding as u32;
+        bytes.extend(group.to_be_bytes()[1..4 - padding].iter());
+    }
+    Some(bytes)
+}
+
+pub(crate) fn parse_cbytes(node: Node) -> Result<MathNode, ParseError> {
+    match decode_base64(node.text().unwrap_or_default()) {
+        Some(bytes) => Ok(MathNode::Cbytes(bytes)),
+        None => structure("<cbytes> doesn't hold base64"),
+    }
+}
+
+/// The `id` attribute of the elements that keep theirs
+fn id(node: &MathNode) -> Option<&str> {
+    let attributes = match node {
+        MathNode::Root { attributes, .. }
+        | MathNode::Apply { attributes, .. }
+        | MathNode::Ci { attributes, .. }
+        | MathNode::Csymbol { attributes, .. }
+        | MathNode::Cn { attributes, .. }
+        | MathNode::Unknown { attributes, .. } => attributes,
+        _ => return None,
+    };
+    attributes
+        .iter()
+        .find(|(name, _)| name.namespace.is_none() && name.local == "id")
+        .map(|(_, value)| value.as_str())
+}
+
+/// Replaces the `<share>`s in `node` by copies of what they link to, which
+/// may share in turn, but not with anything being resolved already
+fn resolve(
+    node: MathNode,
+    ids: &HashMap<&str, &MathNode>,
+    resolving: &mut Vec<String>,
+) -> Result<MathNode, ParseError> {
+    node.try_map(|node| match node {
+        MathNode::Share(href) => {
+            let target = href.trim_start_matches('#').to_owned();
+            if resolving.contains(&target) {
+                return Err(ParseError::InvalidStructure(format!(
+                    "<share href=\"{}\"> is part of what it links to",
+                    href
+                )));
+            }
+            let shared = match ids.get(target.as_str()) {
+                Some(shared) => (*shared).clone(),
+                None => {
+                    return Err(ParseError::InvalidStructure(format!(
+                        "<share href=\"{}\"> links to no element",
+                        href
+                    )))
+                }
+            };
+            resolving.push(target);
+            let resolved = resolve(shared, ids, resolving);
+            resolving.pop();
+            resolved
+        }
+        node => Ok(node),
+    })
+}
+
+/// Writes an object of `to_openmath` as the strict Content MathML element
+/// MathML 3 makes it correspond to
+fn write_strict(node: Node, out: &mut String) {
+    let text = || escape(node.text().unwrap_or_default().trim());
+    let name = || escape(node.attribute("name").unwrap_or_default());
+    match node.tag_name().name() {
+        "OMI" => out.push_str(&format!("<cn type=\"integer\">{}</cn>", text())),
+        "OMF" => match node.attribute("hex") {
+            Some(hex) => out.push_str(&format!("<cn type=\"hexdouble\">{}</cn>", escape(hex))),
+            None => out.push_str(&format!(
+                "<cn type=\"double\">{}</cn>",
+                escape(node.attribute("dec").unwrap_or_default())
+            )),
+        },
+        "OMV" => out.push_str(&format!("<ci>{}</ci>", name())),
+        "OMSTR" => out.push_str(&format!("<cs>{}</cs>", text())),
+        "OMB" => out.push_str(&format!("<cbytes>{}</cbytes>", text())),
+        "OMR" => out.push_str(&format!(
+            "<share href=\"{}\"/>",
+            escape(node.attribute("href").unwrap_or_default())
+        )),
+        "OMS" => {
+            out.push_str("<csymbol");
+            if let Some(base) = node.attribute("cdbase") {
+                out.push_str(&format!(" cdbase=\"{}\"", escape(base)));
+            }
+            out.push_str(&format!(
+                " cd=\"{}\">{}</csymbol>",
+                escape(node.attribute("cd").unwrap_or_default()),
+                name()
+            ));
+        }
+        "OMBVAR" => {
+            for variable in elements(node) {
+                out.push_str("<bvar>");
+                write_strict(variable, out);
+                out.push_str("</bvar>");
+            }
+        }
+        tag => {
+            let element = match tag {
+                "OMA" => "apply",
+                "OMBIND" => "bind",
+                "OME" => "cerror",
+                _ => "math",
+            };
+            out.push_str(&format!("<{}>", element));
+            for child in elements(node) {
+                write_strict(child, out);
+            }
+            out.push_str(&format!("</{}>", element));
+        }
+    }
+}
+
+/// Writes a strict Content MathML element as the OpenMath object it
+/// corresponds to
+fn read_strict(node: Node, out: &mut String) -> Result<(), ParseError> {
+    let text = || node.text().unwrap_or_default().trim().to_owned();
+    match node.tag_name().name() {
+        "math" => {
+            let objects: Vec<_> = elements(node).collect();
+            match objects.as_slice() {
+                [object] => {
+                    out.push_str("<OMOBJ>");
+                    read_strict(*object, out)?;
+                    out.push_str("</OMOBJ>");
+                }
+                _ => return structure("<math> holds a single object"),
+            }
+        }
+        "cn" => {
+            let number = text();
+            let base = node.attribute("base").unwrap_or("10").trim();
+            match node.attribute("type").map(str::trim) {
+                Some("integer") if base == "10" => {
+                    out.push_str(&format!("<OMI>{}</OMI>", escape(&number)))
+                }
+                Some("integer") => {
+                    let integer = base
+                        .parse()
+                        .ok()
+                        .and_then(|base| i64::from_str_radix(&number, base).ok())
+                        .ok_or_else(|| ParseError::InvalidNumber {
+                            num_type: "integer".to_owned(),
+                            text: number.clone(),
+                        })?;
+                    out.push_str(&format!("<OMI>{}</OMI>", integer));
+                }
+                Some("hexdouble") => out.push_str(&format!("<OMF hex=\"{}\"/>", escape(&number))),
+                Some("double") | Some("real") | None => {
+                    out.push_str(&format!("<OMF dec=\"{}\"/>", escape(&number)))
+                }
+                Some(other) => {
+                    return Err(ParseError::InvalidStructure(format!(
+                        "<cn type=\"{}\"> isn't strict Content MathML",
+                        other
+                    )))
+                }
+            }
+        }
+        "ci" => out.push_str(&format!("<OMV name=\"{}\"/>", escape(&text()))),
+        "cs" => out.push_str(&format!("<OMSTR>{}</OMSTR>", escape(&text()))),
+        "cbytes" => out.push_str(&format!("<OMB>{}</OMB>", escape(&text()))),
+        "csymbol" => {
+            out.push_str("<OMS");
+            if let Some(base) = node.attribute("cdbase") {
+                out.push_str(&format!(" cdbase=\"{}\"", escape(base.trim())));
+            }
+            out.push_str(&format!(
+                " cd=\"{}\" name=\"{}\"/>",
+                escape(&attribute(node, "cd")?),
+                escape(&text())
+            ));
+        }
+        "apply" | "cerror" => {
+            let element = match node.tag_name().name() {
+                "apply" => "OMA",
+                _ => "OME",
+            };
+            out.push_str(&format!("<{}>", element));
+            for child in elements(node) {
+                read_strict(child, out)?;
+            }
+            out.push_str(&format!("</{}>", element));
+        }
+        "bind" => {
+            let children: Vec<_> = elements(node).collect();
+            let (head, variables, body) = match children.as_slice() {
+                [head, variables @ .., body] if !variables.is_empty() => (head, variables, body),
+                _ => return structure("<bind> holds a binder, <bvar>s and a body"),
+            };
+            out.push_str("<OMBIND>");
+            read_strict(*head, out)?;
+            out.push_str("<OMBVAR>");
+            for variable in variables {
+                match elements(*variable).next() {
+                    Some(ci)
+                        if variable.tag_name().name() == "bvar" && ci.tag_name().name() == "ci" =>
+                    {
+                        read_strict(ci, out)?
+                    }
+                    _ => return structure("<bind> binds <bvar>s holding a <ci>"),
+                }
+            }
+            out.push_str("</OMBVAR>");
+            read_strict(*body, out)?;
+            out.push_str("</OMBIND>");
+        }
+        // A `<share>` stands for the element it references by its `id`, as
+        // long as that doesn't contain the `<share>` itself
+        "share" => {
+            let id = attribute(node, "href")?;
+            let id = id.trim_start_matches('#');
+            match node
+                .document()
+                .descendants()
+                .find(|n| n.attribute("id") == Some(id))
+            {
+                Some(shared) if !node.ancestors().any(|a| a == shared) => read_strict(shared, out)?,
+                _ => {
+                    return Err(ParseError::InvalidStructure(format!(
+                        "<share> references no object with the id \"{}\"",
+                        id
+                    )))
+                }
+            }
+        }
+        // Annotations only describe the object they start with
+        "semantics" => match elements(node).next() {
+            Some(object) => read_strict(object, out)?,
+            None => return structure("<semantics> is missing its object"),
+        },
+        name => return Err(ParseError::UnknownElement(name.to_owned())),
+    }
+    Ok(())
+}
+
+impl MathNode {
+    /// A copy of the tree with every `<share>` replaced by the element with
+    /// the `id` it links to. Fails if one links to no element of the tree or
+    /// to one containing it
+    pub fn resolve_shares(&self) -> Result<MathNode, ParseError> {
+        let ids = self.iter().filter_map(|n| Some((id(n)?, n))).collect();
+        resolve(self.clone(), &ids, &mut Vec::new())
+    }
+    /// Serialize this node as the strict Content MathML of MathML 3, where
+    /// every operator, constant and qualifier is a `<csymbol>` of its content
+    /// dictionary and bound variables are `<bind>`s. This is the same
+    /// encoding as `to_openmath`, so anything without a symbol becomes a
+    /// `<cerror>`
+    pub fn to_strict_mathml(&self) -> String {
+        let openmath = self.to_openmath();
+        let xml = roxmltree::Document::parse(&openmath).expect("to_openmath writes valid XML");
+        let mut out = String::new();
+        write_strict(xml.root_element(), &mut out);
+        out.replacen("<math>", &format!("<math xmlns=\"{}\">", MATHML_NS), 1)
+    }
+}
+
+/// Parses a strict Content MathML document, e.g. `<math><apply><csymbol
+/// cd="arith1">plus</csymbol>...</apply></math>`, into the pragmatic content
+/// MathML the rest of the crate uses. Symbols of the content dictionaries
+/// `to_strict_mathml` writes become operators, constants and qualifiers
+/// again, and `<share>`s the element they reference
+pub fn parse_strict(text: &str) -> Result<MathNode, ParseError> {
+    let xml = roxmltree::Document::parse(text)?;
+    let root = xml.root_element();
+    let mut openmath = String::new();
+    match root.tag_name().name() {
+        "math" => read_strict(root, &mut openmath)?,
+        _ => {
+            openmath.push_str("<OMOBJ>");
+            read_strict(root, &mut openmath)?;
+            openmath.push_str("</OMOBJ>");
+        }
+    }
+    crate::parse_openmath(&openmath)
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_strict;
+    use crate::{parse_document, MathNode};
+
+    #[test]
+    fn test_strict_mathml() {
+        let test =
+            r#"<apply><plus/><apply><sin/><ci>x</ci></apply><cn type="integer">2</cn></apply>"#;
+        assert_eq!(
+            parse_document(test).unwrap().to_strict_mathml(),
+            concat!(
+                r#"<math xmlns="http://www.w3.org/1998/Math/MathML"><apply>"#,
+                r#"<csymbol cd="arith1">plus</csymbol>"#,
+                r#"<apply><csymbol cd="transc1">sin</csymbol><ci>x</ci></apply>"#,
+                r#"<cn type="integer">2</cn></apply></math>"#
+            )
+        );
+
+        let test = r##"<math xmlns="http://www.w3.org/1998/Math/MathML">
+                        <apply><csymbol cd="relation1">eq</csymbol>
+                          <apply id="s"><csymbol cd="arith1">power</csymbol><ci>x</ci><cn type="integer" base="16">A</cn></apply>
+                          <share href="#s"/>
+                        </apply>
+                      </math>"##;
+        assert_eq!(parse_strict(test).unwrap().to_string(), "x^10 == x^10");
+        assert!(parse_strict("<apply><plus/><ci>x</ci></apply>").is_err());
+
+        // What `to_strict_mathml` writes reads back as the same tree
+        for test in &[
+            r#"<apply><forall/><bvar><ci>x</ci></bvar><apply><geq/><apply><abs/><ci>x</ci></apply><cn>0.5</cn></apply></apply>"#,
+            r#"<apply><int/><bvar><ci>t</ci></bvar><lowlimit><cn type="integer">0</cn></lowlimit><uplimit><ci>T</ci></uplimit>
+                 <apply><log/><logbase><cn type="integer">2</cn></logbase><ci>t</ci></apply></apply>"#,
+        ] {
+            let parsed = parse_document(test).unwrap();
+            assert_eq!(parse_strict(&parsed.to_strict_mathml()).unwrap(), parsed);
+        }
+    }
+
+    #[test]
+    fn test_strict_leaves() {
+        let test = r##"<math>
+                        <apply><eq/><apply id="s"><plus/><ci>x</ci><cn type="integer">1</cn></apply><share href="#s"/></apply>
+                        <cs>a &lt; b</cs>
+                        <cbytes>SGVs bG8=</cbytes>
+                        <cerror><csymbol cd="aritherror">DivisionByZero</csymbol><apply><divide/><ci>x</ci><cn type="integer">0</cn></apply></cerror>
+                      </math>"##;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(
+            parsed.to_string(),
+            "x + 1 == share(#s); \"a < b\"; cbytes(SGVsbG8=); cerror(DivisionByZero, x / 0)"
+        );
+        assert!(parsed
+            .iter()
+            .any(|n| *n == MathNode::Cbytes(b"Hello".to_vec())));
+        assert_eq!(parse_document(&parsed.to_mathml()).unwrap(), parsed);
+        assert!(parsed
+            .resolve_shares()
+            .unwrap()
+            .to_string()
+            .starts_with("x + 1 == x + 1;"));
+
+        let test = r##"<apply id="s"><plus/><ci>x</ci><share href="#s"/></apply>"##;
+        assert!(parse_document(test).unwrap().resolve_shares().is_err());
+        assert!(parse_document("<cbytes>SGVsbG8</cbytes>").is_err());
+    }
+}
@@ -0,0 +1,500 @@
+use super::{BuiltinOp, ConstantSymbol, IntervalClosure, MathNode, NamedConstant, NumType};
+use crate::numbers::NumberSet;
+use crate::ops::parenthesize;
+
+const PREC_ADD: u8 = 1;
+const PREC_MUL: u8 = 2;
+const PREC_UNARY: u8 = 3;
+const PREC_POWER: u8 = 4;
+const PREC_ATOM: u8 = 5;
+
+/// Names `sympify` would read as something other than a plain symbol: Python
+/// keywords, and SymPy's own constants and functions
+const RESERVED: &str = "and as assert async await break class continue def del elif else except \
+                        finally for from global if import in is lambda nonlocal not or pass raise \
+                        return try while with yield None True False E I N O Q S oo nan zoo pi \
+                        true false sqrt root exp log sin cos tan sec csc cot sinh cosh tanh sech \
+                        csch coth asin acos atan asec acsc acot asinh acosh atanh asech acsch \
+                        acoth re im arg conjugate gamma beta zeta li Li Abs Max Min Mod floor \
+                        ceiling factorial gcd lcm Eq Ne Lt Le Gt Ge And Or Not Xor Implies \
+                        Equivalent Sum Product Integral Derivative Piecewise Lambda Symbol \
+                        Function Rational Integer Float Matrix FiniteSet Interval Contains \
+                        EulerGamma GoldenRatio";
+
+struct Python {
+    text: String,
+    precedence: u8,
+}
+
+impl Python {
+    fn new(text: String, precedence: u8) -> Self {
+        Python { text, precedence }
+    }
+    fn atom(text: String) -> Self {
+        Python::new(text, PREC_ATOM)
+    }
+    fn wrap(self, min_precedence: u8) -> String {
+        parenthesize(self.text, self.precedence, min_precedence)
+    }
+}
+
+fn call(name: &str, args: Vec<Python>) -> Python {
+    let args: Vec<String> = args.into_iter().map(|a| a.text).collect();
+    Python::atom(format!("{}({})", name, args.join(", ")))
+}
+
+/// A Python string literal
+fn quoted(text: &str) -> String {
+    format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// A symbol's name as is where `sympify` reads it as that symbol, and as
+/// `Symbol('name')` otherwise, e.g. for `E` or `k cat`
+fn symbol(name: &str) -> Python {
+    let name = name.trim();
+    let identifier = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if identifier && !RESERVED.split_whitespace().any(|r| r == name) {
+        Python::atom(name.to_owned())
+    } else {
+        Python::atom(format!("Symbol({})", quoted(name)))
+    }
+}
+
+/// A call of an undefined function, `Function('f')(x)` when `f` isn't safe
+/// to write as is
+fn function_call(name: &str, args: Vec<Python>) -> Python {
+    let head = symbol(name).text;
+    let head = match head.strip_prefix("Symbol") {
+        Some(quoted) => format!("Function{}", quoted),
+        None => head,
+    };
+    call(&head, args)
+}
+
+/// A number whose text may start with a minus
+fn signed(text: String) -> Python {
+    let precedence = if text.starts_with('-') {
+        PREC_UNARY
+    } else {
+        PREC_ATOM
+    };
+    Python::new(text, precedence)
+}
+
+fn real(value: f64) -> Python {
+    if value.is_nan() {
+        Python::atom("nan".to_owned())
+    } else if value == f64::INFINITY {
+        Python::atom("oo".to_owned())
+    } else if value == f64::NEG_INFINITY {
+        Python::new("-oo".to_owned(), PREC_UNARY)
+    } else {
+        signed(format!("{:?}", value))
+    }
+}
+
+fn literal(num_type: &NumType) -> Python {
+    match num_type {
+        NumType::Real(r) => real(*r),
+        NumType::Integer(i) => signed(i.to_string()),
+        #[cfg(feature = "bigint")]
+        NumType::BigInteger(i) => signed(i.to_string()),
+        NumType::ENotation(m, e) => signed(format!("{:?}e{}", m, e)),
+        NumType::Rational(a, b) => Python::atom(format!("Rational({}, {})", a, b)),
+        NumType::ComplexCartesian(a, b) => Python::new(
+            format!("{} + {}*I", real(*a).text, real(*b).wrap(PREC_MUL)),
+            PREC_ADD,
+        ),
+        NumType::ComplexPolar(r, theta) => Python::new(
+            format!(
+                "{}*exp({}*I)",
+                real(*r).wrap(PREC_MUL),
+                real(*theta).wrap(PREC_MUL)
+            ),
+            PREC_MUL,
+        ),
+        NumType::Constant(NamedConstant::Pi) => Python::atom("pi".to_owned()),
+        NumType::Constant(NamedConstant::Tau) => Python::new("2*pi".to_owned(), PREC_MUL),
+        NumType::Constant(NamedConstant::E) => Python::atom("E".to_owned()),
+        NumType::Constant(NamedConstant::Gamma) => Python::atom("EulerGamma".to_owned()),
+        NumType::Constant(NamedConstant::GoldenRatio) => Python::atom("GoldenRatio".to_owned()),
+        NumType::Constant(NamedConstant::Custom(text)) => symbol(text),
+        NumType::Other { text, .. } => match text.trim().parse::<f64>() {
+            Ok(value) => real(value),
+            Err(_) => symbol(text),
+        },
+    }
+}
+
+/// The SymPy function applying `op` to its operands
+fn function_name(op: BuiltinOp) -> Option<&'static str> {
+    use BuiltinOp::*;
+    let name = match op {
+        exp => "exp",
+        ln => "log",
+        abs => "Abs",
+        floor => "floor",
+        ceiling => "ceiling",
+        factorial => "factorial",
+        max => "Max",
+        min => "Min",
+        conjugate => "conjugate",
+        arg => "arg",
+        real => "re",
+        imaginary => "im",
+        sin => "sin",
+        cos => "cos",
+        tan => "tan",
+        sec => "sec",
+        csc => "csc",
+        cot => "cot",
+        sinh => "sinh",
+        cosh => "cosh",
+        tanh => "tanh",
+        sech => "sech",
+        csch => "csch",
+        coth => "coth",
+        arcsin => "asin",
+        arccos => "acos",
+        arctan => "atan",
+        arcsec => "asec",
+        arccsc => "acsc",
+        arccot => "acot",
+        arcsinh => "asinh",
+        arccosh => "acosh",
+        arctanh => "atanh",
+        arcsech => "asech",
+        arccsch => "acsch",
+        arccoth => "acoth",
+        and => "And",
+        or => "Or",
+        not => "Not",
+        xor => "Xor",
+        implies => "Implies",
+        equivalent => "Equivalent",
+        r#in => "Contains",
+        determinant => "det",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// The SymPy relation for `op`, which isn't written with `<` and the like as
+/// Python chains those
+fn relation(op: BuiltinOp) -> Option<&'static str> {
+    use BuiltinOp::*;
+    let name = match op {
+        eq => "Eq",
+        neq => "Ne",
+        lt => "Lt",
+        leq => "Le",
+        gt => "Gt",
+        geq => "Ge",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// `(i, 1, n)` or `x`, the variable and limits of a sum, integral or derivative
+fn limits(apply: &MathNode) -> Vec<Python> {
+    let variables = apply.bvars();
+    let mut limits = Vec::new();
+    for (name, degree) in variables {
+        let variable = symbol(name).text;
+        limits.push(match (apply.limits(), degree) {
+            (Some((low, high)), _) => Python::atom(format!(
+                "({}, {}, {})",
+                variable,
+                python(low).text,
+                python(high).text
+            )),
+            (None, Some(degree)) => {
+                Python::atom(format!("({}, {})", variable, python(degree).text))
+            }
+            (None, None) => Python::atom(variable),
+        });
+    }
+    limits
+}
+
+fn python_op(op: BuiltinOp, apply: &MathNode) -> Python {
+    use BuiltinOp::*;
+    let operands = apply.operands();
+    let mut args: Vec<Python> = operands.iter().map(|a| python(a)).collect();
+    if !apply.bound_variables().is_empty() {
+        let name = match op {
+            sum => "Sum",
+            product => "Product",
+            int => "Integral",
+            diff | partialdiff => "Derivative",
+            _ => return Python::atom(apply.to_string()),
+        };
+        args.extend(limits(apply));
+        return call(name, args);
+    }
+    if let Some(name) = function_name(op) {
+        return call(name, args);
+    }
+    if let Some(name) = relation(op) {
+        // `a < b < c` holds if each neighbouring pair does
+        let pairs: Vec<Python> = operands
+            .windows(2)
+            .map(|pair| call(name, vec![python(pair[0]), python(pair[1])]))
+            .collect();
+        return match pairs.len() {
+            1 => pairs
+                .into_iter()
+                .next()
+                .unwrap_or_else(|| call(name, Vec::new())),
+            _ => call("And", pairs),
+        };
+    }
+    match (op, args.len()) {
+        (minus, 1) => {
+            let operand = args
+                .pop()
+                .map(|a| a.wrap(PREC_UNARY + 1))
+                .unwrap_or_default();
+            Python::new(format!("-{}", operand), PREC_UNARY)
+        }
+        (plus, n) | (minus, n) | (times, n) | (divide, n) if n >= 2 => {
+            let (precedence, symbol) = match op {
+                plus => (PREC_ADD, " + "),
+                minus => (PREC_ADD, " - "),
+                times => (PREC_MUL, "*"),
+                _ => (PREC_MUL, "/"),
+            };
+            // Left associative
+            let text = args
+                .into_iter()
+                .enumerate()
+                .map(|(i, a)| a.wrap(if i == 0 { precedence } else { precedence + 1 }))
+                .collect::<Vec<_>>()
+                .join(symbol);
+            Python::new(text, precedence)
+        }
+        // Right associative, and binds tighter than a minus on its left
+        (power, 2) => {
+            let exponent = args.pop().map(|a| a.wrap(PREC_POWER)).unwrap_or_default();
+            let base = args
+                .pop()
+                .map(|a| a.wrap(PREC_POWER + 1))
+                .unwrap_or_default();
+            Python::new(format!("{}**{}", base, exponent), PREC_POWER)
+        }
+        (root, 1) => match apply.degree() {
+            Some(degree) => {
+                args.push(python(degree));
+                call("root", args)
+            }
+            None => call("sqrt", args),
+        },
+        // SymPy's `log` is the natural logarithm
+        (log, 1) => {
+            args.push(match apply.logbase() {
+                Some(base) => python(base),
+                None => Python::atom("10".to_owned()),
+            });
+            call("log", args)
+        }
+        // Rounded toward zero like the evaluator, where SymPy's `floor` and
+        // `Mod` round toward negative infinity
+        (quotient, 2) | (rem, 2) => {
+            let divisor = args.pop().map(|a| a.wrap(PREC_MUL + 1)).unwrap_or_default();
+            let dividend = args.pop().map(|a| a.wrap(PREC_MUL)).unwrap_or_default();
+            let ratio = format!("{}/{}", dividend, divisor);
+            let truncated = format!("sign({0})*floor(Abs({0}))", ratio);
+            if op == quotient {
+                Python::new(truncated, PREC_MUL)
+            } else {
+                Python::new(
+                    format!("{} - {}*{}", dividend, divisor, truncated),
+                    PREC_ADD,
+                )
+            }
+        }
+        // Only pairs are supported, so longer lists are folded
+        (gcd, n) | (lcm, n) if n >= 2 => {
+            let name = if op == gcd { "gcd" } else { "lcm" };
+            let mut args = args.into_iter();
+            let first = args.next().unwrap_or_else(|| Python::atom("0".to_owned()));
+            args.fold(first, |acc, next| call(name, vec![acc, next]))
+        }
+        _ => Python::atom(apply.to_string()),
+    }
+}
+
+fn python(node: &MathNode) -> Python {
+    match node {
+        MathNode::Root { children, .. } => {
+            let objects: Vec<_> = children.iter().filter(|c| !c.is_markup()).collect();
+            match objects.as_slice() {
+                [object] => python(object),
+                objects => Python::atom(format!(
+                    "[{}]",
+                    objects
+                        .iter()
+                        .map(|c| python(c).text)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+            }
+        }
+        MathNode::Apply { children, .. } => {
+            let args = || node.operands().iter().map(|a| python(a)).collect();
+            match children.iter().find(|c| !c.is_markup()) {
+                Some(MathNode::Op(op)) => python_op(*op, node),
+                Some(MathNode::Ci { children, .. }) => {
+                    function_call(&crate::text_content(children), args())
+                }
+                Some(MathNode::Csymbol { name, .. }) => function_call(name, args()),
+                _ => Python::atom(node.to_string()),
+            }
+        }
+        MathNode::Ci { children, .. } => symbol(&crate::text_content(children)),
+        MathNode::Csymbol { name, .. } => symbol(name),
+        MathNode::Cn { num_type, .. } => literal(num_type),
+        MathNode::Constant(constant) => Python::atom(
+            match constant {
+                ConstantSymbol::Pi => "pi",
+                ConstantSymbol::ExponentialE => "E",
+                ConstantSymbol::ImaginaryI => "I",
+                ConstantSymbol::EulerGamma => "EulerGamma",
+                ConstantSymbol::Infinity => "oo",
+                ConstantSymbol::NotANumber => "nan",
+                ConstantSymbol::True => "true",
+                ConstantSymbol::False => "false",
+            }
+            .to_owned(),
+        ),
+        MathNode::NumberSet(set) => Python::atom(
+            match set {
+                NumberSet::Integers => "S.Integers",
+                NumberSet::Reals => "S.Reals",
+                NumberSet::Rationals => "S.Rationals",
+                NumberSet::NaturalNumbers => "S.Naturals0",
+                NumberSet::Complexes => "S.Complexes",
+                NumberSet::EmptySet => "S.EmptySet",
+                NumberSet::Primes => return Python::atom(node.to_string()),
+            }
+            .to_owned(),
+        ),
+        MathNode::Piecewise { pieces, otherwise } => {
+            let mut args = Vec::new();
+            for (value, condition) in pieces {
+                args.push(Python::atom(format!(
+                    "({}, {})",
+                    python(value).text,
+                    python(condition).text
+                )));
+            }
+            if let Some(otherwise) = otherwise {
+                args.push(Python::atom(format!("({}, True)", python(otherwise).text)));
+            }
+            call("Piecewise", args)
+        }
+        MathNode::Lambda { bound_vars, body } => {
+            let variables: Vec<String> = bound_vars.iter().map(|v| symbol(v).text).collect();
+            Python::atom(format!(
+                "Lambda(({},), {})",
+                variables.join(", "),
+                python(body).text
+            ))
+        }
+        MathNode::Interval { closure, low, high } => {
+            let (left_open, right_open) = match closure {
+                IntervalClosure::Closed => ("False", "False"),
+                IntervalClosure::Open => ("True", "True"),
+                IntervalClosure::OpenClosed => ("True", "False"),
+                IntervalClosure::ClosedOpen => ("False", "True"),
+            };
+            Python::atom(format!(
+                "Interval({}, {}, {}, {})",
+                python(low).text,
+                python(high).text,
+                left_open,
+                right_open
+            ))
+        }
+        MathNode::Set(items) => call("FiniteSet", items.iter().map(python).collect()),
+        MathNode::Vector(items) => Python::atom(format!(
+            "Matrix([{}])",
+            items
+                .iter()
+                .map(|c| python(c).text)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        MathNode::Matrix(rows) => Python::atom(format!(
+            "Matrix([{}])",
+            rows.iter()
+                .map(|row| format!(
+                    "[{}]",
+                    row.iter()
+                        .map(|c| python(c).text)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        MathNode::Semantics { primary, .. } => python(primary),
+        // SymPy has no counterpart, e.g. for selectors
+        _ => Python::atom(node.to_string()),
+    }
+}
+
+impl MathNode {
+    /// Writes the expression for SymPy's `sympify` or `parse_expr`, e.g.
+    /// `x + a*sin(t)` or `Piecewise((1, Lt(x, 0)), (Rational(1, 2), True))`.
+    /// Names SymPy would read as something else, like `E` or `lambda`, are
+    /// written as `Symbol('E')`, and relations as `Lt(a, b)` rather than
+    /// `a < b`. Things SymPy has no counterpart for are written as `Display`
+    /// does
+    pub fn to_sympy(&self) -> String {
+        python(self).text
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parse_document;
+
+    #[test]
+    fn test_to_sympy() {
+        let test = r#"<apply><plus/>
+                        <apply><times/><ci>a</ci><apply><sin/><ci>t</ci></apply></apply>
+                        <apply><minus/><apply><power/><ci>x</ci><cn type="integer">2</cn></apply></apply>
+                        <apply><divide/><ci>E</ci><apply><times/><ci>k_1</ci><ci>lambda</ci></apply></apply>
+                        <apply><power/><apply><minus/><ci>x</ci></apply><apply><power/><ci>y</ci><cn type="rational">1<sep/>2</cn></apply></apply>
+                        <apply><log/><ci>x</ci></apply>
+                        <apply><ci>f</ci><exponentiale/><cn>-1.5</cn></apply>
+                      </apply>"#;
+        assert_eq!(
+            parse_document(test).unwrap().to_sympy(),
+            "a*sin(t) + -x**2 + Symbol('E')/(k_1*Symbol('lambda')) + (-x)**y**Rational(1, 2) \
+             + log(x, 10) + f(E, -1.5)"
+        );
+
+        let test = r#"<piecewise>
+                        <piece><apply><sum/><bvar><ci>i</ci></bvar><lowlimit><cn type="integer">1</cn></lowlimit><uplimit><ci>n</ci></uplimit><ci>i</ci></apply>
+                          <apply><and/><apply><lt/><cn type="integer">0</cn><ci>x</ci><cn type="integer">1</cn></apply><true/></apply></piece>
+                        <otherwise><apply><diff/><bvar><ci>t</ci><degree><cn type="integer">2</cn></degree></bvar><apply><ci>S</ci><ci>t</ci></apply></apply></otherwise>
+                      </piecewise>"#;
+        assert_eq!(
+            parse_document(test).unwrap().to_sympy(),
+            "Piecewise((Sum(i, (i, 1, n)), And(And(Lt(0, x), Lt(x, 1)), true)), \
+             (Derivative(Function('S')(t), (t, 2)), True))"
+        );
+
+        let test = r#"<apply><plus/>
+                        <apply><quotient/><ci>a</ci><apply><plus/><ci>b</ci><ci>c</ci></apply></apply>
+                        <apply><rem/><ci>a</ci><ci>b</ci></apply>
+                      </apply>"#;
+        assert_eq!(
+            parse_document(test).unwrap().to_sympy(),
+            "sign(a/(b + c))*floor(Abs(a/(b + c))) + (a - b*sign(a/b)*floor(Abs(a/b)))"
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use super::MathNode;
+use std::convert::Infallible;
+
+impl MathNode {
+    /// Rebuilds the tree bottom-up: every node is passed to `f` after its
+    /// children have been, and replaced by what it returns. Names bound by
+    /// `<bvar>` and `<lambda>` are plain strings, so they aren't visited
+    pub fn map(self, mut f: impl FnMut(MathNode) -> MathNode) -> MathNode {
+        match self.map_with(&mut |node| Ok::<_, Infallible>(f(node))) {
+            Ok(node) => node,
+            Err(never) => match never {},
+        }
+    }
+    /// Like `map`, stopping at the first error `f` returns
+    pub fn try_map<E>(
+        self,
+        mut f: impl FnMut(MathNode) -> Result<MathNode, E>,
+    ) -> Result<MathNode, E> {
+        self.map_with(&mut f)
+    }
+    fn map_with<E>(
+        mut self,
+        f: &mut impl FnMut(MathNode) -> Result<MathNode, E>,
+    ) -> Result<MathNode, E> {
+        for child in self.children_mut() {
+            let taken = std::mem::replace(child, MathNode::Text(String::new()));
+            *child = taken.map_with(f)?;
+        }
+        f(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{parse_document, MathNode, NumType};
+
+    #[test]
+    fn test_map() {
+        let test =
+            r#"<apply><times/><ci>k1</ci><apply><plus/><ci>S</ci><cn>1</cn></apply></apply>"#;
+        let parsed = parse_document(test).unwrap();
+        let renamed = parsed.clone().map(|node| match node.as_ci() {
+            Some("S") => MathNode::ci("glucose"),
+            _ => node,
+        });
+        assert_eq!(renamed.to_string(), "k1 * (glucose + 1)");
+
+        // Children are rewritten before their parent sees them
+        let mut order = Vec::new();
+        parsed.clone().map(|node| {
+            order.push(node.to_string());
+            node
+        });
+        assert_eq!(order[..3], ["times", "k1", "plus"]);
+        assert_eq!(order.last().map(String::as_str), Some("k1 * (S + 1)"));
+
+        let doubled = parsed.clone().try_map(|node| match node {
+            MathNode::Cn {
+                num_type: NumType::Integer(i),
+                ..
+            } => Ok(MathNode::integer(i * 2)),
+            MathNode::Ci { .. } if node.as_ci() == Some("S") => Err("unit unknown"),
+            other => Ok(other),
+        });
+        assert_eq!(doubled, Err("unit unknown"));
+    }
+}
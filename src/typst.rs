@@ -0,0 +1,603 @@
+use super::{
+    BuiltinOp, ConstantSymbol, MathNode, NamedConstant, NumType, NumberSet, PresentationNode,
+};
+use crate::content_to_presentation::{Binder, Order};
+use crate::entities;
+use crate::ops::{is_relation, parenthesize, PREC_ATOM, PREC_POWER, PREC_UNARY};
+
+/// Operators Typst predefines in math mode and typesets upright, e.g. `sin`
+const OPERATORS: &str = "sin cos tan sec csc cot sinh cosh tanh coth arcsin arccos arctan \
+                         ln log exp max min gcd lcm det arg";
+
+/// A piece of rendered Typst math together with the precedence of its outermost operator
+struct Typst {
+    text: String,
+    precedence: u8,
+}
+
+impl Typst {
+    fn new(text: String, precedence: u8) -> Self {
+        Typst { text, precedence }
+    }
+    fn atom(text: String) -> Self {
+        Typst::new(text, PREC_ATOM)
+    }
+    fn wrap(self, min_precedence: u8) -> String {
+        parenthesize(self.text, self.precedence, min_precedence)
+    }
+    /// Returns the text for a sub- or superscript, which only takes a single
+    /// token unless parenthesized. Typst drops the parentheses
+    fn script(self) -> String {
+        let token = self.text.chars().all(char::is_alphanumeric)
+            || (self.text.len() > 1
+                && self.text.starts_with('"')
+                && !self.text[1..self.text.len() - 1].contains('"'));
+        if token && !self.text.is_empty() {
+            self.text
+        } else {
+            format!("({})", self.text)
+        }
+    }
+}
+
+/// Quotes text for a Typst string, which math mode typesets upright
+fn quoted(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Single letters are variables, anything longer is quoted so Typst doesn't
+/// read it as a symbol name. Preserved entities like `&tau;` become their character
+fn identifier(name: &str) -> String {
+    let name = entities::entity_name(name)
+        .and_then(entities::lookup)
+        .unwrap_or(name);
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_alphabetic() => name.to_owned(),
+        _ => quoted(name),
+    }
+}
+
+fn number(num_type: &NumType) -> Typst {
+    let signed = |text: String, negative: bool| {
+        Typst::new(text, if negative { PREC_UNARY } else { PREC_ATOM })
+    };
+    match num_type {
+        NumType::Real(r) => signed(r.to_string(), *r < 0.0),
+        NumType::Integer(i) => signed(i.to_string(), *i < 0),
+        NumType::Rational(a, b) => Typst::atom(format!("frac({}, {})", a, b)),
+        NumType::ComplexCartesian(a, b) => Typst::new(format!("{} + {} i", a, b), 5),
+        NumType::ComplexPolar(a, b) => Typst::new(format!("{} e^(i {})", a, b), 6),
+        NumType::Constant(c) => Typst::atom(named_constant(c)),
+        NumType::ENotation(a, b) => Typst::new(format!("{} times 10^({})", a, b), 6),
+        NumType::Other { text, .. } => signed(quoted(text), text.starts_with('-')),
+        #[cfg(feature = "bigint")]
+        NumType::BigInteger(i) => signed(i.to_string(), i.sign() == num_bigint::Sign::Minus),
+    }
+}
+
+fn constant(constant: &ConstantSymbol) -> &'static str {
+    match constant {
+        ConstantSymbol::Pi => "pi",
+        ConstantSymbol::ExponentialE => "e",
+        ConstantSymbol::ImaginaryI => "i",
+        ConstantSymbol::EulerGamma => "gamma",
+        ConstantSymbol::Infinity => "infinity",
+        ConstantSymbol::NotANumber => "\"NaN\"",
+        ConstantSymbol::True => "\"true\"",
+        ConstantSymbol::False => "\"false\"",
+    }
+}
+
+fn named_constant(constant: &NamedConstant) -> String {
+    match constant {
+        NamedConstant::Pi => "pi".to_owned(),
+        NamedConstant::Tau => "tau".to_owned(),
+        NamedConstant::E => "e".to_owned(),
+        NamedConstant::Gamma => "gamma".to_owned(),
+        NamedConstant::GoldenRatio => "phi.alt".to_owned(),
+        NamedConstant::Custom(text) => identifier(text),
+    }
+}
+
+fn number_set(set: &NumberSet) -> &'static str {
+    match set {
+        NumberSet::Integers => "ZZ",
+        NumberSet::Reals => "RR",
+        NumberSet::Rationals => "QQ",
+        NumberSet::NaturalNumbers => "NN",
+        NumberSet::Complexes => "CC",
+        NumberSet::Primes => "bb(P)",
+        NumberSet::EmptySet => "emptyset",
+    }
+}
+
+/// Typst for operators written as a named function, e.g. `sin(x)`
+fn function_name(op: &BuiltinOp) -> String {
+    use BuiltinOp::*;
+    match op {
+        _ if OPERATORS.split_whitespace().any(|o| o == op.to_string()) => op.to_string(),
+        real => "Re".to_owned(),
+        imaginary => "Im".to_owned(),
+        _ => format!("op({})", quoted(&op.to_string())),
+    }
+}
+
+/// Typst for operators written infix between their arguments
+fn infix_symbol(op: &BuiltinOp) -> Option<&'static str> {
+    use BuiltinOp::*;
+    let symbol = match op {
+        plus => " + ",
+        minus => " - ",
+        times => " dot ",
+        rem => " mod ",
+        and => " and ",
+        or => " or ",
+        xor => " plus.circle ",
+        implies => " => ",
+        equivalent => " equiv ",
+        approx => " approx ",
+        eq => " = ",
+        neq => " != ",
+        gt => " > ",
+        lt => " < ",
+        geq => " >= ",
+        leq => " <= ",
+        union => " union ",
+        intersect => " sect ",
+        setdiff => " without ",
+        cartesianproduct | vectorproduct => " times ",
+        scalarproduct => " dot ",
+        outerproduct => " times.circle ",
+        compose => " ∘ ",
+        r#in => " in ",
+        notin => " in.not ",
+        subset => " subset.eq ",
+        prsubset => " subset ",
+        notsubset => " subset.eq.not ",
+        notprsubset => " subset.not ",
+        tendsto => " -> ",
+        factorof => " divides ",
+        _ => return None,
+    };
+    Some(symbol)
+}
+
+fn render_order(order: Order) -> Typst {
+    match order {
+        Order::Degree(degree) => render(degree),
+        Order::Count(count) => Typst::atom(count.to_string()),
+        Order::Sum(degrees) => Typst::new(
+            degrees
+                .iter()
+                .map(|d| d.map_or_else(|| "1".to_owned(), |d| render(d).text))
+                .collect::<Vec<_>>()
+                .join(" + "),
+            BuiltinOp::plus.precedence(),
+        ),
+    }
+}
+
+fn render_binder(op: &BuiltinOp, apply: &MathNode, body: Typst) -> Typst {
+    use BuiltinOp::*;
+    let binder = Binder::of(apply);
+    let vars: Vec<String> = binder
+        .bvars
+        .iter()
+        .map(|(name, _)| identifier(name))
+        .collect();
+    let condition = binder.condition.map(render);
+    match op {
+        sum | product | int => {
+            let bounds = match (binder.limits, condition) {
+                (Some((low, high)), _) => {
+                    let low = match op {
+                        int => render(low),
+                        _ => Typst::new(
+                            format!("{} = {}", vars.join(", "), render(low).text),
+                            BuiltinOp::eq.precedence(),
+                        ),
+                    };
+                    format!("_{}^{}", low.script(), render(high).script())
+                }
+                (None, Some(condition)) => format!("_{}", condition.script()),
+                (None, None) if *op == int => String::new(),
+                (None, None) => format!("_({})", vars.join(", ")),
+            };
+            let text = match op {
+                sum => format!("sum{} {}", bounds, body.wrap(PREC_UNARY)),
+                product => format!("product{} {}", bounds, body.wrap(PREC_UNARY)),
+                _ => format!(
+                    "integral{} {} {}",
+                    bounds,
+                    body.wrap(PREC_UNARY),
+                    vars.iter()
+                        .map(|v| format!("dif {}", v))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                ),
+            };
+            Typst::new(text, PREC_UNARY)
+        }
+        forall | exists => {
+            let condition = condition
+                .map(|c| format!(" | {}", c.text))
+                .unwrap_or_default();
+            Typst::new(
+                format!("{} {}{}: {}", op, vars.join(", "), condition, body.text),
+                0,
+            )
+        }
+        _ => {
+            let d = if *op == diff { "dif" } else { "partial" };
+            let numerator = match binder.order {
+                Some(order) => format!("{}^{}", d, render_order(order).script()),
+                None => d.to_owned(),
+            };
+            let denominator = binder
+                .bvars
+                .iter()
+                .zip(&vars)
+                .map(|((_, degree), var)| match degree {
+                    Some(degree) => format!("{} {}^{}", d, var, render(degree).script()),
+                    None => format!("{} {}", d, var),
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            if body.precedence >= PREC_ATOM {
+                Typst::atom(format!(
+                    "frac({} {}, {})",
+                    numerator, body.text, denominator
+                ))
+            } else {
+                Typst::new(
+                    format!(
+                        "frac({}, {}) {}",
+                        numerator,
+                        denominator,
+                        body.wrap(PREC_ATOM)
+                    ),
+                    PREC_UNARY,
+                )
+            }
+        }
+    }
+}
+
+fn render_op(op: &BuiltinOp, apply: &MathNode, args: &[&MathNode]) -> Typst {
+    use BuiltinOp::*;
+    let prec = op.precedence();
+    let operand = |i: usize| {
+        args.get(i)
+            .map(|a| render(a))
+            .unwrap_or_else(|| Typst::atom(String::new()))
+    };
+    let all = |min_prec: u8| {
+        args.iter()
+            .map(|a| render(a).wrap(min_prec))
+            .collect::<Vec<_>>()
+    };
+    match op {
+        minus if args.len() == 1 => {
+            Typst::new(format!("-{}", operand(0).wrap(PREC_POWER)), PREC_UNARY)
+        }
+        minus => Typst::new(
+            format!("{} - {}", operand(0).wrap(prec), operand(1).wrap(prec + 1)),
+            prec,
+        ),
+        divide => Typst::atom(format!("frac({}, {})", operand(0).text, operand(1).text)),
+        power => Typst::new(
+            format!("{}^{}", operand(0).wrap(PREC_ATOM), operand(1).script()),
+            PREC_POWER,
+        ),
+        root => Typst::atom(format!("sqrt({})", operand(0).text)),
+        exp => Typst::new(format!("e^{}", operand(0).script()), PREC_POWER),
+        not => Typst::new(format!("not {}", operand(0).wrap(PREC_UNARY)), PREC_UNARY),
+        factorial => Typst::new(format!("{}!", operand(0).wrap(PREC_ATOM)), prec),
+        abs | card => Typst::atom(format!("abs({})", operand(0).text)),
+        floor => Typst::atom(format!("floor({})", operand(0).text)),
+        ceiling => Typst::atom(format!("ceil({})", operand(0).text)),
+        quotient => Typst::atom(format!(
+            "op(\"trunc\")(frac({}, {}))",
+            operand(0).text,
+            operand(1).text
+        )),
+        conjugate => Typst::atom(format!("overline({})", operand(0).text)),
+        transpose => Typst::new(format!("{}^T", operand(0).wrap(PREC_ATOM)), PREC_POWER),
+        inverse => Typst::new(format!("{}^(-1)", operand(0).wrap(PREC_ATOM)), PREC_POWER),
+        grad => Typst::new(format!("nabla {}", operand(0).wrap(PREC_ATOM)), PREC_UNARY),
+        divergence => Typst::new(
+            format!("nabla dot {}", operand(0).wrap(PREC_ATOM)),
+            PREC_UNARY,
+        ),
+        curl => Typst::new(
+            format!("nabla times {}", operand(0).wrap(PREC_ATOM)),
+            PREC_UNARY,
+        ),
+        laplacian => Typst::new(
+            format!("nabla^2 {}", operand(0).wrap(PREC_ATOM)),
+            PREC_UNARY,
+        ),
+        selector => Typst::atom(format!(
+            "{}_({})",
+            operand(0).wrap(PREC_ATOM),
+            args.iter()
+                .skip(1)
+                .map(|a| render(a).text)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        sum | product | int | forall | exists | diff | partialdiff
+            if !apply.bound_variables().is_empty() =>
+        {
+            render_binder(op, apply, operand(0))
+        }
+        sum | product | int | forall | exists => {
+            let symbol = match op {
+                int => "integral".to_owned(),
+                _ => op.to_string(),
+            };
+            Typst::new(
+                format!("{} {}", symbol, all(PREC_UNARY).join(" ")),
+                PREC_UNARY,
+            )
+        }
+        diff => Typst::new(format!("{}'", operand(0).wrap(PREC_ATOM)), PREC_POWER),
+        partialdiff => Typst::new(
+            format!("partial {}", operand(0).wrap(PREC_ATOM)),
+            PREC_UNARY,
+        ),
+        _ => match infix_symbol(op) {
+            Some(symbol) if is_relation(op) => Typst::new(all(prec + 1).join(symbol), prec),
+            Some(symbol) => Typst::new(all(prec).join(symbol), prec),
+            None => Typst::atom(format!("{}({})", function_name(op), all(0).join(", "))),
+        },
+    }
+}
+
+fn presentation(node: &PresentationNode) -> String {
+    use PresentationNode::*;
+    let row = |children: &[PresentationNode]| {
+        children
+            .iter()
+            .map(presentation)
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let script = |node: &PresentationNode| Typst::atom(presentation(node)).script();
+    match node {
+        Mi(text) => identifier(text),
+        Mn(text) => text.clone(),
+        Mtext(text) => quoted(text),
+        Mo(text) => match text.as_str() {
+            "+" | "-" | "=" | "<" | ">" | "(" | ")" | "[" | "]" | "," | "!" | "|" => text.clone(),
+            _ => quoted(text),
+        },
+        Mspace => "space".to_owned(),
+        Mglyph { alt, .. } => quoted(alt),
+        Mrow(children) => row(children),
+        Mfrac(a, b) => format!("frac({}, {})", presentation(a), presentation(b)),
+        Msqrt(children) => format!("sqrt({})", row(children)),
+        Mroot(base, index) => format!("root({}, {})", presentation(index), presentation(base)),
+        Msup(base, sup) => format!("{}^{}", script(base), script(sup)),
+        Msub(base, sub) => format!("{}_{}", script(base), script(sub)),
+        Msubsup(base, sub, sup) => {
+            format!("{}_{}^{}", script(base), script(sub), script(sup))
+        }
+        Mtable(rows) => format!("mat({})", table(rows, presentation)),
+        Mfenced {
+            open,
+            close,
+            children,
+        } => format!(
+            "lr({}{}{})",
+            fence(open),
+            children
+                .iter()
+                .map(presentation)
+                .collect::<Vec<_>>()
+                .join(", "),
+            fence(close)
+        ),
+    }
+}
+
+/// Joins table cells with `,` and rows with `;`, as `mat` takes them
+fn table<T>(rows: &[Vec<T>], cell: impl Fn(&T) -> String) -> String {
+    rows.iter()
+        .map(|row| row.iter().map(&cell).collect::<Vec<_>>().join(", "))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn fence(symbol: &str) -> &str {
+    match symbol {
+        "{" => "\\{",
+        "}" => "\\}",
+        other => other,
+    }
+}
+
+fn render(node: &MathNode) -> Typst {
+    match node {
+        MathNode::Root { children, .. } => Typst::atom(
+            children
+                .iter()
+                .map(|c| render(c).text)
+                .filter(|t| !t.is_empty())
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+        MathNode::Apply { children, .. } => {
+            let operands = node.operands();
+            let join = |nodes: &[&MathNode]| {
+                nodes
+                    .iter()
+                    .map(|a| render(a).text)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            match (children.first(), node.degree(), node.logbase()) {
+                (Some(MathNode::Op(BuiltinOp::root)), Some(degree), _) => Typst::atom(format!(
+                    "root({}, {})",
+                    render(degree).text,
+                    join(&operands)
+                )),
+                (Some(MathNode::Op(BuiltinOp::log)), _, Some(base)) => Typst::atom(format!(
+                    "log_{}({})",
+                    render(base).script(),
+                    join(&operands)
+                )),
+                (Some(MathNode::Op(op)), _, _) => render_op(op, node, &operands),
+                (Some(head), _, _) => Typst::atom(format!(
+                    "{}({})",
+                    render(head).wrap(PREC_ATOM),
+                    join(&operands)
+                )),
+                (None, _, _) => Typst::atom(String::new()),
+            }
+        }
+        MathNode::Op(op) => Typst::atom(function_name(op)),
+        MathNode::Constant(c) => Typst::atom(constant(c).to_owned()),
+        MathNode::NumberSet(set) => Typst::atom(number_set(set).to_owned()),
+        MathNode::Text(t) | MathNode::Cs(t) => Typst::atom(quoted(t)),
+        MathNode::Ci { children, .. } => match children.as_slice() {
+            [MathNode::Presentation(p)] => Typst::atom(presentation(p)),
+            _ => Typst::atom(identifier(&crate::text_content(children))),
+        },
+        MathNode::Csymbol { name, .. } => Typst::atom(identifier(name.trim())),
+        MathNode::Cn { num_type, .. } => number(num_type),
+        MathNode::Comment(_) | MathNode::PI(_, _) => Typst::atom(String::new()),
+        MathNode::Error { .. } | MathNode::Cerror(_) | MathNode::Cbytes(_) | MathNode::Share(_) => {
+            Typst::atom("?".to_owned())
+        }
+        MathNode::Unknown { name, children, .. } => Typst::atom(format!(
+            "op({})({})",
+            quoted(name),
+            children
+                .iter()
+                .map(|c| render(c).text)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        MathNode::Presentation(node) => Typst::atom(presentation(node)),
+        MathNode::Piecewise { pieces, otherwise } => {
+            let mut rows: Vec<String> = pieces
+                .iter()
+                .map(|(value, condition)| {
+                    format!(
+                        "{} & \"if \" {}",
+                        render(value).text,
+                        render(condition).text
+                    )
+                })
+                .collect();
+            if let Some(otherwise) = otherwise {
+                rows.push(format!("{} & \"otherwise\"", render(otherwise).text));
+            }
+            Typst::atom(format!("cases({})", rows.join(", ")))
+        }
+        MathNode::Bind { .. } => render(&node.to_apply()),
+        MathNode::Lambda { bound_vars, body } => {
+            let vars: Vec<String> = bound_vars.iter().map(|v| identifier(v)).collect();
+            let vars = match vars.as_slice() {
+                [single] => single.clone(),
+                _ => format!("({})", vars.join(", ")),
+            };
+            Typst::new(format!("{} |-> {}", vars, render(body).text), 0)
+        }
+        MathNode::Degree(inner)
+        | MathNode::Logbase(inner)
+        | MathNode::Lowlimit(inner)
+        | MathNode::Uplimit(inner)
+        | MathNode::Condition(inner) => render(inner),
+        MathNode::Bvar { name, .. } => Typst::atom(identifier(name)),
+        MathNode::Semantics { primary, .. } => render(primary),
+        MathNode::Set(items) => Typst::atom(format!(
+            "{{{}}}",
+            items
+                .iter()
+                .map(|c| render(c).text)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        MathNode::List { items, .. } => Typst::atom(format!(
+            "[{}]",
+            items
+                .iter()
+                .map(|c| render(c).text)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        MathNode::Matrix(rows) => Typst::atom(format!("mat({})", table(rows, |c| render(c).text))),
+        MathNode::Vector(children) => Typst::atom(format!(
+            "vec({})",
+            children
+                .iter()
+                .map(|c| render(c).text)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        MathNode::Interval { closure, low, high } => {
+            let (open, close) = closure.brackets();
+            Typst::atom(format!(
+                "lr({}{}, {}{})",
+                open,
+                render(low).text,
+                render(high).text,
+                close
+            ))
+        }
+    }
+}
+
+impl MathNode {
+    /// Render the expression as Typst math (without the surrounding `$`)
+    pub fn to_typst(&self) -> String {
+        render(self).text
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{parse_document, parse_infix};
+
+    #[test]
+    fn test_typst() {
+        let test = r#"<apply><leq/>
+                        <apply><divide/>
+                            <apply><sin/><ci>theta</ci></apply>
+                            <apply><root/><ci>k1</ci></apply>
+                        </apply>
+                        <apply><times/>
+                            <apply><minus/><apply><power/><ci>x</ci><apply><plus/><ci>n</ci><cn>1</cn></apply></apply></apply>
+                            <apply><log/><logbase><cn>2</cn></logbase><ci>y</ci></apply>
+                            <apply><arcsec/><ci>y</ci></apply>
+                        </apply>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(
+            parsed.to_typst(),
+            r#"frac(sin("theta"), sqrt("k1")) <= -x^(n + 1) dot log_2(y) dot op("arcsec")(y)"#
+        );
+
+        let test = r#"<apply><int/>
+                        <bvar><ci>x</ci></bvar>
+                        <lowlimit><cn>0</cn></lowlimit><uplimit><infinity/></uplimit>
+                        <piecewise>
+                          <piece><cn>1</cn><apply><in/><ci>x</ci><integers/></apply></piece>
+                          <otherwise><apply><exp/><apply><minus/><ci>x</ci></apply></apply></otherwise>
+                        </piecewise>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(
+            parsed.to_typst(),
+            r#"integral_0^infinity cases(1 & "if " x in ZZ, e^(-x) & "otherwise") dif x"#
+        );
+        // `eval` truncates toward zero, so -7 quotient 2 is -3
+        assert_eq!(
+            parse_infix("quotient(-7, 2)").unwrap().to_typst(),
+            r#"op("trunc")(frac(-7, 2))"#
+        );
+    }
+}
@@ -0,0 +1,443 @@
+use super::{BuiltinOp, MathNode, NumType};
+use crate::content_to_presentation::{constant, infix_glyph, number_set, prefix_glyph};
+use crate::entities;
+use crate::ops::{is_relation, parenthesize, PREC_ATOM, PREC_POWER, PREC_UNARY};
+
+/// Precedence of sums and products written with `∑` and `∏`, which extend over
+/// products but not over sums
+const PREC_BIG_OPERATOR: u8 = 5;
+/// Precedence of quantifiers and `↦`, which extend as far right as possible
+const PREC_BINDER: u8 = 0;
+
+const SUPERSCRIPTS: &[(char, char)] = &[
+    ('0', '⁰'),
+    ('1', '¹'),
+    ('2', '²'),
+    ('3', '³'),
+    ('4', '⁴'),
+    ('5', '⁵'),
+    ('6', '⁶'),
+    ('7', '⁷'),
+    ('8', '⁸'),
+    ('9', '⁹'),
+    ('+', '⁺'),
+    ('-', '⁻'),
+    ('\u{2212}', '⁻'),
+    ('=', '⁼'),
+    ('(', '⁽'),
+    (')', '⁾'),
+    ('i', 'ⁱ'),
+    ('n', 'ⁿ'),
+];
+
+const SUBSCRIPTS: &[(char, char)] = &[
+    ('0', '₀'),
+    ('1', '₁'),
+    ('2', '₂'),
+    ('3', '₃'),
+    ('4', '₄'),
+    ('5', '₅'),
+    ('6', '₆'),
+    ('7', '₇'),
+    ('8', '₈'),
+    ('9', '₉'),
+    ('+', '₊'),
+    ('-', '₋'),
+    ('\u{2212}', '₋'),
+    ('=', '₌'),
+    ('(', '₍'),
+    (')', '₎'),
+    ('a', 'ₐ'),
+    ('e', 'ₑ'),
+    ('h', 'ₕ'),
+    ('i', 'ᵢ'),
+    ('j', 'ⱼ'),
+    ('k', 'ₖ'),
+    ('l', 'ₗ'),
+    ('m', 'ₘ'),
+    ('n', 'ₙ'),
+    ('o', 'ₒ'),
+    ('p', 'ₚ'),
+    ('r', 'ᵣ'),
+    ('s', 'ₛ'),
+    ('t', 'ₜ'),
+    ('u', 'ᵤ'),
+    ('v', 'ᵥ'),
+    ('x', 'ₓ'),
+];
+
+/// A piece of Unicode text together with the precedence of its outermost operator
+struct Text {
+    text: String,
+    precedence: u8,
+}
+
+impl Text {
+    fn new(text: String, precedence: u8) -> Self {
+        Text { text, precedence }
+    }
+    fn atom(text: String) -> Self {
+        Text::new(text, PREC_ATOM)
+    }
+    fn wrap(self, min_precedence: u8) -> String {
+        parenthesize(self.text, self.precedence, min_precedence)
+    }
+}
+
+/// The text in superscript or subscript characters, if they all have one
+fn script(text: &str, table: &[(char, char)]) -> Option<String> {
+    text.chars()
+        .filter(|c| *c != ' ')
+        .map(|c| table.iter().find(|(plain, _)| *plain == c).map(|s| s.1))
+        .collect()
+}
+
+/// `x²`, or `x^(a + b)` where the exponent has no superscript characters
+fn superscript(text: Text) -> String {
+    script(&text.text, SUPERSCRIPTS).unwrap_or_else(|| format!("^{}", text.wrap(PREC_ATOM)))
+}
+
+fn subscript(text: Text) -> String {
+    script(&text.text, SUBSCRIPTS).unwrap_or_else(|| format!("_{}", text.wrap(PREC_ATOM)))
+}
+
+/// Turns a preserved entity like `&tau;` into its character
+fn resolve(text: &str) -> String {
+    entities::entity_name(text)
+        .and_then(entities::lookup)
+        .unwrap_or(text)
+        .to_owned()
+}
+
+fn signed(text: String) -> Text {
+    match text.strip_prefix('-') {
+        Some(abs) => Text::new(format!("\u{2212}{}", abs), PREC_UNARY),
+        None => Text::atom(text),
+    }
+}
+
+fn number(num_type: &NumType) -> Text {
+    match num_type {
+        NumType::Real(r) => signed(r.to_string()),
+        NumType::Integer(i) => signed(i.to_string()),
+        NumType::Rational(a, b) => Text::new(
+            format!("{}\u{2044}{}", a, b).replace('-', "\u{2212}"),
+            BuiltinOp::divide.precedence(),
+        ),
+        NumType::ComplexCartesian(a, b) => Text::new(format!("{} + {}ⅈ", a, b), 5),
+        NumType::ComplexPolar(a, b) => Text::new(format!("{}⋅exp(ⅈ⋅{})", a, b), 6),
+        NumType::Constant(c) => Text::atom(c.symbol().to_owned()),
+        NumType::ENotation(a, b) => Text::new(
+            format!("{}×10{}", a, superscript(Text::atom(b.to_string()))),
+            6,
+        ),
+        NumType::Other { text, .. } => signed(text.clone()),
+        #[cfg(feature = "bigint")]
+        NumType::BigInteger(i) => signed(i.to_string()),
+    }
+}
+
+fn call(name: &str, args: Vec<String>) -> Text {
+    Text::atom(format!("{}({})", name, args.join(", ")))
+}
+
+fn list(nodes: &[MathNode]) -> Vec<String> {
+    nodes.iter().map(|n| render(n).text).collect()
+}
+
+/// Big operators with their bounds as scripts, e.g. `∑ᵢ₌₁ⁿ i²` or `∫₀¹ f dx`,
+/// derivatives as `d/dx`, quantifiers as `∀x: p`, and anything else with bound
+/// variables as a call
+fn binder(op: &BuiltinOp, apply: &MathNode, args: &[&MathNode]) -> Text {
+    use BuiltinOp::*;
+    let body = || {
+        args.first()
+            .map(|a| render(a))
+            .unwrap_or_else(|| Text::atom(String::new()))
+    };
+    let bvars = apply.bvars();
+    let names = bvars
+        .iter()
+        .map(|(name, _)| resolve(name))
+        .collect::<Vec<_>>();
+    let limits = apply
+        .limits()
+        .map(|(low, high)| (render(low), render(high)));
+    let condition = apply.condition().map(render);
+    match op {
+        sum | product | int => {
+            let (low, high) = match (limits, condition) {
+                (Some((low, high)), _) if *op == int => (Some(low), Some(high)),
+                (Some((low, high)), _) => (
+                    Some(Text::atom(format!("{}={}", names.join(","), low.text))),
+                    Some(high),
+                ),
+                (None, Some(condition)) => (Some(condition), None),
+                (None, None) if *op == int => (None, None),
+                (None, None) => (Some(Text::atom(names.join(","))), None),
+            };
+            let mut text = format!(
+                "{}{}{} {}",
+                prefix_glyph(op).unwrap_or_default(),
+                low.map(subscript).unwrap_or_default(),
+                high.map(superscript).unwrap_or_default(),
+                body().wrap(6)
+            );
+            if *op == int {
+                for name in &names {
+                    text += &format!(" d{}", name);
+                }
+            }
+            Text::new(text, PREC_BIG_OPERATOR)
+        }
+        diff | partialdiff => {
+            let d = if *op == diff { "d" } else { "\u{2202}" };
+            let mut text = String::new();
+            for ((_, degree), name) in bvars.iter().zip(&names) {
+                let order = degree.map(|degree| superscript(render(degree)));
+                let order = order.unwrap_or_default();
+                text += &format!("{}{}/{}{}{} ", d, order, d, name, order);
+            }
+            Text::new(text + &body().wrap(PREC_POWER), 6)
+        }
+        forall | exists => {
+            let condition = condition
+                .map(|condition| format!(" | {}", condition.text))
+                .unwrap_or_default();
+            Text::new(
+                format!(
+                    "{}{}{}: {}",
+                    prefix_glyph(op).unwrap_or_default(),
+                    names.join(", "),
+                    condition,
+                    body().text
+                ),
+                PREC_BINDER,
+            )
+        }
+        _ => {
+            let mut parts = vec![body().text];
+            parts.extend(names);
+            parts.extend(condition.map(|c| c.text));
+            call(&op.to_string(), parts)
+        }
+    }
+}
+
+fn render_op(op: &BuiltinOp, apply: &MathNode, args: &[&MathNode]) -> Text {
+    use BuiltinOp::*;
+    let prec = op.precedence();
+    let operand = |i: usize| {
+        args.get(i)
+            .map(|a| render(a))
+            .unwrap_or_else(|| Text::atom(String::new()))
+    };
+    let all = |min_prec: u8| {
+        args.iter()
+            .map(|a| render(a).wrap(min_prec))
+            .collect::<Vec<_>>()
+    };
+    if !apply.bound_variables().is_empty() {
+        return binder(op, apply, args);
+    }
+    match (op, apply.degree(), apply.logbase()) {
+        (root, Some(degree), _) => {
+            let degree = render(degree);
+            let radical = match degree.text.as_str() {
+                "2" => "√".to_owned(),
+                "3" => "∛".to_owned(),
+                "4" => "∜".to_owned(),
+                _ => format!("{}√", superscript(degree)),
+            };
+            return Text::new(
+                format!("{}{}", radical, operand(0).wrap(PREC_ATOM)),
+                PREC_POWER,
+            );
+        }
+        (log, _, Some(base)) => {
+            return Text::atom(format!(
+                "log{}({})",
+                subscript(render(base)),
+                operand(0).text
+            ))
+        }
+        _ => {}
+    }
+    match op {
+        minus if args.len() == 1 => Text::new(
+            format!("\u{2212}{}", operand(0).wrap(PREC_POWER)),
+            PREC_UNARY,
+        ),
+        // Fractions of plain operands are written with a fraction slash, `a⁄b`
+        divide if args.len() == 2 && args.iter().all(|a| render(a).precedence == PREC_ATOM) => {
+            Text::new(all(0).join("\u{2044}"), prec)
+        }
+        minus | divide => Text::new(
+            format!(
+                "{} {} {}",
+                operand(0).wrap(prec),
+                infix_glyph(op).unwrap_or("/"),
+                operand(1).wrap(prec + 1)
+            ),
+            prec,
+        ),
+        power => {
+            let base = operand(0).wrap(PREC_POWER + 1);
+            let exponent = operand(1);
+            let text = match script(&exponent.text, SUPERSCRIPTS) {
+                Some(exponent) => format!("{}{}", base, exponent),
+                None => format!("{}^{}", base, exponent.wrap(PREC_POWER)),
+            };
+            Text::new(text, PREC_POWER)
+        }
+        not => Text::new(
+            format!(
+                "{}{}",
+                prefix_glyph(op).unwrap_or_default(),
+                operand(0).wrap(PREC_UNARY)
+            ),
+            PREC_UNARY,
+        ),
+        factorial => Text::new(format!("{}!", operand(0).wrap(PREC_ATOM)), prec),
+        root => Text::new(format!("√{}", operand(0).wrap(PREC_ATOM)), PREC_POWER),
+        abs => Text::atom(format!("|{}|", operand(0).text)),
+        floor => Text::atom(format!("⌊{}⌋", operand(0).text)),
+        ceiling => Text::atom(format!("⌈{}⌉", operand(0).text)),
+        _ => match infix_glyph(op) {
+            Some(glyph) if is_relation(op) => {
+                Text::new(all(prec + 1).join(&format!(" {} ", glyph)), prec)
+            }
+            Some(glyph) => Text::new(all(prec).join(&format!(" {} ", glyph)), prec),
+            None => call(&op.to_string(), all(0)),
+        },
+    }
+}
+
+fn render(node: &MathNode) -> Text {
+    match node {
+        MathNode::Root { children, .. } => Text::atom(
+            children
+                .iter()
+                .filter(|c| !c.is_markup())
+                .map(|c| render(c).text)
+                .collect::<Vec<_>>()
+                .join("; "),
+        ),
+        MathNode::Apply { children, .. } => {
+            let operands = node.operands();
+            match children.first() {
+                Some(MathNode::Op(op)) => render_op(op, node, &operands),
+                Some(head) => Text::atom(format!(
+                    "{}({})",
+                    render(head).wrap(PREC_ATOM),
+                    operands
+                        .iter()
+                        .map(|a| render(a).text)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+                None => Text::atom(String::new()),
+            }
+        }
+        MathNode::Op(op) => Text::atom(op.to_string()),
+        MathNode::Text(t) => Text::atom(resolve(t)),
+        MathNode::Ci { children, .. } => Text::atom(resolve(&crate::text_content(children))),
+        MathNode::Csymbol { name, .. } => Text::atom(name.trim().to_owned()),
+        MathNode::Cn { num_type, .. } => number(num_type),
+        MathNode::Constant(c) => Text::atom(constant(c).to_owned()),
+        MathNode::NumberSet(set) => Text::atom(number_set(set).to_owned()),
+        MathNode::Comment(_) | MathNode::PI(_, _) => Text::atom(String::new()),
+        MathNode::Piecewise { pieces, otherwise } => {
+            let mut cases = pieces
+                .iter()
+                .map(|(value, condition)| {
+                    format!("{} if {}", render(value).text, render(condition).text)
+                })
+                .collect::<Vec<_>>();
+            if let Some(otherwise) = otherwise {
+                cases.push(format!("{} otherwise", render(otherwise).text));
+            }
+            Text::atom(format!("{{ {} }}", cases.join("; ")))
+        }
+        MathNode::Bind { .. } => render(&node.to_apply()),
+        MathNode::Lambda { bound_vars, body } => {
+            let vars = match bound_vars.as_slice() {
+                [var] => resolve(var),
+                _ => format!("({})", bound_vars.join(", ")),
+            };
+            Text::new(format!("{} ↦ {}", vars, render(body).text), PREC_BINDER)
+        }
+        MathNode::Degree(inner)
+        | MathNode::Logbase(inner)
+        | MathNode::Lowlimit(inner)
+        | MathNode::Uplimit(inner)
+        | MathNode::Condition(inner) => render(inner),
+        MathNode::Bvar { name, .. } => Text::atom(resolve(name)),
+        MathNode::Interval { closure, low, high } => {
+            let (open, close) = closure.brackets();
+            Text::atom(format!(
+                "{}{}, {}{}",
+                open,
+                render(low).text,
+                render(high).text,
+                close
+            ))
+        }
+        MathNode::Matrix(rows) => Text::atom(format!(
+            "[{}]",
+            rows.iter()
+                .map(|row| format!("[{}]", list(row).join(", ")))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        MathNode::Vector(items) => Text::atom(format!("({})", list(items).join(", "))),
+        MathNode::Set(items) => Text::atom(format!("{{{}}}", list(items).join(", "))),
+        MathNode::List { items, .. } => Text::atom(format!("[{}]", list(items).join(", "))),
+        MathNode::Semantics { primary, .. } => render(primary),
+        MathNode::Cs(t) => Text::atom(format!("\"{}\"", t)),
+        MathNode::Error { .. }
+        | MathNode::Unknown { .. }
+        | MathNode::Presentation(_)
+        | MathNode::Cerror(_)
+        | MathNode::Cbytes(_)
+        | MathNode::Share(_) => Text::atom(node.to_string()),
+    }
+}
+
+impl MathNode {
+    /// Writes the expression as plain Unicode text for terminals, e.g.
+    /// `∑ᵢ₌₁ⁿ i² ≤ √x ∧ k ∈ ℤ`. Exponents and bounds use superscript and
+    /// subscript characters where they all have one
+    pub fn to_unicode(&self) -> String {
+        render(self).text
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parse_document;
+
+    #[test]
+    fn test_to_unicode() {
+        let test = r#"<apply><and/>
+                        <apply><leq/>
+                          <apply><sum/><bvar><ci>i</ci></bvar><lowlimit><cn>1</cn></lowlimit><uplimit><ci>n</ci></uplimit>
+                            <apply><power/><ci>i</ci><cn>2</cn></apply></apply>
+                          <apply><root/><apply><plus/><ci>x</ci><cn type="rational">1<sep/>2</cn></apply></apply>
+                        </apply>
+                        <apply><in/><ci>k</ci><integers/></apply>
+                        <apply><not/><apply><eq/><apply><divide/><ci>a</ci><ci>b</ci></apply><apply><minus/><ci>&alpha;</ci></apply></apply></apply>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(
+            parsed.to_unicode(),
+            "∑ᵢ₌₁ⁿ i² ≤ √(x + 1⁄2) ∧ k ∈ ℤ ∧ ¬(a⁄b = −α)"
+        );
+
+        let test = r#"<apply><int/><bvar><ci>t</ci></bvar><lowlimit><cn>0</cn></lowlimit><uplimit><ci>T</ci></uplimit>
+                        <apply><power/><exponentiale/><apply><minus/><ci>t</ci></apply></apply>
+                      </apply>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(parsed.to_unicode(), "∫₀^T ⅇ^(−t) dt");
+    }
+}
@@ -0,0 +1,103 @@
+use super::MathNode;
+use crate::error::ValidationError;
+
+/// Whether `head` can start an `<apply>`: an operator, or something naming or
+/// building a function
+fn can_apply(head: &MathNode) -> bool {
+    match head {
+        MathNode::Op(_)
+        | MathNode::Ci { .. }
+        | MathNode::Csymbol { .. }
+        | MathNode::Apply { .. }
+        | MathNode::Lambda { .. }
+        | MathNode::Bind { .. }
+        | MathNode::Share(_)
+        | MathNode::Unknown { .. }
+        | MathNode::Error { .. } => true,
+        MathNode::Semantics { primary, .. } => can_apply(primary),
+        _ => false,
+    }
+}
+
+fn check(node: &MathNode, path: &mut Vec<usize>, errors: &mut Vec<ValidationError>) {
+    if let MathNode::Apply { children, .. } = node {
+        match children.iter().find(|c| !c.is_markup()) {
+            None => errors.push(ValidationError::EmptyApply { path: path.clone() }),
+            Some(MathNode::Op(op)) => {
+                let expected = op.arity();
+                let found = node.operands().len();
+                if !expected.accepts(found) {
+                    errors.push(ValidationError::WrongArity {
+                        path: path.clone(),
+                        op: *op,
+                        expected,
+                        found,
+                    });
+                }
+            }
+            Some(head) if !can_apply(head) => errors.push(ValidationError::NotAnOperator {
+                path: path.clone(),
+                found: head.to_string(),
+            }),
+            Some(_) => {}
+        }
+    }
+    for (i, child) in node.children().into_iter().enumerate() {
+        path.push(i);
+        check(child, path, errors);
+        path.pop();
+    }
+}
+
+impl MathNode {
+    /// Checks that every `<apply>` starts with an operator or function and
+    /// that operators get as many operands as MathML allows them, e.g. one or
+    /// two for `<minus/>` and exactly two for `<quotient/>`. Returns every
+    /// problem found, in document order, so none means the tree is well formed
+    pub fn validate(&self) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        check(self, &mut Vec::new(), &mut errors);
+        errors
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{parse_document, Arity, BuiltinOp, ValidationError};
+
+    #[test]
+    fn test_validate() {
+        let test = r#"<math><apply><divide/>
+                        <apply><times/><ci>Vmax</ci><ci>S</ci></apply>
+                        <apply><plus/><ci>Km</ci><ci>S</ci></apply>
+                      </apply></math>"#;
+        assert!(parse_document(test).unwrap().validate().is_empty());
+
+        let test = r#"<math><apply><plus/>
+                        <apply><minus/><ci>a</ci><ci>b</ci><ci>c</ci></apply>
+                        <apply><cn>2</cn><ci>x</ci></apply>
+                        <apply><quotient/><ci>n</ci></apply>
+                      </apply></math>"#;
+        let errors = parse_document(test).unwrap().validate();
+        assert_eq!(
+            errors[0],
+            ValidationError::WrongArity {
+                path: vec![0, 1],
+                op: BuiltinOp::minus,
+                expected: Arity {
+                    min: 1,
+                    max: Some(2)
+                },
+                found: 3,
+            }
+        );
+        assert!(
+            matches!(&errors[1], ValidationError::NotAnOperator { path, .. } if path == &[0, 2])
+        );
+        assert_eq!(
+            errors[2].to_string(),
+            "at [0, 3]: operator 'quotient' expects 2 arguments, found 1"
+        );
+        assert_eq!(errors.len(), 3);
+    }
+}
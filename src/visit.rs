@@ -0,0 +1,334 @@
+use super::{BuiltinOp, ConstantSymbol, MathNode, NumType};
+
+impl MathNode {
+    /// The direct children of the node, in document order. Token elements like
+    /// `<ci>` and presentation markup count as leaves
+    pub fn children(&self) -> Vec<&MathNode> {
+        match self {
+            MathNode::Root { children, .. }
+            | MathNode::Apply { children, .. }
+            | MathNode::Vector(children)
+            | MathNode::Set(children)
+            | MathNode::List {
+                items: children, ..
+            }
+            | MathNode::Unknown { children, .. }
+            | MathNode::Cerror(children) => children.iter().collect(),
+            MathNode::Piecewise { pieces, otherwise } => pieces
+                .iter()
+                .flat_map(|(value, condition)| vec![value, condition])
+                .chain(otherwise.as_deref())
+                .collect(),
+            MathNode::Lambda { body, .. } => vec![body],
+            MathNode::Bind {
+                binder,
+                condition,
+                body,
+                ..
+            } => std::iter::once(binder)
+                .chain(condition)
+                .chain(Some(body))
+                .map(|b| &**b)
+                .collect(),
+            MathNode::Degree(inner)
+            | MathNode::Logbase(inner)
+            | MathNode::Lowlimit(inner)
+            | MathNode::Uplimit(inner)
+            | MathNode::Condition(inner) => vec![inner],
+            MathNode::Bvar { degree, .. } => degree.as_deref().into_iter().collect(),
+            MathNode::Interval { low, high, .. } => vec![low, high],
+            MathNode::Matrix(rows) => rows.iter().flatten().collect(),
+            MathNode::Semantics { primary, .. } => vec![primary],
+            MathNode::Ci { .. }
+            | MathNode::Csymbol { .. }
+            | MathNode::Cn { .. }
+            | MathNode::Op(_)
+            | MathNode::Constant(_)
+            | MathNode::NumberSet(_)
+            | MathNode::Text(_)
+            | MathNode::Comment(_)
+            | MathNode::PI(_, _)
+            | MathNode::Error { .. }
+            | MathNode::Presentation(_)
+            | MathNode::Cs(_)
+            | MathNode::Cbytes(_)
+            | MathNode::Share(_) => Vec::new(),
+        }
+    }
+    /// Mutable access to the direct children, in the same order as `children`
+    pub fn children_mut(&mut self) -> Vec<&mut MathNode> {
+        match self {
+            MathNode::Root { children, .. }
+            | MathNode::Apply { children, .. }
+            | MathNode::Vector(children)
+            | MathNode::Set(children)
+            | MathNode::List {
+                items: children, ..
+            }
+            | MathNode::Unknown { children, .. }
+            | MathNode::Cerror(children) => children.iter_mut().collect(),
+            MathNode::Piecewise { pieces, otherwise } => pieces
+                .iter_mut()
+                .flat_map(|(value, condition)| vec![value, condition])
+                .chain(otherwise.as_deref_mut())
+                .collect(),
+            MathNode::Lambda { body, .. } => vec![body],
+            MathNode::Bind {
+                binder,
+                condition,
+                body,
+                ..
+            } => std::iter::once(binder)
+                .chain(condition)
+                .chain(Some(body))
+                .map(|b| &mut **b)
+                .collect(),
+            MathNode::Degree(inner)
+            | MathNode::Logbase(inner)
+            | MathNode::Lowlimit(inner)
+            | MathNode::Uplimit(inner)
+            | MathNode::Condition(inner) => vec![inner],
+            MathNode::Bvar { degree, .. } => degree.as_deref_mut().into_iter().collect(),
+            MathNode::Interval { low, high, .. } => vec![low, high],
+            MathNode::Matrix(rows) => rows.iter_mut().flatten().collect(),
+            MathNode::Semantics { primary, .. } => vec![primary],
+            MathNode::Ci { .. }
+            | MathNode::Csymbol { .. }
+            | MathNode::Cn { .. }
+            | MathNode::Op(_)
+            | MathNode::Constant(_)
+            | MathNode::NumberSet(_)
+            | MathNode::Text(_)
+            | MathNode::Comment(_)
+            | MathNode::PI(_, _)
+            | MathNode::Error { .. }
+            | MathNode::Presentation(_)
+            | MathNode::Cs(_)
+            | MathNode::Cbytes(_)
+            | MathNode::Share(_) => Vec::new(),
+        }
+    }
+    /// Depth-first iterator over this node and everything below it, parents first
+    pub fn iter(&self) -> impl Iterator<Item = &MathNode> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.children().into_iter().rev());
+            Some(node)
+        })
+    }
+    /// Depth-first iterator over this node and everything below it, children first
+    pub fn iter_postorder(&self) -> impl Iterator<Item = &MathNode> {
+        // The flag is set once a node's children have been pushed
+        let mut stack = vec![(self, false)];
+        std::iter::from_fn(move || loop {
+            let (node, expanded) = stack.pop()?;
+            if expanded {
+                return Some(node);
+            }
+            stack.push((node, true));
+            stack.extend(node.children().into_iter().rev().map(|c| (c, false)));
+        })
+    }
+    /// Mutable depth-first traversal of this node and everything below it,
+    /// parents first
+    pub fn iter_mut(&mut self) -> IterMut<'_> {
+        IterMut {
+            root: self,
+            path: Some(Vec::new()),
+            started: false,
+        }
+    }
+    /// Every node strictly below this one that is the same variant as `like`,
+    /// e.g. `ast.descendants_of_type(&MathNode::ci(""))`
+    pub fn descendants_of_type<'a>(
+        &'a self,
+        like: &MathNode,
+    ) -> impl Iterator<Item = &'a MathNode> {
+        let kind = std::mem::discriminant(like);
+        self.iter()
+            .skip(1)
+            .filter(move |n| std::mem::discriminant(*n) == kind)
+    }
+    /// The name of a `<ci>`
+    pub fn as_ci(&self) -> Option<&str> {
+        match self {
+            MathNode::Ci { children, .. } => children.iter().find_map(|c| match c {
+                MathNode::Text(t) => Some(t.as_str()),
+                _ => None,
+            }),
+            _ => None,
+        }
+    }
+    /// Calls the method of `visitor` matching this node
+    pub fn accept<V: Visitor + ?Sized>(&self, visitor: &mut V) {
+        match self {
+            MathNode::Apply { .. } => visitor.visit_apply(self),
+            MathNode::Op(op) => visitor.visit_op(op),
+            MathNode::Ci { children, .. } => visitor.visit_ci(&crate::text_content(children)),
+            MathNode::Cn { num_type, .. } => visitor.visit_cn(num_type),
+            MathNode::Csymbol { .. } => visitor.visit_csymbol(self),
+            MathNode::Constant(constant) => visitor.visit_constant(constant),
+            MathNode::Piecewise { .. } => visitor.visit_piecewise(self),
+            MathNode::Lambda { .. } => visitor.visit_lambda(self),
+            _ => visitor.visit_other(self),
+        }
+    }
+}
+
+/// The traversal of `MathNode::iter_mut`. A parent's borrow covers its
+/// children, so each node borrows the traversal and this can't be an
+/// `Iterator`: use `while let Some(node) = nodes.next_node()`. A node that is
+/// replaced is descended into as it is after the replacement
+pub struct IterMut<'a> {
+    root: &'a mut MathNode,
+    /// Child indices from the root to the node returned last, `None` once
+    /// the traversal is over
+    path: Option<Vec<usize>>,
+    started: bool,
+}
+
+impl IterMut<'_> {
+    /// The next node, or `None` once every node has been returned
+    pub fn next_node(&mut self) -> Option<&mut MathNode> {
+        let path = self.path.as_mut()?;
+        if !self.started {
+            self.started = true;
+        } else if at(self.root, path).children_mut().is_empty() {
+            // Up to the nearest ancestor with a next sibling
+            loop {
+                let last = match path.pop() {
+                    Some(last) => last,
+                    None => {
+                        self.path = None;
+                        return None;
+                    }
+                };
+                if last + 1 < at(self.root, path).children_mut().len() {
+                    path.push(last + 1);
+                    break;
+                }
+            }
+        } else {
+            path.push(0);
+        }
+        Some(at(self.root, path))
+    }
+}
+
+/// The node `path` leads to from `node`, as child indices
+fn at<'a>(mut node: &'a mut MathNode, path: &[usize]) -> &'a mut MathNode {
+    for &i in path {
+        node = node.children_mut().swap_remove(i);
+    }
+    node
+}
+
+/// Visits the children of `node`. The default methods of `Visitor` call this,
+/// overriding implementations can call it to keep descending
+pub fn walk<V: Visitor + ?Sized>(visitor: &mut V, node: &MathNode) {
+    for child in node.children() {
+        child.accept(visitor);
+    }
+}
+
+/// A read-only pass over a tree, driven by `MathNode::accept`. Every method
+/// does nothing (or just descends) by default, so only override what you need
+pub trait Visitor {
+    fn visit_apply(&mut self, node: &MathNode) {
+        walk(self, node)
+    }
+    fn visit_op(&mut self, _op: &BuiltinOp) {}
+    fn visit_ci(&mut self, _name: &str) {}
+    fn visit_cn(&mut self, _num_type: &NumType) {}
+    fn visit_csymbol(&mut self, _node: &MathNode) {}
+    fn visit_constant(&mut self, _constant: &ConstantSymbol) {}
+    fn visit_piecewise(&mut self, node: &MathNode) {
+        walk(self, node)
+    }
+    fn visit_lambda(&mut self, node: &MathNode) {
+        walk(self, node)
+    }
+    /// Everything without a dedicated method: the root, qualifiers, containers...
+    fn visit_other(&mut self, node: &MathNode) {
+        walk(self, node)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Visitor;
+    use crate::{parse_document, BuiltinOp, MathNode, NumType};
+    use std::collections::HashMap;
+
+    #[derive(Default)]
+    struct Census {
+        identifiers: Vec<String>,
+        operators: HashMap<String, usize>,
+        numbers: usize,
+    }
+
+    impl Visitor for Census {
+        fn visit_op(&mut self, op: &BuiltinOp) {
+            *self.operators.entry(op.to_string()).or_insert(0) += 1;
+        }
+        fn visit_ci(&mut self, name: &str) {
+            self.identifiers.push(name.to_owned());
+        }
+        fn visit_cn(&mut self, _num_type: &NumType) {
+            self.numbers += 1;
+        }
+    }
+
+    #[test]
+    fn test_visitor() {
+        let test = r#"<math xmlns="http://www.w3.org/1998/Math/MathML">
+                        <apply><plus/>
+                            <apply><times/><ci> k1 </ci><ci> S </ci></apply>
+                            <piecewise>
+                                <piece><apply><times/><ci> k2 </ci><cn> 2 </cn></apply><apply><gt/><ci> S </ci><cn> 0 </cn></apply></piece>
+                            </piecewise>
+                        </apply>
+                      </math>"#;
+        let parsed = parse_document(test).unwrap();
+        let mut census = Census::default();
+        parsed.accept(&mut census);
+        assert_eq!(census.identifiers, vec!["k1", "S", "k2", "S"]);
+        assert_eq!(census.operators["times"], 2);
+        assert_eq!(census.operators.len(), 3);
+        assert_eq!(census.numbers, 2);
+    }
+    #[test]
+    fn test_iterators() {
+        let test = r#"<apply><plus/><ci>a</ci><apply><times/><ci>b</ci><cn>2</cn></apply></apply>"#;
+        let mut parsed = parse_document(test).unwrap();
+        let names: Vec<&str> = parsed.iter().filter_map(MathNode::as_ci).collect();
+        assert_eq!(names, vec!["a", "b"]);
+        let postorder: Vec<String> = parsed
+            .iter_postorder()
+            .filter(|n| n.children().is_empty())
+            .map(|n| n.to_string())
+            .collect();
+        assert_eq!(postorder, vec!["plus", "a", "times", "b", "2"]);
+        assert_eq!(parsed.iter_postorder().last(), Some(&parsed));
+        assert_eq!(
+            parsed
+                .descendants_of_type(&MathNode::call(MathNode::ci(""), vec![]))
+                .count(),
+            1
+        );
+
+        let mut nodes = parsed.iter_mut();
+        while let Some(node) = nodes.next_node() {
+            match node.as_ci() {
+                Some("a") => {
+                    *node = MathNode::call(MathNode::Op(BuiltinOp::minus), vec![MathNode::ci("d")])
+                }
+                Some("b") => *node = MathNode::ci("c"),
+                Some("d") => *node = MathNode::ci("e"),
+                _ => {}
+            }
+        }
+        assert_eq!(parsed.to_string(), "-e + c * 2");
+    }
+}
@@ -0,0 +1,411 @@
+use super::{BuiltinOp, ConstantSymbol, MathNode, NamedConstant, NumType};
+use crate::numbers::NumberSet;
+
+/// Names of the `System` context a plain symbol would be read as instead
+const RESERVED: &str = "C D E I K N O Pi Degree Infinity Indeterminate True False List Plus \
+                        Times Power Sqrt Surd Exp Log Sin Cos Tan Abs Max Min Mod Sum Product \
+                        Integrate Function Interval Element Re Im Arg Beta Gamma Zeta";
+
+/// A Wolfram Language string literal
+fn quoted(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// A symbol's name, as `Subscript[k, 1]` for `k_1` as `_` would start a
+/// pattern, and in the `Global` context where it names a built-in like `E`.
+/// Characters symbols can't have become `$`
+fn symbol(name: &str) -> String {
+    let name = name.trim();
+    if let Some((base, subscript)) = name.split_once('_') {
+        if !base.is_empty() && !subscript.is_empty() {
+            return format!("Subscript[{}, {}]", symbol(base), symbol(subscript));
+        }
+    }
+    if !name.is_empty() && name.chars().all(|c| c.is_ascii_digit()) {
+        return name.to_owned();
+    }
+    // Symbols only have letters, digits and `$`, and don't start with a digit
+    let mut valid: String = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '$' })
+        .collect();
+    if !valid.starts_with(char::is_alphabetic) {
+        valid.insert(0, '$');
+    }
+    if RESERVED.split_whitespace().any(|r| r == valid) {
+        format!("Global`{}", valid)
+    } else {
+        valid
+    }
+}
+
+fn call(head: &str, args: impl IntoIterator<Item = String>) -> String {
+    format!(
+        "{}[{}]",
+        head,
+        args.into_iter().collect::<Vec<_>>().join(", ")
+    )
+}
+
+/// A brace-delimited list, as of sets or vectors
+fn list(items: impl IntoIterator<Item = String>) -> String {
+    format!("{{{}}}", items.into_iter().collect::<Vec<_>>().join(", "))
+}
+
+/// A machine real, with the exponent as `*^` instead of `e`
+fn real(value: f64) -> String {
+    if value.is_nan() {
+        "Indeterminate".to_owned()
+    } else if value == f64::INFINITY {
+        "Infinity".to_owned()
+    } else if value == f64::NEG_INFINITY {
+        "DirectedInfinity[-1]".to_owned()
+    } else {
+        let text = format!("{:?}", value);
+        // Without a point `1*^-10` would be an exact rational
+        match text.split_once('e') {
+            Some((mantissa, exponent)) if !mantissa.contains('.') => {
+                format!("{}.*^{}", mantissa, exponent)
+            }
+            _ => text.replace('e', "*^"),
+        }
+    }
+}
+
+fn literal(num_type: &NumType) -> String {
+    match num_type {
+        NumType::Real(r) => real(*r),
+        NumType::Integer(i) => i.to_string(),
+        #[cfg(feature = "bigint")]
+        NumType::BigInteger(i) => i.to_string(),
+        NumType::ENotation(m, e) => format!("{}*^{}", real(*m), e),
+        NumType::Rational(a, b) => call("Rational", vec![a.to_string(), b.to_string()]),
+        NumType::ComplexCartesian(a, b) => call("Complex", vec![real(*a), real(*b)]),
+        NumType::ComplexPolar(r, theta) => call(
+            "Times",
+            vec![
+                real(*r),
+                call(
+                    "Exp",
+                    vec![call("Times", vec!["I".to_owned(), real(*theta)])],
+                ),
+            ],
+        ),
+        NumType::Constant(NamedConstant::Pi) => "Pi".to_owned(),
+        NumType::Constant(NamedConstant::Tau) => "Times[2, Pi]".to_owned(),
+        NumType::Constant(NamedConstant::E) => "E".to_owned(),
+        NumType::Constant(NamedConstant::Gamma) => "EulerGamma".to_owned(),
+        NumType::Constant(NamedConstant::GoldenRatio) => "GoldenRatio".to_owned(),
+        NumType::Constant(NamedConstant::Custom(text)) => symbol(text),
+        NumType::Other { text, .. } => match text.trim().parse::<f64>() {
+            Ok(value) => real(value),
+            Err(_) => quoted(text),
+        },
+    }
+}
+
+/// The head applied to the operands of `op` as they are
+fn head(op: BuiltinOp) -> Option<&'static str> {
+    use BuiltinOp::*;
+    let head = match op {
+        plus => "Plus",
+        times => "Times",
+        divide => "Divide",
+        power => "Power",
+        exp => "Exp",
+        ln => "Log",
+        abs => "Abs",
+        floor => "Floor",
+        ceiling => "Ceiling",
+        factorial => "Factorial",
+        gcd => "GCD",
+        lcm => "LCM",
+        max => "Max",
+        min => "Min",
+        conjugate => "Conjugate",
+        arg => "Arg",
+        real => "Re",
+        imaginary => "Im",
+        sin => "Sin",
+        cos => "Cos",
+        tan => "Tan",
+        sec => "Sec",
+        csc => "Csc",
+        cot => "Cot",
+        sinh => "Sinh",
+        cosh => "Cosh",
+        tanh => "Tanh",
+        sech => "Sech",
+        csch => "Csch",
+        coth => "Coth",
+        arcsin => "ArcSin",
+        arccos => "ArcCos",
+        arctan => "ArcTan",
+        arcsec => "ArcSec",
+        arccsc => "ArcCsc",
+        arccot => "ArcCot",
+        arcsinh => "ArcSinh",
+        arccosh => "ArcCosh",
+        arctanh => "ArcTanh",
+        arcsech => "ArcSech",
+        arccsch => "ArcCsch",
+        arccoth => "ArcCoth",
+        and => "And",
+        or => "Or",
+        not => "Not",
+        xor => "Xor",
+        implies => "Implies",
+        equivalent => "Equivalent",
+        eq => "Equal",
+        neq => "Unequal",
+        lt => "Less",
+        gt => "Greater",
+        leq => "LessEqual",
+        geq => "GreaterEqual",
+        approx => "TildeTilde",
+        inverse => "InverseFunction",
+        ident => "Identity",
+        domain => "FunctionDomain",
+        codomain | image => "FunctionRange",
+        compose => "Composition",
+        determinant => "Det",
+        transpose => "Transpose",
+        divergence => "Div",
+        grad => "Grad",
+        curl => "Curl",
+        laplacian => "Laplacian",
+        vectorproduct => "Cross",
+        scalarproduct => "Dot",
+        card => "Length",
+        setdiff => "Complement",
+        union => "Union",
+        intersect => "Intersection",
+        r#in => "Element",
+        notin => "NotElement",
+        subset => "SubsetEqual",
+        prsubset => "Subset",
+        notsubset => "NotSubsetEqual",
+        notprsubset => "NotSubset",
+        selector => "Part",
+        tendsto => "Rule",
+        _ => return None,
+    };
+    Some(head)
+}
+
+/// `{i, 1, n}`, `{x, 2}` or `x`, the variables of a sum, integral or derivative
+fn iterators(apply: &MathNode) -> Vec<String> {
+    let mut iterators = Vec::new();
+    for (name, degree) in apply.bvars() {
+        let variable = symbol(name);
+        iterators.push(match (apply.limits(), degree) {
+            (Some((low, high)), _) => list(vec![variable, wolfram(low), wolfram(high)]),
+            (None, Some(degree)) => list(vec![variable, wolfram(degree)]),
+            (None, None) => variable,
+        });
+    }
+    iterators
+}
+
+fn wolfram_op(op: BuiltinOp, apply: &MathNode) -> String {
+    use BuiltinOp::*;
+    let mut args: Vec<String> = apply.operands().into_iter().map(wolfram).collect();
+    let variables = apply.bound_variables();
+    if !variables.is_empty() {
+        let head = match op {
+            sum => "Sum",
+            product => "Product",
+            int => "Integrate",
+            diff | partialdiff => "D",
+            forall => "ForAll",
+            exists => "Exists",
+            _ => return apply.to_string(),
+        };
+        if let (forall, Some(condition)) | (exists, Some(condition)) = (op, apply.condition()) {
+            args.insert(0, wolfram(condition));
+        }
+        let variables = match op {
+            forall | exists => {
+                let variables: Vec<String> = variables.into_iter().map(symbol).collect();
+                match variables.len() {
+                    1 => variables,
+                    _ => vec![list(variables)],
+                }
+            }
+            _ => iterators(apply),
+        };
+        return match op {
+            forall | exists => call(head, variables.into_iter().chain(args)),
+            _ => call(head, args.into_iter().chain(variables)),
+        };
+    }
+    match (op, args.len()) {
+        (minus, 1) => call("Minus", args),
+        (minus, _) => call("Subtract", args),
+        (root, _) => match apply.degree() {
+            Some(degree) => call("Surd", args.into_iter().chain(Some(wolfram(degree)))),
+            None => call("Sqrt", args),
+        },
+        (log, _) => {
+            let base = apply.logbase().map_or_else(|| "10".to_owned(), wolfram);
+            call("Log", Some(base).into_iter().chain(args))
+        }
+        // Rounded toward zero like the evaluator, where `Quotient` and `Mod`
+        // round toward negative infinity
+        (quotient, 2) | (rem, 2) => {
+            let truncated = call("IntegerPart", vec![call("Divide", args.clone())]);
+            match op {
+                quotient => truncated,
+                _ => {
+                    let multiple = call("Times", vec![args[1].clone(), truncated]);
+                    call("Subtract", vec![args[0].clone(), multiple])
+                }
+            }
+        }
+        // `a` is a factor of `b` if `b` is divisible by it
+        (factorof, 2) => call("Divisible", args.into_iter().rev()),
+        (outerproduct, _) => call("Outer", Some("Times".to_owned()).into_iter().chain(args)),
+        (cartesianproduct, _) => call("Tuples", vec![list(args)]),
+        // The statistics of several values are taken of their list
+        (mean, _) | (sdev, _) | (variance, _) | (median, _) | (mode, _) => {
+            let head = match op {
+                mean => "Mean",
+                sdev => "StandardDeviation",
+                variance => "Variance",
+                median => "Median",
+                _ => "Commonest",
+            };
+            match args.len() {
+                1 => call(head, args),
+                _ => call(head, vec![list(args)]),
+            }
+        }
+        // The derivative of a function rather than of an expression
+        (diff, 1) => format!("Derivative[1][{}]", args.remove(0)),
+        _ => match head(op) {
+            Some(head) => call(head, args),
+            None => apply.to_string(),
+        },
+    }
+}
+
+fn wolfram(node: &MathNode) -> String {
+    match node {
+        MathNode::Root { children, .. } => {
+            let objects: Vec<_> = children.iter().filter(|c| !c.is_markup()).collect();
+            match objects.as_slice() {
+                [object] => wolfram(object),
+                objects => list(objects.iter().map(|c| wolfram(c))),
+            }
+        }
+        MathNode::Apply { children, .. } => {
+            let args = || node.operands().into_iter().map(wolfram);
+            match children.iter().find(|c| !c.is_markup()) {
+                Some(MathNode::Op(op)) => wolfram_op(*op, node),
+                Some(MathNode::Ci { children, .. }) => {
+                    call(&symbol(&crate::text_content(children)), args())
+                }
+                Some(MathNode::Csymbol { name, .. }) => call(&symbol(name), args()),
+                Some(head) => call(&wolfram(head), args()),
+                None => node.to_string(),
+            }
+        }
+        MathNode::Ci { children, .. } => symbol(&crate::text_content(children)),
+        MathNode::Csymbol { name, .. } => symbol(name),
+        MathNode::Cn { num_type, .. } => literal(num_type),
+        MathNode::Text(text) => quoted(text),
+        MathNode::Constant(constant) => match constant {
+            ConstantSymbol::Pi => "Pi",
+            ConstantSymbol::ExponentialE => "E",
+            ConstantSymbol::ImaginaryI => "I",
+            ConstantSymbol::EulerGamma => "EulerGamma",
+            ConstantSymbol::Infinity => "Infinity",
+            ConstantSymbol::NotANumber => "Indeterminate",
+            ConstantSymbol::True => "True",
+            ConstantSymbol::False => "False",
+        }
+        .to_owned(),
+        MathNode::NumberSet(set) => match set {
+            NumberSet::Integers => "Integers",
+            NumberSet::Reals => "Reals",
+            NumberSet::Rationals => "Rationals",
+            NumberSet::NaturalNumbers => "NonNegativeIntegers",
+            NumberSet::Complexes => "Complexes",
+            NumberSet::Primes => "Primes",
+            NumberSet::EmptySet => "{}",
+        }
+        .to_owned(),
+        MathNode::Piecewise { pieces, otherwise } => {
+            let pieces = list(
+                pieces
+                    .iter()
+                    .map(|(value, condition)| list(vec![wolfram(value), wolfram(condition)])),
+            );
+            match otherwise {
+                Some(otherwise) => call("Piecewise", vec![pieces, wolfram(otherwise)]),
+                None => call("Piecewise", vec![pieces]),
+            }
+        }
+        MathNode::Lambda { bound_vars, body } => call(
+            "Function",
+            vec![list(bound_vars.iter().map(|v| symbol(v))), wolfram(body)],
+        ),
+        MathNode::Interval { low, high, .. } => {
+            call("Interval", vec![list(vec![wolfram(low), wolfram(high)])])
+        }
+        MathNode::Set(items) | MathNode::Vector(items) | MathNode::List { items, .. } => {
+            list(items.iter().map(wolfram))
+        }
+        MathNode::Matrix(rows) => list(rows.iter().map(|row| list(row.iter().map(wolfram)))),
+        MathNode::Semantics { primary, .. } => wolfram(primary),
+        // No Wolfram Language counterpart, e.g. presentation markup
+        _ => node.to_string(),
+    }
+}
+
+impl MathNode {
+    /// Writes the expression as Wolfram Language input in `FullForm`, e.g.
+    /// `Plus[x, Times[a, Sin[t]]]`, for Mathematica. Identifiers like `k_1`
+    /// become `Subscript[k, 1]`, names of built-ins like `E` are put in the
+    /// `Global` context, and intervals are written as `Interval`, which is
+    /// closed. Things it has no counterpart for are written as `Display` does
+    pub fn to_wolfram(&self) -> String {
+        wolfram(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parse_document;
+
+    #[test]
+    fn test_to_wolfram() {
+        let test = r#"<apply><plus/>
+                        <apply><times/><ci>a</ci><apply><sin/><ci>t</ci></apply></apply>
+                        <apply><minus/><ci>E</ci><cn>2.5e-3</cn></apply>
+                        <apply><log/><logbase><cn type="integer">2</cn></logbase><ci>k_1</ci></apply>
+                        <apply><root/><degree><cn type="integer">3</cn></degree><cn type="rational">1<sep/>2</cn></apply>
+                        <apply><mean/><ci>x</ci><ci>y</ci></apply>
+                        <apply><card/><apply><union/><set><pi/></set><emptyset/></apply></apply>
+                        <apply><rem/><ci>a</ci><cn type="integer">-3</cn></apply>
+                      </apply>"#;
+        assert_eq!(
+            parse_document(test).unwrap().to_wolfram(),
+            "Plus[Times[a, Sin[t]], Subtract[Global`E, 0.0025], Log[2, Subscript[k, 1]], \
+             Surd[Rational[1, 2], 3], Mean[{x, y}], Length[Union[{Pi}, {}]], \
+             Subtract[a, Times[-3, IntegerPart[Divide[a, -3]]]]]"
+        );
+
+        let test = r#"<piecewise>
+                        <piece><apply><int/><bvar><ci>x</ci></bvar><lowlimit><cn type="integer">0</cn></lowlimit><uplimit><infinity/></uplimit>
+                          <apply><exp/><apply><minus/><ci>x</ci></apply></apply></apply><apply><lt/><ci>x</ci><ci>y</ci><cn>1e-10</cn></apply></piece>
+                        <otherwise><apply><forall/><bvar><ci>n</ci></bvar><condition><apply><in/><ci>n</ci><integers/></apply></condition>
+                          <apply><factorof/><cn type="integer">2</cn><apply><times/><ci>n</ci><cn type="integer">4</cn></apply></apply></apply></otherwise>
+                      </piecewise>"#;
+        assert_eq!(
+            parse_document(test).unwrap().to_wolfram(),
+            "Piecewise[{{Integrate[Exp[Minus[x]], {x, 0, Infinity}], Less[x, y, 1.*^-10]}}, \
+             ForAll[n, Element[n, Integers], Divisible[Times[n, 4], 2]]]"
+        );
+    }
+}
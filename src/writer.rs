@@ -0,0 +1,675 @@
+use super::{Annotation, IntervalClosure, MathNode, NumType, PresentationNode, QualifiedName};
+use crate::entities;
+use crate::sbml::{is_sbml_namespace, SBML_CORE};
+use crate::strict::encode_base64;
+use std::collections::HashMap;
+use std::io;
+
+pub(crate) const MATHML_NS: &str = "http://www.w3.org/1998/Math/MathML";
+
+/// Escapes text for use in XML. Named entities kept by
+/// `EntityPolicy::Preserve`, like `&tau;`, are written back out as they were
+pub(crate) fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for (i, c) in text.char_indices() {
+        match c {
+            entities::PRESERVED if is_entity_at(&text[i..]) => out.push('&'),
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+/// Comment text XML allows, which can't contain `--` nor end with `-`
+fn comment_text(text: &str) -> String {
+    let mut out = text.to_owned();
+    while out.contains("--") {
+        out = out.replace("--", "- -");
+    }
+    if out.ends_with('-') {
+        out.push(' ');
+    }
+    out
+}
+/// Whether `text` starts with a preserved entity
+fn is_entity_at(text: &str) -> bool {
+    text.find(';')
+        .is_some_and(|end| entities::entity_name(&text[..=end]).is_some())
+}
+
+fn format_int(value: i64, base: u32) -> String {
+    if base == 10 || !(2..=36).contains(&base) {
+        return value.to_string();
+    }
+    let mut digits = Vec::new();
+    let mut n = value.unsigned_abs();
+    while n > 0 {
+        digits.push(std::char::from_digit((n % base as u64) as u32, base).unwrap());
+        n /= base as u64;
+    }
+    if digits.is_empty() {
+        digits.push('0');
+    }
+    if value < 0 {
+        digits.push('-');
+    }
+    digits.iter().rev().collect::<String>().to_uppercase()
+}
+
+/// The digits of an integral float too large for `i64`, worked out on 32-bit
+/// limbs of its mantissa shifted by its exponent
+fn format_huge(whole: f64, base: u32) -> String {
+    let bits = whole.to_bits();
+    let shift = ((bits >> 52) & 0x7ff) as usize - 1075;
+    let mantissa = (bits & ((1 << 52) - 1)) | (1 << 52);
+    let wide = u128::from(mantissa) << (shift % 32);
+    let mut limbs = vec![0u32; shift / 32];
+    limbs.extend(&[wide as u32, (wide >> 32) as u32, (wide >> 64) as u32]);
+    let mut digits = Vec::new();
+    while limbs.iter().any(|&limb| limb != 0) {
+        let mut rem = 0u64;
+        for limb in limbs.iter_mut().rev() {
+            let current = (rem << 32) | u64::from(*limb);
+            *limb = (current / u64::from(base)) as u32;
+            rem = current % u64::from(base);
+        }
+        digits.push(std::char::from_digit(rem as u32, base).unwrap());
+    }
+    digits.iter().rev().collect::<String>().to_uppercase()
+}
+
+/// Writes the digits of a real in `base`, with as many fractional digits as a
+/// float can tell apart
+fn format_real(value: f64, base: u32) -> String {
+    if base == 10 || !(2..=36).contains(&base) || !value.is_finite() {
+        return value.to_string();
+    }
+    let whole = value.abs().trunc();
+    // `as i64` saturates at 2^63
+    let mut text = if whole < 2f64.powi(63) {
+        format_int(whole as i64, base)
+    } else {
+        format_huge(whole, base)
+    };
+    if value < 0.0 {
+        text.insert(0, '-');
+    }
+    let mut fraction = value.abs() - whole;
+    if fraction > 0.0 {
+        text.push('.');
+        let digits = (f64::MANTISSA_DIGITS as f64 / (base as f64).log2()).ceil() as usize;
+        for _ in 0..digits {
+            if fraction == 0.0 {
+                break;
+            }
+            fraction *= base as f64;
+            let digit = fraction.trunc();
+            text.push(std::char::from_digit(digit as u32, base).unwrap_or('0'));
+            fraction -= digit;
+        }
+    }
+    text.to_uppercase()
+}
+
+fn num_type_name(num_type: &NumType) -> &str {
+    match num_type {
+        NumType::Real(_) => "real",
+        NumType::Integer(_) => "integer",
+        NumType::Rational(_, _) => "rational",
+        NumType::ComplexCartesian(_, _) => "complex-cartesian",
+        NumType::ComplexPolar(_, _) => "complex-polar",
+        NumType::Constant(_) => "constant",
+        NumType::ENotation(_, _) => "e-notation",
+        NumType::Other { type_name, .. } => type_name,
+        #[cfg(feature = "bigint")]
+        NumType::BigInteger(_) => "integer",
+    }
+}
+
+fn num_type_body(num_type: &NumType, base: u32) -> String {
+    match num_type {
+        NumType::Real(r) => format_real(*r, base),
+        NumType::Integer(i) => format_int(*i, base),
+        NumType::Rational(a, b) => {
+            format!("{}<sep/>{}", format_int(*a, base), format_int(*b, base))
+        }
+        NumType::ComplexCartesian(a, b) | NumType::ComplexPolar(a, b) => {
+            format!("{}<sep/>{}", a, b)
+        }
+        NumType::Constant(c) => escape(c.symbol()),
+        NumType::ENotation(a, b) => format!("{}<sep/>{}", a, b),
+        NumType::Other { text, .. } => escape(text),
+        #[cfg(feature = "bigint")]
+        NumType::BigInteger(i) if base != 10 && (2..=36).contains(&base) => {
+            i.to_str_radix(base).to_uppercase()
+        }
+        #[cfg(feature = "bigint")]
+        NumType::BigInteger(i) => i.to_string(),
+    }
+}
+
+const XML_NS: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// Picks a prefix for a namespace, preferring the one it had in the source
+/// document unless that is already taken by another namespace
+fn prefix_for(
+    namespace: &str,
+    original: Option<&str>,
+    used: &mut HashMap<String, String>,
+) -> String {
+    if let Some(prefix) = used.get(namespace) {
+        return prefix.clone();
+    }
+    let taken = |prefix: &str| used.values().any(|p| p == prefix);
+    let prefix = match original {
+        Some(prefix) if !taken(prefix) => prefix.to_owned(),
+        _ if namespace.contains("sbml.org") && !taken("sbml") => "sbml".to_owned(),
+        _ if namespace.contains("cellml.org") && !taken("cellml") => "cellml".to_owned(),
+        _ => format!("ns{}", used.len()),
+    };
+    used.insert(namespace.to_owned(), prefix.clone());
+    prefix
+}
+
+/// Writes preserved attributes back out with their prefixes, declaring every
+/// namespace on the element itself
+fn format_attributes(attributes: &[(QualifiedName, String)]) -> String {
+    let mut namespaces = HashMap::new();
+    namespaces.insert(XML_NS.to_owned(), "xml".to_owned());
+    let mut attrs = String::new();
+    for (name, value) in attributes {
+        let value = escape(value);
+        match &name.namespace {
+            Some(ns) => {
+                let prefix = prefix_for(ns, name.prefix.as_deref(), &mut namespaces);
+                attrs.push_str(&format!(" {}:{}=\"{}\"", prefix, name.local, value));
+            }
+            None => attrs.push_str(&format!(" {}=\"{}\"", name.local, value)),
+        }
+    }
+    let mut declarations: Vec<(&String, &String)> = namespaces
+        .iter()
+        .filter(|(ns, _)| ns.as_str() != XML_NS)
+        .collect();
+    declarations.sort();
+    let mut out: String = declarations
+        .into_iter()
+        .map(|(ns, prefix)| format!(" xmlns:{}=\"{}\"", prefix, escape(ns)))
+        .collect();
+    out.push_str(&attrs);
+    out
+}
+
+fn write_children<W: io::Write>(children: &[MathNode], w: &mut W) -> io::Result<()> {
+    for child in children {
+        write_node(child, w)?;
+    }
+    Ok(())
+}
+
+fn write_presentation_children<W: io::Write>(
+    children: &[PresentationNode],
+    w: &mut W,
+) -> io::Result<()> {
+    for child in children {
+        write_presentation(child, w)?;
+    }
+    Ok(())
+}
+
+fn write_presentation<W: io::Write>(node: &PresentationNode, w: &mut W) -> io::Result<()> {
+    use PresentationNode::*;
+    let (tag, children): (&str, Vec<&PresentationNode>) = match node {
+        Mi(text) => return write!(w, "<mi>{}</mi>", escape(text)),
+        Mn(text) => return write!(w, "<mn>{}</mn>", escape(text)),
+        Mo(text) => return write!(w, "<mo>{}</mo>", escape(text)),
+        Mtext(text) => return write!(w, "<mtext>{}</mtext>", escape(text)),
+        Mspace => return write!(w, "<mspace/>"),
+        Mglyph { src, alt } => {
+            write!(w, "<mglyph")?;
+            if let Some(src) = src {
+                write!(w, " src=\"{}\"", escape(src))?;
+            }
+            return write!(w, " alt=\"{}\"/>", escape(alt));
+        }
+        Mrow(children) => {
+            write!(w, "<mrow>")?;
+            write_presentation_children(children, w)?;
+            return write!(w, "</mrow>");
+        }
+        Msqrt(children) => {
+            write!(w, "<msqrt>")?;
+            write_presentation_children(children, w)?;
+            return write!(w, "</msqrt>");
+        }
+        Mfenced {
+            open,
+            close,
+            children,
+        } => {
+            write!(
+                w,
+                "<mfenced open=\"{}\" close=\"{}\">",
+                escape(open),
+                escape(close)
+            )?;
+            write_presentation_children(children, w)?;
+            return write!(w, "</mfenced>");
+        }
+        Mtable(rows) => {
+            write!(w, "<mtable>")?;
+            for row in rows {
+                write!(w, "<mtr>")?;
+                for cell in row {
+                    write!(w, "<mtd>")?;
+                    write_presentation(cell, w)?;
+                    write!(w, "</mtd>")?;
+                }
+                write!(w, "</mtr>")?;
+            }
+            return write!(w, "</mtable>");
+        }
+        Mfrac(a, b) => ("mfrac", vec![a, b]),
+        Mroot(a, b) => ("mroot", vec![a, b]),
+        Msup(a, b) => ("msup", vec![a, b]),
+        Msub(a, b) => ("msub", vec![a, b]),
+        Msubsup(a, b, c) => ("msubsup", vec![a, b, c]),
+    };
+    write!(w, "<{}>", tag)?;
+    for child in children {
+        write_presentation(child, w)?;
+    }
+    write!(w, "</{}>", tag)
+}
+
+fn write_node<W: io::Write>(node: &MathNode, w: &mut W) -> io::Result<()> {
+    match node {
+        MathNode::Root {
+            children,
+            attributes,
+        } => {
+            write!(
+                w,
+                "<math xmlns=\"{}\"{}>",
+                MATHML_NS,
+                format_attributes(attributes)
+            )?;
+            write_children(children, w)?;
+            write!(w, "</math>")
+        }
+        MathNode::Apply {
+            children,
+            attributes,
+        } => {
+            write!(w, "<apply{}>", format_attributes(attributes))?;
+            write_children(children, w)?;
+            write!(w, "</apply>")
+        }
+        MathNode::Op(op) => write!(w, "<{}/>", op),
+        MathNode::Constant(constant) => write!(w, "<{}/>", constant),
+        MathNode::NumberSet(set) => write!(w, "<{}/>", set),
+        MathNode::Text(text) => write!(w, "{}", escape(text)),
+        MathNode::Ci {
+            children,
+            ci_type,
+            attributes,
+        } => {
+            write!(w, "<ci")?;
+            if let Some(ci_type) = ci_type {
+                write!(w, " type=\"{}\"", ci_type)?;
+            }
+            write!(w, "{}>", format_attributes(attributes))?;
+            write_children(children, w)?;
+            write!(w, "</ci>")
+        }
+        MathNode::Csymbol {
+            definition_url,
+            cd,
+            encoding,
+            name,
+            attributes,
+            ..
+        } => {
+            write!(w, "<csymbol")?;
+            if let Some(url) = definition_url {
+                write!(w, " definitionURL=\"{}\"", escape(url))?;
+            }
+            if let Some(cd) = cd {
+                write!(w, " cd=\"{}\"", escape(cd))?;
+            }
+            if let Some(encoding) = encoding {
+                write!(w, " encoding=\"{}\"", escape(encoding))?;
+            }
+            write!(
+                w,
+                "{}>{}</csymbol>",
+                format_attributes(attributes),
+                escape(name)
+            )
+        }
+        MathNode::Cn {
+            num_type,
+            base,
+            definition_url,
+            encoding,
+            units,
+            units_namespace,
+            attributes,
+        } => {
+            write!(w, "<cn type=\"{}\"", escape(num_type_name(num_type)))?;
+            if *base != 10 {
+                write!(w, " base=\"{}\"", base)?;
+            }
+            if let Some(url) = definition_url {
+                write!(w, " definitionURL=\"{}\"", escape(url))?;
+            }
+            if let Some(encoding) = encoding {
+                write!(w, " encoding=\"{}\"", escape(encoding))?;
+            }
+            let mut attributes = attributes.clone();
+            if let Some(units) = units {
+                // Without its own namespace, shares the SBML namespace of the
+                // other attributes if there is one
+                let (namespace, prefix) = match units_namespace {
+                    Some(namespace) => (Some(namespace.clone()), None),
+                    None => attributes
+                        .iter()
+                        .map(|(name, _)| name)
+                        .find(|name| name.namespace.as_deref().is_some_and(is_sbml_namespace))
+                        .map(|name| (name.namespace.clone(), name.prefix.clone()))
+                        .unwrap_or_else(|| (Some(SBML_CORE.to_owned()), Some("sbml".to_owned()))),
+                };
+                let name = QualifiedName {
+                    prefix,
+                    namespace,
+                    local: "units".to_owned(),
+                };
+                attributes.insert(0, (name, units.clone()));
+            }
+            write!(w, "{}", format_attributes(&attributes))?;
+            write!(w, ">{}</cn>", num_type_body(num_type, *base))
+        }
+        MathNode::Comment(text) => write!(w, "<!--{}-->", comment_text(text)),
+        MathNode::Cs(text) => write!(w, "<cs>{}</cs>", escape(text)),
+        MathNode::Cbytes(bytes) => write!(w, "<cbytes>{}</cbytes>", encode_base64(bytes)),
+        MathNode::Cerror(children) => {
+            write!(w, "<cerror>")?;
+            write_children(children, w)?;
+            write!(w, "</cerror>")
+        }
+        MathNode::Share(href) => write!(w, "<share href=\"{}\"/>", escape(href)),
+        MathNode::Unknown {
+            name,
+            attributes,
+            children,
+        } => {
+            write!(w, "<{}{}>", name, format_attributes(attributes))?;
+            write_children(children, w)?;
+            write!(w, "</{}>", name)
+        }
+        MathNode::Error { message, .. } => {
+            write!(w, "<merror><mtext>{}</mtext></merror>", escape(message))
+        }
+        MathNode::PI(target, value) => match value {
+            Some(value) => write!(w, "<?{} {}?>", target, value),
+            None => write!(w, "<?{}?>", target),
+        },
+        MathNode::Presentation(node) => write_presentation(node, w),
+        MathNode::Piecewise { pieces, otherwise } => {
+            write!(w, "<piecewise>")?;
+            for (value, condition) in pieces {
+                write!(w, "<piece>")?;
+                write_node(value, w)?;
+                write_node(condition, w)?;
+                write!(w, "</piece>")?;
+            }
+            if let Some(otherwise) = otherwise {
+                write!(w, "<otherwise>")?;
+                write_node(otherwise, w)?;
+                write!(w, "</otherwise>")?;
+            }
+            write!(w, "</piecewise>")
+        }
+        MathNode::Bind {
+            binder,
+            bound_vars,
+            condition,
+            body,
+        } => {
+            write!(w, "<bind>")?;
+            write_node(binder, w)?;
+            for var in bound_vars {
+                write!(w, "<bvar><ci>{}</ci></bvar>", escape(var))?;
+            }
+            if let Some(condition) = condition {
+                write_wrapped("condition", condition, w)?;
+            }
+            write_node(body, w)?;
+            write!(w, "</bind>")
+        }
+        MathNode::Lambda { bound_vars, body } => {
+            write!(w, "<lambda>")?;
+            for var in bound_vars {
+                write!(w, "<bvar><ci>{}</ci></bvar>", escape(var))?;
+            }
+            write_node(body, w)?;
+            write!(w, "</lambda>")
+        }
+        MathNode::Degree(degree) => write_wrapped("degree", degree, w),
+        MathNode::Logbase(base) => write_wrapped("logbase", base, w),
+        MathNode::Lowlimit(limit) => write_wrapped("lowlimit", limit, w),
+        MathNode::Uplimit(limit) => write_wrapped("uplimit", limit, w),
+        MathNode::Condition(condition) => write_wrapped("condition", condition, w),
+        MathNode::Bvar { name, degree } => {
+            write!(w, "<bvar><ci>{}</ci>", escape(name))?;
+            if let Some(degree) = degree {
+                write_wrapped("degree", degree, w)?;
+            }
+            write!(w, "</bvar>")
+        }
+        MathNode::Interval { closure, low, high } => {
+            match closure {
+                IntervalClosure::Closed => write!(w, "<interval>")?,
+                _ => write!(
+                    w,
+                    "<interval closure=\"{}\">",
+                    serde_plain::to_string(closure).expect("closures serialize to plain strings")
+                )?,
+            }
+            write_node(low, w)?;
+            write_node(high, w)?;
+            write!(w, "</interval>")
+        }
+        MathNode::Matrix(rows) => {
+            write!(w, "<matrix>")?;
+            for row in rows {
+                write!(w, "<matrixrow>")?;
+                write_children(row, w)?;
+                write!(w, "</matrixrow>")?;
+            }
+            write!(w, "</matrix>")
+        }
+        MathNode::Vector(children) => {
+            write!(w, "<vector>")?;
+            write_children(children, w)?;
+            write!(w, "</vector>")
+        }
+        MathNode::Semantics {
+            primary,
+            annotations,
+        } => {
+            write!(w, "<semantics>")?;
+            write_node(primary, w)?;
+            for annotation in annotations {
+                let (tag, body) = match annotation {
+                    Annotation::Text { text, .. } => ("annotation", escape(text)),
+                    // Already serialized (and escaped) when parsed
+                    Annotation::Xml { xml, .. } => ("annotation-xml", xml.clone()),
+                };
+                match annotation.encoding() {
+                    Some(encoding) => write!(w, "<{} encoding=\"{}\">", tag, escape(encoding))?,
+                    None => write!(w, "<{}>", tag)?,
+                }
+                write!(w, "{}</{}>", body, tag)?;
+            }
+            write!(w, "</semantics>")
+        }
+        MathNode::Set(children) => {
+            write!(w, "<set>")?;
+            write_children(children, w)?;
+            write!(w, "</set>")
+        }
+        MathNode::List { order, items } => {
+            match order {
+                Some(order) => write!(
+                    w,
+                    "<list order=\"{}\">",
+                    serde_plain::to_string(order).expect("orders serialize to plain strings")
+                )?,
+                None => write!(w, "<list>")?,
+            }
+            write_children(items, w)?;
+            write!(w, "</list>")
+        }
+    }
+}
+
+/// Writes a qualifier element holding a single child
+fn write_wrapped<W: io::Write>(tag: &str, inner: &MathNode, w: &mut W) -> io::Result<()> {
+    write!(w, "<{}>", tag)?;
+    write_node(inner, w)?;
+    write!(w, "</{}>", tag)
+}
+
+impl MathNode {
+    /// Serialize this node (and its children) as Content MathML
+    pub fn to_mathml(&self) -> String {
+        let mut buffer = Vec::new();
+        self.write_mathml(&mut buffer)
+            .expect("writing to a Vec can't fail");
+        String::from_utf8(buffer).expect("the writer only emits UTF-8")
+    }
+    /// Write this node (and its children) as Content MathML into `w`
+    pub fn write_mathml<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+        write_node(self, w)
+    }
+}
+
+impl PresentationNode {
+    /// Serialize this node (and its children) as Presentation MathML
+    pub fn to_mathml(&self) -> String {
+        let mut buffer = Vec::new();
+        self.write_mathml(&mut buffer)
+            .expect("writing to a Vec can't fail");
+        String::from_utf8(buffer).expect("the writer only emits UTF-8")
+    }
+    /// Write this node (and its children) as Presentation MathML into `w`
+    pub fn write_mathml<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+        write_presentation(self, w)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        parse_document, parse_document_with_options, EntityPolicy, MathNode, ParserOptions,
+    };
+
+    #[test]
+    fn test_write_simple() {
+        let test = r#"<math xmlns="http://www.w3.org/1998/Math/MathML">
+                            <apply>
+                          <plus/>
+                      <ci> x </ci>
+                      <cn type="integer" base="16"> AB3 </cn>
+                    </apply></math>"#;
+        let res = parse_document(test).unwrap();
+        assert_eq!(
+            res.to_mathml(),
+            r#"<math xmlns="http://www.w3.org/1998/Math/MathML"><apply><plus/><ci>x</ci><cn type="integer" base="16">AB3</cn></apply></math>"#
+        );
+
+        // 2^70, past where `i64` saturates
+        let huge = parse_document(r#"<cn type="real" base="16">400000000000000000</cn>"#).unwrap();
+        assert_eq!(
+            huge.to_mathml(),
+            r#"<cn type="real" base="16">400000000000000000</cn>"#
+        );
+        let comment = MathNode::Comment(" a --- b -".to_owned());
+        assert_eq!(comment.to_mathml(), "<!-- a - - - b - -->");
+    }
+    #[test]
+    fn test_round_trip() {
+        let test = r#"<math xmlns="http://www.w3.org/1998/Math/MathML"
+                    xmlns:sbml="http://www.sbml.org/sbml/level3/version2/core">
+        <apply>
+            <times/>
+            <cn type="real"> 12345.7 </cn>
+            <cn type="rational"> 12342 <sep/> 2342342 </cn>
+            <cn type="complex-cartesian"> 12.3 <sep/> 5 </cn>
+            <cn type="complex-polar"> 2 <sep/> 3.1415 </cn>
+            <cn type="constant">  &tau; </cn>
+            <cn type="e-notation"> 2 <sep/> -5 </cn>
+            <cn sbml:units="mole"> 1 </cn>
+            <csymbol definitionURL="http://www.sbml.org/sbml/symbols/time" encoding="text"> t </csymbol>
+            <apply><csymbol cd="transc1"> cos </csymbol><ci> x </ci></apply>
+            <piecewise>
+                <piece><cn> 1 </cn><apply><lt/><ci> x </ci><cn> 0 </cn></apply></piece>
+                <otherwise><cn> 2 </cn></otherwise>
+            </piecewise>
+        </apply>
+        <lambda><bvar><ci> x </ci></bvar><apply><exp/><ci> x </ci></apply></lambda>
+        <apply><root/><degree><cn> 3 </cn></degree><ci> x </ci></apply>
+        <apply><log/><logbase><cn> 2 </cn></logbase><ci> x </ci></apply>
+        <apply><sum/><bvar><ci> i </ci></bvar><lowlimit><cn> 1 </cn></lowlimit><uplimit><ci> n </ci></uplimit><ci> i </ci></apply>
+        <apply><int/><bvar><ci> x </ci></bvar><interval closure="closed-open"><cn> 0 </cn><cn> 1 </cn></interval><ci> x </ci></apply>
+        <apply><exists/><bvar><ci> x </ci></bvar><condition><apply><gt/><ci> x </ci><cn> 0 </cn></apply></condition><ci> x </ci></apply>
+        <apply><diff/><bvar><ci> x </ci><degree><cn> 2 </cn></degree></bvar><ci> f </ci></apply>
+        <apply><or/><false/><apply><neq/><exponentiale/><notanumber/></apply></apply>
+        <apply><intersect/><set><ci> a </ci><ci> b </ci></set><set><ci> b </ci></set></apply>
+        <apply><union/><naturalnumbers/><emptyset/></apply>
+        <apply><and/>
+            <apply><in/><ci> x </ci><reals/></apply>
+            <apply><notin/><ci> x </ci><primes/></apply>
+            <apply><subset/><ci> A </ci><ci> B </ci></apply>
+            <apply><prsubset/><ci> A </ci><ci> B </ci></apply>
+            <apply><notsubset/><ci> A </ci><ci> C </ci></apply>
+            <apply><notprsubset/><ci> A </ci><ci> C </ci></apply>
+            <apply><tendsto/><ci> x </ci><cn> 0 </cn></apply>
+            <apply><factorof/><cn> 3 </cn><cn> 12 </cn></apply>
+        </apply>
+        <apply><median/><list order="numeric"><cn> 3 </cn><cn> 1 </cn></list></apply>
+        <apply><vectorproduct/><vector><ci> a </ci><ci> b </ci></vector><vector><cn> 1 </cn><cn> 2 </cn></vector></apply>
+        <apply><transpose/><matrix><matrixrow><ci> a </ci><ci> b </ci></matrixrow><matrixrow><ci> c </ci><ci> d </ci></matrixrow></matrix></apply>
+        </math>"#;
+        let parsed = parse_document(test).unwrap();
+        let written = parsed.to_mathml();
+        assert!(written.contains("<apply><in/><ci>x</ci><reals/></apply>"));
+        assert_eq!(parse_document(&written).unwrap(), parsed);
+    }
+    #[test]
+    fn test_write_presentation() {
+        let test = r#"<mrow><msubsup><mi>x</mi><mn>1</mn><mn>2</mn></msubsup><mo>&lt;</mo><mfenced><mi>y</mi></mfenced></mrow>"#;
+        let parsed = parse_document(test).unwrap();
+        assert_eq!(
+            parsed.to_mathml(),
+            r#"<mrow><msubsup><mi>x</mi><mn>1</mn><mn>2</mn></msubsup><mo>&lt;</mo><mfenced open="(" close=")"><mi>y</mi></mfenced></mrow>"#
+        );
+    }
+    #[test]
+    fn test_write_entities() {
+        let literal = parse_document("<ci>&amp;pi; &alpha;</ci>").unwrap();
+        assert_eq!(literal.to_mathml(), "<ci>&amp;pi; \u{3b1}</ci>");
+        assert_eq!(parse_document(&literal.to_mathml()).unwrap(), literal);
+
+        let options = ParserOptions {
+            entities: EntityPolicy::Preserve,
+            ..ParserOptions::default()
+        };
+        let preserved = parse_document_with_options("<mi>&amp;pi;&pi;</mi>", &options).unwrap();
+        assert_eq!(preserved.to_mathml(), "<mi>&amp;pi;&pi;</mi>");
+    }
+}